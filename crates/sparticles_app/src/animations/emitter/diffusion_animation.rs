@@ -36,9 +36,12 @@ impl RegisterEmitterAnimation for RegisterDiffusionAnimation {
         "diffusion"
     }
 
-    fn import(&self, value: serde_json::Value) -> Box<dyn EmitterAnimation> {
-        let anim: DiffusionAnimation = serde_json::from_value(value).unwrap();
-        Box::new(anim)
+    fn import(
+        &self,
+        value: serde_json::Value,
+    ) -> Result<Box<dyn EmitterAnimation>, serde_json::Error> {
+        let anim: DiffusionAnimation = serde_json::from_value(value)?;
+        Ok(Box::new(anim))
     }
 
     fn create_default(&self) -> Box<dyn EmitterAnimation> {
@@ -47,6 +50,7 @@ impl RegisterEmitterAnimation for RegisterDiffusionAnimation {
                 from_sec: 0.,
                 until_sec: 5.,
                 lifetime_sec: 5.,
+                ..Default::default()
             },
             [0., 45.].into(),
             [0., 15.].into(),
@@ -89,11 +93,19 @@ impl HandleAction for DiffusionAnimation {
     fn enabled(&self) -> bool {
         self.enabled
     }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
 }
 
 impl EmitterAnimation for DiffusionAnimation {
     fn animate(&mut self, emitter: &mut EmitterUniform, clock: &Clock) {
-        let current_sec = self.life_cycle.get_current_sec(clock);
+        // Holds the end state once all repetitions have played
+        let current_sec = self
+            .life_cycle
+            .get_current_sec(clock)
+            .unwrap_or(self.life_cycle.end_sec());
 
         if !self.life_cycle.shoud_animate(current_sec) {
             return;
@@ -108,4 +120,17 @@ impl EmitterAnimation for DiffusionAnimation {
     fn as_any(&mut self) -> &mut dyn Any {
         self
     }
+
+    fn accepts_data(&self, data: &serde_json::Value) -> bool {
+        Self::deserialize(data).is_ok()
+    }
+
+    fn apply_data(&mut self, data: &serde_json::Value) {
+        if let Ok(anim) = Self::deserialize(data) {
+            *self = Self {
+                selected_action: std::mem::take(&mut self.selected_action),
+                ..anim
+            };
+        }
+    }
 }
@@ -0,0 +1,238 @@
+use std::any::Any;
+use std::fmt::{Display, Formatter};
+
+use crate::{
+    model::{easing::cubic_bezier, Clock, EmitterUniform},
+    traits::{EmitterAnimation, HandleAction, RegisterEmitterAnimation},
+    util::persistence::DynamicExport,
+    util::ListAction,
+};
+use glam::Vec2;
+use serde::{Deserialize, Serialize};
+
+/// Interpolation towards the next keyframe
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Interpolation {
+    /// Holds the value until the next keyframe
+    Step,
+    Linear,
+    /// Cubic bezier timing curve from (0, 0) to (1, 1) with two control points
+    Bezier {
+        p1: Vec2,
+        p2: Vec2,
+    },
+}
+
+impl Interpolation {
+    pub const ALL: [Interpolation; 3] = [
+        Self::Step,
+        Self::Linear,
+        Self::Bezier {
+            p1: Vec2::new(0.42, 0.),
+            p2: Vec2::new(0.58, 1.),
+        },
+    ];
+
+    pub fn same_kind(&self, other: &Self) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
+
+    fn apply(&self, fraction: f32) -> f32 {
+        match self {
+            Self::Step => 0.,
+            Self::Linear => fraction,
+            Self::Bezier { p1, p2 } => cubic_bezier(*p1, *p2, fraction),
+        }
+    }
+}
+
+impl Display for Interpolation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Step => f.write_str("Step"),
+            Self::Linear => f.write_str("Linear"),
+            Self::Bezier { .. } => f.write_str("Bezier"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Keyframe {
+    pub time_sec: f32,
+    pub value: f32,
+    pub interpolation: Interpolation,
+}
+
+/// Keyframes of a single emitter property
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KeyframeTrack {
+    /// One of EmitterUniform::PROPERTIES
+    pub property: String,
+    pub keys: Vec<Keyframe>,
+}
+
+impl KeyframeTrack {
+    pub fn new(property: impl Into<String>) -> Self {
+        Self {
+            property: property.into(),
+            keys: vec![],
+        }
+    }
+
+    pub fn sort_keys(&mut self) {
+        self.keys.sort_by(|a, b| a.time_sec.total_cmp(&b.time_sec));
+    }
+
+    pub fn duration_sec(&self) -> f32 {
+        self.keys.last().map_or(0., |key| key.time_sec)
+    }
+
+    /// Expects the keys to be sorted
+    pub fn value_at(&self, current_sec: f32) -> Option<f32> {
+        let first = self.keys.first()?;
+        let last = self.keys.last()?;
+
+        if current_sec <= first.time_sec {
+            return Some(first.value);
+        } else if last.time_sec <= current_sec {
+            return Some(last.value);
+        }
+
+        let next_idx = self
+            .keys
+            .iter()
+            .position(|key| current_sec < key.time_sec)?;
+
+        let from = &self.keys[next_idx - 1];
+        let until = &self.keys[next_idx];
+
+        let fraction = (current_sec - from.time_sec) / (until.time_sec - from.time_sec);
+        let fraction = from.interpolation.apply(fraction);
+
+        Some(from.value + fraction * (until.value - from.value))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct KeyframeAnimation {
+    pub tracks: Vec<KeyframeTrack>,
+    /// Restarts after the last keyframe of the longest track
+    pub looping: bool,
+
+    #[serde(skip_serializing, skip_deserializing)]
+    pub selected_action: ListAction,
+
+    pub enabled: bool,
+}
+
+#[derive(Clone, Copy)]
+pub struct RegisterKeyframeAnimation;
+
+impl RegisterEmitterAnimation for RegisterKeyframeAnimation {
+    fn tag(&self) -> &'static str {
+        "keyframe-animation"
+    }
+
+    fn import(
+        &self,
+        value: serde_json::Value,
+    ) -> Result<Box<dyn EmitterAnimation>, serde_json::Error> {
+        let mut anim: KeyframeAnimation = serde_json::from_value(value)?;
+        anim.sort_keys();
+        Ok(Box::new(anim))
+    }
+
+    fn create_default(&self) -> Box<dyn EmitterAnimation> {
+        let mut track = KeyframeTrack::new("hdr_mul");
+
+        for (time_sec, value) in [(0., 1.), (2., 10.), (4., 1.)] {
+            track.keys.push(Keyframe {
+                time_sec,
+                value,
+                interpolation: Interpolation::Linear,
+            });
+        }
+
+        Box::new(KeyframeAnimation::new(vec![track], true))
+    }
+}
+
+impl KeyframeAnimation {
+    pub fn new(tracks: Vec<KeyframeTrack>, looping: bool) -> Self {
+        Self {
+            tracks,
+            looping,
+            selected_action: ListAction::None,
+            enabled: true,
+        }
+    }
+
+    pub fn duration_sec(&self) -> f32 {
+        self.tracks
+            .iter()
+            .map(|track| track.duration_sec())
+            .fold(0., f32::max)
+    }
+
+    fn sort_keys(&mut self) {
+        for track in self.tracks.iter_mut() {
+            track.sort_keys();
+        }
+    }
+}
+
+impl HandleAction for KeyframeAnimation {
+    fn selected_action(&mut self) -> &mut ListAction {
+        &mut self.selected_action
+    }
+
+    fn export(&self) -> DynamicExport {
+        DynamicExport {
+            tag: RegisterKeyframeAnimation.tag().to_string(),
+            data: serde_json::to_value(self).unwrap(),
+        }
+    }
+
+    fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+}
+
+impl EmitterAnimation for KeyframeAnimation {
+    fn animate(&mut self, emitter: &mut EmitterUniform, clock: &Clock) {
+        let duration_sec = self.duration_sec();
+        let mut current_sec = clock.elapsed_sec();
+
+        if self.looping && 0. < duration_sec {
+            current_sec %= duration_sec;
+        }
+
+        for track in self.tracks.iter() {
+            if let Some(value) = track.value_at(current_sec) {
+                emitter.set_property(&track.property, value);
+            }
+        }
+    }
+
+    fn as_any(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn accepts_data(&self, data: &serde_json::Value) -> bool {
+        Self::deserialize(data).is_ok()
+    }
+
+    fn apply_data(&mut self, data: &serde_json::Value) {
+        if let Ok(anim) = Self::deserialize(data) {
+            *self = Self {
+                selected_action: std::mem::take(&mut self.selected_action),
+                ..anim
+            };
+            self.sort_keys();
+        }
+    }
+}
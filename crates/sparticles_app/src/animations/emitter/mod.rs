@@ -1,5 +1,9 @@
 pub mod diffusion_animation;
+pub mod keyframe_animation;
+pub mod path_animation;
 pub mod sway_animation;
 
 pub use diffusion_animation::DiffusionAnimation;
+pub use keyframe_animation::KeyframeAnimation;
+pub use path_animation::PathAnimation;
 pub use sway_animation::SwayAnimation;
@@ -0,0 +1,301 @@
+use std::any::Any;
+
+use crate::{
+    model::{Clock, Easing, EmitterUniform},
+    traits::{EmitterAnimation, HandleAction, RegisterEmitterAnimation},
+    util::persistence::DynamicExport,
+    util::ListAction,
+};
+use glam::{Mat4, Vec3};
+use serde::{Deserialize, Serialize};
+
+/// Samples per spline segment for the arc length table
+const SEGMENT_SAMPLES: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum SplineType {
+    /// Passes through every control point
+    CatmullRom,
+    /// Cubic segments, every segment uses 2 handles between its end points
+    Bezier,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum PathMode {
+    /// Stays at the end of the path
+    Once,
+    Loop,
+    PingPong,
+}
+
+/// Moves the emitter box position along a spline
+#[derive(Serialize, Deserialize)]
+pub struct PathAnimation {
+    pub spline: SplineType,
+    /// Relative to the parent of the emitter
+    pub points: Vec<Vec3>,
+    /// Time to travel the whole path once
+    pub duration_sec: f32,
+    pub mode: PathMode,
+    /// Speed easing over the duration
+    pub easing: Easing,
+    /// Points the emission direction along the path
+    pub orient_to_tangent: bool,
+
+    /// Cumulative arc lengths, rebuilt when the points change
+    #[serde(skip_serializing, skip_deserializing)]
+    arc_lengths: Vec<f32>,
+    #[serde(skip_serializing, skip_deserializing)]
+    arc_points: Vec<Vec3>,
+    #[serde(skip_serializing, skip_deserializing)]
+    arc_spline: Option<SplineType>,
+
+    /// Parent world matrix of the animated emitter, used to draw the path
+    #[serde(skip_serializing, skip_deserializing)]
+    pub parent_world: Mat4,
+
+    #[serde(skip_serializing, skip_deserializing)]
+    pub selected_action: ListAction,
+
+    pub enabled: bool,
+}
+
+#[derive(Clone, Copy)]
+pub struct RegisterPathAnimation;
+
+impl RegisterEmitterAnimation for RegisterPathAnimation {
+    fn tag(&self) -> &'static str {
+        "path-animation"
+    }
+
+    fn import(
+        &self,
+        value: serde_json::Value,
+    ) -> Result<Box<dyn EmitterAnimation>, serde_json::Error> {
+        let anim: PathAnimation = serde_json::from_value(value)?;
+        Ok(Box::new(anim))
+    }
+
+    fn create_default(&self) -> Box<dyn EmitterAnimation> {
+        let points = vec![
+            Vec3::new(-5., 0., 0.),
+            Vec3::new(-2., 3., -2.),
+            Vec3::new(2., 3., 2.),
+            Vec3::new(5., 0., 0.),
+        ];
+
+        Box::new(PathAnimation::new(SplineType::CatmullRom, points, 4.))
+    }
+}
+
+impl PathAnimation {
+    pub fn new(spline: SplineType, points: Vec<Vec3>, duration_sec: f32) -> Self {
+        Self {
+            spline,
+            points,
+            duration_sec,
+            mode: PathMode::Loop,
+            easing: Easing::Linear,
+            orient_to_tangent: false,
+            arc_lengths: vec![],
+            arc_points: vec![],
+            arc_spline: None,
+            parent_world: Mat4::IDENTITY,
+            selected_action: ListAction::None,
+            enabled: true,
+        }
+    }
+
+    pub fn segment_count(&self) -> usize {
+        match self.spline {
+            SplineType::CatmullRom => self.points.len().saturating_sub(1),
+            SplineType::Bezier => self.points.len().saturating_sub(1) / 3,
+        }
+    }
+
+    /// Position on the spline, u goes from 0 until the segment count
+    pub fn sample(&self, u: f32) -> Vec3 {
+        let segment_count = self.segment_count();
+
+        if segment_count == 0 {
+            return self.points.first().copied().unwrap_or(Vec3::ZERO);
+        }
+
+        let u = u.clamp(0., segment_count as f32);
+        let segment = (u.floor() as usize).min(segment_count - 1);
+        let t = u - segment as f32;
+
+        match self.spline {
+            SplineType::CatmullRom => {
+                let last = self.points.len() - 1;
+                let p0 = self.points[segment.saturating_sub(1)];
+                let p1 = self.points[segment];
+                let p2 = self.points[segment + 1];
+                let p3 = self.points[(segment + 2).min(last)];
+
+                let t2 = t * t;
+                let t3 = t2 * t;
+
+                0.5 * ((2. * p1)
+                    + (p2 - p0) * t
+                    + (2. * p0 - 5. * p1 + 4. * p2 - p3) * t2
+                    + (3. * p1 - p0 - 3. * p2 + p3) * t3)
+            }
+            SplineType::Bezier => {
+                let p = &self.points[segment * 3..segment * 3 + 4];
+                let inv = 1. - t;
+
+                inv * inv * inv * p[0]
+                    + 3. * inv * inv * t * p[1]
+                    + 3. * inv * t * t * p[2]
+                    + t * t * t * p[3]
+            }
+        }
+    }
+
+    /// Points along the spline, e.g. to draw the path
+    pub fn polyline(&self) -> Vec<Vec3> {
+        let sample_count = self.segment_count() * SEGMENT_SAMPLES;
+
+        (0..=sample_count)
+            .map(|i| self.sample(i as f32 / SEGMENT_SAMPLES as f32))
+            .collect()
+    }
+
+    fn update_arc_lengths(&mut self) {
+        if self.arc_points == self.points && self.arc_spline == Some(self.spline) {
+            return;
+        }
+
+        let polyline = self.polyline();
+        let mut length = 0.;
+
+        self.arc_lengths = vec![0.];
+
+        for window in polyline.windows(2) {
+            length += window[0].distance(window[1]);
+            self.arc_lengths.push(length);
+        }
+
+        self.arc_points = self.points.clone();
+        self.arc_spline = Some(self.spline);
+    }
+
+    /// Maps a fraction of the path length to the spline parameter
+    fn arc_to_param(&self, fraction: f32) -> f32 {
+        let total = *self.arc_lengths.last().unwrap_or(&0.);
+
+        if total <= 0. {
+            return 0.;
+        }
+
+        let distance = fraction * total;
+        let idx = self.arc_lengths.partition_point(|len| *len < distance);
+
+        if idx == 0 {
+            return 0.;
+        } else if self.arc_lengths.len() <= idx {
+            return self.segment_count() as f32;
+        }
+
+        let from = self.arc_lengths[idx - 1];
+        let until = self.arc_lengths[idx];
+        let sub_fraction = (distance - from) / (until - from).max(f32::EPSILON);
+
+        (idx as f32 - 1. + sub_fraction) / SEGMENT_SAMPLES as f32
+    }
+
+    fn path_fraction(&self, elapsed_sec: f32) -> f32 {
+        if self.duration_sec <= 0. {
+            return 0.;
+        }
+
+        let cycles = elapsed_sec / self.duration_sec;
+
+        let fraction = match self.mode {
+            PathMode::Once => cycles.min(1.),
+            PathMode::Loop => cycles.fract(),
+            PathMode::PingPong => {
+                let fraction = cycles % 2.;
+
+                if 1. < fraction {
+                    2. - fraction
+                } else {
+                    fraction
+                }
+            }
+        };
+
+        self.easing.apply(fraction)
+    }
+}
+
+impl HandleAction for PathAnimation {
+    fn selected_action(&mut self) -> &mut ListAction {
+        &mut self.selected_action
+    }
+
+    fn export(&self) -> DynamicExport {
+        DynamicExport {
+            tag: RegisterPathAnimation.tag().to_string(),
+            data: serde_json::to_value(self).unwrap(),
+        }
+    }
+
+    fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+}
+
+impl EmitterAnimation for PathAnimation {
+    fn animate(&mut self, emitter: &mut EmitterUniform, clock: &Clock) {
+        self.parent_world = emitter.parent_world;
+
+        if self.points.is_empty() {
+            return;
+        }
+
+        self.update_arc_lengths();
+
+        let fraction = self.path_fraction(clock.elapsed_sec());
+        let u = self.arc_to_param(fraction);
+
+        emitter.box_position = self.sample(u);
+
+        if self.orient_to_tangent {
+            let delta = 0.01;
+            let tangent = self.sample(u + delta) - self.sample(u - delta);
+
+            if let Some(dir) = tangent.try_normalize() {
+                // The emission direction is the rotated y axis, see transform::rotation_matrix
+                emitter.box_rotation.y = dir.x.clamp(-1., 1.).asin();
+                emitter.box_rotation.z = (-dir.z).atan2(dir.y);
+            }
+        }
+    }
+
+    fn as_any(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    /// The arc lengths are rebuilt on the next animate if the points changed
+    fn accepts_data(&self, data: &serde_json::Value) -> bool {
+        Self::deserialize(data).is_ok()
+    }
+
+    fn apply_data(&mut self, data: &serde_json::Value) {
+        if let Ok(anim) = Self::deserialize(data) {
+            self.spline = anim.spline;
+            self.points = anim.points;
+            self.duration_sec = anim.duration_sec;
+            self.mode = anim.mode;
+            self.easing = anim.easing;
+            self.orient_to_tangent = anim.orient_to_tangent;
+            self.enabled = anim.enabled;
+        }
+    }
+}
@@ -39,9 +39,12 @@ impl RegisterEmitterAnimation for RegisterSwayAnimation {
         "sway-animation"
     }
 
-    fn import(&self, value: serde_json::Value) -> Box<dyn EmitterAnimation> {
-        let anim: SwayAnimation = serde_json::from_value(value).unwrap();
-        Box::new(anim)
+    fn import(
+        &self,
+        value: serde_json::Value,
+    ) -> Result<Box<dyn EmitterAnimation>, serde_json::Error> {
+        let anim: SwayAnimation = serde_json::from_value(value)?;
+        Ok(Box::new(anim))
     }
 
     fn create_default(&self) -> Box<dyn EmitterAnimation> {
@@ -50,6 +53,7 @@ impl RegisterEmitterAnimation for RegisterSwayAnimation {
                 from_sec: 0.,
                 until_sec: 4.,
                 lifetime_sec: 4.,
+                ..Default::default()
             },
             glam::Vec2::ZERO,
             Vec2::new(30., 120.),
@@ -95,11 +99,19 @@ impl HandleAction for SwayAnimation {
     fn enabled(&self) -> bool {
         self.enabled
     }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
 }
 
 impl EmitterAnimation for SwayAnimation {
     fn animate(&mut self, emitter: &mut EmitterUniform, clock: &Clock) {
-        let current_sec = self.life_cycle.get_current_sec(clock);
+        // Holds the end state once all repetitions have played
+        let current_sec = self
+            .life_cycle
+            .get_current_sec(clock)
+            .unwrap_or(self.life_cycle.end_sec());
 
         if !self.life_cycle.shoud_animate(current_sec) {
             return;
@@ -116,6 +128,19 @@ impl EmitterAnimation for SwayAnimation {
         self
     }
 
+    fn accepts_data(&self, data: &serde_json::Value) -> bool {
+        Self::deserialize(data).is_ok()
+    }
+
+    fn apply_data(&mut self, data: &serde_json::Value) {
+        if let Ok(anim) = Self::deserialize(data) {
+            *self = Self {
+                selected_action: std::mem::take(&mut self.selected_action),
+                ..anim
+            };
+        }
+    }
+
     //fn create_ui(&mut self, ui: &mut Ui, ui_state: &GuiState) {
     //self.selected_action = ui_state.create_li_header(ui, "Sway animation");
     //let life_cycle = &mut self.life_cycle;
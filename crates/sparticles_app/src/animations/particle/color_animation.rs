@@ -64,9 +64,9 @@ impl RegisterParticleAnimation for RegisterColorAnimation {
         gfx_state: &GfxState,
         emitter: &EmitterState,
         value: serde_json::Value,
-    ) -> Box<dyn ParticleAnimation> {
-        let uniform = serde_json::from_value(value).unwrap();
-        Box::new(ColorAnimation::new(uniform, emitter, gfx_state))
+    ) -> Result<Box<dyn ParticleAnimation>, serde_json::Error> {
+        let uniform = serde_json::from_value(value)?;
+        Ok(Box::new(ColorAnimation::new(uniform, emitter, gfx_state)))
     }
 }
 
@@ -85,6 +85,10 @@ impl HandleAction for ColorAnimation {
     fn enabled(&self) -> bool {
         self.enabled
     }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
 }
 
 impl ParticleAnimation for ColorAnimation {
@@ -96,6 +100,17 @@ impl ParticleAnimation for ColorAnimation {
         }
     }
 
+    fn accepts_data(&self, data: &serde_json::Value) -> bool {
+        ColorUniform::deserialize(data).is_ok()
+    }
+
+    fn apply_data(&mut self, data: &serde_json::Value) {
+        if let Ok(uniform) = serde_json::from_value(data.clone()) {
+            self.uniform = uniform;
+            self.update_uniform = true;
+        }
+    }
+
     fn compute<'a>(
         &'a self,
         emitter: &'a EmitterState,
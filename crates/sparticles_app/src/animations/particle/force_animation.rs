@@ -2,8 +2,8 @@ use std::any::Any;
 
 use crate::{
     model::{Clock, EmitterState, GfxState, LifeCycle},
-    shaders::ShaderOptions,
-    traits::{HandleAction, ParticleAnimation, RegisterParticleAnimation},
+    shaders::{layout::gpu_struct, ShaderOptions},
+    traits::{BufferContent, HandleAction, ParticleAnimation, RegisterParticleAnimation},
     util::persistence::DynamicExport,
     util::ListAction,
 };
@@ -26,6 +26,7 @@ impl Default for ForceUniform {
                 from_sec: 0.,
                 until_sec: 5.,
                 lifetime_sec: 10.,
+                ..Default::default()
             },
             velocity: [-15., -15., 0.].into(),
             mass_per_unit: 0.5,
@@ -34,13 +35,24 @@ impl Default for ForceUniform {
 }
 
 impl ForceUniform {
-    fn create_buffer_content(&self) -> [f32; 4] {
-        [
-            self.velocity.x,
-            self.velocity.y,
-            self.velocity.z,
-            self.mass_per_unit,
-        ]
+    fn create_buffer_content(&self) -> Vec<u8> {
+        ForceGpu {
+            vel_x: self.velocity.x,
+            vel_y: self.velocity.y,
+            vel_z: self.velocity.z,
+            mass: self.mass_per_unit,
+        }
+        .buffer_content()
+    }
+}
+
+gpu_struct! {
+    /// Matches `Force` in force_anim.wgsl
+    pub struct ForceGpu as "Force" {
+        pub vel_x: f32,
+        pub vel_y: f32,
+        pub vel_z: f32,
+        pub mass: f32,
     }
 }
 
@@ -78,9 +90,9 @@ impl RegisterParticleAnimation for RegisterForceAnimation {
         gfx_state: &GfxState,
         emitter: &EmitterState,
         value: serde_json::Value,
-    ) -> Box<dyn ParticleAnimation> {
-        let uniform = serde_json::from_value(value).unwrap();
-        Box::new(ForceAnimation::new(uniform, emitter, gfx_state))
+    ) -> Result<Box<dyn ParticleAnimation>, serde_json::Error> {
+        let uniform = serde_json::from_value(value)?;
+        Ok(Box::new(ForceAnimation::new(uniform, emitter, gfx_state)))
     }
 }
 
@@ -112,23 +124,37 @@ impl HandleAction for ForceAnimation {
     fn enabled(&self) -> bool {
         self.enabled
     }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
 }
 
 impl ParticleAnimation for ForceAnimation {
     fn update(&mut self, clock: &Clock, gfx_state: &GfxState) {
         let queue = &gfx_state.queue;
-        let uniform = &self.uniform;
-        let current_sec = uniform.life_cycle.get_current_sec(clock);
-        self.should_animate = uniform.life_cycle.shoud_animate(current_sec);
+        let life_cycle = &self.uniform.life_cycle;
+        let current_sec = life_cycle.get_current_sec(clock);
+        self.should_animate = current_sec.is_some_and(|sec| life_cycle.shoud_animate(sec));
 
         if self.update_uniform {
-            let buf_content_raw = self.uniform.create_buffer_content();
-            let buf_content = bytemuck::cast_slice(&buf_content_raw);
-            queue.write_buffer(&self.buffer, 0, buf_content);
+            let buf_content = self.uniform.create_buffer_content();
+            queue.write_buffer(&self.buffer, 0, &buf_content);
             self.update_uniform = false;
         }
     }
 
+    fn accepts_data(&self, data: &serde_json::Value) -> bool {
+        ForceUniform::deserialize(data).is_ok()
+    }
+
+    fn apply_data(&mut self, data: &serde_json::Value) {
+        if let Ok(uniform) = serde_json::from_value(data.clone()) {
+            self.uniform = uniform;
+            self.update_uniform = true;
+        }
+    }
+
     fn as_any(&mut self) -> &mut dyn Any {
         self
     }
@@ -169,7 +195,7 @@ impl ForceAnimation {
 
         let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Force buffer"),
-            contents: bytemuck::cast_slice(&buffer_content),
+            contents: &buffer_content,
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
 
@@ -182,7 +208,7 @@ impl ForceAnimation {
                     ty: wgpu::BindingType::Buffer {
                         ty: wgpu::BufferBindingType::Uniform,
                         has_dynamic_offset: false,
-                        min_binding_size: wgpu::BufferSize::new(buffer_content.len() as u64 * 4),
+                        min_binding_size: wgpu::BufferSize::new(buffer_content.len() as u64),
                     },
                     count: None,
                 },
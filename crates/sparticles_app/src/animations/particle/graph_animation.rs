@@ -0,0 +1,168 @@
+use super::node_graph::NodeGraph;
+use super::script_animation::{ScriptAnimation, ScriptUniform};
+use crate::{
+    model::{Clock, EmitterState, GfxState},
+    traits::{HandleAction, ParticleAnimation, RegisterParticleAnimation},
+    util::{persistence::DynamicExport, ListAction},
+};
+use egui_wgpu::wgpu;
+use serde::{Deserialize, Serialize};
+use std::any::Any;
+
+const GRAPH_SCRIPT_NAME: &str = "Node graph";
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct GraphUniform {
+    pub graph: NodeGraph,
+}
+
+#[derive(Clone, Copy)]
+pub struct RegisterGraphAnimation;
+
+impl RegisterGraphAnimation {
+    /// Will append animation to emitter
+    pub fn append(uniform: GraphUniform, emitter: &mut EmitterState, gfx_state: &GfxState) {
+        let anim = Box::new(GraphAnimation::new(uniform, emitter, gfx_state));
+
+        emitter.push_particle_animation(anim);
+    }
+}
+
+impl RegisterParticleAnimation for RegisterGraphAnimation {
+    fn tag(&self) -> &'static str {
+        "node_graph"
+    }
+
+    fn create_default(
+        &self,
+        gfx_state: &GfxState,
+        emitter: &EmitterState,
+    ) -> Box<dyn ParticleAnimation> {
+        Box::new(GraphAnimation::new(
+            GraphUniform::default(),
+            emitter,
+            gfx_state,
+        ))
+    }
+
+    fn import(
+        &self,
+        gfx_state: &GfxState,
+        emitter: &EmitterState,
+        value: serde_json::Value,
+    ) -> Result<Box<dyn ParticleAnimation>, serde_json::Error> {
+        let uniform = serde_json::from_value(value)?;
+        Ok(Box::new(GraphAnimation::new(uniform, emitter, gfx_state)))
+    }
+}
+
+/// Node graph compiled into a script animation, the constants of the graph are script
+/// parameters so editing them doesn't rebuild the shader
+pub struct GraphAnimation {
+    pub uniform: GraphUniform,
+    pub script: ScriptAnimation,
+    /// Graph errors, shader errors are kept by the script
+    pub error: Option<String>,
+    /// Compiles the graph and its shader again with the next update
+    pub recompile: bool,
+    /// Writes the constants of the graph with the next update
+    pub update_constants: bool,
+    pub selected_action: ListAction,
+    pub enabled: bool,
+}
+
+impl HandleAction for GraphAnimation {
+    fn selected_action(&mut self) -> &mut ListAction {
+        &mut self.selected_action
+    }
+
+    fn export(&self) -> DynamicExport {
+        let data = serde_json::to_value(&self.uniform).unwrap();
+        let tag = RegisterGraphAnimation.tag().to_owned();
+
+        DynamicExport { tag, data }
+    }
+
+    fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+}
+
+impl ParticleAnimation for GraphAnimation {
+    fn update(&mut self, clock: &Clock, gfx_state: &GfxState) {
+        if self.recompile {
+            match self.uniform.graph.compile(GRAPH_SCRIPT_NAME) {
+                Ok(script) => {
+                    self.script.uniform = script;
+                    self.script.recompile = true;
+                    self.error = None;
+                }
+                Err(err) => self.error = Some(err),
+            }
+
+            self.recompile = false;
+            self.update_constants = false;
+        }
+
+        if self.update_constants {
+            // Same structure, so the compiled script only differs in its parameter values
+            if let Ok(script) = self.uniform.graph.compile(GRAPH_SCRIPT_NAME) {
+                self.script.uniform.params = script.params;
+                self.script.update_uniform = true;
+            }
+
+            self.update_constants = false;
+        }
+
+        self.script.update(clock, gfx_state);
+    }
+
+    fn compute<'a>(
+        &'a self,
+        emitter: &'a EmitterState,
+        clock: &Clock,
+        compute_pass: &mut wgpu::ComputePass<'a>,
+    ) {
+        self.script.compute(emitter, clock, compute_pass);
+    }
+
+    fn recreate(&self, gfx_state: &GfxState, emitter: &EmitterState) -> Box<dyn ParticleAnimation> {
+        Box::new(Self::new(self.uniform.clone(), emitter, gfx_state))
+    }
+
+    fn as_any(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl GraphAnimation {
+    pub fn new(uniform: GraphUniform, emitter: &EmitterState, gfx_state: &GfxState) -> Self {
+        let (script, error) = match uniform.graph.compile(GRAPH_SCRIPT_NAME) {
+            Ok(script) => (script, None),
+            Err(err) => {
+                let script = ScriptUniform {
+                    name: GRAPH_SCRIPT_NAME.to_string(),
+                    params: vec![],
+                    source: String::new(),
+                    functions: String::new(),
+                };
+
+                (script, Some(err))
+            }
+        };
+
+        Self {
+            uniform,
+            script: ScriptAnimation::new(script, emitter, gfx_state),
+            error,
+            recompile: false,
+            update_constants: false,
+            selected_action: ListAction::None,
+            enabled: true,
+        }
+    }
+}
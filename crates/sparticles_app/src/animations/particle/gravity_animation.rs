@@ -1,5 +1,6 @@
 use crate::model::clock::Clock;
 use crate::model::{EmitterState, GfxState, LifeCycle};
+use crate::shaders::layout::gpu_struct;
 use crate::shaders::ShaderOptions;
 use crate::traits::*;
 use crate::util::persistence::DynamicExport;
@@ -16,9 +17,11 @@ pub struct GravityUniform {
     pub gravitational_force: f32,
     pub dead_zone: f32,
     pub mass: f32,
+    #[serde(skip)]
     pub should_animate: bool,
     pub start_pos: Vec3,
     pub end_pos: Vec3,
+    #[serde(skip)]
     pub current_pos: Vec3,
 }
 
@@ -29,6 +32,7 @@ impl Default for GravityUniform {
                 from_sec: 0.,
                 until_sec: 6.,
                 lifetime_sec: 12.,
+                ..Default::default()
             },
             gravitational_force: 0.01,
             dead_zone: 4.,
@@ -41,6 +45,18 @@ impl Default for GravityUniform {
     }
 }
 
+gpu_struct! {
+    /// Matches `GravitationalForce` in gravity_anim.wgsl
+    pub struct GravityGpu as "GravitationalForce" {
+        pub gravitational_force: f32,
+        pub dead_zone: f32,
+        pub mass: f32,
+        pub current_pos_x: f32,
+        pub current_pos_y: f32,
+        pub current_pos_z: f32,
+    }
+}
+
 pub struct GravityUniformOptions {
     /// In newton
     pub gravitational_force: f32,
@@ -66,15 +82,16 @@ impl GravityUniform {
         }
     }
 
-    fn create_buffer_content(&self) -> [f32; 6] {
-        [
-            self.gravitational_force,
-            self.dead_zone,
-            self.mass,
-            self.current_pos.x,
-            self.current_pos.y,
-            self.current_pos.z,
-        ]
+    fn create_buffer_content(&self) -> Vec<u8> {
+        GravityGpu {
+            gravitational_force: self.gravitational_force,
+            dead_zone: self.dead_zone,
+            mass: self.mass,
+            current_pos_x: self.current_pos.x,
+            current_pos_y: self.current_pos.y,
+            current_pos_z: self.current_pos.z,
+        }
+        .buffer_content()
     }
 }
 
@@ -112,9 +129,9 @@ impl RegisterParticleAnimation for RegisterGravityAnimation {
         gfx_state: &GfxState,
         emitter: &EmitterState,
         value: serde_json::Value,
-    ) -> Box<dyn ParticleAnimation> {
-        let uniform = serde_json::from_value(value).unwrap();
-        Box::new(GravityAnimation::new(uniform, emitter, gfx_state))
+    ) -> Result<Box<dyn ParticleAnimation>, serde_json::Error> {
+        let uniform = serde_json::from_value(value)?;
+        Ok(Box::new(GravityAnimation::new(uniform, emitter, gfx_state)))
     }
 }
 
@@ -144,6 +161,10 @@ impl HandleAction for GravityAnimation {
     fn enabled(&self) -> bool {
         self.enabled
     }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
 }
 
 impl ParticleAnimation for GravityAnimation {
@@ -175,14 +196,25 @@ impl ParticleAnimation for GravityAnimation {
         let life_cycle = &mut uniform.life_cycle;
         let current_sec = life_cycle.get_current_sec(clock);
 
-        uniform.should_animate = life_cycle.shoud_animate(current_sec);
+        uniform.should_animate = current_sec.is_some_and(|sec| life_cycle.shoud_animate(sec));
 
-        if uniform.should_animate {
+        if let Some(current_sec) = current_sec.filter(|_| uniform.should_animate) {
             let fraction = life_cycle.get_fraction(current_sec);
             uniform.current_pos = uniform.start_pos.lerp(uniform.end_pos, fraction);
             let buffer_content = uniform.create_buffer_content();
 
-            queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&buffer_content));
+            queue.write_buffer(&self.buffer, 0, &buffer_content);
+        }
+    }
+
+    /// The buffer is written every update while animating
+    fn accepts_data(&self, data: &serde_json::Value) -> bool {
+        GravityUniform::deserialize(data).is_ok()
+    }
+
+    fn apply_data(&mut self, data: &serde_json::Value) {
+        if let Ok(uniform) = serde_json::from_value(data.clone()) {
+            self.uniform = uniform;
         }
     }
 
@@ -205,7 +237,7 @@ impl GravityAnimation {
 
         let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Gravitational buffer"),
-            contents: bytemuck::cast_slice(&buffer_content),
+            contents: &buffer_content,
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
 
@@ -218,7 +250,7 @@ impl GravityAnimation {
                     ty: wgpu::BindingType::Buffer {
                         ty: wgpu::BufferBindingType::Uniform,
                         has_dynamic_offset: false,
-                        min_binding_size: wgpu::BufferSize::new(buffer_content.len() as u64 * 4),
+                        min_binding_size: wgpu::BufferSize::new(buffer_content.len() as u64),
                     },
                     count: None,
                 },
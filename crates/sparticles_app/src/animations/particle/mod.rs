@@ -1,11 +1,18 @@
 pub mod color_animation;
 pub mod force_animation;
+pub mod graph_animation;
 pub mod gravity_animation;
+pub mod node_graph;
+pub mod script_animation;
 pub mod stray_animation;
 
 pub use color_animation::{ColorAnimation, ColorUniform, RegisterColorAnimation};
-pub use force_animation::{ForceAnimation, ForceUniform, RegisterForceAnimation};
+pub use force_animation::{ForceAnimation, ForceGpu, ForceUniform, RegisterForceAnimation};
+pub use graph_animation::{GraphAnimation, GraphUniform, RegisterGraphAnimation};
 pub use gravity_animation::{
-    GravityAnimation, GravityUniform, GravityUniformOptions, RegisterGravityAnimation,
+    GravityAnimation, GravityGpu, GravityUniform, GravityUniformOptions, RegisterGravityAnimation,
+};
+pub use script_animation::{
+    RegisterScriptAnimation, ScriptAnimation, ScriptParam, ScriptParamValue, ScriptUniform,
 };
 pub use stray_animation::{RegisterStrayAnimation, StrayAnimation, StrayUniform};
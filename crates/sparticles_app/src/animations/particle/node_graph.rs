@@ -0,0 +1,657 @@
+use super::script_animation::{ScriptParam, ScriptParamValue, ScriptUniform};
+use crate::model::Easing;
+use glam::{Vec2, Vec3, Vec4};
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MathOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Min,
+    Max,
+    Dot,
+    Cross,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FunctionOp {
+    Negate,
+    Abs,
+    Sin,
+    Cos,
+    Fract,
+    Length,
+    Normalize,
+}
+
+/// Every value in the graph is a vec4, scalars are splatted and vectors have w = 0
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum NodeKind {
+    // Particle attributes, read before any output is written
+    Position,
+    Velocity,
+    /// Age of the particle from 0 to 1
+    Age,
+    Color,
+    Time,
+    // Constants
+    Float {
+        value: f32,
+    },
+    Vector {
+        value: Vec3,
+    },
+    ColorValue {
+        value: Vec4,
+    },
+    // Math
+    Math {
+        op: MathOp,
+    },
+    Function {
+        op: FunctionOp,
+    },
+    Mix,
+    /// Value noise of the xyz input
+    Noise {
+        scale: f32,
+    },
+    /// Maps the x input from 0 to 1 on the eased range
+    Curve {
+        from: f32,
+        to: f32,
+        easing: Easing,
+    },
+    // Outputs
+    SetVelocity,
+    SetColor,
+    /// Accelerates the particle by force / mass
+    Force,
+}
+
+impl NodeKind {
+    /// Templates for the add node menu
+    pub const ALL: [NodeKind; 16] = [
+        Self::Position,
+        Self::Velocity,
+        Self::Age,
+        Self::Color,
+        Self::Time,
+        Self::Float { value: 1. },
+        Self::Vector { value: Vec3::Y },
+        Self::ColorValue { value: Vec4::ONE },
+        Self::Math {
+            op: MathOp::Multiply,
+        },
+        Self::Function {
+            op: FunctionOp::Sin,
+        },
+        Self::Mix,
+        Self::Noise { scale: 1. },
+        Self::Curve {
+            from: 0.,
+            to: 1.,
+            easing: Easing::Linear,
+        },
+        Self::SetVelocity,
+        Self::SetColor,
+        Self::Force,
+    ];
+
+    pub fn inputs(&self) -> &'static [&'static str] {
+        match self {
+            Self::Math { .. } => &["a", "b"],
+            Self::Function { .. } => &["value"],
+            Self::Mix => &["a", "b", "t"],
+            Self::Noise { .. } => &["position"],
+            Self::Curve { .. } => &["t"],
+            Self::SetVelocity => &["velocity"],
+            Self::SetColor => &["color"],
+            Self::Force => &["force"],
+            _ => &[],
+        }
+    }
+
+    /// Outputs write the particle and can't be linked
+    pub fn is_output(&self) -> bool {
+        matches!(self, Self::SetVelocity | Self::SetColor | Self::Force)
+    }
+
+    /// The kind without its constants, which are passed as script parameters
+    fn structure(&self) -> NodeKind {
+        match *self {
+            Self::Float { .. } => Self::Float { value: 0. },
+            Self::Vector { .. } => Self::Vector { value: Vec3::ZERO },
+            Self::ColorValue { .. } => Self::ColorValue { value: Vec4::ZERO },
+            Self::Noise { .. } => Self::Noise { scale: 0. },
+            Self::Curve { .. } => Self::Curve {
+                from: 0.,
+                to: 0.,
+                easing: Easing::Linear,
+            },
+            kind => kind,
+        }
+    }
+
+    /// Constant of the node as script parameter value
+    fn constant(&self) -> Option<ScriptParamValue> {
+        match *self {
+            Self::Float { value } | Self::Noise { scale: value } => {
+                Some(ScriptParamValue::Float { value })
+            }
+            Self::Vector { value } => Some(ScriptParamValue::Vec3 { value }),
+            Self::ColorValue { value } => Some(ScriptParamValue::Color { value }),
+            Self::Curve { from, to, easing } => Some(ScriptParamValue::Curve { from, to, easing }),
+            _ => None,
+        }
+    }
+}
+
+impl Display for NodeKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Position => f.write_str("Position"),
+            Self::Velocity => f.write_str("Velocity"),
+            Self::Age => f.write_str("Age"),
+            Self::Color => f.write_str("Color"),
+            Self::Time => f.write_str("Time"),
+            Self::Float { .. } => f.write_str("Float"),
+            Self::Vector { .. } => f.write_str("Vector"),
+            Self::ColorValue { .. } => f.write_str("Color value"),
+            Self::Math { .. } => f.write_str("Math"),
+            Self::Function { .. } => f.write_str("Function"),
+            Self::Mix => f.write_str("Mix"),
+            Self::Noise { .. } => f.write_str("Noise"),
+            Self::Curve { .. } => f.write_str("Curve"),
+            Self::SetVelocity => f.write_str("Set velocity"),
+            Self::SetColor => f.write_str("Set color"),
+            Self::Force => f.write_str("Force"),
+        }
+    }
+}
+
+impl Display for MathOp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl Display for FunctionOp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl MathOp {
+    pub const ALL: [MathOp; 8] = [
+        Self::Add,
+        Self::Subtract,
+        Self::Multiply,
+        Self::Divide,
+        Self::Min,
+        Self::Max,
+        Self::Dot,
+        Self::Cross,
+    ];
+}
+
+impl FunctionOp {
+    pub const ALL: [FunctionOp; 7] = [
+        Self::Negate,
+        Self::Abs,
+        Self::Sin,
+        Self::Cos,
+        Self::Fract,
+        Self::Length,
+        Self::Normalize,
+    ];
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct NodeInput {
+    /// Index of the node that is linked
+    pub link: Option<usize>,
+    /// Splatted if nothing is linked
+    pub value: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Node {
+    pub kind: NodeKind,
+    pub inputs: Vec<NodeInput>,
+    /// Position in the graph editor
+    pub pos: Vec2,
+}
+
+impl Node {
+    pub fn new(kind: NodeKind, pos: Vec2) -> Self {
+        let inputs = kind
+            .inputs()
+            .iter()
+            .map(|_| NodeInput {
+                link: None,
+                value: 0.,
+            })
+            .collect();
+
+        Self { kind, inputs, pos }
+    }
+
+    fn linked(mut self, links: &[usize]) -> Self {
+        for (input, link) in self.inputs.iter_mut().zip(links) {
+            input.link = Some(*link);
+        }
+
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Visit {
+    Pending,
+    Active,
+    Done,
+}
+
+/// Particle behaviour built from nodes, compiled into a script animation body
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NodeGraph {
+    pub nodes: Vec<Node>,
+}
+
+impl Default for NodeGraph {
+    /// Pulls particles towards the emitter and fades them out over their lifetime
+    fn default() -> Self {
+        Self {
+            nodes: vec![
+                Node::new(NodeKind::Position, Vec2::new(0., 0.)),
+                Node::new(NodeKind::Float { value: -2. }, Vec2::new(0., 80.)),
+                Node::new(
+                    NodeKind::Math {
+                        op: MathOp::Multiply,
+                    },
+                    Vec2::new(200., 20.),
+                )
+                .linked(&[0, 1]),
+                Node::new(NodeKind::Force, Vec2::new(400., 20.)).linked(&[2]),
+                Node::new(NodeKind::Age, Vec2::new(0., 180.)),
+                Node::new(
+                    NodeKind::Curve {
+                        from: 1.,
+                        to: 0.,
+                        easing: Easing::Linear,
+                    },
+                    Vec2::new(200., 180.),
+                )
+                .linked(&[4]),
+                Node::new(NodeKind::Color, Vec2::new(0., 260.)),
+                Node::new(
+                    NodeKind::Math {
+                        op: MathOp::Multiply,
+                    },
+                    Vec2::new(200., 260.),
+                )
+                .linked(&[6, 5]),
+                Node::new(NodeKind::SetColor, Vec2::new(400., 260.)).linked(&[7]),
+            ],
+        }
+    }
+}
+
+impl NodeGraph {
+    /// Links to the node are removed and the links after it are shifted
+    pub fn remove(&mut self, idx: usize) {
+        self.nodes.remove(idx);
+
+        for input in self
+            .nodes
+            .iter_mut()
+            .flat_map(|node| node.inputs.iter_mut())
+        {
+            input.link = match input.link {
+                Some(link) if link == idx => None,
+                Some(link) if idx < link => Some(link - 1),
+                link => link,
+            };
+        }
+    }
+
+    /// Graphs with the same structure compile to the same shader, only the values of
+    /// the constants differ
+    pub fn same_structure(&self, other: &NodeGraph) -> bool {
+        self.nodes.len() == other.nodes.len()
+            && self.nodes.iter().zip(other.nodes.iter()).all(|(a, b)| {
+                a.kind.structure() == b.kind.structure()
+                    && a.inputs.len() == b.inputs.len()
+                    && a.inputs
+                        .iter()
+                        .zip(b.inputs.iter())
+                        .all(|(a, b)| a.link == b.link)
+            })
+    }
+
+    /// Only nodes that lead to an output are compiled, every attribute is read before
+    /// the outputs are written in node order. Constants and unlinked input values are
+    /// script parameters, so changing them doesn't change the shader
+    pub fn compile(&self, name: &str) -> Result<ScriptUniform, String> {
+        let mut visits = vec![Visit::Pending; self.nodes.len()];
+        let mut order = Vec::new();
+
+        for (idx, node) in self.nodes.iter().enumerate() {
+            if node.inputs.len() != node.kind.inputs().len() {
+                return Err(format!("Node {} {} has invalid inputs", idx, node.kind));
+            }
+
+            for input in node.inputs.iter() {
+                match input.link {
+                    Some(link) if self.nodes.len() <= link => {
+                        return Err(format!("Node {} links to missing node {}", idx, link));
+                    }
+                    Some(link) if self.nodes[link].kind.is_output() => {
+                        return Err(format!("Node {} links to output node {}", idx, link));
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        for (idx, node) in self.nodes.iter().enumerate() {
+            if node.kind.is_output() {
+                self.visit(idx, &mut visits, &mut order)?;
+            }
+        }
+
+        let mut functions = String::new();
+        let mut source = String::new();
+        let mut params = Vec::new();
+
+        for idx in order.iter().copied() {
+            let node = &self.nodes[idx];
+
+            if let Some(value) = node.kind.constant() {
+                params.push(ScriptParam {
+                    name: constant_name(idx, &node.kind),
+                    value,
+                });
+            }
+
+            for (i, input) in node.inputs.iter().enumerate() {
+                if input.link.is_none() {
+                    params.push(ScriptParam {
+                        name: input_name(idx, i),
+                        value: ScriptParamValue::Float { value: input.value },
+                    });
+                }
+            }
+        }
+
+        let has_noise = order
+            .iter()
+            .any(|idx| matches!(self.nodes[*idx].kind, NodeKind::Noise { .. }));
+
+        if has_noise {
+            functions += NOISE_FUNCTION;
+        }
+
+        for idx in order.iter().copied() {
+            let node = &self.nodes[idx];
+
+            if node.kind.is_output() {
+                continue;
+            }
+
+            source += &format!("let n{} = {};\n", idx, self.expression(idx));
+        }
+
+        for idx in order.iter().copied() {
+            let node = &self.nodes[idx];
+            let input = |i: usize| self.input(idx, i);
+
+            match node.kind {
+                NodeKind::SetVelocity => {
+                    source += &format!(
+                        "particle.vel_mass = vec4<f32>(({}).xyz, particle.vel_mass.w);\n",
+                        input(0)
+                    );
+                }
+                NodeKind::SetColor => {
+                    source += &format!("particle.color = {};\n", input(0));
+                }
+                NodeKind::Force => {
+                    source += &format!(
+                        "particle.vel_mass += vec4<f32>(({}).xyz / max(particle.vel_mass.w, 0.0001) * em.delta_sec, 0.);\n",
+                        input(0)
+                    );
+                }
+                _ => {}
+            }
+        }
+
+        Ok(ScriptUniform {
+            name: name.to_string(),
+            params,
+            source,
+            functions,
+        })
+    }
+
+    /// Depth first, inputs are ordered before the nodes that use them
+    fn visit(
+        &self,
+        idx: usize,
+        visits: &mut [Visit],
+        order: &mut Vec<usize>,
+    ) -> Result<(), String> {
+        match visits[idx] {
+            Visit::Done => return Ok(()),
+            Visit::Active => {
+                return Err(format!(
+                    "Node {} {} is part of a cycle",
+                    idx, self.nodes[idx].kind
+                ))
+            }
+            Visit::Pending => {}
+        }
+
+        visits[idx] = Visit::Active;
+
+        for link in self.nodes[idx].inputs.iter().filter_map(|input| input.link) {
+            self.visit(link, visits, order)?;
+        }
+
+        visits[idx] = Visit::Done;
+        order.push(idx);
+
+        Ok(())
+    }
+
+    fn input(&self, idx: usize, i: usize) -> String {
+        match self.nodes[idx].inputs[i].link {
+            Some(link) => format!("n{}", link),
+            None => format!("vec4<f32>({})", input_name(idx, i)),
+        }
+    }
+
+    fn expression(&self, idx: usize) -> String {
+        let node = &self.nodes[idx];
+        let input = |i: usize| self.input(idx, i);
+        let constant = constant_name(idx, &node.kind);
+
+        match node.kind {
+            NodeKind::Position => "vec4<f32>(particle.model.w.xyz, 0.)".to_string(),
+            NodeKind::Velocity => "vec4<f32>(particle.vel_mass.xyz, 0.)".to_string(),
+            NodeKind::Age => "vec4<f32>(age_fraction)".to_string(),
+            NodeKind::Color => "particle.color".to_string(),
+            NodeKind::Time => "vec4<f32>(em.elapsed_sec)".to_string(),
+            NodeKind::Float { .. } => format!("vec4<f32>({})", constant),
+            NodeKind::Vector { .. } => format!("vec4<f32>({}, 0.)", constant),
+            NodeKind::ColorValue { .. } => constant,
+            NodeKind::Math { op } => {
+                let (a, b) = (input(0), input(1));
+
+                match op {
+                    MathOp::Add => format!("{} + {}", a, b),
+                    MathOp::Subtract => format!("{} - {}", a, b),
+                    MathOp::Multiply => format!("{} * {}", a, b),
+                    MathOp::Divide => format!("{} / {}", a, b),
+                    MathOp::Min => format!("min({}, {})", a, b),
+                    MathOp::Max => format!("max({}, {})", a, b),
+                    MathOp::Dot => format!("vec4<f32>(dot(({}).xyz, ({}).xyz))", a, b),
+                    MathOp::Cross => {
+                        format!("vec4<f32>(cross(({}).xyz, ({}).xyz), 0.)", a, b)
+                    }
+                }
+            }
+            NodeKind::Function { op } => {
+                let value = input(0);
+
+                match op {
+                    FunctionOp::Negate => format!("-{}", value),
+                    FunctionOp::Abs => format!("abs({})", value),
+                    FunctionOp::Sin => format!("sin({})", value),
+                    FunctionOp::Cos => format!("cos({})", value),
+                    FunctionOp::Fract => format!("fract({})", value),
+                    FunctionOp::Length => format!("vec4<f32>(length(({}).xyz))", value),
+                    FunctionOp::Normalize => {
+                        format!("vec4<f32>(normalize(({}).xyz), 0.)", value)
+                    }
+                }
+            }
+            NodeKind::Mix => format!("mix({}, {}, ({}).x)", input(0), input(1), input(2)),
+            NodeKind::Noise { .. } => {
+                format!("vec4<f32>(graph_noise(({}).xyz * {}))", input(0), constant)
+            }
+            NodeKind::Curve { .. } => format!("vec4<f32>({}(({}).x))", constant, input(0)),
+            NodeKind::SetVelocity | NodeKind::SetColor | NodeKind::Force => {
+                unreachable!("Outputs have no value")
+            }
+        }
+    }
+}
+
+const NOISE_FUNCTION: &str = "fn graph_hash(p: vec3<f32>) -> f32 {
+    return fract(sin(dot(p, vec3<f32>(127.1, 311.7, 74.7))) * 43758.5453);
+}
+
+fn graph_noise(p: vec3<f32>) -> f32 {
+    let i = floor(p);
+    let f = fract(p);
+    let u = f * f * (3. - 2. * f);
+
+    let x00 = mix(graph_hash(i), graph_hash(i + vec3<f32>(1., 0., 0.)), u.x);
+    let x10 = mix(graph_hash(i + vec3<f32>(0., 1., 0.)), graph_hash(i + vec3<f32>(1., 1., 0.)), u.x);
+    let x01 = mix(graph_hash(i + vec3<f32>(0., 0., 1.)), graph_hash(i + vec3<f32>(1., 0., 1.)), u.x);
+    let x11 = mix(graph_hash(i + vec3<f32>(0., 1., 1.)), graph_hash(i + vec3<f32>(1., 1., 1.)), u.x);
+
+    return mix(mix(x00, x10, u.y), mix(x01, x11, u.y), u.z) * 2. - 1.;
+}
+
+";
+
+/// Script parameter of the constant of a node, curves are functions
+fn constant_name(idx: usize, kind: &NodeKind) -> String {
+    match kind {
+        NodeKind::Curve { .. } => format!("curve{}", idx),
+        _ => format!("c{}", idx),
+    }
+}
+
+/// Script parameter of the value of an unlinked input
+fn input_name(idx: usize, input: usize) -> String {
+    format!("c{}_{}", idx, input)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{EasingCurve, EasingMode};
+    use crate::shaders::validate_source;
+
+    /// Every node kind, linked so each one is compiled
+    fn all_kinds_graph() -> NodeGraph {
+        let mut graph = NodeGraph::default();
+
+        for kind in NodeKind::ALL.iter().filter(|kind| !kind.is_output()) {
+            let idx = graph.nodes.len();
+            let mut node = Node::new(*kind, Vec2::ZERO);
+
+            for input in node.inputs.iter_mut() {
+                input.link = Some(0);
+            }
+
+            graph.nodes.push(node);
+            graph
+                .nodes
+                .push(Node::new(NodeKind::SetColor, Vec2::ZERO).linked(&[idx]));
+        }
+
+        graph
+    }
+
+    #[test]
+    fn compiled_graphs_validate() {
+        for graph in [NodeGraph::default(), all_kinds_graph()] {
+            let script = graph.compile("test graph").unwrap();
+            let source = script.shader_source().unwrap();
+
+            validate_source(&source).unwrap();
+        }
+    }
+
+    #[test]
+    fn constants_are_parameters() {
+        let graph = all_kinds_graph();
+        let mut edited = graph.clone();
+
+        for node in edited.nodes.iter_mut() {
+            node.pos = Vec2::ONE;
+
+            match &mut node.kind {
+                NodeKind::Float { value } | NodeKind::Noise { scale: value } => *value += 1.,
+                NodeKind::Vector { value } => *value = Vec3::X,
+                NodeKind::ColorValue { value } => *value = Vec4::ZERO,
+                NodeKind::Curve { from, easing, .. } => {
+                    *from = 0.5;
+                    *easing = Easing::Penner {
+                        curve: EasingCurve::Quad,
+                        mode: EasingMode::In,
+                    };
+                }
+                _ => {}
+            }
+
+            for input in node.inputs.iter_mut() {
+                input.value += 2.;
+            }
+        }
+
+        assert!(graph.same_structure(&edited));
+
+        let script = graph.compile("test graph").unwrap();
+        let edited_script = edited.compile("test graph").unwrap();
+
+        assert!(script.has_same_layout(&edited_script));
+        assert_ne!(script.params, edited_script.params);
+    }
+
+    #[test]
+    fn links_and_operations_change_the_structure() {
+        let graph = NodeGraph::default();
+
+        let mut relinked = graph.clone();
+        relinked.nodes[2].inputs[1].link = None;
+        assert!(!graph.same_structure(&relinked));
+
+        let mut op_changed = graph.clone();
+        op_changed.nodes[2].kind = NodeKind::Math { op: MathOp::Add };
+        assert!(!graph.same_structure(&op_changed));
+
+        let mut removed = graph.clone();
+        removed.remove(1);
+        assert!(!graph.same_structure(&removed));
+    }
+}
@@ -0,0 +1,483 @@
+use crate::{
+    model::{Clock, Easing, EmitterState, GfxState},
+    shaders::preprocessor::{is_identifier, PreprocessError, Preprocessor, ShaderSource},
+    traits::{HandleAction, ParticleAnimation, RegisterParticleAnimation},
+    util::{persistence::DynamicExport, ListAction},
+};
+use egui_wgpu::wgpu::{self, util::DeviceExt};
+use glam::{Vec3, Vec4};
+use serde::{Deserialize, Serialize};
+use std::any::Any;
+use std::collections::HashSet;
+
+/// Amount of samples a curve parameter is baked into, interpolated linearly on the GPU
+pub const CURVE_SAMPLES: usize = 16;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ScriptParamValue {
+    /// Available as `f32`
+    Float { value: f32 },
+    /// Available as `vec3<f32>`
+    Vec3 { value: Vec3 },
+    /// Available as `vec4<f32>`
+    Color { value: Vec4 },
+    /// Available as a function `fn name(t: f32) -> f32` with t from 0 to 1
+    Curve { from: f32, to: f32, easing: Easing },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScriptParam {
+    pub name: String,
+    #[serde(flatten)]
+    pub value: ScriptParamValue,
+}
+
+/// WGSL function body that runs for every living particle. In scope are:
+/// - `particle`, the particle as var, written back after the body
+/// - `em`, the `Emitter` uniform
+/// - `index`, the particle index
+/// - `age_fraction`, the particle age from 0 to 1
+/// - every parameter by its name
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScriptUniform {
+    pub name: String,
+    pub params: Vec<ScriptParam>,
+    pub source: String,
+    /// Helper functions declared before the entry point
+    #[serde(default)]
+    pub functions: String,
+}
+
+impl Default for ScriptUniform {
+    fn default() -> Self {
+        Self {
+            name: "Swirl".to_string(),
+            params: vec![
+                ScriptParam {
+                    name: "strength".to_string(),
+                    value: ScriptParamValue::Float { value: 2. },
+                },
+                ScriptParam {
+                    name: "axis".to_string(),
+                    value: ScriptParamValue::Vec3 { value: Vec3::Y },
+                },
+                ScriptParam {
+                    name: "tint".to_string(),
+                    value: ScriptParamValue::Color {
+                        value: Vec4::new(0.4, 0.6, 1., 1.),
+                    },
+                },
+                ScriptParam {
+                    name: "fade".to_string(),
+                    value: ScriptParamValue::Curve {
+                        from: 1.,
+                        to: 0.,
+                        easing: Easing::Linear,
+                    },
+                },
+            ],
+            source: [
+                "let position = particle.model.w.xyz;",
+                "let swirl = cross(normalize(axis), position) * strength;",
+                "let velocity = particle.vel_mass.xyz + swirl * em.delta_sec;",
+                "",
+                "particle.vel_mass = vec4<f32>(velocity, particle.vel_mass.w);",
+                "particle.color = vec4<f32>(tint.rgb, tint.a * fade(age_fraction));",
+            ]
+            .join("\n"),
+            functions: String::new(),
+        }
+    }
+}
+
+impl ScriptUniform {
+    /// Every parameter takes one vec4 slot, curves take one slot per four samples
+    fn buffer_content(&self) -> Vec<u8> {
+        let mut content: Vec<f32> = Vec::new();
+
+        for param in self.params.iter() {
+            match param.value {
+                ScriptParamValue::Float { value } => content.extend([value, 0., 0., 0.]),
+                ScriptParamValue::Vec3 { value } => content.extend(value.extend(0.).to_array()),
+                ScriptParamValue::Color { value } => content.extend(value.to_array()),
+                ScriptParamValue::Curve { from, to, easing } => {
+                    content.extend((0..CURVE_SAMPLES).map(|i| {
+                        let fraction = i as f32 / (CURVE_SAMPLES - 1) as f32;
+                        from + (to - from) * easing.apply(fraction)
+                    }));
+                }
+            }
+        }
+
+        // Uniform structs can't be empty
+        if content.is_empty() {
+            content.extend([0.; 4]);
+        }
+
+        bytemuck::cast_slice(&content).to_vec()
+    }
+
+    /// Same source and parameters apart from their values
+    pub fn has_same_layout(&self, other: &ScriptUniform) -> bool {
+        self.source == other.source
+            && self.functions == other.functions
+            && self.params.len() == other.params.len()
+            && self.params.iter().zip(other.params.iter()).all(|(a, b)| {
+                a.name == b.name
+                    && std::mem::discriminant(&a.value) == std::mem::discriminant(&b.value)
+            })
+    }
+
+    fn check_params(&self) -> Result<(), String> {
+        let mut names = HashSet::new();
+
+        for param in self.params.iter() {
+            if !is_identifier(&param.name) {
+                return Err(format!(
+                    "Parameter name {} is not an identifier",
+                    param.name
+                ));
+            }
+
+            if !names.insert(&param.name) {
+                return Err(format!("Parameter {} is declared twice", param.name));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Errors in the body point to lines of the script
+    pub fn shader_source(&self) -> Result<ShaderSource, PreprocessError> {
+        let mut fields = String::new();
+        let mut bindings = String::new();
+        let mut functions = String::new();
+
+        for param in self.params.iter() {
+            let name = &param.name;
+
+            match param.value {
+                ScriptParamValue::Float { .. } => {
+                    fields += &format!("    {}: vec4<f32>,\n", name);
+                    bindings += &format!("    let {0} = params.{0}.x;\n", name);
+                }
+                ScriptParamValue::Vec3 { .. } => {
+                    fields += &format!("    {}: vec4<f32>,\n", name);
+                    bindings += &format!("    let {0} = params.{0}.xyz;\n", name);
+                }
+                ScriptParamValue::Color { .. } => {
+                    fields += &format!("    {}: vec4<f32>,\n", name);
+                    bindings += &format!("    let {0} = params.{0};\n", name);
+                }
+                ScriptParamValue::Curve { .. } => {
+                    let last = CURVE_SAMPLES - 1;
+
+                    fields += &format!("    {}: array<vec4<f32>, {}>,\n", name, CURVE_SAMPLES / 4);
+                    functions += &format!(
+                        "fn {0}(t: f32) -> f32 {{\n    \
+                            let x = clamp(t, 0., 1.) * {1}.;\n    \
+                            let a = u32(floor(x));\n    \
+                            let b = min(a + 1u, {1}u);\n    \
+                            return mix(params.{0}[a / 4u][a % 4u], params.{0}[b / 4u][b % 4u], fract(x));\n\
+                        }}\n\n",
+                        name, last
+                    );
+                }
+            }
+        }
+
+        if fields.is_empty() {
+            fields += "    _unused: vec4<f32>,\n";
+        }
+
+        let header = format!(
+            "#include \"declarations.wgsl\"\n\n\
+            struct ScriptParams {{\n{}}}\n\n\
+            @group(0) @binding(1) var<storage, read_write> particles: array<Particle>;\n\
+            @group(0) @binding(2) var<uniform> em: Emitter;\n\
+            @group(1) @binding(0) var<uniform> params: ScriptParams;\n\n\
+            {}",
+            fields, functions
+        );
+
+        let entry = format!(
+            "@compute\n\
+            @workgroup_size(128)\n\
+            fn main(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {{\n    \
+                let particle_len = arrayLength(&particles);\n    \
+                let index = global_invocation_id.x;\n\n    \
+                if particle_len <= index {{\n        return;\n    }}\n\n    \
+                var particle = particles[index];\n\n    \
+                if particle.lifetime == -1. || em.particle_lifetime < particle.lifetime {{\n        \
+                    return;\n    \
+                }}\n\n    \
+                let age_fraction = particle.lifetime / em.particle_lifetime;\n\
+                {}\n    {{\n",
+            bindings
+        );
+
+        let footer = "    }\n\n    particles[index] = particle;\n}\n";
+
+        let mut preprocessor = Preprocessor::new(&[]);
+        preprocessor.include_source("script header", &header)?;
+
+        if !self.functions.is_empty() {
+            preprocessor.include_source(&format!("{} functions", self.name), &self.functions)?;
+        }
+
+        preprocessor.include_source("script entry", &entry)?;
+        preprocessor.include_source(&self.name, &self.source)?;
+        preprocessor.include_source("script footer", footer)?;
+
+        Ok(preprocessor.finish())
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct RegisterScriptAnimation;
+
+impl RegisterScriptAnimation {
+    /// Will append animation to emitter
+    pub fn append(uniform: ScriptUniform, emitter: &mut EmitterState, gfx_state: &GfxState) {
+        let anim = Box::new(ScriptAnimation::new(uniform, emitter, gfx_state));
+
+        emitter.push_particle_animation(anim);
+    }
+}
+
+impl RegisterParticleAnimation for RegisterScriptAnimation {
+    fn tag(&self) -> &'static str {
+        "script"
+    }
+
+    fn create_default(
+        &self,
+        gfx_state: &GfxState,
+        emitter: &EmitterState,
+    ) -> Box<dyn ParticleAnimation> {
+        Box::new(ScriptAnimation::new(
+            ScriptUniform::default(),
+            emitter,
+            gfx_state,
+        ))
+    }
+
+    fn import(
+        &self,
+        gfx_state: &GfxState,
+        emitter: &EmitterState,
+        value: serde_json::Value,
+    ) -> Result<Box<dyn ParticleAnimation>, serde_json::Error> {
+        let uniform = serde_json::from_value(value)?;
+        Ok(Box::new(ScriptAnimation::new(uniform, emitter, gfx_state)))
+    }
+}
+
+/// Particle animation from a user authored WGSL body, see [`ScriptUniform`]
+pub struct ScriptAnimation {
+    /// Missing while the script doesn't compile
+    pub pipeline: Option<wgpu::ComputePipeline>,
+    pub pipeline_layout: wgpu::PipelineLayout,
+    /// Not sized, so the parameters can change with a recompile
+    pub params_layout: wgpu::BindGroupLayout,
+    pub bind_group: wgpu::BindGroup,
+    pub buffer: wgpu::Buffer,
+    pub uniform: ScriptUniform,
+    pub error: Option<String>,
+    pub update_uniform: bool,
+    /// Compiles the source and creates the parameter buffer again with the next update
+    pub recompile: bool,
+    pub selected_action: ListAction,
+    pub enabled: bool,
+}
+
+impl HandleAction for ScriptAnimation {
+    fn selected_action(&mut self) -> &mut ListAction {
+        &mut self.selected_action
+    }
+
+    fn export(&self) -> DynamicExport {
+        let data = serde_json::to_value(&self.uniform).unwrap();
+        let tag = RegisterScriptAnimation.tag().to_owned();
+
+        DynamicExport { tag, data }
+    }
+
+    fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+}
+
+impl ParticleAnimation for ScriptAnimation {
+    fn update(&mut self, _clock: &Clock, gfx_state: &GfxState) {
+        if self.update_uniform {
+            let buf_content = self.uniform.buffer_content();
+            gfx_state.queue.write_buffer(&self.buffer, 0, &buf_content);
+            self.update_uniform = false;
+        }
+
+        if self.recompile {
+            self.create_params(gfx_state);
+            self.compile(gfx_state);
+            self.recompile = false;
+        }
+    }
+
+    /// Only parameter values are applied, other changes need a new buffer or shader
+    fn accepts_data(&self, data: &serde_json::Value) -> bool {
+        ScriptUniform::deserialize(data).is_ok_and(|uniform| self.uniform.has_same_layout(&uniform))
+    }
+
+    fn apply_data(&mut self, data: &serde_json::Value) {
+        if let Ok(uniform) = serde_json::from_value(data.clone()) {
+            self.uniform = uniform;
+            self.update_uniform = true;
+        }
+    }
+
+    fn compute<'a>(
+        &'a self,
+        emitter: &'a EmitterState,
+        clock: &Clock,
+        compute_pass: &mut wgpu::ComputePass<'a>,
+    ) {
+        let Some(pipeline) = &self.pipeline else {
+            return;
+        };
+
+        let nr = clock.get_bindgroup_nr();
+
+        compute_pass.set_pipeline(pipeline);
+        compute_pass.set_bind_group(0, &emitter.bgs[nr], &[]);
+        compute_pass.set_bind_group(1, &self.bind_group, &[]);
+        compute_pass.dispatch_workgroups(emitter.dispatch_x_count, 1, 1);
+    }
+
+    fn recreate(&self, gfx_state: &GfxState, emitter: &EmitterState) -> Box<dyn ParticleAnimation> {
+        Box::new(Self::new(self.uniform.clone(), emitter, gfx_state))
+    }
+
+    fn as_any(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl ScriptAnimation {
+    pub fn new(uniform: ScriptUniform, emitter: &EmitterState, gfx_state: &GfxState) -> Self {
+        let device = &gfx_state.device;
+
+        let params_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Script animation params layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Script animation layout"),
+            bind_group_layouts: &[&emitter.bg_layout, &params_layout],
+            push_constant_ranges: &[],
+        });
+
+        let (buffer, bind_group) = Self::params_buffer(&uniform, &params_layout, gfx_state);
+
+        let mut anim = Self {
+            pipeline: None,
+            pipeline_layout,
+            params_layout,
+            bind_group,
+            buffer,
+            uniform,
+            error: None,
+            update_uniform: false,
+            recompile: false,
+            selected_action: ListAction::None,
+            enabled: true,
+        };
+
+        anim.compile(gfx_state);
+        anim
+    }
+
+    fn params_buffer(
+        uniform: &ScriptUniform,
+        params_layout: &wgpu::BindGroupLayout,
+        gfx_state: &GfxState,
+    ) -> (wgpu::Buffer, wgpu::BindGroup) {
+        let device = &gfx_state.device;
+
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Script animation params"),
+            contents: &uniform.buffer_content(),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Script animation params"),
+            layout: params_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+        });
+
+        (buffer, bind_group)
+    }
+
+    fn create_params(&mut self, gfx_state: &GfxState) {
+        let (buffer, bind_group) =
+            Self::params_buffer(&self.uniform, &self.params_layout, gfx_state);
+
+        self.buffer = buffer;
+        self.bind_group = bind_group;
+        self.update_uniform = false;
+    }
+
+    fn compile(&mut self, gfx_state: &GfxState) {
+        let label = format!("Script animation {}", self.uniform.name);
+
+        let module = self.uniform.check_params().and_then(|_| {
+            let source = self
+                .uniform
+                .shader_source()
+                .map_err(|err| err.to_string())?;
+            gfx_state.create_shader_module(&source, &label)
+        });
+
+        match module {
+            Ok(module) => {
+                let pipeline =
+                    gfx_state
+                        .device
+                        .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                            label: Some(&label),
+                            layout: Some(&self.pipeline_layout),
+                            module: &module,
+                            entry_point: "main",
+                        });
+
+                self.pipeline = Some(pipeline);
+                self.error = None;
+            }
+            Err(err) => {
+                println!(
+                    "Script animation {} doesn't compile:\n{}",
+                    self.uniform.name, err
+                );
+                self.error = Some(err);
+            }
+        }
+    }
+}
@@ -61,9 +61,9 @@ impl RegisterParticleAnimation for RegisterStrayAnimation {
         gfx_state: &GfxState,
         emitter: &EmitterState,
         value: serde_json::Value,
-    ) -> Box<dyn ParticleAnimation> {
-        let uniform = serde_json::from_value(value).unwrap();
-        Box::new(StrayAnimation::new(uniform, emitter, gfx_state))
+    ) -> Result<Box<dyn ParticleAnimation>, serde_json::Error> {
+        let uniform = serde_json::from_value(value)?;
+        Ok(Box::new(StrayAnimation::new(uniform, emitter, gfx_state)))
     }
 }
 
@@ -94,6 +94,10 @@ impl HandleAction for StrayAnimation {
     fn enabled(&self) -> bool {
         self.enabled
     }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
 }
 
 impl ParticleAnimation for StrayAnimation {
@@ -107,6 +111,17 @@ impl ParticleAnimation for StrayAnimation {
         }
     }
 
+    fn accepts_data(&self, data: &serde_json::Value) -> bool {
+        StrayUniform::deserialize(data).is_ok()
+    }
+
+    fn apply_data(&mut self, data: &serde_json::Value) {
+        if let Ok(uniform) = serde_json::from_value(data.clone()) {
+            self.uniform = uniform;
+            self.update_uniform = true;
+        }
+    }
+
     fn as_any(&mut self) -> &mut dyn Any {
         self
     }
@@ -0,0 +1,455 @@
+use crate::loader::{Model, BUILTIN_ID};
+use crate::model::{EmitterState, GfxState, SparEvents, SparState};
+use crate::util::ID;
+use async_std::task;
+use egui_wgpu::wgpu;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+
+/// Every kind of asset has its own folder inside an asset root
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AssetKind {
+    Model,
+    Texture,
+    Environment,
+    Shader,
+    Export,
+}
+
+impl AssetKind {
+    /// Folder relative to an asset root
+    pub fn dir(&self) -> &'static str {
+        match self {
+            Self::Model => "src/assets/models",
+            Self::Texture => "src/assets/textures",
+            Self::Environment => "src/assets/environments",
+            Self::Shader => "src/shaders",
+            Self::Export => "export",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum AssetError {
+    NotFound { kind: AssetKind, name: String },
+    Io { path: PathBuf, err: std::io::Error },
+}
+
+impl Display for AssetError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotFound { kind, name } => {
+                write!(f, "{:?} asset '{}' not found in any asset root", kind, name)
+            }
+            Self::Io { path, err } => write!(f, "Can't read {:?}: {}", path, err),
+        }
+    }
+}
+
+impl std::error::Error for AssetError {}
+
+pub struct AssetConfig {
+    /// Searched in order, the first root that contains the asset is used
+    pub roots: Vec<PathBuf>,
+    /// Compiled into the binary, searched before the roots
+    pub embedded: HashMap<(AssetKind, String), &'static [u8]>,
+}
+
+impl Default for AssetConfig {
+    /// The crate folder while developing and the folder of the executable when shipped
+    fn default() -> Self {
+        let mut roots = vec![PathBuf::from(env!("CARGO_MANIFEST_DIR"))];
+
+        if let Some(exe_dir) = std::env::current_exe()
+            .ok()
+            .and_then(|exe| exe.parent().map(|dir| dir.to_path_buf()))
+        {
+            roots.push(exe_dir);
+        }
+
+        Self {
+            roots,
+            embedded: embedded_shaders(),
+        }
+    }
+}
+
+/// Resolves assets from the configured roots and the embedded assets
+pub struct AssetManager;
+
+impl AssetManager {
+    fn config() -> &'static RwLock<AssetConfig> {
+        static CONFIG: OnceLock<RwLock<AssetConfig>> = OnceLock::new();
+        CONFIG.get_or_init(|| RwLock::new(AssetConfig::default()))
+    }
+
+    /// Replaces the roots and embedded assets, call before creating the state
+    pub fn configure(config: AssetConfig) {
+        *Self::config().write().unwrap() = config;
+    }
+
+    pub fn embed(kind: AssetKind, name: &str, bytes: &'static [u8]) {
+        let mut config = Self::config().write().unwrap();
+        config.embedded.insert((kind, name.to_string()), bytes);
+    }
+
+    /// Path of the asset on disk
+    pub fn resolve(kind: AssetKind, name: &str) -> Result<PathBuf, AssetError> {
+        let config = Self::config().read().unwrap();
+
+        config
+            .roots
+            .iter()
+            .map(|root| root.join(kind.dir()).join(name))
+            .find(|path| path.is_file())
+            .ok_or_else(|| AssetError::NotFound {
+                kind,
+                name: name.to_string(),
+            })
+    }
+
+    /// Folder of the asset kind in the first root that has one
+    pub fn dir(kind: AssetKind) -> Option<PathBuf> {
+        let config = Self::config().read().unwrap();
+
+        config
+            .roots
+            .iter()
+            .map(|root| root.join(kind.dir()))
+            .find(|dir| dir.is_dir())
+    }
+
+    pub fn read(kind: AssetKind, name: &str) -> Result<Cow<'static, [u8]>, AssetError> {
+        let key = (kind, name.to_string());
+
+        if let Some(bytes) = Self::config().read().unwrap().embedded.get(&key) {
+            return Ok(Cow::Borrowed(bytes));
+        }
+
+        let path = Self::resolve(kind, name)?;
+
+        match std::fs::read(&path) {
+            Ok(bytes) => Ok(Cow::Owned(bytes)),
+            Err(err) => Err(AssetError::Io { path, err }),
+        }
+    }
+
+    pub fn read_to_string(kind: AssetKind, name: &str) -> Result<String, AssetError> {
+        let bytes = Self::read(kind, name)?;
+
+        String::from_utf8(bytes.into_owned()).map_err(|err| AssetError::Io {
+            path: PathBuf::from(name),
+            err: std::io::Error::new(std::io::ErrorKind::InvalidData, err),
+        })
+    }
+
+    /// Reads an URI relative to another asset, like the buffers and images of a glTF file
+    pub fn read_relative(
+        kind: AssetKind,
+        name: &str,
+        uri: &str,
+    ) -> Result<Cow<'static, [u8]>, AssetError> {
+        let relative = Path::new(name)
+            .parent()
+            .map(|parent| parent.join(uri))
+            .unwrap_or_else(|| PathBuf::from(uri));
+
+        Self::read(kind, &relative.to_string_lossy())
+    }
+
+    /// Files with one of the extensions of every root, first root wins on equal names
+    pub fn list(kind: AssetKind, extensions: &[&str]) -> Vec<PathBuf> {
+        let config = Self::config().read().unwrap();
+        let mut files: Vec<PathBuf> = Vec::new();
+
+        for root in config.roots.iter() {
+            let Ok(dir) = root.join(kind.dir()).read_dir() else {
+                continue;
+            };
+
+            for path in dir.filter_map(|item| item.ok()).map(|item| item.path()) {
+                let has_extension = path
+                    .extension()
+                    .is_some_and(|ext| extensions.iter().any(|other| ext == *other));
+
+                let is_listed = files
+                    .iter()
+                    .any(|other| other.file_name() == path.file_name());
+
+                if has_extension && !is_listed {
+                    files.push(path);
+                }
+            }
+        }
+
+        files
+    }
+
+    /// Path to write the asset to, in the first root that has a folder for the kind
+    pub fn write_path(kind: AssetKind, name: &str) -> Result<PathBuf, AssetError> {
+        let dir = match Self::dir(kind) {
+            Some(dir) => dir,
+            None => {
+                let config = Self::config().read().unwrap();
+                let dir = config.roots[0].join(kind.dir());
+
+                std::fs::create_dir_all(&dir).map_err(|err| AssetError::Io {
+                    path: dir.to_path_buf(),
+                    err,
+                })?;
+
+                dir
+            }
+        };
+
+        Ok(dir.join(name))
+    }
+
+    /// Loads the glTF model into the collection once, the handle is the collection id
+    pub async fn load_model(
+        gfx: &Arc<async_std::sync::RwLock<GfxState>>,
+        collection: &mut HashMap<ID, Model>,
+        name: &str,
+    ) -> anyhow::Result<ID> {
+        if !collection.contains_key(name) {
+            let model = Model::load_gltf(gfx, name).await?;
+            collection.insert(name.to_string(), model);
+        }
+
+        Ok(name.to_string())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LoadStatus {
+    Loading,
+    Loaded,
+    Failed(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AssetEvent {
+    Started(ID),
+    Loaded(ID),
+    Failed { id: ID, err: String },
+}
+
+impl Display for AssetEvent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Started(id) => write!(f, "Loading {}", id),
+            Self::Loaded(id) => write!(f, "Loaded {}", id),
+            Self::Failed { id, err } => write!(f, "Can't load {}: {}", id, err),
+        }
+    }
+}
+
+type FinishedLoads = Arc<Mutex<Vec<(ID, Result<(), String>)>>>;
+
+/// Loads models on background tasks, until then the builtin circle is used in their place
+pub struct AssetLoader {
+    statuses: HashMap<ID, LoadStatus>,
+    finished: FinishedLoads,
+    /// Events since the last update, SparState::update hands them to SparEvents
+    events: Vec<AssetEvent>,
+}
+
+impl Default for AssetLoader {
+    fn default() -> Self {
+        Self {
+            statuses: HashMap::from([(BUILTIN_ID.to_string(), LoadStatus::Loaded)]),
+            finished: Arc::new(Mutex::new(vec![])),
+            events: vec![],
+        }
+    }
+}
+
+impl AssetLoader {
+    pub fn drain_events(&mut self) -> Vec<AssetEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    pub fn status(&self, id: &str) -> Option<&LoadStatus> {
+        self.statuses.get(id)
+    }
+
+    /// Finished and total requested loads
+    pub fn progress(&self) -> (usize, usize) {
+        let finished = self
+            .statuses
+            .values()
+            .filter(|status| **status != LoadStatus::Loading)
+            .count();
+
+        (finished, self.statuses.len())
+    }
+
+    /// Starts loading the model unless it is loading or loaded, failed loads are retried
+    pub fn request(
+        &mut self,
+        gfx: &Arc<async_std::sync::RwLock<GfxState>>,
+        collection: &Arc<async_std::sync::RwLock<HashMap<ID, Model>>>,
+        id: &str,
+    ) {
+        if matches!(
+            self.statuses.get(id),
+            Some(LoadStatus::Loading | LoadStatus::Loaded)
+        ) {
+            return;
+        }
+
+        self.statuses.insert(id.to_string(), LoadStatus::Loading);
+        self.events.push(AssetEvent::Started(id.to_string()));
+
+        let gfx = gfx.clone();
+        let collection = collection.clone();
+        let finished = self.finished.clone();
+        let id = id.to_string();
+
+        task::spawn(async move {
+            let is_loaded = collection.read().await.contains_key(&id);
+
+            let result = if is_loaded {
+                Ok(())
+            } else {
+                // Parse and upload without holding the collection lock
+                match Model::load_gltf(&gfx, &id).await {
+                    Ok(model) => {
+                        collection.write().await.insert(id.to_string(), model);
+                        Ok(())
+                    }
+                    Err(err) => Err(err.to_string()),
+                }
+            };
+
+            finished.lock().unwrap().push((id, result));
+        });
+    }
+
+    /// Emitters that reference a model that finished loading are recreated, they were created
+    /// with the placeholder mesh and material
+    pub async fn update(state: &mut SparState, events: &SparEvents) {
+        let SparState {
+            assets,
+            emitters,
+            static_scene,
+            collection,
+            gfx,
+            ..
+        } = state;
+
+        if let Some(id) = &events.load_model {
+            assets.request(gfx, collection, id);
+        }
+
+        let referenced = emitters
+            .iter()
+            .flat_map(|em| {
+                [
+                    &em.uniform.mesh.collection_id,
+                    &em.uniform.material.collection_id,
+                ]
+            })
+            .chain(static_scene.model_id.iter());
+
+        let missing: Vec<ID> = referenced
+            .filter(|id| !assets.statuses.contains_key(*id))
+            .cloned()
+            .collect();
+
+        for id in missing {
+            assets.request(gfx, collection, &id);
+        }
+
+        let finished: Vec<(ID, Result<(), String>)> =
+            assets.finished.lock().unwrap().drain(..).collect();
+
+        let mut loaded = Vec::new();
+
+        for (id, result) in finished {
+            match result {
+                Ok(()) => {
+                    assets.statuses.insert(id.to_string(), LoadStatus::Loaded);
+                    assets.events.push(AssetEvent::Loaded(id.to_string()));
+                    loaded.push(id);
+                }
+                Err(err) => {
+                    println!("Can't load {}: {}", id, err);
+                    assets
+                        .statuses
+                        .insert(id.to_string(), LoadStatus::Failed(err.to_string()));
+                    assets.events.push(AssetEvent::Failed { id, err });
+                }
+            }
+        }
+
+        if loaded.is_empty() {
+            return;
+        }
+
+        let mut encoder =
+            gfx.read()
+                .await
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Asset reload encoder"),
+                });
+
+        EmitterState::recreate_emitters(state, &mut encoder, |em| {
+            loaded.iter().any(|id| em.uses_model(id))
+        })
+        .await;
+
+        state.gfx.read().await.queue.submit(Some(encoder.finish()));
+    }
+}
+
+#[cfg(feature = "embed-assets")]
+macro_rules! embed_shaders {
+    ($($file:literal),* $(,)?) => {
+        HashMap::from([
+            $(((AssetKind::Shader, $file.to_string()), include_bytes!(concat!("shaders/", $file)) as &'static [u8]),)*
+        ])
+    };
+}
+
+/// Shaders are read from disk unless the embed-assets feature is enabled
+#[cfg(feature = "embed-assets")]
+fn embedded_shaders() -> HashMap<(AssetKind, String), &'static [u8]> {
+    embed_shaders![
+        "brdf_lut.wgsl",
+        "color_anim.wgsl",
+        "declarations.wgsl",
+        "emitter.wgsl",
+        "env_background.wgsl",
+        "env_bake.wgsl",
+        "force_anim.wgsl",
+        "fx/blend.wgsl",
+        "fx/color_processing.wgsl",
+        "fx/depth_import.wgsl",
+        "fx/downscale.wgsl",
+        "fx/finalize.wgsl",
+        "fx/gaussian_blur.wgsl",
+        "gravity_anim.wgsl",
+        "light_cluster.wgsl",
+        "light_particle.wgsl",
+        "noise.wgsl",
+        "particle.wgsl",
+        "pbr/ibl.wgsl",
+        "pbr/lighting.wgsl",
+        "pbr/pbr.wgsl",
+        "pbr/tonemapping.wgsl",
+        "static_scene.wgsl",
+        "stray_anim.wgsl",
+        "sub_emitter_spawn.wgsl",
+        "sub_emitter_trigger.wgsl",
+    ]
+}
+
+#[cfg(not(feature = "embed-assets"))]
+fn embedded_shaders() -> HashMap<(AssetKind, String), &'static [u8]> {
+    HashMap::new()
+}
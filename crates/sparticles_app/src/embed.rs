@@ -0,0 +1,129 @@
+use crate::fx::PostProcessState;
+use crate::init::AppVisitor;
+use crate::model::{
+    EffectLibrary, EmbedOptions, EmitterState, ExternalView, SparEvents, SparState,
+};
+use crate::shaders::ShaderHotReload;
+use crate::util::{DynamicExport, ImportDiagnostic};
+use egui_wgpu::wgpu;
+use egui_winit::winit::dpi::PhysicalSize;
+
+/// Frame of the host engine the particles are rendered into
+pub struct FrameTarget<'a> {
+    /// Particles are added on top of the color, it needs the format of [`EmbedOptions`]
+    pub color: &'a wgpu::TextureView,
+    /// Depth of the host scene, particles behind it are hidden. Requires texture binding usage.
+    pub depth: Option<&'a wgpu::TextureView>,
+    pub depth_range: DepthRange,
+    pub view: ExternalView,
+}
+
+/// How the depth of the host maps to distance
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DepthRange {
+    /// 0 at near and 1 at far of the view, like the projection of [`ExternalView`]
+    #[default]
+    Standard,
+    /// 1 at near and 0 at infinity, like Bevy
+    ReversedInfinite,
+}
+
+impl DepthRange {
+    /// Scale and offset that convert the host depth to the depth of the view
+    pub fn remap(&self, view: &ExternalView) -> (f32, f32) {
+        match self {
+            Self::Standard => (1., 0.),
+            Self::ReversedInfinite => {
+                let k = view.far / (view.far - view.near);
+                (-k, k)
+            }
+        }
+    }
+}
+
+/// Renders sparticles inside another wgpu engine, the host owns the window and event loop
+/// and submits the command encoder
+pub struct SparRenderer {
+    pub state: SparState,
+}
+
+impl SparRenderer {
+    pub async fn new(options: EmbedOptions) -> Self {
+        Self::with_visitor(&mut (), options).await
+    }
+
+    /// The visitor provides the data source, registries and animations like it does for apps
+    pub async fn with_visitor(app_visitor: &mut impl AppVisitor, options: EmbedOptions) -> Self {
+        let state = SparState::embedded(app_visitor, options).await;
+
+        Self { state }
+    }
+
+    pub fn effects(&mut self) -> &mut EffectLibrary {
+        &mut self.state.effects
+    }
+
+    /// Replaces the post fx, like the `post_fx` of a scene document
+    pub async fn set_post_fx(&mut self, post_fx: Vec<DynamicExport>) -> Vec<ImportDiagnostic> {
+        let state = &mut self.state;
+        let pp = &mut state.post_process;
+
+        pp.effects.clear();
+        pp.unrecognized_fx.clear();
+        pp.import_fx(&state.gfx, &state.registered_post_fx, post_fx)
+            .await
+    }
+
+    /// Call when the frame target of the host changed size
+    pub async fn resize(&mut self, width: u32, height: u32) {
+        self.state.resize(PhysicalSize::new(width, height)).await;
+    }
+
+    /// Simulates and records the particles into the encoder of the host, returns what happened
+    /// during the update like finished asset loads
+    pub async fn render(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        target: FrameTarget<'_>,
+    ) -> SparEvents {
+        let state = &mut self.state;
+        let mut events = SparEvents::default();
+
+        state.camera.external = Some(target.view);
+        state.update(&mut events).await;
+
+        ShaderHotReload::update(state, encoder).await;
+
+        if state.play {
+            EmitterState::compute_particles(state, encoder).await;
+        }
+
+        let depth_load = match target.depth {
+            Some(host_depth) => {
+                let gfx = &state.gfx.read().await;
+                let pp = &state.post_process;
+
+                if let Some(embed) = &pp.embed {
+                    let remap = target.depth_range.remap(&target.view);
+                    embed.import_depth(gfx, host_depth, remap, pp.depth_view(), encoder);
+                }
+
+                wgpu::LoadOp::Load
+            }
+            None => wgpu::LoadOp::Clear(1.0),
+        };
+
+        EmitterState::render_particles(state, encoder, depth_load).await;
+        PostProcessState::compute(state, encoder).await;
+
+        let pp = &state.post_process;
+
+        if let Some(embed) = &pp.embed {
+            embed.composite(&pp.fx_state, &pp.io_ctx, target.color, encoder);
+        }
+
+        state.clock.measure_cpu_time();
+
+        events
+    }
+}
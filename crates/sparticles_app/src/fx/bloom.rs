@@ -69,9 +69,13 @@ impl RegisterPostFx for RegisterBloomFx {
         "bloom"
     }
 
-    fn import(&self, options: &FxOptions, value: serde_json::Value) -> Box<dyn PostFx> {
-        let bloom_settings = serde_json::from_value(value).unwrap();
-        Box::new(BloomFx::new(options, bloom_settings))
+    fn import(
+        &self,
+        options: &FxOptions,
+        value: serde_json::Value,
+    ) -> Result<Box<dyn PostFx>, serde_json::Error> {
+        let bloom_settings = serde_json::from_value(value)?;
+        Ok(Box::new(BloomFx::new(options, bloom_settings)))
     }
 
     fn create_default(&self, options: &FxOptions) -> Box<dyn PostFx> {
@@ -181,6 +185,10 @@ impl HandleAction for BloomFx {
     fn enabled(&self) -> bool {
         self.enabled
     }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
 }
 
 impl BloomFx {
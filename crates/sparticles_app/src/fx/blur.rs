@@ -66,10 +66,14 @@ impl RegisterPostFx for RegisterBlurFx {
         Box::new(BlurFx::new(options, settings))
     }
 
-    fn import(&self, options: &FxOptions, value: serde_json::Value) -> Box<dyn PostFx> {
-        let settings = serde_json::from_value(value).expect("Can't parse blur");
-
-        Box::new(BlurFx::new(options, settings))
+    fn import(
+        &self,
+        options: &FxOptions,
+        value: serde_json::Value,
+    ) -> Result<Box<dyn PostFx>, serde_json::Error> {
+        let settings = serde_json::from_value(value)?;
+
+        Ok(Box::new(BlurFx::new(options, settings)))
     }
 }
 
@@ -141,6 +145,10 @@ impl HandleAction for BlurFx {
     fn enabled(&self) -> bool {
         self.enabled
     }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
 }
 
 impl BlurFx {
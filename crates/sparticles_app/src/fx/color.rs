@@ -3,7 +3,7 @@ use std::{any::Any, sync::Arc};
 use super::{FxIOUniform, FxOptions, FxState};
 use crate::{
     model::{gfx_state::Profiler, Camera, GfxState},
-    shaders::{ShaderOptions, SDR_TONEMAPPING},
+    shaders::ShaderOptions,
     traits::{BufferContent, HandleAction, PostFx, RegisterPostFx},
     util::{DynamicExport, ListAction, UniformContext},
 };
@@ -52,10 +52,14 @@ impl RegisterPostFx for RegisterColorFx {
         Box::new(ColorFx::new(options, settings))
     }
 
-    fn import(&self, options: &FxOptions, value: serde_json::Value) -> Box<dyn PostFx> {
-        let settings = serde_json::from_value(value).expect("Can't parse color processing Fx");
+    fn import(
+        &self,
+        options: &FxOptions,
+        value: serde_json::Value,
+    ) -> Result<Box<dyn PostFx>, serde_json::Error> {
+        let settings = serde_json::from_value(value)?;
 
-        Box::new(ColorFx::new(options, settings))
+        Ok(Box::new(ColorFx::new(options, settings)))
     }
 }
 
@@ -142,6 +146,10 @@ impl HandleAction for ColorFx {
     fn enabled(&self) -> bool {
         self.enabled
     }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
 }
 
 impl ColorFx {
@@ -177,7 +185,7 @@ impl ColorFx {
 
         let shader = gfx_state.create_shader_builtin(ShaderOptions {
             if_directives: &[],
-            files: &[SDR_TONEMAPPING, "fx/color_processing.wgsl"],
+            files: &["fx/color_processing.wgsl"],
             label: "Color processing",
         });
 
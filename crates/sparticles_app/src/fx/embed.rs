@@ -0,0 +1,235 @@
+use super::post_process::FxState;
+use crate::model::GfxState;
+use crate::shaders::{layout::gpu_struct, ShaderOptions};
+use crate::traits::BufferContent;
+use crate::util::UniformContext;
+use egui_wgpu::wgpu;
+use encase::ShaderType;
+use std::num::NonZeroU64;
+
+gpu_struct! {
+    pub(crate) struct DepthImportUniform as "DepthImport" {
+        scale: f32,
+        offset: f32,
+    }
+}
+
+/// Pipelines used when rendering into the frame of a host engine
+pub struct EmbedPipelines {
+    composite_pipeline: wgpu::RenderPipeline,
+    depth_pipeline: wgpu::RenderPipeline,
+    depth_bg_layout: wgpu::BindGroupLayout,
+    depth_buffer: wgpu::Buffer,
+}
+
+impl EmbedPipelines {
+    /// Hides particles behind the geometry of the host, the host depth is sampled so it may
+    /// have another size than the particle frame. The remap converts it to 0..1 with near at 0.
+    pub fn import_depth(
+        &self,
+        gfx: &GfxState,
+        host_depth: &wgpu::TextureView,
+        (scale, offset): (f32, f32),
+        depth_view: &wgpu::TextureView,
+        encoder: &mut wgpu::CommandEncoder,
+    ) {
+        let uniform = DepthImportUniform { scale, offset };
+        gfx.queue
+            .write_buffer(&self.depth_buffer, 0, &uniform.buffer_content());
+
+        let bg = gfx.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Host depth bindgroup"),
+            layout: &self.depth_bg_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(host_depth),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.depth_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let mut r_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Import host depth"),
+            color_attachments: &[],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: depth_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        r_pass.set_pipeline(&self.depth_pipeline);
+        r_pass.set_bind_group(0, &bg, &[]);
+        r_pass.draw(0..3, 0..1);
+    }
+
+    /// Adds the particle frame to the color target of the host. Particles are rendered on
+    /// black, so dark particles can't darken the scene of the host.
+    pub fn composite(
+        &self,
+        fx_state: &FxState,
+        io_ctx: &UniformContext,
+        target: &wgpu::TextureView,
+        encoder: &mut wgpu::CommandEncoder,
+    ) {
+        let mut r_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Composite into host"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        r_pass.set_pipeline(&self.composite_pipeline);
+        r_pass.set_bind_group(0, &fx_state.r_bg, &[]);
+        r_pass.set_bind_group(1, &io_ctx.bg, &[]);
+        r_pass.draw(0..3, 0..1);
+    }
+
+    pub fn new(gfx: &GfxState, fx_state: &FxState, io_ctx: &UniformContext) -> Self {
+        let device = &gfx.device;
+
+        let finalize_shader = gfx.create_shader_builtin(ShaderOptions {
+            if_directives: &[],
+            files: &["fx/finalize.wgsl"],
+            label: "Composite Post FX",
+        });
+
+        let depth_shader = gfx.create_shader_builtin(ShaderOptions {
+            if_directives: &[],
+            files: &["fx/depth_import.wgsl"],
+            label: "Import host depth",
+        });
+
+        let composite_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Composite layout"),
+            bind_group_layouts: &[&fx_state.r_bg_layout, &io_ctx.bg_layout],
+            push_constant_ranges: &[],
+        });
+
+        let additive = wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::One,
+            dst_factor: wgpu::BlendFactor::One,
+            operation: wgpu::BlendOperation::Add,
+        };
+
+        let composite_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Composite pipeline"),
+            layout: Some(&composite_layout),
+            vertex: wgpu::VertexState {
+                module: &finalize_shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &finalize_shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: gfx.surface_config.format,
+                    blend: Some(wgpu::BlendState {
+                        color: additive,
+                        alpha: wgpu::BlendComponent::OVER,
+                    }),
+                    write_mask: wgpu::ColorWrites::COLOR,
+                })],
+            }),
+            multiview: None,
+        });
+
+        let depth_bg_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Host depth layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Depth,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: NonZeroU64::new(DepthImportUniform::min_size().get()),
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let depth_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Host depth buffer"),
+            size: DepthImportUniform::min_size().get(),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let depth_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Import depth layout"),
+            bind_group_layouts: &[&depth_bg_layout],
+            push_constant_ranges: &[],
+        });
+
+        let depth_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Import depth pipeline"),
+            layout: Some(&depth_layout),
+            vertex: wgpu::VertexState {
+                module: &depth_shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: GfxState::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Always,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &depth_shader,
+                entry_point: "fs_main",
+                targets: &[],
+            }),
+            multiview: None,
+        });
+
+        Self {
+            composite_pipeline,
+            depth_pipeline,
+            depth_bg_layout,
+            depth_buffer,
+        }
+    }
+}
@@ -4,6 +4,7 @@ pub mod blur;
 pub mod blur_pass;
 pub mod color;
 pub mod downscale;
+pub mod embed;
 pub mod fx_io;
 pub mod post_process;
 
@@ -11,5 +12,6 @@ pub use blend::BlendPass;
 pub use bloom::BloomFx;
 pub use color::{ColorFx, ColorFxSettings, ColorFxUniform, RegisterColorFx};
 pub use downscale::Downscale;
+pub use embed::EmbedPipelines;
 pub use fx_io::{FxIO, FxIOSwapCtx, FxIOUniform, FxIOUniformOptions, FxOptions};
 pub use post_process::{FxState, PostProcessState};
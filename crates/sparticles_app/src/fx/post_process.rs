@@ -1,11 +1,14 @@
-use super::{FxIOUniform, FxOptions};
+use super::{EmbedPipelines, FxIOUniform, FxOptions};
 use crate::init::AppVisitor;
 use crate::model::events::ViewIOEvent;
 use crate::model::gfx_state::Profiler;
 use crate::model::{GfxState, SparEvents, SparState};
-use crate::shaders::{ShaderOptions, SDR_TONEMAPPING};
+use crate::shaders::ShaderOptions;
 use crate::traits::*;
-use crate::util::{DynamicExport, ExportType, ListAction, Persistence, UniformContext};
+use crate::util::{
+    with_unrecognized, DiagnosticKind, DynamicExport, ImportDiagnostic, ListAction, UniformContext,
+    Unrecognized,
+};
 use async_std::sync::RwLock;
 use egui_wgpu::wgpu;
 use egui_winit::egui::ClippedPrimitive;
@@ -21,6 +24,11 @@ pub struct PostProcessState {
 
     pub io_uniform: FxIOUniform,
     pub io_ctx: UniformContext,
+    /// Only created for embedded renderers
+    pub embed: Option<EmbedPipelines>,
+    /// Imported effects that aren't registered or can't be read, exported as they were at
+    /// their position in the imported list
+    pub unrecognized_fx: Vec<Unrecognized<DynamicExport>>,
 }
 
 impl PostProcessState {
@@ -151,26 +159,72 @@ impl PostProcessState {
 
     pub fn new(gfx: &GfxState, app_settings: &impl AppVisitor) -> Self {
         let device = &gfx.device;
+
+        let fx_state = FxState::new(gfx);
+
+        let io_uniform = FxIOUniform::zero(&fx_state);
+        let io_ctx = UniformContext::from_uniform(&io_uniform, device, "IO");
+
+        let render_pipeline = Self::create_render_pipeline(gfx, &fx_state, &io_ctx);
+
+        let embed = gfx
+            .window
+            .is_none()
+            .then(|| EmbedPipelines::new(gfx, &fx_state, &io_ctx));
+
+        let mut effects = vec![];
+
+        app_settings.add_post_fx(
+            &FxOptions {
+                fx_state: &fx_state,
+                gfx,
+            },
+            &mut effects,
+        );
+
+        Self {
+            fx_state,
+            effects,
+
+            render_pipeline,
+
+            io_uniform,
+            io_ctx,
+            embed,
+            unrecognized_fx: vec![],
+        }
+    }
+
+    /// Used after fx/finalize.wgsl or fx/depth_import.wgsl changed
+    pub fn recreate_pipelines(&mut self, gfx: &GfxState) {
+        self.render_pipeline = Self::create_render_pipeline(gfx, &self.fx_state, &self.io_ctx);
+
+        if self.embed.is_some() {
+            self.embed = Some(EmbedPipelines::new(gfx, &self.fx_state, &self.io_ctx));
+        }
+    }
+
+    fn create_render_pipeline(
+        gfx: &GfxState,
+        fx_state: &FxState,
+        io_ctx: &UniformContext,
+    ) -> wgpu::RenderPipeline {
+        let device = &gfx.device;
         let config = &gfx.surface_config;
 
         let finalize_shader = gfx.create_shader_builtin(ShaderOptions {
             if_directives: &[],
-            files: &[SDR_TONEMAPPING, "fx/finalize.wgsl"],
+            files: &["fx/finalize.wgsl"],
             label: "Finalize Post FX",
         });
 
-        let fx_state = FxState::new(gfx);
-
-        let io_uniform = FxIOUniform::zero(&fx_state);
-        let io_ctx = UniformContext::from_uniform(&io_uniform, device, "IO");
-
         let r_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: Some("Post fx layout"),
             bind_group_layouts: &[&fx_state.r_bg_layout, &io_ctx.bg_layout],
             push_constant_ranges: &[],
         });
 
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
             label: Some("Finalize pipeline"),
             layout: Some(&r_pipeline_layout),
             vertex: wgpu::VertexState {
@@ -194,27 +248,7 @@ impl PostProcessState {
                 })],
             }),
             multiview: None,
-        });
-
-        let mut effects = vec![];
-
-        app_settings.add_post_fx(
-            &FxOptions {
-                fx_state: &fx_state,
-                gfx,
-            },
-            &mut effects,
-        );
-
-        Self {
-            fx_state,
-            effects,
-
-            render_pipeline,
-
-            io_uniform,
-            io_ctx,
-        }
+        })
     }
 
     pub async fn import_fx(
@@ -222,32 +256,70 @@ impl PostProcessState {
         gfx: &Arc<RwLock<GfxState>>,
         registry_fx: &Vec<Box<dyn RegisterPostFx>>,
         to_export: Vec<DynamicExport>,
-    ) {
+    ) -> Vec<ImportDiagnostic> {
         let gfx = &gfx.read().await;
+        let mut diagnostics = Vec::new();
 
         let options = FxOptions {
             gfx,
             fx_state: &self.fx_state,
         };
 
-        for item in to_export {
-            for reg in registry_fx {
-                if item.tag == reg.tag() {
-                    self.effects.push(reg.import(&options, item.data));
-                    break;
+        for (idx, item) in to_export.into_iter().enumerate() {
+            let Some(reg) = registry_fx.iter().find(|reg| reg.tag() == item.tag) else {
+                let kind = DiagnosticKind::UnknownTag(item.tag.to_string());
+                diagnostics.push(ImportDiagnostic::new(
+                    format!("post_fx[{}].type", idx),
+                    kind,
+                ));
+                self.unrecognized_fx.push((idx, item));
+                continue;
+            };
+
+            match reg.import(&options, item.data.clone()) {
+                Ok(fx) => self.effects.push(fx),
+                Err(err) => {
+                    let kind = DiagnosticKind::InvalidData(err.to_string());
+                    diagnostics.push(ImportDiagnostic::new(
+                        format!("post_fx[{}].data", idx),
+                        kind,
+                    ));
+                    self.unrecognized_fx.push((idx, item));
                 }
             }
         }
+
+        diagnostics
     }
 
-    pub fn export(pp: &PostProcessState) {
-        let mut to_export = Vec::new();
+    /// Recreates the pipelines of the effects, effects that aren't registered are kept
+    pub async fn recreate_fx(
+        &mut self,
+        gfx: &Arc<RwLock<GfxState>>,
+        registry_fx: &[Box<dyn RegisterPostFx>],
+    ) {
+        let gfx = &gfx.read().await;
 
-        for fx in pp.effects.iter() {
-            to_export.push(fx.export());
+        let options = FxOptions {
+            gfx,
+            fx_state: &self.fx_state,
+        };
+
+        for fx in self.effects.iter_mut() {
+            let export = fx.export();
+
+            let reg = registry_fx.iter().find(|reg| reg.tag() == export.tag);
+
+            if let Some(Ok(recreated)) = reg.map(|reg| reg.import(&options, export.data)) {
+                *fx = recreated;
+            }
         }
+    }
+
+    pub fn export(pp: &PostProcessState) -> Vec<DynamicExport> {
+        let effects = pp.effects.iter().map(|fx| fx.export()).collect();
 
-        Persistence::write_to_file(to_export, ExportType::PostFx);
+        with_unrecognized(effects, &pp.unrecognized_fx)
     }
 }
 
@@ -255,8 +327,8 @@ pub struct FxState {
     pub bg: wgpu::BindGroup,
     pub bg_layout: wgpu::BindGroupLayout,
 
-    r_bg: wgpu::BindGroup,
-    r_bg_layout: wgpu::BindGroupLayout,
+    pub(crate) r_bg: wgpu::BindGroup,
+    pub(crate) r_bg_layout: wgpu::BindGroupLayout,
 
     pub count_x: u32,
     pub count_y: u32,
@@ -1,15 +1,21 @@
 use std::collections::HashMap;
-use std::path::{Path, PathBuf};
+use std::path::PathBuf;
 use std::sync::Arc;
 
 use async_std::sync::RwLock;
 use egui_wgpu::wgpu;
-use egui_winit::winit::event::KeyboardInput;
+use egui_winit::winit::event::KeyEvent;
 
 use crate::animations::color_animation::RegisterColorAnimation;
 use crate::animations::diffusion_animation::RegisterDiffusionAnimation;
+use crate::animations::keyframe_animation::RegisterKeyframeAnimation;
+use crate::animations::path_animation::RegisterPathAnimation;
 use crate::animations::sway_animation::RegisterSwayAnimation;
-use crate::animations::{RegisterForceAnimation, RegisterGravityAnimation, RegisterStrayAnimation};
+use crate::animations::{
+    RegisterForceAnimation, RegisterGraphAnimation, RegisterGravityAnimation,
+    RegisterScriptAnimation, RegisterStrayAnimation,
+};
+use crate::asset_manager::{AssetConfig, AssetKind, AssetManager};
 use crate::fx::bloom::RegisterBloomFx;
 use crate::fx::blur::RegisterBlurFx;
 use crate::fx::FxOptions;
@@ -17,20 +23,22 @@ use crate::fx::PostProcessState;
 use crate::fx::RegisterColorFx;
 use crate::loader::Model;
 use crate::model::{
-    Camera, CreateEmitterOptions, EmitterState, EmitterType, EmitterUniform, GfxState,
+    Camera, CreateEmitterOptions, EffectLibrary, EffectTransform, EmitterState, EmitterType,
+    EmitterUniform, GfxState, LightsCtx, SceneLighting,
 };
 pub use crate::model::{SparEvents, SparState};
 use crate::traits::*;
 use crate::util::persistence::ExportEmitter;
-use crate::util::{Persistence, ID};
+use crate::util::{
+    DiagnosticKind, ExportCamera, ImportDiagnostic, SceneDocument, Unrecognized, ID,
+};
 
 #[derive(Default)]
 pub enum DataSource {
-    Json {
-        path: PathBuf,
-    },
+    /// Scene document, older versions are migrated
+    Json { path: PathBuf },
     Code {
-        lights: Box<EmitterUniform>,
+        lights: Vec<EmitterUniform>,
         emitters: Vec<EmitterUniform>,
     },
     /// Will generate some default values
@@ -44,24 +52,33 @@ pub trait AppVisitor {
         DataSource::default()
     }
 
+    /// Roots to search assets in and assets compiled into the binary
+    fn asset_config(&self) -> AssetConfig {
+        AssetConfig::default()
+    }
+
     fn model_dir(&self) -> PathBuf {
-        Path::new(env!("CARGO_MANIFEST_DIR")).join("src/assets/models")
+        AssetManager::dir(AssetKind::Model).unwrap_or_else(|| PathBuf::from(AssetKind::Model.dir()))
     }
 
+    /// Directory the scene document is exported to
     fn exports_dir(&self) -> PathBuf {
-        Path::new(env!("CARGO_MANIFEST_DIR")).join("exports")
+        AssetManager::dir(AssetKind::Export)
+            .unwrap_or_else(|| PathBuf::from(AssetKind::Export.dir()))
     }
 
-    fn add_widget_builders(&mut self, state: &mut SparState);
-
-    fn draw_ui(&mut self, state: &mut SparState, encoder: &mut wgpu::CommandEncoder) -> SparEvents;
+    fn add_widget_builders(&mut self, state: &mut SparState) {}
 
-    fn process_events(
+    /// Events holds what happened during the last update, the UI adds events for the next
+    fn draw_ui(
         &mut self,
+        state: &mut SparState,
         events: &mut SparEvents,
-        input: &KeyboardInput,
-        shift_pressed: bool,
-    );
+        encoder: &mut wgpu::CommandEncoder,
+    ) {
+    }
+
+    fn process_events(&mut self, events: &mut SparEvents, input: &KeyEvent, shift_pressed: bool) {}
 
     /// If you want to add through code use this function otherwise use gui
     fn add_particle_anim(&self, emitter: &mut EmitterState, gfx_state: &GfxState) {}
@@ -81,10 +98,23 @@ pub trait AppVisitor {
 
     /// If you want your post FX available in the gui add to the registry
     fn register_post_fx(&self, registry: &mut Vec<Box<dyn RegisterPostFx>>) {}
+
+    /// Load effect templates that can be spawned at runtime
+    fn register_effects(&self, library: &mut EffectLibrary) {}
 }
 
+/// Uses the defaults, for embedded renderers that are driven by the host engine
+impl AppVisitor for () {}
+
 pub struct Init {
     pub emitters: Vec<EmitterState>,
+    pub scene_lighting: SceneLighting,
+    pub camera: Option<ExportCamera>,
+    pub effect_root: EffectTransform,
+    pub unknown_scene_fields: serde_json::Map<String, serde_json::Value>,
+    pub unrecognized_emitters: Vec<Unrecognized<serde_json::Value>>,
+    /// Problems found while importing the scene document
+    pub diagnostics: Vec<ImportDiagnostic>,
     pub registry_par_anims: Vec<Box<dyn RegisterParticleAnimation>>,
     pub registry_em_anims: Vec<Box<dyn RegisterEmitterAnimation>>,
     pub registry_post_fx: Vec<Box<dyn RegisterPostFx>>,
@@ -92,26 +122,21 @@ pub struct Init {
 
 impl Init {
     async fn code_emitters(
-        lights_uniform: EmitterUniform,
+        light_uniforms: Vec<EmitterUniform>,
         emitter_uniforms: Vec<EmitterUniform>,
         gfx: &Arc<RwLock<GfxState>>,
         collection: &Arc<RwLock<HashMap<ID, Model>>>,
         camera: &Camera,
+        lights: &LightsCtx,
     ) -> Vec<EmitterState> {
         let mut emitters: Vec<EmitterState> = Vec::new();
 
-        assert!(!&lights_uniform.id.is_empty(), "Id can not be empty");
-
-        let lights = EmitterState::new(CreateEmitterOptions {
-            uniform: lights_uniform,
-            camera,
-            collection,
-            gfx,
-            emitter_type: EmitterType::Lights,
-        })
-        .await;
+        let all_uniforms = light_uniforms
+            .into_iter()
+            .map(|uniform| (uniform, true))
+            .chain(emitter_uniforms.into_iter().map(|uniform| (uniform, false)));
 
-        for emitter_uniform in emitter_uniforms {
+        for (emitter_uniform, is_light) in all_uniforms {
             let is_unique = emitters
                 .iter()
                 .all(|emitter| emitter.uniform.id != emitter_uniform.id);
@@ -119,22 +144,26 @@ impl Init {
             assert!(!emitter_uniform.id.is_empty(), "Id can not be empty");
             assert!(is_unique, "Emitters require an unique ID");
 
+            let emitter_type = if is_light {
+                EmitterType::Lights
+            } else {
+                EmitterType::Normal {
+                    lights_layout: &lights.bg_layout,
+                }
+            };
+
             emitters.push(
                 EmitterState::new(CreateEmitterOptions {
                     uniform: emitter_uniform,
                     camera,
                     collection,
                     gfx,
-                    emitter_type: EmitterType::Normal {
-                        lights_layout: &lights.bg_layout,
-                    },
+                    emitter_type,
                 })
                 .await,
             );
         }
 
-        emitters.insert(0, lights);
-
         emitters
     }
 
@@ -142,6 +171,7 @@ impl Init {
         app_visitor: &impl AppVisitor,
         gfx: &Arc<RwLock<GfxState>>,
         camera: &Camera,
+        lights: &LightsCtx,
         collection: &Arc<RwLock<HashMap<ID, Model>>>,
         pp: &mut PostProcessState,
     ) -> Init {
@@ -150,6 +180,8 @@ impl Init {
             Box::new(RegisterForceAnimation),
             Box::new(RegisterGravityAnimation),
             Box::new(RegisterStrayAnimation),
+            Box::new(RegisterScriptAnimation),
+            Box::new(RegisterGraphAnimation),
         ];
 
         app_visitor.register_particle_animations(&mut registry_par_anims);
@@ -157,6 +189,8 @@ impl Init {
         let mut registry_em_anims: Vec<Box<dyn RegisterEmitterAnimation>> = vec![
             Box::new(RegisterSwayAnimation),
             Box::new(RegisterDiffusionAnimation),
+            Box::new(RegisterKeyframeAnimation),
+            Box::new(RegisterPathAnimation),
         ];
 
         app_visitor.register_emitter_animations(&mut registry_em_anims);
@@ -170,9 +204,13 @@ impl Init {
         app_visitor.register_post_fx(&mut registry_post_fx);
 
         match app_visitor.data_source() {
-            DataSource::Code { lights, emitters } => {
+            DataSource::Code {
+                lights: lights_uniforms,
+                emitters,
+            } => {
                 let mut emitters =
-                    Self::code_emitters(*lights, emitters, gfx, collection, camera).await;
+                    Self::code_emitters(lights_uniforms, emitters, gfx, collection, camera, lights)
+                        .await;
 
                 let gfx = &gfx.read().await;
 
@@ -183,128 +221,169 @@ impl Init {
 
                 Init {
                     emitters,
+                    scene_lighting: SceneLighting::default(),
+                    camera: None,
+                    effect_root: EffectTransform::default(),
+                    unknown_scene_fields: serde_json::Map::new(),
+                    unrecognized_emitters: vec![],
+                    diagnostics: vec![],
                     registry_em_anims,
                     registry_par_anims,
                     registry_post_fx,
                 }
             }
             DataSource::Demo => {
-                let lights = EmitterUniform::new("lights".to_string());
-                let emitters = Self::code_emitters(lights, vec![], gfx, collection, camera).await;
+                let light_uniforms = vec![EmitterUniform::new("lights".to_string())];
+                let emitters =
+                    Self::code_emitters(light_uniforms, vec![], gfx, collection, camera, lights)
+                        .await;
 
                 Self {
                     emitters,
+                    scene_lighting: SceneLighting::default(),
+                    camera: None,
+                    effect_root: EffectTransform::default(),
+                    unknown_scene_fields: serde_json::Map::new(),
+                    unrecognized_emitters: vec![],
+                    diagnostics: vec![],
                     registry_par_anims,
                     registry_em_anims,
                     registry_post_fx,
                 }
             }
-            DataSource::Json { path } => match Persistence::import_emitter_states(path) {
-                Ok(exported_emitters) => {
-                    match Persistence::import_post_fx() {
-                        Ok(val) => pp.import_fx(gfx, &registry_post_fx, val).await,
-                        Err(err) => println!("{}", err.msg),
-                    }
-
-                    Self::json_emitters(
-                        exported_emitters,
+            DataSource::Json { path } => match SceneDocument::load(&path) {
+                Ok(document) => {
+                    let mut diagnostics = document.diagnostics;
+                    let fx_diagnostics =
+                        pp.import_fx(gfx, &registry_post_fx, document.post_fx).await;
+
+                    let (emitters, emitter_diagnostics) = Self::json_emitters(
+                        document.emitters,
                         gfx,
                         camera,
+                        lights,
                         collection,
+                        &registry_par_anims,
+                        &registry_em_anims,
+                    )
+                    .await;
+
+                    diagnostics.extend(fx_diagnostics);
+                    diagnostics.extend(emitter_diagnostics);
+
+                    for diagnostic in diagnostics.iter() {
+                        println!("{}", diagnostic);
+                    }
+
+                    Self {
+                        emitters,
+                        scene_lighting: document.scene_lighting,
+                        camera: document.camera,
+                        effect_root: document.effect_root,
+                        unknown_scene_fields: document.unknown_fields,
+                        unrecognized_emitters: document.unrecognized_emitters,
+                        diagnostics,
                         registry_par_anims,
                         registry_em_anims,
                         registry_post_fx,
-                    )
-                    .await
+                    }
                 }
                 Err(err) => {
-                    panic!("{}", err.msg);
+                    panic!("{}", err);
                 }
             },
         }
     }
 
-    async fn json_emitters(
-        mut emitters_export: Vec<ExportEmitter>,
+    /// Animations that aren't registered or can't be read are kept on the emitter as they were,
+    /// paths of the diagnostics are relative to the emitters of the document
+    pub(crate) async fn json_emitters(
+        emitters_export: Vec<ExportEmitter>,
         gfx: &Arc<RwLock<GfxState>>,
         camera: &Camera,
+        lights: &LightsCtx,
         collection: &Arc<RwLock<HashMap<ID, Model>>>,
-        registry_par_anims: Vec<Box<dyn RegisterParticleAnimation>>,
-        registry_em_anims: Vec<Box<dyn RegisterEmitterAnimation>>,
-        registry_post_fx: Vec<Box<dyn RegisterPostFx>>,
-    ) -> Self {
+        registry_par_anims: &[Box<dyn RegisterParticleAnimation>],
+        registry_em_anims: &[Box<dyn RegisterEmitterAnimation>],
+    ) -> (Vec<EmitterState>, Vec<ImportDiagnostic>) {
         let mut emitters = Vec::new();
+        let mut diagnostics = Vec::new();
 
-        let lights_export = emitters_export.remove(0);
-
-        assert!(
-            lights_export.is_light,
-            "Lights is not in JSON export please remove exports emitters.json file"
-        );
-
-        let mut lights = EmitterState::new(CreateEmitterOptions {
-            uniform: lights_export.emitter,
-            camera,
-            collection,
-            gfx,
-            emitter_type: EmitterType::Lights,
-        })
-        .await;
-
-        // Import animations
-        {
-            let gfx_lock = &gfx.read().await;
-
-            for export_animation in lights_export.particle_animations {
-                for reg in registry_par_anims.iter() {
-                    if export_animation.tag == reg.tag() {
-                        let anim = reg.import(gfx_lock, &lights, export_animation.data);
-                        lights.push_particle_animation(anim);
-                        break;
-                    }
+        let mut diagnose = |path: String, tag: &str, result: Result<(), serde_json::Error>| {
+            let diagnostic = match result {
+                Ok(()) => {
+                    let kind = DiagnosticKind::UnknownTag(tag.to_string());
+                    ImportDiagnostic::new(format!("{}.type", path), kind)
                 }
-            }
+                Err(err) => {
+                    let kind = DiagnosticKind::InvalidData(err.to_string());
+                    ImportDiagnostic::new(format!("{}.data", path), kind)
+                }
+            };
 
-            for export_animation in lights_export.emitter_animations {
-                for reg in registry_em_anims.iter() {
-                    if export_animation.tag == reg.tag() {
-                        let anim = reg.import(export_animation.data);
-                        lights.push_emitter_animation(anim);
-                        break;
-                    }
+            diagnostics.push(diagnostic);
+        };
+
+        for (idx, emitter_export) in emitters_export.into_iter().enumerate() {
+            let em_idx = emitter_export.document_index.unwrap_or(idx);
+
+            let emitter_type = if emitter_export.is_light {
+                EmitterType::Lights
+            } else {
+                EmitterType::Normal {
+                    lights_layout: &lights.bg_layout,
                 }
-            }
-        }
+            };
 
-        for emitter_export in emitters_export {
             let mut emitter = EmitterState::new(CreateEmitterOptions {
                 uniform: emitter_export.emitter,
                 camera,
                 collection,
                 gfx,
-                emitter_type: EmitterType::Normal {
-                    lights_layout: &lights.bg_layout,
-                },
+                emitter_type,
             })
             .await;
 
-            for export_animation in emitter_export.particle_animations {
-                for reg in registry_par_anims.iter() {
-                    if export_animation.tag == reg.tag() {
+            for (idx, export) in emitter_export.particle_animations.into_iter().enumerate() {
+                let path = format!("emitters[{}].particle_animations[{}]", em_idx, idx);
+                let reg = registry_par_anims
+                    .iter()
+                    .find(|reg| reg.tag() == export.tag);
+
+                let result = match reg {
+                    Some(reg) => {
                         let gfx_lock = &gfx.read().await;
-                        let anim = reg.import(gfx_lock, &emitter, export_animation.data);
-                        emitter.push_particle_animation(anim);
-                        break;
+                        reg.import(gfx_lock, &emitter, export.data.clone())
+                    }
+                    None => {
+                        diagnose(path, &export.tag, Ok(()));
+                        emitter.unrecognized_particle_animations.push((idx, export));
+                        continue;
+                    }
+                };
+
+                match result {
+                    Ok(anim) => emitter.push_particle_animation(anim),
+                    Err(err) => {
+                        diagnose(path, &export.tag, Err(err));
+                        emitter.unrecognized_particle_animations.push((idx, export));
                     }
                 }
             }
 
-            for export_animation in emitter_export.emitter_animations {
-                for reg in registry_em_anims.iter() {
-                    if export_animation.tag == reg.tag() {
-                        let anim = reg.import(export_animation.data);
-                        emitter.push_emitter_animation(anim);
-                        break;
+            for (idx, export) in emitter_export.emitter_animations.into_iter().enumerate() {
+                let path = format!("emitters[{}].emitter_animations[{}]", em_idx, idx);
+                let reg = registry_em_anims.iter().find(|reg| reg.tag() == export.tag);
+
+                match reg.map(|reg| reg.import(export.data.clone())) {
+                    Some(Ok(anim)) => emitter.push_emitter_animation(anim),
+                    Some(Err(err)) => {
+                        diagnose(path, &export.tag, Err(err));
+                        emitter.unrecognized_emitter_animations.push((idx, export));
+                    }
+                    None => {
+                        diagnose(path, &export.tag, Ok(()));
+                        emitter.unrecognized_emitter_animations.push((idx, export));
                     }
                 }
             }
@@ -312,13 +391,6 @@ impl Init {
             emitters.push(emitter);
         }
 
-        emitters.insert(0, lights);
-
-        Init {
-            emitters,
-            registry_em_anims,
-            registry_par_anims,
-            registry_post_fx,
-        }
+        (emitters, diagnostics)
     }
 }
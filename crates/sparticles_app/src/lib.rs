@@ -3,12 +3,11 @@ use egui_winit::winit;
 use init::AppVisitor;
 use model::{GfxState, SparEvents, SparState};
 use winit::event::Event::*;
-use winit::event_loop::{ControlFlow, EventLoop};
+use winit::event_loop::EventLoop;
 use winit::window::{self, WindowId};
 
 pub use egui_wgpu::wgpu;
 pub use glam;
-pub use wgpu_profiler as profiler;
 
 pub mod gui {
     pub use egui_wgpu::*;
@@ -16,10 +15,13 @@ pub mod gui {
 }
 
 pub mod animations;
+pub mod asset_manager;
+pub mod embed;
 pub mod fx;
 pub mod init;
 pub mod loader;
 pub mod model;
+pub mod profiler;
 pub mod shaders;
 pub mod texture;
 pub mod traits;
@@ -28,7 +30,7 @@ pub mod util;
 pub fn start(mut app_visitor: impl AppVisitor + 'static) {
     env_logger::init();
 
-    let event_loop = EventLoop::new();
+    let event_loop = EventLoop::new().unwrap();
 
     let window = window::WindowBuilder::new()
         .with_decorations(true)
@@ -43,45 +45,55 @@ pub fn start(mut app_visitor: impl AppVisitor + 'static) {
     let mut shift_pressed = false;
     let mut events = SparEvents::default();
 
-    event_loop.run(move |event, _, control_flow| {
-        let gfx_window_id = task::block_on(GfxState::window_id(&state.gfx));
-        let do_exec = |window_id: WindowId| window_id == gfx_window_id;
+    event_loop
+        .run(move |event, elwt| {
+            let gfx_window_id = task::block_on(GfxState::window_id(&state.gfx));
+            let do_exec = |window_id: WindowId| Some(window_id) == gfx_window_id;
 
-        match event {
-            RedrawRequested(window_id) if do_exec(window_id) => {
-                task::block_on(state.update(&events));
-                events = task::block_on(GfxState::render(&mut state, &mut app_visitor));
-            }
-            MainEventsCleared => {
-                let gfx = task::block_on(state.gfx.read());
-                gfx.request_redraw();
-            }
-            WindowEvent { event, window_id } if do_exec(window_id) => {
-                let response = GfxState::handle_event(&state.gfx, &event);
+            match event {
+                AboutToWait => {
+                    let gfx = task::block_on(state.gfx.read());
+                    gfx.request_redraw();
+                }
+                WindowEvent { event, window_id } if do_exec(window_id) => {
+                    let response = GfxState::handle_event(&state.gfx, &event);
 
-                match event {
-                    winit::event::WindowEvent::Resized(size) => {
-                        task::block_on(state.resize(size));
-                    }
-                    winit::event::WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
-                        task::block_on(state.resize(*new_inner_size));
-                    }
-                    winit::event::WindowEvent::CloseRequested => {
-                        *control_flow = ControlFlow::Exit;
-                    }
-                    winit::event::WindowEvent::KeyboardInput { input, .. } => {
-                        if !response.consumed {
-                            state.process_events(&input);
-                            app_visitor.process_events(&mut events, &input, shift_pressed);
+                    match event {
+                        winit::event::WindowEvent::RedrawRequested => {
+                            task::block_on(state.update(&mut events));
+                            task::block_on(GfxState::render(
+                                &mut state,
+                                &mut app_visitor,
+                                &mut events,
+                            ));
                         }
+                        winit::event::WindowEvent::Resized(size) => {
+                            task::block_on(state.resize(size));
+                        }
+                        winit::event::WindowEvent::ScaleFactorChanged { .. } => {
+                            let size = task::block_on(GfxState::window_size(&state.gfx));
+
+                            if let Some(size) = size {
+                                task::block_on(state.resize(size));
+                            }
+                        }
+                        winit::event::WindowEvent::CloseRequested => {
+                            elwt.exit();
+                        }
+                        winit::event::WindowEvent::KeyboardInput { event, .. } => {
+                            if !response.consumed {
+                                state.process_events(&event);
+                                app_visitor.process_events(&mut events, &event, shift_pressed);
+                            }
+                        }
+                        winit::event::WindowEvent::ModifiersChanged(modifiers) => {
+                            shift_pressed = modifiers.state().shift_key()
+                        }
+                        _ => {}
                     }
-                    winit::event::WindowEvent::ModifiersChanged(modifier) => {
-                        shift_pressed = modifier.shift()
-                    }
-                    _ => {}
                 }
+                _ => (),
             }
-            _ => (),
-        }
-    });
+        })
+        .unwrap();
 }
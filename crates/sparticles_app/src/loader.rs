@@ -1,3 +1,4 @@
+use crate::asset_manager::{AssetKind, AssetManager};
 use crate::model::emitter_state::FsEntryPoint;
 use crate::model::material::MaterialCtx;
 use crate::model::{GfxState, Material, Mesh, ModelVertex};
@@ -16,27 +17,17 @@ pub struct Model {
     pub id: ID,
     pub materials: HashMap<ID, Material>,
     pub meshes: HashMap<ID, Mesh>,
+    /// Mesh instances of the scene hierarchy
+    pub nodes: Vec<ModelNode>,
 }
 
-async fn load_binary(filename: &str) -> anyhow::Result<Vec<u8>> {
-    let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
-        .join("src/assets/models")
-        .join(filename);
-
-    println!("path: {:?}", &path);
-    let data = std::fs::read(path)?;
-
-    Ok(data)
-}
-
-async fn load_texture(
-    filename: &str,
-    gfx: &Arc<RwLock<GfxState>>,
-) -> anyhow::Result<wgpu::Texture> {
-    println!("file: {:?}", filename);
-    let data = load_binary(filename).await?;
-
-    Ok(TextureHandler::tex_from_bytes(gfx, &data, true).await)
+/// Node of a glTF scene that references a mesh
+#[derive(Debug, Clone)]
+pub struct ModelNode {
+    pub mesh_id: ID,
+    /// Material of the first primitive, none if it uses the default material
+    pub material_id: Option<ID>,
+    pub world: glam::Mat4,
 }
 
 impl Model {
@@ -50,33 +41,39 @@ impl Model {
             id: BUILTIN_ID.to_string(),
             materials,
             meshes,
+            nodes: vec![],
         }
     }
 
-    fn get_parents<'a>(
-        gltf: &'a gltf::Gltf,
-        node: &gltf::Node<'a>,
-        list: &mut Vec<gltf::Node<'a>>,
+    /// Flattens the node hierarchy with the world matrix of every node
+    fn collect_nodes<'a>(
+        node: gltf::Node<'a>,
+        parent_world: glam::Mat4,
+        list: &mut Vec<(gltf::Node<'a>, glam::Mat4)>,
     ) {
-        for other in gltf.nodes() {
-            for child in other.children() {
-                if node.index() == child.index() {
-                    list.push(child.clone());
-                    Self::get_parents(gltf, &child, list);
-                    break;
-                }
-            }
+        let world = parent_world * glam::Mat4::from_cols_array_2d(&node.transform().matrix());
+
+        for child in node.children() {
+            Self::collect_nodes(child, world, list);
         }
+
+        list.push((node, world));
     }
 
-    pub async fn load_gltf(gfx: &Arc<RwLock<GfxState>>, filename: &str) -> anyhow::Result<Self> {
-        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
-            .join("src/assets/models")
-            .join(filename);
+    fn material_id(material: &gltf::Material) -> Option<ID> {
+        let index = material.index()?;
 
-        let file = std::fs::File::open(path)?;
-        let reader = std::io::BufReader::new(file);
-        let gltf = gltf::Gltf::from_reader(reader)?;
+        Some(
+            material
+                .name()
+                .map(|name| name.to_string())
+                .unwrap_or_else(|| format!("material-{}", index)),
+        )
+    }
+
+    pub async fn load_gltf(gfx: &Arc<RwLock<GfxState>>, filename: &str) -> anyhow::Result<Self> {
+        let data = AssetManager::read(AssetKind::Model, filename)?;
+        let gltf = gltf::Gltf::from_slice(&data)?;
 
         // Load buffers
         let mut buffer_data: Vec<Vec<u8>> = Vec::new();
@@ -90,8 +87,8 @@ impl Model {
                     };
                 }
                 gltf::buffer::Source::Uri(uri) => {
-                    buffer_data.push(std::fs::read(uri)?);
-                    println!("Found uri not saving")
+                    let data = AssetManager::read_relative(AssetKind::Model, filename, uri)?;
+                    buffer_data.push(data.into_owned());
                 }
             }
         }
@@ -149,39 +146,55 @@ impl Model {
             })
         }
 
+        /// Images used by multiple textures are only uploaded once per color space
         async fn fetch_texture(
             img: gltf::image::Image<'_>,
             s_rgb: bool,
-            buffer_data: &mut [Vec<u8>],
+            filename: &str,
+            buffer_data: &[Vec<u8>],
+            cache: &mut HashMap<(usize, bool), Arc<wgpu::Texture>>,
             gfx: &Arc<RwLock<GfxState>>,
-        ) -> wgpu::Texture {
-            match img.source() {
+        ) -> anyhow::Result<Arc<wgpu::Texture>> {
+            let key = (img.index(), s_rgb);
+
+            if let Some(texture) = cache.get(&key) {
+                return Ok(texture.clone());
+            }
+
+            let texture = match img.source() {
                 gltf::image::Source::View { view, mime_type: _ } => {
                     let start = view.offset();
                     let end = start + view.length();
                     let buf_idx = view.buffer().index();
 
                     TextureHandler::tex_from_bytes(gfx, &buffer_data[buf_idx][start..end], s_rgb)
-                        .await
+                        .await?
                 }
                 gltf::image::Source::Uri { uri, mime_type: _ } => {
-                    load_texture(uri, gfx).await.expect("Can't load diffuse")
+                    let data = AssetManager::read_relative(AssetKind::Model, filename, uri)?;
+                    TextureHandler::tex_from_bytes(gfx, &data, s_rgb).await?
                 }
-            }
+            };
+
+            let texture = Arc::new(texture);
+            cache.insert(key, texture.clone());
+
+            Ok(texture)
         }
 
+        let mut textures = HashMap::new();
         let mut materials: HashMap<ID, Material> = HashMap::new();
 
         for (i, material) in gltf.materials().enumerate() {
-            let albedo_tex: wgpu::Texture;
+            let albedo_tex: Arc<wgpu::Texture>;
             let albedo_s: wgpu::Sampler;
-            let metallic_roughness_tex: wgpu::Texture;
+            let metallic_roughness_tex: Arc<wgpu::Texture>;
             let metallic_roughness_s: wgpu::Sampler;
-            let normal_tex: wgpu::Texture;
+            let normal_tex: Arc<wgpu::Texture>;
             let normal_s: wgpu::Sampler;
-            let emissive_tex: wgpu::Texture;
+            let emissive_tex: Arc<wgpu::Texture>;
             let emissive_s: wgpu::Sampler;
-            let ao_tex: wgpu::Texture;
+            let ao_tex: Arc<wgpu::Texture>;
             let ao_s: wgpu::Sampler;
             let cull_mode = Some(wgpu::Face::Back);
 
@@ -189,19 +202,34 @@ impl Model {
 
             if let Some(tex_data) = pbr.base_color_texture() {
                 let tex = tex_data.texture();
-                albedo_tex = fetch_texture(tex.source(), true, &mut buffer_data, gfx).await;
+                albedo_tex = fetch_texture(
+                    tex.source(),
+                    true,
+                    filename,
+                    &buffer_data,
+                    &mut textures,
+                    gfx,
+                )
+                .await?;
                 albedo_s = fetch_sampler(tex.sampler(), gfx).await;
                 println!("Contains albedo tex");
             } else {
                 let gfx = &gfx.read().await;
-                albedo_tex = gfx.create_builtin_tex(TexType::White);
+                albedo_tex = Arc::new(gfx.create_builtin_tex(TexType::White));
                 albedo_s = gfx.create_sampler();
             }
 
             if let Some(tex_data) = pbr.metallic_roughness_texture() {
                 let tex = tex_data.texture();
-                metallic_roughness_tex =
-                    fetch_texture(tex.source(), true, &mut buffer_data, gfx).await;
+                metallic_roughness_tex = fetch_texture(
+                    tex.source(),
+                    true,
+                    filename,
+                    &buffer_data,
+                    &mut textures,
+                    gfx,
+                )
+                .await?;
                 metallic_roughness_s = fetch_sampler(tex.sampler(), gfx).await;
                 println!("Contains metallic_roughness_tex");
             } else {
@@ -209,27 +237,43 @@ impl Model {
                 let roughness_factor = pbr.roughness_factor();
 
                 let gfx = &gfx.read().await;
-                metallic_roughness_tex = gfx.create_builtin_tex(TexType::Custom {
+                metallic_roughness_tex = Arc::new(gfx.create_builtin_tex(TexType::Custom {
                     srgb: true,
                     value: glam::Vec4::new(metallic_factor, roughness_factor, 0., 0.),
-                });
+                }));
                 metallic_roughness_s = gfx.create_sampler();
             }
 
             if let Some(tex_data) = material.normal_texture() {
                 let tex = tex_data.texture();
-                normal_tex = fetch_texture(tex.source(), false, &mut buffer_data, gfx).await;
+                normal_tex = fetch_texture(
+                    tex.source(),
+                    false,
+                    filename,
+                    &buffer_data,
+                    &mut textures,
+                    gfx,
+                )
+                .await?;
                 normal_s = fetch_sampler(tex.sampler(), gfx).await;
                 println!("Contains normal_tex");
             } else {
                 let gfx = &gfx.read().await;
-                normal_tex = gfx.create_builtin_tex(TexType::Normal);
+                normal_tex = Arc::new(gfx.create_builtin_tex(TexType::Normal));
                 normal_s = gfx.create_sampler();
             }
 
             if let Some(tex_data) = material.emissive_texture() {
                 let tex = tex_data.texture();
-                emissive_tex = fetch_texture(tex.source(), true, &mut buffer_data, gfx).await;
+                emissive_tex = fetch_texture(
+                    tex.source(),
+                    true,
+                    filename,
+                    &buffer_data,
+                    &mut textures,
+                    gfx,
+                )
+                .await?;
                 emissive_s = fetch_sampler(tex.sampler(), gfx).await;
 
                 if let Some(strenght) = material.emissive_strength() {
@@ -240,28 +284,33 @@ impl Model {
             } else {
                 let gfx = &gfx.read().await;
                 let vec3: glam::Vec3 = material.emissive_factor().into();
-                emissive_tex = gfx.create_builtin_tex(TexType::Custom {
+                emissive_tex = Arc::new(gfx.create_builtin_tex(TexType::Custom {
                     srgb: true,
                     value: vec3.extend(0.),
-                });
+                }));
                 emissive_s = gfx.create_sampler();
             }
 
             if let Some(tex_data) = material.occlusion_texture() {
                 let tex = tex_data.texture();
-                ao_tex = fetch_texture(tex.source(), true, &mut buffer_data, gfx).await;
+                ao_tex = fetch_texture(
+                    tex.source(),
+                    true,
+                    filename,
+                    &buffer_data,
+                    &mut textures,
+                    gfx,
+                )
+                .await?;
                 ao_s = fetch_sampler(tex.sampler(), gfx).await;
                 println!("contains occlusion_texture");
             } else {
                 let gfx = &gfx.read().await;
-                ao_tex = gfx.create_builtin_tex(TexType::White);
+                ao_tex = Arc::new(gfx.create_builtin_tex(TexType::White));
                 ao_s = gfx.create_sampler();
             }
 
-            let id = material
-                .name()
-                .map(|name| name.to_string())
-                .unwrap_or_else(|| format!("material-{}", i));
+            let id = Self::material_id(&material).unwrap_or_else(|| format!("material-{}", i));
 
             println!("Importing material: {:?}", &id);
 
@@ -288,113 +337,121 @@ impl Model {
         }
 
         let mut meshes: HashMap<ID, Mesh> = HashMap::new();
+        let mut nodes = Vec::new();
+        let mut scene_nodes = Vec::new();
 
         for scene in gltf.scenes() {
             for node in scene.nodes() {
-                let mut model = glam::Mat4::from_cols_array_2d(&node.transform().matrix());
-
-                let mut parents = vec![];
-                Self::get_parents(&gltf, &node, &mut parents);
-
-                for parent in parents {
-                    println!(
-                        "parent: {} from {}",
-                        parent.name().unwrap_or("parent"),
-                        node.name().unwrap_or("child")
-                    );
-                    model *= glam::Mat4::from_cols_array_2d(&parent.transform().matrix());
+                Self::collect_nodes(node, glam::Mat4::IDENTITY, &mut scene_nodes);
+            }
+        }
+
+        for (node, world) in scene_nodes {
+            let Some(mesh) = node.mesh() else {
+                println!("Not a mesh! {}", node.name().unwrap_or("no name"));
+                continue;
+            };
+
+            let id = mesh
+                .name()
+                .map(|name| name.to_string())
+                .unwrap_or_else(|| format!("mesh-{}", mesh.index()));
+
+            nodes.push(ModelNode {
+                mesh_id: id.to_string(),
+                material_id: mesh
+                    .primitives()
+                    .next()
+                    .and_then(|primitive| Self::material_id(&primitive.material())),
+                world,
+            });
+
+            if meshes.contains_key(&id) {
+                continue;
+            }
+
+            let mut vertices: Vec<ModelVertex> = Vec::new();
+            let mut indices = Vec::new();
+
+            for primitive in mesh.primitives() {
+                let reader = primitive.reader(|buffer| Some(&buffer_data[buffer.index()]));
+                let base = vertices.len();
+
+                if let Some(vertex_attribute) = reader.read_positions() {
+                    vertex_attribute.for_each(|vertex| {
+                        vertices.push(ModelVertex {
+                            position: vertex,
+                            uv: Default::default(),
+                            normal: Default::default(),
+                            tangent: Default::default(),
+                            bitangent: Default::default(),
+                        })
+                    });
+                }
+
+                if let Some(normal_attribute) = reader.read_normals() {
+                    for (i, normal) in normal_attribute.enumerate() {
+                        vertices[base + i].normal = normal;
+                    }
                 }
 
-                if let Some(mesh) = node.mesh() {
-                    let mut vertices = Vec::new();
-                    let mut indices = Vec::new();
-
-                    for primitive in mesh.primitives() {
-                        let reader = primitive.reader(|buffer| Some(&buffer_data[buffer.index()]));
-
-                        if let Some(vertex_attribute) = reader.read_positions() {
-                            vertex_attribute.for_each(|vertex| {
-                                vertices.push(ModelVertex {
-                                    position: vertex,
-                                    uv: Default::default(),
-                                    normal: Default::default(),
-                                    tangent: Default::default(),
-                                    bitangent: Default::default(),
-                                })
-                            });
-                        }
-
-                        if let Some(normal_attribute) = reader.read_normals() {
-                            for (i, normal) in normal_attribute.enumerate() {
-                                vertices[i].normal = normal;
-                            }
-                        }
-
-                        if let Some(tex_coords) = reader.read_tex_coords(0).map(|v| v.into_f32()) {
-                            for (i, uv) in tex_coords.enumerate() {
-                                vertices[i].uv = uv;
-                            }
-                        }
-
-                        if let Some(tangents) = reader.read_tangents() {
-                            for (i, tangent) in tangents.enumerate() {
-                                let tn: glam::Vec3 = glam::Vec3::from_slice(&tangent[..3]);
-                                let nm: glam::Vec3 = vertices[i].normal.into();
-
-                                vertices[i].tangent.copy_from_slice(&tangent[..3]);
-                                vertices[i].bitangent = (nm.cross(tn) * tangent[3]).into();
-                            }
-                        }
-
-                        if let Some(indices_raw) = reader.read_indices() {
-                            indices.append(&mut indices_raw.into_u32().collect::<Vec<u32>>());
-                        }
+                if let Some(tex_coords) = reader.read_tex_coords(0).map(|v| v.into_f32()) {
+                    for (i, uv) in tex_coords.enumerate() {
+                        vertices[base + i].uv = uv;
                     }
+                }
 
-                    let device = &gfx.read().await.device;
-
-                    let vertex_buffer =
-                        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                            label: Some(&format!("{:?} Vertex Buffer", filename)),
-                            contents: bytemuck::cast_slice(&vertices),
-                            usage: wgpu::BufferUsages::VERTEX,
-                        });
-
-                    let index_buffer =
-                        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                            label: Some(&format!("{:?} Index Buffer", filename)),
-                            contents: bytemuck::cast_slice(&indices),
-                            usage: wgpu::BufferUsages::INDEX,
-                        });
-
-                    let id = mesh
-                        .name()
-                        .map(|name| name.to_string())
-                        .unwrap_or_else(|| format!("mesh-{}", materials.len()));
-
-                    println!("Importing mesh: {:?}", &id);
-
-                    meshes.insert(
-                        id,
-                        Mesh {
-                            indices,
-                            vertices,
-                            vertex_buffer,
-                            index_buffer,
-                            model,
-                            fs_entry_point: FsEntryPoint::Model,
-                        },
-                    );
-                } else {
-                    println!("Not a mesh! {}", node.name().unwrap_or("no name"));
+                if let Some(tangents) = reader.read_tangents() {
+                    for (i, tangent) in tangents.enumerate() {
+                        let vertex = &mut vertices[base + i];
+                        let tn: glam::Vec3 = glam::Vec3::from_slice(&tangent[..3]);
+                        let nm: glam::Vec3 = vertex.normal.into();
+
+                        vertex.tangent.copy_from_slice(&tangent[..3]);
+                        vertex.bitangent = (nm.cross(tn) * tangent[3]).into();
+                    }
+                }
+
+                // Indices of every primitive start at its own first vertex
+                if let Some(indices_raw) = reader.read_indices() {
+                    indices.extend(indices_raw.into_u32().map(|idx| idx + base as u32));
                 }
             }
+
+            let device = &gfx.read().await.device;
+
+            let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(&format!("{:?} Vertex Buffer", filename)),
+                contents: bytemuck::cast_slice(&vertices),
+                usage: wgpu::BufferUsages::VERTEX,
+            });
+
+            let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(&format!("{:?} Index Buffer", filename)),
+                contents: bytemuck::cast_slice(&indices),
+                usage: wgpu::BufferUsages::INDEX,
+            });
+
+            println!("Importing mesh: {:?}", &id);
+
+            meshes.insert(
+                id,
+                Mesh {
+                    indices,
+                    vertices,
+                    vertex_buffer,
+                    index_buffer,
+                    model: world,
+                    fs_entry_point: FsEntryPoint::Model,
+                },
+            );
         }
 
         Ok(Self {
             id: filename.to_string(),
             materials,
             meshes,
+            nodes,
         })
     }
 }
@@ -2,10 +2,14 @@ use super::{gfx_state::GfxState, SparEvents, SparState};
 use egui_wgpu::wgpu;
 use egui_winit::{
     egui::WidgetText,
-    winit::event::{ElementState, KeyboardInput, VirtualKeyCode},
+    winit::{
+        event::{ElementState, KeyEvent},
+        keyboard::{KeyCode, PhysicalKey},
+    },
 };
 use encase::UniformBuffer;
 use glam::*;
+use serde::{Deserialize, Serialize};
 
 #[rustfmt::skip]
 pub const OPENGL_TO_WGPU_MATRIX: Mat4 = Mat4 {
@@ -15,7 +19,7 @@ pub const OPENGL_TO_WGPU_MATRIX: Mat4 = Mat4 {
     w_axis: Vec4::new(0.0, 0.0, 0.0, 1.0),
 };
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum TonemapType {
     AcesNarkowicz,
     AcesHill,
@@ -51,6 +55,15 @@ impl From<TonemapType> for u32 {
     }
 }
 
+/// View and projection of a host engine, the projection maps depth to 0..1 like wgpu
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExternalView {
+    pub view: Mat4,
+    pub proj: Mat4,
+    pub near: f32,
+    pub far: f32,
+}
+
 #[allow(dead_code)]
 #[derive(Debug)]
 pub struct Camera {
@@ -62,6 +75,8 @@ pub struct Camera {
     pub bloom_treshold: Vec3, // To prepare for post FX
     pub tonemap_type: TonemapType,
     pub look_at: Vec3,
+    /// Replaces the fly camera while set
+    pub external: Option<ExternalView>,
     fov: f32,  // Field of view (frustum vertical degrees)
     near: f32, // What is too close to show
     far: f32,  // What is too far to show
@@ -149,6 +164,7 @@ impl Camera {
             position,
             view_dir,
             look_at,
+            external: None,
             buffer,
             bg_layout,
             bg,
@@ -240,40 +256,42 @@ impl Camera {
         self.proj = Mat4::perspective_rh(self.fov, gfx_state.aspect(), self.near, self.far);
     }
 
-    pub fn process_input(&mut self, input: &KeyboardInput) -> bool {
+    pub fn process_input(&mut self, input: &KeyEvent) -> bool {
         let press_state = input.state;
-        let keycode = input.virtual_keycode.unwrap_or(VirtualKeyCode::Return);
+        let PhysicalKey::Code(keycode) = input.physical_key else {
+            return false;
+        };
         let is_pressed = press_state == ElementState::Pressed;
 
         match keycode {
-            VirtualKeyCode::W => {
+            KeyCode::KeyW => {
                 self.is_forward_pressed = is_pressed;
             }
-            VirtualKeyCode::A => {
+            KeyCode::KeyA => {
                 self.is_left_pressed = is_pressed;
             }
-            VirtualKeyCode::S => {
+            KeyCode::KeyS => {
                 self.is_backward_pressed = is_pressed;
             }
-            VirtualKeyCode::D => {
+            KeyCode::KeyD => {
                 self.is_right_pressed = is_pressed;
             }
-            VirtualKeyCode::Up => {
+            KeyCode::ArrowUp => {
                 self.is_rotate_up_pressed = is_pressed;
             }
-            VirtualKeyCode::Left => {
+            KeyCode::ArrowLeft => {
                 self.is_rotate_left_pressed = is_pressed;
             }
-            VirtualKeyCode::Right => {
+            KeyCode::ArrowRight => {
                 self.is_rotate_right_pressed = is_pressed;
             }
-            VirtualKeyCode::Down => {
+            KeyCode::ArrowDown => {
                 self.is_rotate_down_pressed = is_pressed;
             }
-            VirtualKeyCode::LControl => {
+            KeyCode::ControlLeft => {
                 self.is_down_pressed = is_pressed;
             }
-            VirtualKeyCode::Space => {
+            KeyCode::Space => {
                 self.is_up_pressed = is_pressed;
             }
             _ => return false,
@@ -289,7 +307,7 @@ impl Camera {
         let uniform = CameraUniform {
             view_proj,
             view: view_mat,
-            position: self.position,
+            position: self.eye(),
             bloom_treshold: self.bloom_treshold,
             tonemap: self.tonemap_type as u32,
         };
@@ -300,6 +318,10 @@ impl Camera {
     }
 
     pub fn view_mat(&self) -> Mat4 {
+        if let Some(external) = &self.external {
+            return external.view;
+        }
+
         let yaw_mat = Mat3::from_rotation_y(self.yaw);
         let pitch_mat = Mat3::from_rotation_x(self.pitch);
 
@@ -308,7 +330,30 @@ impl Camera {
     }
 
     pub fn view_proj(&self, view_mat: &Mat4) -> Mat4 {
-        OPENGL_TO_WGPU_MATRIX * self.proj * (*view_mat)
+        self.proj() * (*view_mat)
+    }
+
+    pub fn proj(&self) -> Mat4 {
+        match &self.external {
+            Some(external) => external.proj,
+            None => OPENGL_TO_WGPU_MATRIX * self.proj,
+        }
+    }
+
+    /// World position the camera looks from
+    pub fn eye(&self) -> Vec3 {
+        match &self.external {
+            Some(external) => external.view.inverse().w_axis.truncate(),
+            None => self.position,
+        }
+    }
+
+    pub fn near(&self) -> f32 {
+        self.external.map_or(self.near, |external| external.near)
+    }
+
+    pub fn far(&self) -> f32 {
+        self.external.map_or(self.far, |external| external.far)
     }
 }
 
@@ -1,11 +1,15 @@
 use std::time::{Duration, Instant};
 
+#[derive(Clone)]
 pub struct Clock {
     instant: Instant,
     last_update: Duration,
     current_delta: Duration,
     cpu_time: Duration,
     frame: usize,
+    /// Accumulated time, emitter clocks only accumulate while running
+    elapsed: Duration,
+    is_running: bool,
 }
 
 impl Default for Clock {
@@ -16,6 +20,8 @@ impl Default for Clock {
             current_delta: Duration::ZERO,
             cpu_time: Duration::ZERO,
             frame: 0,
+            elapsed: Duration::ZERO,
+            is_running: true,
         }
     }
 }
@@ -25,12 +31,41 @@ impl Clock {
         let now = self.instant.elapsed();
         self.current_delta = now - self.last_update;
         self.last_update = now;
+        self.elapsed += self.current_delta;
 
         if play {
             self.frame += 1;
         }
     }
 
+    /// Follows the frames of the global clock, elapsed time only moves forward while running
+    pub fn sync(&mut self, global: &Clock) {
+        self.current_delta = global.current_delta;
+        self.last_update = global.last_update;
+        self.frame = global.frame;
+
+        if self.is_running {
+            self.elapsed += self.current_delta;
+        }
+    }
+
+    pub fn start(&mut self) {
+        self.is_running = true;
+    }
+
+    pub fn stop(&mut self) {
+        self.is_running = false;
+    }
+
+    pub fn restart(&mut self) {
+        self.elapsed = Duration::ZERO;
+        self.is_running = true;
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.is_running
+    }
+
     pub fn measure_cpu_time(&mut self) {
         self.cpu_time = self.instant.elapsed() - self.last_update;
         // TODO fix
@@ -45,11 +80,11 @@ impl Clock {
     }
 
     pub fn elapsed_sec(&self) -> f32 {
-        self.instant.elapsed().as_secs_f32()
+        self.elapsed.as_secs_f32()
     }
 
     pub fn elapsed_sec_f64(&self) -> f64 {
-        self.instant.elapsed().as_secs_f64()
+        self.elapsed.as_secs_f64()
     }
 
     pub fn frame(&self) -> usize {
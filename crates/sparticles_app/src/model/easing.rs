@@ -0,0 +1,194 @@
+use glam::Vec2;
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+use std::fmt::{Display, Formatter};
+
+/// Penner easing curves
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EasingCurve {
+    Sine,
+    Quad,
+    Cubic,
+    Quart,
+    Quint,
+    Expo,
+    Circ,
+    Back,
+    Elastic,
+    Bounce,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EasingMode {
+    In,
+    Out,
+    InOut,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Easing {
+    #[default]
+    Linear,
+    Penner {
+        curve: EasingCurve,
+        mode: EasingMode,
+    },
+    /// Timing curve from (0, 0) to (1, 1) with two control points, like CSS cubic-bezier
+    CubicBezier { p1: Vec2, p2: Vec2 },
+}
+
+impl EasingCurve {
+    pub const ALL: [EasingCurve; 10] = [
+        Self::Sine,
+        Self::Quad,
+        Self::Cubic,
+        Self::Quart,
+        Self::Quint,
+        Self::Expo,
+        Self::Circ,
+        Self::Back,
+        Self::Elastic,
+        Self::Bounce,
+    ];
+
+    /// Ease in variant, the other modes are derived from it
+    fn ease_in(&self, t: f32) -> f32 {
+        match self {
+            Self::Sine => 1. - (t * PI / 2.).cos(),
+            Self::Quad => t.powi(2),
+            Self::Cubic => t.powi(3),
+            Self::Quart => t.powi(4),
+            Self::Quint => t.powi(5),
+            Self::Expo => {
+                if t <= 0. {
+                    0.
+                } else {
+                    2f32.powf(10. * t - 10.)
+                }
+            }
+            Self::Circ => 1. - (1. - t * t).max(0.).sqrt(),
+            Self::Back => {
+                let c1 = 1.70158;
+                let c3 = c1 + 1.;
+                c3 * t.powi(3) - c1 * t.powi(2)
+            }
+            Self::Elastic => {
+                if t <= 0. || 1. <= t {
+                    t
+                } else {
+                    let c4 = 2. * PI / 3.;
+                    -(2f32.powf(10. * t - 10.)) * ((t * 10. - 10.75) * c4).sin()
+                }
+            }
+            Self::Bounce => 1. - bounce_out(1. - t),
+        }
+    }
+}
+
+fn bounce_out(t: f32) -> f32 {
+    let n1 = 7.5625;
+    let d1 = 2.75;
+
+    if t < 1. / d1 {
+        n1 * t * t
+    } else if t < 2. / d1 {
+        let t = t - 1.5 / d1;
+        n1 * t * t + 0.75
+    } else if t < 2.5 / d1 {
+        let t = t - 2.25 / d1;
+        n1 * t * t + 0.9375
+    } else {
+        let t = t - 2.625 / d1;
+        n1 * t * t + 0.984375
+    }
+}
+
+/// Solves the bezier x for the fraction and returns its y
+pub fn cubic_bezier(p1: Vec2, p2: Vec2, fraction: f32) -> f32 {
+    let bezier = |a: f32, b: f32, t: f32| {
+        let inv = 1. - t;
+        3. * inv * inv * t * a + 3. * inv * t * t * b + t * t * t
+    };
+
+    // Bisection, x is monotonic as long as the control points stay within 0..=1
+    let mut low = 0.;
+    let mut high = 1.;
+    let mut t = fraction;
+
+    for _ in 0..20 {
+        if bezier(p1.x, p2.x, t) < fraction {
+            low = t;
+        } else {
+            high = t;
+        }
+
+        t = (low + high) / 2.;
+    }
+
+    bezier(p1.y, p2.y, t)
+}
+
+impl Easing {
+    pub const KINDS: [Easing; 3] = [
+        Self::Linear,
+        Self::Penner {
+            curve: EasingCurve::Quad,
+            mode: EasingMode::InOut,
+        },
+        Self::CubicBezier {
+            p1: Vec2::new(0.42, 0.),
+            p2: Vec2::new(0.58, 1.),
+        },
+    ];
+
+    pub fn same_kind(&self, other: &Self) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
+
+    /// Maps a linear fraction (0..=1) to the eased fraction
+    pub fn apply(&self, fraction: f32) -> f32 {
+        let t = fraction.clamp(0., 1.);
+
+        match self {
+            Self::Linear => t,
+            Self::Penner { curve, mode } => match mode {
+                EasingMode::In => curve.ease_in(t),
+                EasingMode::Out => 1. - curve.ease_in(1. - t),
+                EasingMode::InOut => {
+                    if t < 0.5 {
+                        curve.ease_in(2. * t) / 2.
+                    } else {
+                        1. - curve.ease_in(2. - 2. * t) / 2.
+                    }
+                }
+            },
+            Self::CubicBezier { p1, p2 } => cubic_bezier(*p1, *p2, t),
+        }
+    }
+}
+
+impl Display for Easing {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Linear => f.write_str("Linear"),
+            Self::Penner { .. } => f.write_str("Penner"),
+            Self::CubicBezier { .. } => f.write_str("Cubic bezier"),
+        }
+    }
+}
+
+impl Display for EasingCurve {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl Display for EasingMode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::In => f.write_str("In"),
+            Self::Out => f.write_str("Out"),
+            Self::InOut => f.write_str("In out"),
+        }
+    }
+}
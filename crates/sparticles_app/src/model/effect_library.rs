@@ -0,0 +1,583 @@
+use super::{
+    EffectTransform, EmitterParent, EmitterState, EmitterUniform, LightSelection, ParamError,
+    ParamValue, SparState,
+};
+use crate::asset_manager::{AssetError, AssetKind, AssetManager};
+use crate::init::Init;
+use crate::util::persistence::ExportEmitter;
+use crate::util::{ImportDiagnostic, SceneDocument, SceneError, ID};
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+
+/// Identifies a spawned effect instance, handles are never reused
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct EffectHandle(u64);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopMode {
+    /// Removes the emitters and their particles with the next update
+    Immediate,
+    /// Stops spawning and removes the emitters once the spawned particles, and the particles
+    /// of the sub emitters they trigger, decayed
+    Finish,
+}
+
+#[derive(Debug)]
+pub enum EffectError {
+    UnknownTemplate(String),
+    Asset(AssetError),
+    Document { name: String, err: SceneError },
+}
+
+impl Display for EffectError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownTemplate(name) => write!(f, "Effect template '{}' doesn't exist", name),
+            Self::Asset(err) => err.fmt(f),
+            Self::Document { name, err } => write!(f, "Invalid effect export {}: {}", name, err),
+        }
+    }
+}
+
+impl std::error::Error for EffectError {}
+
+impl From<AssetError> for EffectError {
+    fn from(err: AssetError) -> Self {
+        Self::Asset(err)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum InstanceState {
+    /// Emitters are created with the next update
+    Pending,
+    Playing,
+    /// The emitters stop spawning with the next update
+    Stopping,
+    Finishing {
+        remaining_sec: f32,
+    },
+    Removing,
+}
+
+struct EffectInstance {
+    handle: EffectHandle,
+    template: String,
+    transform: EffectTransform,
+    emitter_ids: Vec<ID>,
+    state: InstanceState,
+    /// Exposed parameters that are applied with the next update
+    params: Vec<(String, ParamValue)>,
+    /// Problems found while creating the emitters, paths refer to the template
+    diagnostics: Vec<ImportDiagnostic>,
+}
+
+/// Named effect templates, a group of emitters with their animations as exported by the
+/// editor, that game code can spawn any number of times at runtime
+#[derive(Default)]
+pub struct EffectLibrary {
+    templates: HashMap<String, Vec<ExportEmitter>>,
+    instances: Vec<EffectInstance>,
+    next_handle: u64,
+}
+
+impl EffectLibrary {
+    /// Reads a scene document, like scene.json, from the export folder. Only the emitters of
+    /// the scene become the template.
+    pub fn load(&mut self, name: &str, file: &str) -> Result<Vec<ImportDiagnostic>, EffectError> {
+        let json = AssetManager::read_to_string(AssetKind::Export, file)?;
+        self.insert_json(name, &json)
+    }
+
+    /// Parses a scene document that was read by the engine of the host. Emitters with problems
+    /// are left out of the template, the returned diagnostics tell which and why.
+    pub fn insert_json(
+        &mut self,
+        name: &str,
+        json: &str,
+    ) -> Result<Vec<ImportDiagnostic>, EffectError> {
+        let document = SceneDocument::from_json(json).map_err(|err| EffectError::Document {
+            name: name.to_string(),
+            err,
+        })?;
+
+        self.insert(name, document.emitters);
+        Ok(document.diagnostics)
+    }
+
+    pub fn insert(&mut self, name: &str, emitters: Vec<ExportEmitter>) {
+        self.templates.insert(name.to_string(), emitters);
+    }
+
+    pub fn templates(&self) -> impl Iterator<Item = &str> {
+        self.templates.keys().map(|name| name.as_str())
+    }
+
+    /// The emitters are created with the next update, the handle is alive right away
+    pub fn spawn(
+        &mut self,
+        template: &str,
+        transform: EffectTransform,
+    ) -> Result<EffectHandle, EffectError> {
+        if !self.templates.contains_key(template) {
+            return Err(EffectError::UnknownTemplate(template.to_string()));
+        }
+
+        let handle = EffectHandle(self.next_handle);
+        self.next_handle += 1;
+
+        self.instances.push(EffectInstance {
+            handle,
+            template: template.to_string(),
+            transform,
+            emitter_ids: vec![],
+            state: InstanceState::Pending,
+            params: vec![],
+            diagnostics: vec![],
+        });
+
+        Ok(handle)
+    }
+
+    /// Moves every emitter of the instance, returns false if the instance is gone
+    pub fn set_transform(&mut self, handle: EffectHandle, transform: EffectTransform) -> bool {
+        match self.instance_mut(handle) {
+            Some(instance) => {
+                instance.transform = transform;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Sets an exposed parameter of the emitters of the instance with the next update, does
+    /// nothing if the instance is gone
+    pub fn set_param(
+        &mut self,
+        handle: EffectHandle,
+        name: &str,
+        value: ParamValue,
+    ) -> Result<(), ParamError> {
+        let Some(instance) = self
+            .instances
+            .iter_mut()
+            .find(|instance| instance.handle == handle)
+        else {
+            return Ok(());
+        };
+
+        let is_exposed = self.templates[&instance.template].iter().any(|export| {
+            export
+                .emitter
+                .exposed_params
+                .iter()
+                .any(|param| param.name == name)
+        });
+
+        if !is_exposed {
+            return Err(ParamError::UnknownParam(name.to_string()));
+        }
+
+        instance.params.retain(|(param, _)| param != name);
+        instance.params.push((name.to_string(), value));
+        Ok(())
+    }
+
+    pub fn transform(&self, handle: EffectHandle) -> Option<EffectTransform> {
+        self.instance(handle).map(|instance| instance.transform)
+    }
+
+    pub fn stop(&mut self, handle: EffectHandle, mode: StopMode) {
+        let Some(instance) = self.instance_mut(handle) else {
+            return;
+        };
+
+        instance.state = match (mode, instance.state) {
+            (StopMode::Immediate, _) | (StopMode::Finish, InstanceState::Pending) => {
+                InstanceState::Removing
+            }
+            (StopMode::Finish, InstanceState::Playing) => InstanceState::Stopping,
+            (StopMode::Finish, state) => state,
+        };
+    }
+
+    /// False once the emitters of the instance are removed
+    pub fn is_alive(&self, handle: EffectHandle) -> bool {
+        self.instance(handle).is_some()
+    }
+
+    /// Whether the instance still spawns particles
+    pub fn is_playing(&self, handle: EffectHandle) -> bool {
+        self.instance(handle).is_some_and(|instance| {
+            matches!(
+                instance.state,
+                InstanceState::Pending | InstanceState::Playing
+            )
+        })
+    }
+
+    /// Problems found while creating the emitters of the instance, like animations that don't
+    /// fit the emitter. Empty until the emitters are created and once the instance is gone.
+    pub fn diagnostics(&self, handle: EffectHandle) -> &[ImportDiagnostic] {
+        self.instance(handle)
+            .map_or(&[], |instance| instance.diagnostics.as_slice())
+    }
+
+    pub fn handles(&self) -> impl Iterator<Item = EffectHandle> + '_ {
+        self.instances.iter().map(|instance| instance.handle)
+    }
+
+    pub async fn update(state: &mut SparState) {
+        let SparState {
+            effects,
+            emitters,
+            clock,
+            gfx,
+            camera,
+            lights,
+            collection,
+            registry_par_anims,
+            registry_em_anims,
+            ..
+        } = state;
+
+        let EffectLibrary {
+            templates,
+            instances,
+            ..
+        } = effects;
+
+        let delta_sec = clock.delta_sec();
+
+        for instance in instances.iter_mut() {
+            match &mut instance.state {
+                InstanceState::Pending => {
+                    let template = &templates[&instance.template];
+                    let exports = Self::instantiate(template, instance.handle, &instance.template);
+
+                    instance.emitter_ids = exports
+                        .iter()
+                        .map(|export| export.emitter.id.to_string())
+                        .collect();
+
+                    let (created, diagnostics) = Init::json_emitters(
+                        exports,
+                        gfx,
+                        camera,
+                        lights,
+                        collection,
+                        registry_par_anims,
+                        registry_em_anims,
+                    )
+                    .await;
+
+                    instance.diagnostics = diagnostics;
+                    emitters.extend(created);
+                    instance.state = InstanceState::Playing;
+                }
+                InstanceState::Stopping => {
+                    for emitter in emitters
+                        .iter_mut()
+                        .filter(|em| instance.emitter_ids.contains(&em.uniform.id))
+                    {
+                        emitter.stop();
+                    }
+
+                    let uniforms: Vec<&EmitterUniform> =
+                        emitters.iter().map(|em| &em.uniform).collect();
+
+                    let remaining_sec = uniforms
+                        .iter()
+                        .filter(|uniform| instance.emitter_ids.contains(&uniform.id))
+                        .map(|uniform| Self::finish_sec(&uniforms, uniform))
+                        .fold(0., f32::max);
+
+                    instance.state = InstanceState::Finishing { remaining_sec };
+                }
+                InstanceState::Finishing { remaining_sec } => {
+                    *remaining_sec -= delta_sec;
+
+                    if *remaining_sec <= 0. {
+                        instance.state = InstanceState::Removing;
+                    }
+                }
+                InstanceState::Playing | InstanceState::Removing => {}
+            }
+
+            let gfx = &gfx.read().await;
+
+            let mut instance_emitters: Vec<&mut EmitterState> = emitters
+                .iter_mut()
+                .filter(|em| instance.emitter_ids.contains(&em.uniform.id))
+                .collect();
+
+            for emitter in instance_emitters.iter_mut() {
+                emitter.instance_root = Some(instance.transform);
+            }
+
+            for (name, value) in instance.params.iter() {
+                let mut exposing: Vec<&mut &mut EmitterState> = instance_emitters
+                    .iter_mut()
+                    .filter(|em| em.uniform.exposed_params.iter().any(|p| &p.name == name))
+                    .collect();
+
+                // All emitters of the instance are checked before any of them is written
+                let result = exposing
+                    .iter()
+                    .try_for_each(|em| {
+                        em.check_param(name, *value, registry_par_anims, registry_em_anims)
+                    })
+                    .and_then(|_| {
+                        exposing.iter_mut().try_for_each(|em| {
+                            em.set_param(name, *value, gfx, registry_par_anims, registry_em_anims)
+                        })
+                    });
+
+                if let Err(err) = result {
+                    println!("{}", err);
+                }
+            }
+
+            instance.params.clear();
+        }
+
+        for instance in instances.iter() {
+            if instance.state == InstanceState::Removing {
+                emitters.retain(|em| !instance.emitter_ids.contains(&em.uniform.id));
+            }
+        }
+
+        instances.retain(|instance| instance.state != InstanceState::Removing);
+    }
+
+    /// Seconds until the particles of a stopped emitter decayed. Particles spawned right
+    /// before stopping live the longest and sub emitters spawn during or at the end of the
+    /// lifetime of a parent particle, so the lifetimes along the sub emitter chain add up.
+    fn finish_sec(uniforms: &[&EmitterUniform], uniform: &EmitterUniform) -> f32 {
+        let mut finish_sec = uniform.particle_lifetime_sec;
+        let mut chain = vec![&uniform.id];
+        let mut parent = uniform;
+
+        while let Some(child) = parent.sub_emitter.as_ref().and_then(|sub_emitter| {
+            uniforms
+                .iter()
+                .find(|uniform| uniform.id == sub_emitter.child_id)
+        }) {
+            // Cyclic chains keep spawning, they are cut off after one round
+            if chain.contains(&&child.id) {
+                break;
+            }
+
+            finish_sec += child.particle_lifetime_sec;
+            chain.push(&child.id);
+            parent = child;
+        }
+
+        finish_sec
+    }
+
+    /// Copies the template with ids that are unique per instance, references between the
+    /// emitters of the template are renamed as well. Emitters without a parent follow the
+    /// instance transform.
+    fn instantiate(
+        template: &[ExportEmitter],
+        handle: EffectHandle,
+        name: &str,
+    ) -> Vec<ExportEmitter> {
+        let ids: Vec<&ID> = template.iter().map(|export| &export.emitter.id).collect();
+        let rename = |id: &ID| -> ID {
+            if ids.contains(&id) {
+                format!("{}#{}/{}", name, handle.0, id)
+            } else {
+                id.to_string()
+            }
+        };
+
+        template
+            .iter()
+            .map(|export| {
+                let mut export = export.clone();
+                let emitter = &mut export.emitter;
+
+                emitter.id = rename(&emitter.id);
+
+                emitter.parent = match &emitter.parent {
+                    Some(EmitterParent::Emitter(id)) => Some(EmitterParent::Emitter(rename(id))),
+                    _ => Some(EmitterParent::EffectRoot),
+                };
+
+                if let Some(sub_emitter) = &mut emitter.sub_emitter {
+                    sub_emitter.child_id = rename(&sub_emitter.child_id);
+                }
+
+                if let LightSelection::Only(light_ids) = &mut emitter.lights {
+                    for id in light_ids.iter_mut() {
+                        *id = rename(id);
+                    }
+                }
+
+                export
+            })
+            .collect()
+    }
+
+    fn instance(&self, handle: EffectHandle) -> Option<&EffectInstance> {
+        self.instances
+            .iter()
+            .find(|instance| instance.handle == handle)
+    }
+
+    fn instance_mut(&mut self, handle: EffectHandle) -> Option<&mut EffectInstance> {
+        self.instances
+            .iter_mut()
+            .find(|instance| instance.handle == handle)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::SubEmitter;
+
+    fn export(id: &str, parent: Option<EmitterParent>) -> ExportEmitter {
+        let mut emitter = EmitterUniform::new(id.to_string());
+        emitter.parent = parent;
+
+        ExportEmitter {
+            emitter,
+            is_light: false,
+            particle_animations: vec![],
+            emitter_animations: vec![],
+            document_index: None,
+        }
+    }
+
+    fn uniform(id: &str, lifetime_sec: f32, child_id: Option<&str>) -> EmitterUniform {
+        let mut uniform = EmitterUniform::new(id.to_string());
+        uniform.particle_lifetime_sec = lifetime_sec;
+        uniform.sub_emitter = child_id.map(|id| SubEmitter::new(id.to_string()));
+        uniform
+    }
+
+    #[test]
+    fn instantiate_renames_references_inside_the_template() {
+        let mut root = export("root", None);
+        root.emitter.sub_emitter = Some(SubEmitter::new("sparks".to_string()));
+        root.emitter.lights = LightSelection::Only(vec!["glow".to_string(), "sun".to_string()]);
+
+        let sparks = export("sparks", Some(EmitterParent::Emitter("root".to_string())));
+        let glow = export("glow", Some(EmitterParent::Emitter("scene".to_string())));
+        let smoke = export("smoke", Some(EmitterParent::EffectRoot));
+
+        let template = [root, sparks, glow, smoke];
+        let exports = EffectLibrary::instantiate(&template, EffectHandle(3), "fire");
+        let emitters: Vec<&EmitterUniform> = exports.iter().map(|e| &e.emitter).collect();
+
+        let ids: Vec<&str> = emitters.iter().map(|em| em.id.as_str()).collect();
+        assert_eq!(
+            ids,
+            [
+                "fire#3/root",
+                "fire#3/sparks",
+                "fire#3/glow",
+                "fire#3/smoke"
+            ]
+        );
+
+        assert_eq!(emitters[0].parent, Some(EmitterParent::EffectRoot));
+        assert_eq!(
+            emitters[1].parent,
+            Some(EmitterParent::Emitter("fire#3/root".to_string()))
+        );
+        assert_eq!(
+            emitters[2].parent,
+            Some(EmitterParent::Emitter("scene".to_string()))
+        );
+        assert_eq!(emitters[3].parent, Some(EmitterParent::EffectRoot));
+
+        assert_eq!(
+            emitters[0]
+                .sub_emitter
+                .as_ref()
+                .map(|sub| sub.child_id.as_str()),
+            Some("fire#3/sparks")
+        );
+        assert_eq!(
+            emitters[0].lights,
+            LightSelection::Only(vec!["fire#3/glow".to_string(), "sun".to_string()])
+        );
+    }
+
+    #[test]
+    fn instances_get_unique_ids() {
+        let template = [export("root", None)];
+
+        let first = EffectLibrary::instantiate(&template, EffectHandle(0), "fire");
+        let second = EffectLibrary::instantiate(&template, EffectHandle(1), "fire");
+
+        assert_ne!(first[0].emitter.id, second[0].emitter.id);
+        assert_eq!(template[0].emitter.id, "root");
+    }
+
+    #[test]
+    fn finish_waits_for_the_sub_emitter_chain() {
+        let root = uniform("root", 2., Some("sparks"));
+        let sparks = uniform("sparks", 1.5, Some("smoke"));
+        let smoke = uniform("smoke", 4., None);
+        let uniforms = [&root, &sparks, &smoke];
+
+        assert_eq!(EffectLibrary::finish_sec(&uniforms, &root), 7.5);
+        assert_eq!(EffectLibrary::finish_sec(&uniforms, &sparks), 5.5);
+        assert_eq!(EffectLibrary::finish_sec(&uniforms, &smoke), 4.);
+
+        let missing = uniform("missing", 2., Some("gone"));
+        assert_eq!(EffectLibrary::finish_sec(&[&missing], &missing), 2.);
+    }
+
+    #[test]
+    fn finish_cuts_off_cyclic_chains() {
+        let a = uniform("a", 1., Some("b"));
+        let b = uniform("b", 2., Some("a"));
+
+        assert_eq!(EffectLibrary::finish_sec(&[&a, &b], &a), 3.);
+
+        let own = uniform("own", 1., Some("own"));
+        assert_eq!(EffectLibrary::finish_sec(&[&own], &own), 1.);
+    }
+
+    #[test]
+    fn stop_finish_waits_for_the_next_update() {
+        let mut library = EffectLibrary::default();
+        library.insert("fire", vec![export("root", None)]);
+
+        let pending = library.spawn("fire", EffectTransform::default()).unwrap();
+        let playing = library.spawn("fire", EffectTransform::default()).unwrap();
+        library.instance_mut(playing).unwrap().state = InstanceState::Playing;
+
+        library.stop(pending, StopMode::Finish);
+        library.stop(playing, StopMode::Finish);
+
+        assert_eq!(
+            library.instance(pending).unwrap().state,
+            InstanceState::Removing
+        );
+        assert_eq!(
+            library.instance(playing).unwrap().state,
+            InstanceState::Stopping
+        );
+        assert!(library.is_alive(playing));
+        assert!(!library.is_playing(playing));
+
+        library.stop(playing, StopMode::Finish);
+        assert_eq!(
+            library.instance(playing).unwrap().state,
+            InstanceState::Stopping
+        );
+
+        library.stop(playing, StopMode::Immediate);
+        assert_eq!(
+            library.instance(playing).unwrap().state,
+            InstanceState::Removing
+        );
+    }
+}
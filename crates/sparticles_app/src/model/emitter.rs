@@ -1,15 +1,19 @@
-use super::Clock;
+use super::{
+    transform, Clock, EmitterParent, ExposedParam, LightSelection, SimulationSpace, SubEmitter,
+};
 use crate::loader::{Model, BUILTIN_ID, CIRCLE_MAT_ID, CIRCLE_MESH_ID};
 use crate::model::state::FastFetch;
-use crate::traits::{FromRGB, HandleAngles};
+use crate::shaders::layout::gpu_struct;
+use crate::traits::{BufferContent, FromRGB, HandleAngles};
 use crate::util::ID;
 use async_std::sync::RwLock;
-use glam::{f32::Vec3, f32::Vec4};
+use glam::{f32::Mat4, f32::Vec3, f32::Vec4};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::Arc;
 
-const PARTICLE_BUFFER_SIZE: u64 = 26 * 4;
+/// Lifetime of particles that haven't been spawned yet, always larger than the emitters lifetime
+const UNSPAWNED_LIFETIME: f32 = f32::MAX;
 
 pub struct EmitSpawnOptions {
     pub spawn_count: u32,
@@ -39,18 +43,85 @@ pub struct MaterialRef {
     pub material_id: ID,
 }
 
+gpu_struct! {
+    /// Element of the particle buffers, matches `Particle` in declarations.wgsl
+    #[derive(Clone, Copy)]
+    pub struct ParticleGpu as "Particle" {
+        pub model: Mat4,
+        pub color: Vec4,
+        pub vel_mass: Vec4,
+        pub scale: f32,
+        pub lifetime: f32,
+    }
+}
+
+/// Array stride of Particle in WGSL, the layout check keeps it in sync with the shaders
+pub(crate) fn particle_stride() -> u64 {
+    use encase::ShaderType;
+
+    ParticleGpu::min_size().get()
+}
+
+gpu_struct! {
+    /// Uniform buffer content of the emitter, matches `Emitter` in declarations.wgsl
+    #[derive(Default)]
+    pub struct EmitterGpu as "Emitter" {
+        pub delta_sec: f32,
+        pub elapsed_sec: f32,
+        pub spawn_from: f32,
+        pub spawn_until: f32,
+        pub box_x: f32,
+        pub box_y: f32,
+        pub box_z: f32,
+        pub box_width: f32,
+        pub box_height: f32,
+        pub box_depth: f32,
+        pub box_yaw: f32,
+        pub box_pitch: f32,
+        pub box_roll: f32,
+        pub diffusion_width: f32,
+        pub diffusion_depth: f32,
+        pub light_mask: u32,
+        pub particle_model: Mat4,
+        pub particle_color_r: f32,
+        pub particle_color_g: f32,
+        pub particle_color_b: f32,
+        pub particle_color_a: f32,
+        pub particle_speed_min: f32,
+        pub particle_speed_max: f32,
+        pub particle_size_min: f32,
+        pub particle_size_max: f32,
+        pub particle_friction_coefficient: f32,
+        pub material_mass: f32,
+        pub particle_lifetime: f32,
+        pub padding_2: f32,
+        pub parent_world: Mat4,
+        pub space_delta: Mat4,
+        pub velocity: Vec3,
+        pub inherit_velocity: f32,
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EmitterUniform {
     pub id: ID,
+    #[serde(skip)]
     spawn_from: u32,
+    #[serde(skip)]
     spawn_until: u32,
-    spawn_batches_count: u32,
+    #[serde(skip)]
     elapsed_sec: f32,
+    #[serde(skip)]
     delta_sec: f32,
+    #[serde(skip, default = "EmitterUniform::default_iteration")]
     iteration: u32,
 
     pub spawn_count: u32,
     pub spawn_delay_sec: f32,
+    /// Batches of particles that are alive at the same time, pinned by scenes of format
+    /// version 0 where it didn't follow the lifetime and delay. Derived from those if None.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spawn_batches_count: Option<u32>,
 
     pub box_position: Vec3,
     /// width, height, depth
@@ -73,6 +144,49 @@ pub struct EmitterUniform {
     pub particle_lifetime_sec: f32,
     pub mesh: MeshRef,
     pub material: MaterialRef,
+
+    #[serde(default)]
+    pub sub_emitter: Option<SubEmitter>,
+    /// Box position and rotation are relative to the parent
+    #[serde(default)]
+    pub parent: Option<EmitterParent>,
+    /// Composed every update from the parent chain
+    #[serde(skip)]
+    pub parent_world: Mat4,
+
+    #[serde(default)]
+    pub simulation_space: SimulationSpace,
+    /// Fraction of the emitter velocity added to spawned particles
+    #[serde(default)]
+    pub inherit_velocity: f32,
+    #[serde(skip)]
+    prev_world: Option<Mat4>,
+    /// Transform from last frame's world matrix to the current one, identity in world space
+    #[serde(skip)]
+    space_delta: Mat4,
+    #[serde(skip)]
+    velocity: Vec3,
+    /// Animated spawn count, can't exceed the spawn count the particle buffers were created with
+    #[serde(skip)]
+    spawn_limit: Option<u32>,
+
+    /// Light emitters that light this emitter, unused by light emitters
+    #[serde(default)]
+    pub lights: LightSelection,
+    /// Selected light sets of the shared light buffer, see LightsCtx
+    #[serde(skip)]
+    pub light_mask: u32,
+    /// Distance at which a light particle stops lighting, only used by light emitters
+    #[serde(default = "EmitterUniform::default_light_range")]
+    pub light_range: f32,
+
+    /// Named values game code can set, see EmitterState::set_param
+    #[serde(default)]
+    pub exposed_params: Vec<ExposedParam>,
+
+    /// Fields of newer versions, exported as they were
+    #[serde(flatten)]
+    pub unknown_fields: serde_json::Map<String, serde_json::Value>,
 }
 
 pub struct EmitterSettings {
@@ -99,15 +213,115 @@ pub struct EmitterSettings {
 
     pub particle_color: Vec4,
     pub hdr_mul: f32,
+
+    pub sub_emitter: Option<SubEmitter>,
+
+    pub simulation_space: SimulationSpace,
+    pub inherit_velocity: f32,
+
+    pub light_range: f32,
 }
 
 impl EmitterUniform {
+    /// Numeric properties that can be animated by name
+    pub const PROPERTIES: [&'static str; 25] = [
+        "spawn_count",
+        "box_position.x",
+        "box_position.y",
+        "box_position.z",
+        "box_dimensions.x",
+        "box_dimensions.y",
+        "box_dimensions.z",
+        "box_rotation.yaw",
+        "box_rotation.pitch",
+        "box_rotation.roll",
+        "diff_width",
+        "diff_depth",
+        "hdr_mul",
+        "particle_color.r",
+        "particle_color.g",
+        "particle_color.b",
+        "particle_color.a",
+        "particle_friction_coefficient",
+        "particle_speed.min",
+        "particle_speed.max",
+        "particle_size.min",
+        "particle_size.max",
+        "particle_material_mass",
+        "inherit_velocity",
+        "light_range",
+    ];
+
+    pub fn get_property(&self, name: &str) -> Option<f32> {
+        let value = match name {
+            "spawn_count" => self.spawn_limit.unwrap_or(self.spawn_count) as f32,
+            "box_position.x" => self.box_position.x,
+            "box_position.y" => self.box_position.y,
+            "box_position.z" => self.box_position.z,
+            "box_dimensions.x" => self.box_dimensions.x,
+            "box_dimensions.y" => self.box_dimensions.y,
+            "box_dimensions.z" => self.box_dimensions.z,
+            "box_rotation.yaw" => self.box_rotation.x,
+            "box_rotation.pitch" => self.box_rotation.y,
+            "box_rotation.roll" => self.box_rotation.z,
+            "diff_width" => self.diff_width,
+            "diff_depth" => self.diff_depth,
+            "hdr_mul" => self.hdr_mul,
+            "particle_color.r" => self.particle_color.x,
+            "particle_color.g" => self.particle_color.y,
+            "particle_color.b" => self.particle_color.z,
+            "particle_color.a" => self.particle_color.w,
+            "particle_friction_coefficient" => self.particle_friction_coefficient,
+            "particle_speed.min" => self.particle_speed.0,
+            "particle_speed.max" => self.particle_speed.1,
+            "particle_size.min" => self.particle_size.0,
+            "particle_size.max" => self.particle_size.1,
+            "particle_material_mass" => self.particle_material_mass,
+            "inherit_velocity" => self.inherit_velocity,
+            "light_range" => self.light_range,
+            _ => return None,
+        };
+
+        Some(value)
+    }
+
+    /// Returns false if the property doesn't exist
+    pub fn set_property(&mut self, name: &str, value: f32) -> bool {
+        match name {
+            "spawn_count" => self.spawn_limit = Some(value.round().max(0.) as u32),
+            "box_position.x" => self.box_position.x = value,
+            "box_position.y" => self.box_position.y = value,
+            "box_position.z" => self.box_position.z = value,
+            "box_dimensions.x" => self.box_dimensions.x = value,
+            "box_dimensions.y" => self.box_dimensions.y = value,
+            "box_dimensions.z" => self.box_dimensions.z = value,
+            "box_rotation.yaw" => self.box_rotation.x = value,
+            "box_rotation.pitch" => self.box_rotation.y = value,
+            "box_rotation.roll" => self.box_rotation.z = value,
+            "diff_width" => self.diff_width = value,
+            "diff_depth" => self.diff_depth = value,
+            "hdr_mul" => self.hdr_mul = value,
+            "particle_color.r" => self.particle_color.x = value,
+            "particle_color.g" => self.particle_color.y = value,
+            "particle_color.b" => self.particle_color.z = value,
+            "particle_color.a" => self.particle_color.w = value,
+            "particle_friction_coefficient" => self.particle_friction_coefficient = value,
+            "particle_speed.min" => self.particle_speed.0 = value,
+            "particle_speed.max" => self.particle_speed.1 = value,
+            "particle_size.min" => self.particle_size.0 = value,
+            "particle_size.max" => self.particle_size.1 = value,
+            "particle_material_mass" => self.particle_material_mass = value,
+            "inherit_velocity" => self.inherit_velocity = value,
+            "light_range" => self.light_range = value.max(0.),
+            _ => return false,
+        }
+
+        true
+    }
+
     pub fn new(id: ID) -> Self {
         let spawn_count: u32 = 6;
         let particle_lifetime_sec: f32 = 6.;
-        let spawn_delay_sec: f32 = 0.5;
-
-        let spawn_batches_count = (particle_lifetime_sec / spawn_delay_sec).ceil() as u32;
 
         let box_pos = Vec3::ZERO;
         let box_dimensions = [1., 0.5, 1.].into();
@@ -119,10 +333,10 @@ impl EmitterUniform {
         Self {
             id,
             spawn_delay_sec: 0.5,
+            spawn_batches_count: None,
             spawn_from: 0,
             spawn_until: 0,
             spawn_count,
-            spawn_batches_count,
 
             box_position: box_pos,
             box_dimensions,
@@ -140,7 +354,7 @@ impl EmitterUniform {
             particle_friction_coefficient: 0.99,
             particle_color: Vec4::from_rgb(0, 255, 0),
 
-            iteration: 1000,
+            iteration: Self::default_iteration(),
             elapsed_sec: 0.,
             delta_sec: 0.0,
 
@@ -152,9 +366,45 @@ impl EmitterUniform {
                 collection_id: BUILTIN_ID.to_string(),
                 mesh_id: CIRCLE_MESH_ID.to_string(),
             },
+
+            sub_emitter: None,
+            parent: None,
+            parent_world: Mat4::IDENTITY,
+
+            simulation_space: SimulationSpace::World,
+            inherit_velocity: 0.,
+            prev_world: None,
+            space_delta: Mat4::IDENTITY,
+            velocity: Vec3::ZERO,
+            spawn_limit: None,
+
+            lights: LightSelection::All,
+            light_mask: 0,
+            light_range: Self::default_light_range(),
+            exposed_params: vec![],
+            unknown_fields: serde_json::Map::new(),
         }
     }
 
+    fn default_light_range() -> f32 {
+        20.
+    }
+
+    fn default_iteration() -> u32 {
+        1000
+    }
+
+    /// Batches of particles that are alive at the same time
+    fn spawn_batches_count(&self) -> u32 {
+        self.spawn_batches_count.unwrap_or_else(|| {
+            Self::derived_batches_count(self.particle_lifetime_sec, self.spawn_delay_sec)
+        })
+    }
+
+    pub(crate) fn derived_batches_count(particle_lifetime_sec: f32, spawn_delay_sec: f32) -> u32 {
+        (particle_lifetime_sec / spawn_delay_sec).ceil() as u32
+    }
+
     pub fn update_settings(&mut self, settings: &EmitterSettings) {
         self.box_rotation = settings.box_rotation_deg.to_radians();
         self.box_dimensions = settings.box_dimensions;
@@ -175,8 +425,20 @@ impl EmitterUniform {
         // TODO iets beter dan string kopieren
         self.mesh = settings.mesh.clone();
         self.material = settings.material.clone();
+        self.sub_emitter = settings.sub_emitter.clone();
+
+        self.simulation_space = settings.simulation_space;
+        self.inherit_velocity = settings.inherit_velocity;
+        self.light_range = settings.light_range;
 
         if settings.recreate {
+            let is_timing_changed = self.spawn_delay_sec != settings.spawn_delay_sec
+                || self.particle_lifetime_sec != settings.particle_lifetime_sec;
+
+            if is_timing_changed {
+                self.spawn_batches_count = None;
+            }
+
             self.spawn_count = settings.spawn_count;
             self.spawn_delay_sec = settings.spawn_delay_sec;
             self.particle_lifetime_sec = settings.particle_lifetime_sec;
@@ -202,6 +464,10 @@ impl EmitterUniform {
             particle_size_max: self.particle_size.1,
             mesh: self.mesh.clone(),
             material: self.material.clone(),
+            sub_emitter: self.sub_emitter.clone(),
+            simulation_space: self.simulation_space,
+            inherit_velocity: self.inherit_velocity,
+            light_range: self.light_range,
 
             recreate: false,
         }
@@ -212,7 +478,7 @@ impl EmitterUniform {
         self.elapsed_sec = clock.elapsed_sec();
 
         let new_iteration = (self.elapsed_sec / self.spawn_delay_sec) as u32;
-        let current_batch = new_iteration % self.spawn_batches_count;
+        let current_batch = new_iteration % self.spawn_batches_count();
 
         if new_iteration != self.iteration {
             self.spawn_from = current_batch * self.spawn_count;
@@ -225,57 +491,119 @@ impl EmitterUniform {
         }
     }
 
+    /// Spawns on the next update, regardless of the current spawn iteration
+    pub fn restart(&mut self) {
+        self.iteration = u32::MAX;
+    }
+
+    /// Used by emitters that are spawned through a parent sub emitter
+    pub fn disable_spawn(&mut self) {
+        self.spawn_from = 0;
+        self.spawn_until = 0;
+    }
+
+    pub fn local_matrix(&self) -> Mat4 {
+        transform::local_matrix(self.box_position, self.box_rotation)
+    }
+
+    pub fn world_matrix(&self) -> Mat4 {
+        self.parent_world * self.local_matrix()
+    }
+
+    /// Tracks the emitter motion since last frame, call after the world matrices are composed
+    pub fn update_motion(&mut self) {
+        let world = self.world_matrix();
+        let prev_world = self.prev_world.unwrap_or(world);
+
+        self.velocity = if 0. < self.delta_sec {
+            (world.w_axis - prev_world.w_axis).truncate() / self.delta_sec
+        } else {
+            Vec3::ZERO
+        };
+
+        self.space_delta = match self.simulation_space {
+            SimulationSpace::World => Mat4::IDENTITY,
+            SimulationSpace::Local => world * prev_world.inverse(),
+        };
+
+        self.prev_world = Some(world);
+    }
+
+    pub fn velocity(&self) -> Vec3 {
+        self.velocity
+    }
+
     pub fn particle_count(&self) -> u64 {
-        self.spawn_count as u64 * self.spawn_batches_count as u64
+        self.spawn_count as u64 * self.spawn_batches_count() as u64
     }
 
     pub fn particle_buffer_size(&self) -> u64 {
-        self.particle_count() * PARTICLE_BUFFER_SIZE
+        self.particle_count() * particle_stride()
+    }
+
+    /// Every particle starts as decayed so nothing is rendered or triggered before it's spawned
+    pub fn particle_buffer_content(&self) -> Vec<u8> {
+        let unspawned = ParticleGpu {
+            model: Mat4::ZERO,
+            color: Vec4::ZERO,
+            vel_mass: Vec4::ZERO,
+            scale: 0.,
+            lifetime: UNSPAWNED_LIFETIME,
+        };
+
+        let particles = vec![unspawned; self.particle_count() as usize];
+        let mut buffer = encase::StorageBuffer::new(Vec::new());
+        buffer.write(&particles).unwrap();
+        buffer.into_inner()
     }
 
     pub async fn create_buffer_content(
         &self,
         collection: &Arc<RwLock<HashMap<String, Model>>>,
-    ) -> Vec<f32> {
+    ) -> Vec<u8> {
         let collection = &collection.read().await;
         let mesh = collection.get_mesh(&self.mesh);
-        let particle_model = mesh.model.to_cols_array();
-
-        [
-            &[
-                self.delta_sec,
-                self.elapsed_sec,
-                self.spawn_from as f32,
-                self.spawn_until as f32,
-                self.box_position.x,
-                self.box_position.y,
-                self.box_position.z,
-                self.box_dimensions.x,
-                self.box_dimensions.y,
-                self.box_dimensions.z,
-                self.box_rotation.x,
-                self.box_rotation.y,
-                self.box_rotation.z,
-                self.diff_width,
-                self.diff_depth,
-                0., // padding
-            ],
-            particle_model.as_slice(),
-            &[
-                self.particle_color.x * self.hdr_mul,
-                self.particle_color.y * self.hdr_mul,
-                self.particle_color.z * self.hdr_mul,
-                self.particle_color.w,
-                self.particle_speed.0,
-                self.particle_speed.1,
-                self.particle_size.0,
-                self.particle_size.1,
-                self.particle_friction_coefficient,
-                self.particle_material_mass,
-                self.particle_lifetime_sec,
-                0., // padding
-            ],
-        ]
-        .concat()
+
+        let spawn_until = match self.spawn_limit {
+            Some(limit) => self.spawn_until.min(self.spawn_from + limit),
+            None => self.spawn_until,
+        };
+
+        EmitterGpu {
+            delta_sec: self.delta_sec,
+            elapsed_sec: self.elapsed_sec,
+            spawn_from: self.spawn_from as f32,
+            spawn_until: spawn_until as f32,
+            box_x: self.box_position.x,
+            box_y: self.box_position.y,
+            box_z: self.box_position.z,
+            box_width: self.box_dimensions.x,
+            box_height: self.box_dimensions.y,
+            box_depth: self.box_dimensions.z,
+            box_yaw: self.box_rotation.x,
+            box_pitch: self.box_rotation.y,
+            box_roll: self.box_rotation.z,
+            diffusion_width: self.diff_width,
+            diffusion_depth: self.diff_depth,
+            light_mask: self.light_mask,
+            particle_model: mesh.model,
+            particle_color_r: self.particle_color.x * self.hdr_mul,
+            particle_color_g: self.particle_color.y * self.hdr_mul,
+            particle_color_b: self.particle_color.z * self.hdr_mul,
+            particle_color_a: self.particle_color.w,
+            particle_speed_min: self.particle_speed.0,
+            particle_speed_max: self.particle_speed.1,
+            particle_size_min: self.particle_size.0,
+            particle_size_max: self.particle_size.1,
+            particle_friction_coefficient: self.particle_friction_coefficient,
+            material_mass: self.particle_material_mass,
+            particle_lifetime: self.particle_lifetime_sec,
+            padding_2: 0.,
+            parent_world: self.parent_world,
+            space_delta: self.space_delta,
+            velocity: self.velocity,
+            inherit_velocity: self.inherit_velocity,
+        }
+        .buffer_content()
     }
 }
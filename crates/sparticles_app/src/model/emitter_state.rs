@@ -1,12 +1,15 @@
 use super::gfx_state::Profiler;
 use super::state::FastFetch;
-use super::{Camera, EmitterUniform, GfxState, Material, Mesh, ModelVertex, SparEvents, SparState};
+use super::{
+    Camera, Clock, EffectTransform, EmitterUniform, GfxState, Material, Mesh, ModelVertex,
+    SparEvents, SparState, SubEmitterCtx,
+};
 use crate::fx::PostProcessState;
 use crate::loader::{Model, BUILTIN_ID};
-use crate::shaders::{ShaderOptions, SDR_PBR, SDR_TONEMAPPING};
+use crate::shaders::ShaderOptions;
 use crate::traits::{EmitterAnimation, ParticleAnimation};
-use crate::util::persistence::{ExportEmitter, ExportType};
-use crate::util::{ListAction, Persistence, ID};
+use crate::util::persistence::ExportEmitter;
+use crate::util::{with_unrecognized, DynamicExport, ListAction, Unrecognized, ID};
 use async_std::sync::RwLock;
 use egui_wgpu::wgpu::{self, ShaderModule};
 use std::fmt::Display;
@@ -28,12 +31,21 @@ pub struct EmitterState {
 
     pub particle_animations: Vec<Box<dyn ParticleAnimation>>,
     pub emitter_animations: Vec<Box<dyn EmitterAnimation>>,
+    pub sub_emitter: Option<SubEmitterCtx>,
+    /// Local timeline, animations are evaluated against it
+    pub clock: Clock,
     pub shader: ShaderModule,
     pub uniform: EmitterUniform,
     pub dispatch_x_count: u32,
     pub bgs: Vec<wgpu::BindGroup>,
     pub bg_layout: wgpu::BindGroupLayout,
     pub is_light: bool,
+    /// Set for emitters of a spawned effect instance, replaces the effect root
+    pub instance_root: Option<EffectTransform>,
+    /// Imported animations that aren't registered or can't be read, exported as they were at
+    /// their position in the imported list
+    pub unrecognized_particle_animations: Vec<Unrecognized<DynamicExport>>,
+    pub unrecognized_emitter_animations: Vec<Unrecognized<DynamicExport>>,
 }
 
 pub enum EmitterType<'a> {
@@ -83,6 +95,10 @@ impl EmitterState {
         let SparState {
             clock,
             emitters,
+            effect_root,
+            lights,
+            scene_lighting,
+            environment,
             gfx,
             camera,
             collection,
@@ -97,18 +113,38 @@ impl EmitterState {
                 uniform: EmitterUniform::new(id.to_string()),
                 collection,
                 emitter_type: EmitterType::Normal {
-                    lights_layout: &emitters[0].bg_layout,
+                    lights_layout: &lights.bg_layout,
                 },
                 gfx,
             };
 
+            emitters.push(Self::new(options).await);
+        } else if let Some(id) = &events.create_light_emitter {
+            let options = CreateEmitterOptions {
+                camera,
+                uniform: EmitterUniform::new(id.to_string()),
+                collection,
+                emitter_type: EmitterType::Lights,
+                gfx,
+            };
+
             emitters.push(Self::new(options).await);
         }
 
+        SubEmitterCtx::link_all(emitters, &*gfx.read().await);
+
+        let child_ids = SubEmitterCtx::child_ids(emitters);
         let mut update_mesh = false;
 
         for emitter in emitters.iter_mut() {
-            emitter.uniform.update(clock);
+            emitter.clock.sync(clock);
+            emitter.uniform.update(&emitter.clock);
+
+            let is_child = child_ids.iter().any(|id| id == emitter.id());
+
+            if is_child || !emitter.clock.is_running() {
+                emitter.uniform.disable_spawn();
+            }
 
             ListAction::update_list(&mut emitter.emitter_animations);
 
@@ -121,20 +157,33 @@ impl EmitterState {
                 .iter_mut()
                 .filter(|item| item.enabled())
             {
-                anim.animate(&mut emitter.uniform, clock);
+                anim.animate(&mut emitter.uniform, &emitter.clock);
             }
+        }
+
+        // Parents need to be animated before their world matrix is passed on
+        effect_root.update_world_matrices(emitters);
+        lights.update(
+            emitters,
+            camera,
+            scene_lighting,
+            environment,
+            &*gfx.read().await,
+        );
+
+        for emitter in emitters.iter_mut() {
+            emitter.uniform.update_motion();
 
-            let buffer_content_raw = emitter.uniform.create_buffer_content(collection).await;
-            let buffer_content = bytemuck::cast_slice(&buffer_content_raw);
+            let buffer_content = emitter.uniform.create_buffer_content(collection).await;
 
             let gfx = &gfx.read().await;
             gfx.queue
-                .write_buffer(&emitter.emitter_buffer, 0, buffer_content);
+                .write_buffer(&emitter.emitter_buffer, 0, &buffer_content);
 
             ListAction::update_list(&mut emitter.particle_animations);
 
             for anim in emitter.particle_animations.iter_mut() {
-                anim.update(clock, gfx);
+                anim.update(&emitter.clock, gfx);
             }
         }
 
@@ -152,6 +201,7 @@ impl EmitterState {
         let SparState {
             clock,
             emitters,
+            lights,
             gfx,
             ..
         } = state;
@@ -179,15 +229,27 @@ impl EmitterState {
                 .iter()
                 .filter(|item| item.enabled())
             {
-                anim.compute(emitter, clock, &mut c_pass);
+                anim.compute(emitter, &emitter.clock, &mut c_pass);
             }
             Profiler::end_scope(gfx, &mut c_pass).await;
         }
 
+        Profiler::begin_scope(gfx, "Compute sub emitters", &mut c_pass).await;
+        SubEmitterCtx::compute(emitters, clock, &mut c_pass);
+        Profiler::end_scope(gfx, &mut c_pass).await;
+
         Profiler::end_scope(gfx, &mut c_pass).await;
+        drop(c_pass);
+
+        lights.cull_lights(emitters, clock, encoder);
     }
 
-    pub async fn render_particles(state: &mut SparState, encoder: &mut wgpu::CommandEncoder) {
+    /// Depth is loaded when it holds the depth of a host engine
+    pub async fn render_particles(
+        state: &mut SparState,
+        encoder: &mut wgpu::CommandEncoder,
+        depth_load: wgpu::LoadOp<f32>,
+    ) {
         let pp = &state.post_process;
         let collection = &state.collection.read().await;
 
@@ -214,7 +276,7 @@ impl EmitterState {
             depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
                 view: pp.depth_view(),
                 depth_ops: Some(wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(1.0),
+                    load: depth_load,
                     store: wgpu::StoreOp::Store,
                 }),
                 stencil_ops: None,
@@ -225,6 +287,7 @@ impl EmitterState {
 
         let clock = &state.clock;
         let emitters = &state.emitters;
+        let lights = &state.lights;
         let camera = &state.camera;
         let gfx = &state.gfx;
 
@@ -232,6 +295,11 @@ impl EmitterState {
 
         Profiler::begin_scope(gfx, "Render", &mut r_pass).await;
 
+        state.environment.render_background(camera, &mut r_pass);
+        state
+            .static_scene
+            .render(camera, lights, collection, &mut r_pass);
+
         for em in emitters.iter() {
             let mesh = collection.get_mesh(&em.uniform.mesh);
             let mat = collection.get_mat(&em.uniform.material);
@@ -248,7 +316,7 @@ impl EmitterState {
             r_pass.set_bind_group(2, &em.bgs[nr], &[]);
 
             if !em.is_light {
-                r_pass.set_bind_group(3, &emitters[0].bgs[nr], &[]);
+                r_pass.set_bind_group(3, lights.bg(), &[]);
             }
 
             r_pass.draw_indexed(mesh.indices_range(), 0, 0..em.particle_count() as u32);
@@ -259,6 +327,50 @@ impl EmitterState {
         Profiler::end_scope(gfx, &mut r_pass).await;
     }
 
+    /// Recreates the emitters that match the filter, the particles that are alive are kept
+    pub async fn recreate_emitters(
+        state: &mut SparState,
+        encoder: &mut wgpu::CommandEncoder,
+        filter: impl Fn(&EmitterState) -> bool,
+    ) {
+        let SparState {
+            emitters,
+            camera,
+            lights,
+            gfx,
+            collection,
+            ..
+        } = state;
+
+        for em in emitters.iter_mut().filter(|em| filter(em)) {
+            let emitter_type = if em.is_light {
+                EmitterType::Lights
+            } else {
+                EmitterType::Normal {
+                    lights_layout: &lights.bg_layout,
+                }
+            };
+
+            *em = Self::recreate_emitter(
+                RecreateEmitterOptions {
+                    old_self: em,
+                    gfx,
+                    camera,
+                    collection,
+                    emitter_type,
+                },
+                encoder,
+            )
+            .await;
+        }
+    }
+
+    /// True if the mesh or material is taken from the model
+    pub fn uses_model(&self, collection_id: &str) -> bool {
+        self.uniform.mesh.collection_id == collection_id
+            || self.uniform.material.collection_id == collection_id
+    }
+
     pub async fn recreate_emitter(
         options: RecreateEmitterOptions<'_>,
         encoder: &mut wgpu::CommandEncoder,
@@ -292,9 +404,32 @@ impl EmitterState {
             &mut old_self.emitter_animations,
         );
 
+        new_self.clock = old_self.clock.clone();
+        new_self.instance_root = old_self.instance_root;
+        new_self.unrecognized_particle_animations =
+            std::mem::take(&mut old_self.unrecognized_particle_animations);
+        new_self.unrecognized_emitter_animations =
+            std::mem::take(&mut old_self.unrecognized_emitter_animations);
+
         new_self
     }
 
+    /// Resumes the local timeline
+    pub fn start(&mut self) {
+        self.clock.start();
+    }
+
+    /// Pauses the local timeline and stops spawning, spawned particles keep simulating
+    pub fn stop(&mut self) {
+        self.clock.stop();
+    }
+
+    /// Plays the emitter and its animations from the beginning
+    pub fn restart(&mut self) {
+        self.clock.restart();
+        self.uniform.restart();
+    }
+
     pub fn push_particle_animation(&mut self, animation: Box<dyn ParticleAnimation>) {
         self.particle_animations.push(animation);
     }
@@ -303,31 +438,47 @@ impl EmitterState {
         self.emitter_animations.push(animation);
     }
 
+    pub fn particle_buffer(&self, nr: usize) -> &wgpu::Buffer {
+        &self.particle_buffers[nr]
+    }
+
     pub fn particle_count(&self) -> u64 {
         self.uniform.particle_count()
     }
 
-    pub fn export(emitters: &[EmitterState]) {
+    pub fn export(emitters: &[EmitterState]) -> Vec<ExportEmitter> {
         let mut to_export = Vec::new();
 
-        for emitter in emitters.iter() {
+        // Spawned effect instances are owned by the effect library
+        for emitter in emitters.iter().filter(|em| em.instance_root.is_none()) {
+            let particle_animations = emitter
+                .particle_animations
+                .iter()
+                .map(|anim| anim.export())
+                .collect();
+
+            let emitter_animations = emitter
+                .emitter_animations
+                .iter()
+                .map(|anim| anim.export())
+                .collect();
+
             to_export.push(ExportEmitter {
-                particle_animations: emitter
-                    .particle_animations
-                    .iter()
-                    .map(|anim| anim.export())
-                    .collect(),
+                particle_animations: with_unrecognized(
+                    particle_animations,
+                    &emitter.unrecognized_particle_animations,
+                ),
                 emitter: emitter.uniform.clone(),
                 is_light: emitter.is_light,
-                emitter_animations: emitter
-                    .emitter_animations
-                    .iter()
-                    .map(|anim| anim.export())
-                    .collect(),
+                emitter_animations: with_unrecognized(
+                    emitter_animations,
+                    &emitter.unrecognized_emitter_animations,
+                ),
+                document_index: None,
             });
         }
 
-        Persistence::write_to_file(to_export, ExportType::EmitterStates);
+        to_export
     }
 
     pub async fn new(options: CreateEmitterOptions<'_>) -> Self {
@@ -336,51 +487,28 @@ impl EmitterState {
         let gfx = options.gfx;
         let collection = options.collection;
 
-        {
-            let mut collection = collection.write().await;
-
-            let mesh_key = &uniform.mesh.collection_id;
-            let mat_key = &uniform.material.collection_id;
-
-            if !collection.contains_key(mesh_key) {
-                collection.insert(
-                    mesh_key.to_string(),
-                    Model::load_gltf(gfx, mesh_key)
-                        .await
-                        .expect("Can't load model"),
-                );
-            }
-
-            if !collection.contains_key(mat_key) {
-                collection.insert(
-                    mat_key.to_string(),
-                    Model::load_gltf(gfx, mat_key)
-                        .await
-                        .expect("Can't load model"),
-                );
-            }
-        }
-
         let emitter_buf_content = uniform.create_buffer_content(collection).await;
+        let particle_buf_content = uniform.particle_buffer_content();
 
         let mut particle_buffers = Vec::<wgpu::Buffer>::new();
         let mut bind_groups = Vec::<wgpu::BindGroup>::new();
 
         for i in 0..2 {
             let device = &gfx.read().await.device;
-            particle_buffers.push(device.create_buffer(&wgpu::BufferDescriptor {
-                label: Some(&format!("Particle Buffer {}", i)),
-                mapped_at_creation: false,
-                size: uniform.particle_buffer_size(),
-                usage: wgpu::BufferUsages::VERTEX
-                    | wgpu::BufferUsages::STORAGE
-                    | wgpu::BufferUsages::COPY_SRC
-                    | wgpu::BufferUsages::COPY_DST,
-            }));
+            particle_buffers.push(
+                device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some(&format!("Particle Buffer {}", i)),
+                    contents: &particle_buf_content,
+                    usage: wgpu::BufferUsages::VERTEX
+                        | wgpu::BufferUsages::STORAGE
+                        | wgpu::BufferUsages::COPY_SRC
+                        | wgpu::BufferUsages::COPY_DST,
+                }),
+            );
         }
 
         let particle_buffer_size = NonZeroU64::new(particle_buffers[0].size());
-        let emitter_buffer_size = NonZeroU64::new(emitter_buf_content.len() as u64 * 4);
+        let emitter_buffer_size = NonZeroU64::new(emitter_buf_content.len() as u64);
 
         let visibility = match &options.emitter_type {
             EmitterType::Lights => {
@@ -436,7 +564,7 @@ impl EmitterState {
 
         let emitter_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Emitters buffer"),
-            contents: bytemuck::cast_slice(&emitter_buf_content),
+            contents: &emitter_buf_content,
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
 
@@ -495,7 +623,7 @@ impl EmitterState {
         match &options.emitter_type {
             EmitterType::Lights => {
                 shader = gfx.create_shader_builtin(ShaderOptions {
-                    files: &[SDR_TONEMAPPING, SDR_PBR, "light_particle.wgsl"],
+                    files: &["light_particle.wgsl"],
                     if_directives: &[],
                     label: "Light particle render",
                 });
@@ -509,7 +637,7 @@ impl EmitterState {
             }
             EmitterType::Normal { lights_layout } => {
                 shader = gfx.create_shader_builtin(ShaderOptions {
-                    files: &[SDR_TONEMAPPING, SDR_PBR, "particle.wgsl"],
+                    files: &["particle.wgsl"],
                     if_directives: &[],
                     label: "Particle render",
                 });
@@ -560,8 +688,13 @@ impl EmitterState {
             dispatch_x_count,
             particle_animations: vec![],
             emitter_animations: vec![],
+            sub_emitter: None,
+            clock: Clock::default(),
             shader,
             is_light,
+            instance_root: None,
+            unrecognized_particle_animations: vec![],
+            unrecognized_emitter_animations: vec![],
         }
     }
 
@@ -0,0 +1,690 @@
+use super::{Camera, GfxState, SparState};
+use crate::asset_manager::{AssetKind, AssetManager};
+use crate::fx::PostProcessState;
+use crate::shaders::{layout::gpu_struct, ShaderOptions};
+use crate::traits::{BufferContent, CreateFxView};
+use egui_wgpu::wgpu::{self, util::DeviceExt};
+use encase::ShaderType;
+use glam::{Mat4, Vec4};
+use serde::{Deserialize, Serialize};
+use std::num::NonZeroU64;
+use std::path::PathBuf;
+
+const CUBE_SIZE: u32 = 512;
+const IRRADIANCE_SIZE: u32 = 32;
+const SPECULAR_SIZE: u32 = 128;
+const SPECULAR_MIP_COUNT: u32 = 5;
+const SPECULAR_SAMPLE_COUNT: u32 = 512;
+const BRDF_LUT_SIZE: u32 = 256;
+const WORKGROUP_SIZE: u32 = 8;
+const TEXTURE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// Equirectangular .hdr or .exr file that lights and surrounds the scene
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EnvironmentMap {
+    /// Filename in the environments asset folder, no environment lighting if none
+    pub file: Option<String>,
+    pub intensity: f32,
+    /// Draws the environment behind the particles instead of a black background
+    pub is_background: bool,
+}
+
+impl Default for EnvironmentMap {
+    fn default() -> Self {
+        Self {
+            file: None,
+            intensity: 1.,
+            is_background: true,
+        }
+    }
+}
+
+gpu_struct! {
+    pub(crate) struct EnvironmentUniform as "Environment" {
+        inv_view_proj: Mat4,
+        intensity: f32,
+        specular_max_mip: f32,
+    }
+}
+
+gpu_struct! {
+    pub(crate) struct EnvBakeUniform as "EnvBake" {
+        roughness: f32,
+        sample_count: u32,
+    }
+}
+
+/// Prefiltered environment maps for split sum image based lighting.
+/// The equirectangular image is converted to a cubemap on the GPU, which is convolved into
+/// an irradiance map (diffuse) and a mip chain by roughness (specular).
+pub struct EnvironmentCtx {
+    pub irradiance_view: wgpu::TextureView,
+    pub specular_view: wgpu::TextureView,
+    pub brdf_lut_view: wgpu::TextureView,
+    pub sampler: wgpu::Sampler,
+    pub buffer: wgpu::Buffer,
+    env_view: wgpu::TextureView,
+    specular_max_mip: f32,
+    background_bg_layout: wgpu::BindGroupLayout,
+    background_bg: wgpu::BindGroup,
+    background_pipeline: wgpu::RenderPipeline,
+    loaded_file: Option<String>,
+    is_background: bool,
+    /// Increases when the maps are replaced, bind groups using them need to be recreated
+    generation: u64,
+}
+
+impl EnvironmentCtx {
+    /// Environment files (.hdr and .exr) in the environments folder of every asset root
+    pub fn files() -> Vec<PathBuf> {
+        AssetManager::list(AssetKind::Environment, &["hdr", "exr"])
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub async fn update(state: &mut SparState) {
+        let SparState {
+            environment,
+            scene_lighting,
+            camera,
+            gfx,
+            ..
+        } = state;
+
+        let gfx = &gfx.read().await;
+        let settings = &scene_lighting.environment;
+
+        if settings.file != environment.loaded_file {
+            let is_loaded = match &settings.file {
+                Some(file) => match environment.load(gfx, file) {
+                    Ok(()) => true,
+                    Err(err) => {
+                        println!("Can't load environment {}: {}", file, err);
+                        false
+                    }
+                },
+                None => false,
+            };
+
+            if !is_loaded {
+                environment.clear(gfx);
+            }
+
+            environment.loaded_file = settings.file.clone();
+        }
+
+        environment.is_background = settings.is_background && 0. < environment.specular_max_mip;
+
+        let mut view = camera.view_mat();
+        view.w_axis = Vec4::W;
+
+        let uniform = EnvironmentUniform {
+            inv_view_proj: (camera.proj() * view).inverse(),
+            intensity: settings.intensity,
+            specular_max_mip: environment.specular_max_mip,
+        };
+
+        gfx.queue
+            .write_buffer(&environment.buffer, 0, &uniform.buffer_content());
+    }
+
+    /// Draws the environment on the far plane, call before drawing the particles
+    pub fn render_background<'a>(&'a self, camera: &'a Camera, r_pass: &mut wgpu::RenderPass<'a>) {
+        if !self.is_background {
+            return;
+        }
+
+        r_pass.set_pipeline(&self.background_pipeline);
+        r_pass.set_bind_group(0, camera.bg(), &[]);
+        r_pass.set_bind_group(1, &self.background_bg, &[]);
+        r_pass.draw(0..3, 0..1);
+    }
+
+    pub fn new(gfx: &GfxState, camera: &Camera) -> Self {
+        let device = &gfx.device;
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Environment sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Environment buffer"),
+            size: EnvironmentUniform::min_size().get(),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let background_bg_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Environment background layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::Cube,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: NonZeroU64::new(EnvironmentUniform::min_size().get()),
+                        },
+                        count: None,
+                    },
+                ],
+            });
+
+        let background_pipeline =
+            Self::create_background_pipeline(gfx, camera, &background_bg_layout);
+
+        let env_view = Self::black_cube_view(gfx, "Environment cubemap");
+        let background_bg =
+            Self::create_background_bg(gfx, &background_bg_layout, &env_view, &sampler, &buffer);
+
+        Self {
+            irradiance_view: Self::black_cube_view(gfx, "Irradiance cubemap"),
+            specular_view: Self::black_cube_view(gfx, "Specular cubemap"),
+            brdf_lut_view: Self::bake_brdf_lut(gfx),
+            sampler,
+            buffer,
+            env_view,
+            specular_max_mip: 0.,
+            background_bg_layout,
+            background_bg,
+            background_pipeline,
+            loaded_file: None,
+            is_background: false,
+            generation: 0,
+        }
+    }
+
+    /// Used after env_background.wgsl changed
+    pub fn recreate_background_pipeline(&mut self, gfx: &GfxState, camera: &Camera) {
+        self.background_pipeline =
+            Self::create_background_pipeline(gfx, camera, &self.background_bg_layout);
+    }
+
+    /// The maps are baked again with the next update, used after env_bake.wgsl changed
+    pub fn rebake(&mut self) {
+        self.loaded_file = None;
+    }
+
+    /// Used after brdf_lut.wgsl changed
+    pub fn rebake_brdf_lut(&mut self, gfx: &GfxState) {
+        self.brdf_lut_view = Self::bake_brdf_lut(gfx);
+        self.generation += 1;
+    }
+
+    fn create_background_pipeline(
+        gfx: &GfxState,
+        camera: &Camera,
+        background_bg_layout: &wgpu::BindGroupLayout,
+    ) -> wgpu::RenderPipeline {
+        let device = &gfx.device;
+
+        let shader = gfx.create_shader_builtin(ShaderOptions {
+            files: &["env_background.wgsl"],
+            if_directives: &[],
+            label: "Environment background",
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Environment background pipeline layout"),
+            bind_group_layouts: &[&camera.bg_layout, background_bg_layout],
+            push_constant_ranges: &[],
+        });
+
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Environment background pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[
+                    Some(wgpu::ColorTargetState {
+                        format: PostProcessState::TEXTURE_FORMAT,
+                        blend: Some(wgpu::BlendState::REPLACE),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }),
+                    Some(wgpu::ColorTargetState {
+                        format: PostProcessState::TEXTURE_FORMAT,
+                        blend: Some(wgpu::BlendState::REPLACE),
+                        write_mask: wgpu::ColorWrites::COLOR,
+                    }),
+                ],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: GfxState::DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::Always,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        })
+    }
+
+    /// Replaces the maps with black maps, which disables environment lighting
+    fn clear(&mut self, gfx: &GfxState) {
+        self.env_view = Self::black_cube_view(gfx, "Environment cubemap");
+        self.irradiance_view = Self::black_cube_view(gfx, "Irradiance cubemap");
+        self.specular_view = Self::black_cube_view(gfx, "Specular cubemap");
+        self.specular_max_mip = 0.;
+        self.replace_maps(gfx);
+    }
+
+    fn load(&mut self, gfx: &GfxState, file: &str) -> anyhow::Result<()> {
+        let device = &gfx.device;
+
+        let data = AssetManager::read(AssetKind::Environment, file)?;
+        let image = image::load_from_memory(&data)?.into_rgba32f();
+        let (width, height) = image.dimensions();
+
+        let equirect_size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+
+        let equirect = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Environment equirect"),
+            size: equirect_size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba32Float,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+
+        gfx.queue.write_texture(
+            equirect.as_image_copy(),
+            bytemuck::cast_slice(image.as_raw()),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(16 * width),
+                rows_per_image: Some(height),
+            },
+            equirect_size,
+        );
+
+        let equirect_view = equirect.default_view();
+        let env = Self::create_cube(gfx, "Environment cubemap", CUBE_SIZE, 1);
+        let irradiance = Self::create_cube(gfx, "Irradiance cubemap", IRRADIANCE_SIZE, 1);
+        let specular =
+            Self::create_cube(gfx, "Specular cubemap", SPECULAR_SIZE, SPECULAR_MIP_COUNT);
+
+        let env_view = Self::cube_view(&env);
+
+        // Only sampled by the passes after the conversion
+        let placeholder_view = Self::black_cube_view(gfx, "Placeholder cubemap");
+
+        let bg_layout = Self::bake_bg_layout(device);
+
+        let shader = gfx.create_shader_builtin(ShaderOptions {
+            files: &["env_bake.wgsl"],
+            if_directives: &[],
+            label: "Environment bake",
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Environment bake layout"),
+            bind_group_layouts: &[&bg_layout],
+            push_constant_ranges: &[],
+        });
+
+        let create_pipeline = |entry_point| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Environment bake pipeline"),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point,
+            })
+        };
+
+        let cube_pipeline = create_pipeline("equirect_to_cube");
+        let irradiance_pipeline = create_pipeline("irradiance");
+        let prefilter_pipeline = create_pipeline("prefilter");
+
+        let create_bg = |sampled_cube: &wgpu::TextureView,
+                         output: &wgpu::Texture,
+                         mip_level: u32,
+                         roughness: f32| {
+            let output_view = output.create_view(&wgpu::TextureViewDescriptor {
+                dimension: Some(wgpu::TextureViewDimension::D2Array),
+                base_mip_level: mip_level,
+                mip_level_count: Some(1),
+                ..Default::default()
+            });
+
+            let bake_uniform = EnvBakeUniform {
+                roughness,
+                sample_count: SPECULAR_SAMPLE_COUNT,
+            };
+
+            let bake_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Environment bake buffer"),
+                contents: &bake_uniform.buffer_content(),
+                usage: wgpu::BufferUsages::UNIFORM,
+            });
+
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Environment bake bind group"),
+                layout: &bg_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&equirect_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(sampled_cube),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::Sampler(&self.sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: wgpu::BindingResource::TextureView(&output_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 4,
+                        resource: bake_buffer.as_entire_binding(),
+                    },
+                ],
+            })
+        };
+
+        let cube_bg = create_bg(&placeholder_view, &env, 0, 0.);
+        let irradiance_bg = create_bg(&env_view, &irradiance, 0, 0.);
+        let prefilter_bgs: Vec<(u32, wgpu::BindGroup)> = (0..SPECULAR_MIP_COUNT)
+            .map(|mip| {
+                let roughness = mip as f32 / (SPECULAR_MIP_COUNT - 1) as f32;
+                (
+                    SPECULAR_SIZE >> mip,
+                    create_bg(&env_view, &specular, mip, roughness),
+                )
+            })
+            .collect();
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Environment bake encoder"),
+        });
+
+        {
+            let mut c_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Environment bake"),
+                timestamp_writes: None,
+            });
+
+            let dispatch = |c_pass: &mut wgpu::ComputePass, size: u32| {
+                let count = size.div_ceil(WORKGROUP_SIZE);
+                c_pass.dispatch_workgroups(count, count, 6);
+            };
+
+            c_pass.set_pipeline(&cube_pipeline);
+            c_pass.set_bind_group(0, &cube_bg, &[]);
+            dispatch(&mut c_pass, CUBE_SIZE);
+
+            c_pass.set_pipeline(&irradiance_pipeline);
+            c_pass.set_bind_group(0, &irradiance_bg, &[]);
+            dispatch(&mut c_pass, IRRADIANCE_SIZE);
+
+            c_pass.set_pipeline(&prefilter_pipeline);
+
+            for (size, bg) in prefilter_bgs.iter() {
+                c_pass.set_bind_group(0, bg, &[]);
+                dispatch(&mut c_pass, *size);
+            }
+        }
+
+        gfx.queue.submit(Some(encoder.finish()));
+
+        self.env_view = env_view;
+        self.irradiance_view = Self::cube_view(&irradiance);
+        self.specular_view = Self::cube_view(&specular);
+        self.specular_max_mip = (SPECULAR_MIP_COUNT - 1) as f32;
+        self.replace_maps(gfx);
+
+        Ok(())
+    }
+
+    fn replace_maps(&mut self, gfx: &GfxState) {
+        self.background_bg = Self::create_background_bg(
+            gfx,
+            &self.background_bg_layout,
+            &self.env_view,
+            &self.sampler,
+            &self.buffer,
+        );
+
+        self.generation += 1;
+    }
+
+    /// Textures are zero initialized
+    fn black_cube_view(gfx: &GfxState, label: &str) -> wgpu::TextureView {
+        Self::cube_view(&Self::create_cube(gfx, label, 1, 1))
+    }
+
+    fn cube_view(texture: &wgpu::Texture) -> wgpu::TextureView {
+        texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::Cube),
+            ..Default::default()
+        })
+    }
+
+    fn create_cube(gfx: &GfxState, label: &str, size: u32, mip_level_count: u32) -> wgpu::Texture {
+        gfx.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: 6,
+            },
+            mip_level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: TEXTURE_FORMAT,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::STORAGE_BINDING,
+            view_formats: &[],
+        })
+    }
+
+    fn bake_bg_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        let visibility = wgpu::ShaderStages::COMPUTE;
+
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Environment bake layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::Cube,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                        format: TEXTURE_FORMAT,
+                        view_dimension: wgpu::TextureViewDimension::D2Array,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: NonZeroU64::new(EnvBakeUniform::min_size().get()),
+                    },
+                    count: None,
+                },
+            ],
+        })
+    }
+
+    /// Split sum lookup table, independent of the environment so it's baked once
+    fn bake_brdf_lut(gfx: &GfxState) -> wgpu::TextureView {
+        let device = &gfx.device;
+
+        let lut = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("BRDF lookup table"),
+            size: wgpu::Extent3d {
+                width: BRDF_LUT_SIZE,
+                height: BRDF_LUT_SIZE,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: TEXTURE_FORMAT,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::STORAGE_BINDING,
+            view_formats: &[],
+        });
+
+        let lut_view = lut.default_view();
+
+        let bg_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("BRDF lookup table layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::StorageTexture {
+                    access: wgpu::StorageTextureAccess::WriteOnly,
+                    format: TEXTURE_FORMAT,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                },
+                count: None,
+            }],
+        });
+
+        let bg = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("BRDF lookup table bind group"),
+            layout: &bg_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&lut_view),
+            }],
+        });
+
+        let shader = gfx.create_shader_builtin(ShaderOptions {
+            files: &["brdf_lut.wgsl"],
+            if_directives: &[],
+            label: "BRDF lookup table",
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("BRDF lookup table pipeline layout"),
+            bind_group_layouts: &[&bg_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("BRDF lookup table pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: "main",
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("BRDF lookup table encoder"),
+        });
+
+        {
+            let mut c_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("BRDF lookup table"),
+                timestamp_writes: None,
+            });
+
+            let count = BRDF_LUT_SIZE.div_ceil(WORKGROUP_SIZE);
+
+            c_pass.set_pipeline(&pipeline);
+            c_pass.set_bind_group(0, &bg, &[]);
+            c_pass.dispatch_workgroups(count, count, 1);
+        }
+
+        gfx.queue.submit(Some(encoder.finish()));
+
+        lut_view
+    }
+
+    fn create_background_bg(
+        gfx: &GfxState,
+        layout: &wgpu::BindGroupLayout,
+        env_view: &wgpu::TextureView,
+        sampler: &wgpu::Sampler,
+        buffer: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        gfx.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Environment background bind group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(env_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: buffer.as_entire_binding(),
+                },
+            ],
+        })
+    }
+}
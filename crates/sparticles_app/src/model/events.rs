@@ -1,3 +1,4 @@
+use crate::asset_manager::AssetEvent;
 use crate::util::ID;
 
 #[derive(Debug)]
@@ -16,7 +17,15 @@ pub enum EventAction {
 pub struct SparEvents {
     pub reset_camera: bool,
     pub create_emitter: Option<ID>,
+    pub create_light_emitter: Option<ID>,
     pub delete_emitter: Option<ID>,
+    /// Model file to load in the background
+    pub load_model: Option<ID>,
     pub io_view: Option<ViewIOEvent>,
     pub toggle_play: bool,
+    /// Writes the scene document to the exports dir of the app
+    pub export_scene: bool,
+    /// Background loads that started, finished or failed, filled in by SparState::update
+    /// for the UI of the next frame
+    pub asset_events: Vec<AssetEvent>,
 }
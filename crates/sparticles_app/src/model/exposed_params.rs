@@ -0,0 +1,535 @@
+use super::{EmitterState, EmitterUniform, GfxState};
+use crate::traits::{
+    EmitterAnimation, ParticleAnimation, RegisterEmitterAnimation, RegisterParticleAnimation,
+};
+use crate::util::persistence::DynamicExport;
+use glam::{Vec3, Vec4};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt::{Display, Formatter};
+
+const VEC3_COMPONENTS: [[&str; 3]; 2] = [["x", "y", "z"], ["yaw", "pitch", "roll"]];
+const COLOR_COMPONENTS: [&str; 4] = ["r", "g", "b", "a"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum ParamValue {
+    Float(f32),
+    Vec3(Vec3),
+    Color(Vec4),
+}
+
+/// What an exposed parameter writes to
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ParamTarget {
+    /// Property of [`EmitterUniform::PROPERTIES`], vectors and colors without their component
+    Emitter {
+        property: String,
+    },
+    /// Dot separated field of the exported animation data, like "params.0.value"
+    ParticleAnimation {
+        index: usize,
+        field: String,
+    },
+    EmitterAnimation {
+        index: usize,
+        field: String,
+    },
+}
+
+/// Named parameter of an emitter that game code can set at runtime
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExposedParam {
+    pub name: String,
+    pub target: ParamTarget,
+    /// Last value that was set, exported with the emitter
+    pub value: ParamValue,
+}
+
+#[derive(Debug)]
+pub enum ParamError {
+    UnknownParam(String),
+    /// Target doesn't exist or has another type than the value
+    InvalidTarget {
+        name: String,
+        target: ParamTarget,
+    },
+}
+
+impl Display for ParamError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownParam(name) => write!(f, "No exposed parameter named '{}'", name),
+            Self::InvalidTarget { name, target } => {
+                write!(f, "Parameter '{}' can't be written to {}", name, target)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParamError {}
+
+impl Display for ParamTarget {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Emitter { property } => write!(f, "emitter.{}", property),
+            Self::ParticleAnimation { index, field } => {
+                write!(f, "particle_animations[{}].{}", index, field)
+            }
+            Self::EmitterAnimation { index, field } => {
+                write!(f, "emitter_animations[{}].{}", index, field)
+            }
+        }
+    }
+}
+
+impl Display for ParamValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Float(_) => f.write_str("float"),
+            Self::Vec3(_) => f.write_str("vec3"),
+            Self::Color(_) => f.write_str("color"),
+        }
+    }
+}
+
+impl ParamValue {
+    fn components(&self) -> Vec<f32> {
+        match self {
+            Self::Float(v) => vec![*v],
+            Self::Vec3(v) => v.to_array().to_vec(),
+            Self::Color(v) => v.to_array().to_vec(),
+        }
+    }
+
+    /// Numbers keep being integers if the field was one
+    fn write_json(&self, field: &mut Value) -> bool {
+        fn write_number(field: &mut Value, value: f32) -> bool {
+            *field = match field {
+                Value::Number(n) if n.is_u64() => Value::from(value.round().max(0.) as u64),
+                Value::Number(n) if n.is_i64() => Value::from(value.round() as i64),
+                Value::Number(_) => Value::from(value),
+                _ => return false,
+            };
+
+            true
+        }
+
+        let components = self.components();
+
+        match (self, field) {
+            (Self::Float(value), field) => write_number(field, *value),
+            (_, Value::Array(items)) if items.len() == components.len() => items
+                .iter_mut()
+                .zip(components)
+                .all(|(item, value)| write_number(item, value)),
+            _ => false,
+        }
+    }
+
+    fn read_json(field: &Value) -> Option<Self> {
+        match field {
+            Value::Number(n) => Some(Self::Float(n.as_f64()? as f32)),
+            Value::Array(items) => {
+                let numbers: Option<Vec<f32>> = items
+                    .iter()
+                    .map(|item| item.as_f64().map(|v| v as f32))
+                    .collect();
+
+                match numbers?.as_slice() {
+                    [x, y, z] => Some(Self::Vec3(Vec3::new(*x, *y, *z))),
+                    [r, g, b, a] => Some(Self::Color(Vec4::new(*r, *g, *b, *a))),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+impl ParamTarget {
+    /// Every target of the emitter with its current value, used to expose new parameters
+    pub fn candidates(emitter: &EmitterState) -> Vec<(ParamTarget, ParamValue)> {
+        let uniform = &emitter.uniform;
+        let mut candidates = Vec::new();
+
+        let mut bases: Vec<&str> = EmitterUniform::PROPERTIES
+            .iter()
+            .map(|name| name.split('.').next().unwrap_or(name))
+            .collect();
+        bases.dedup();
+
+        for base in bases {
+            let suffixes: Vec<&str> = EmitterUniform::PROPERTIES
+                .iter()
+                .filter_map(|name| name.strip_prefix(base)?.strip_prefix('.'))
+                .collect();
+
+            let is_vec3 = VEC3_COMPONENTS.iter().any(|c| c.as_slice() == suffixes);
+            let is_color = COLOR_COMPONENTS.as_slice() == suffixes;
+
+            let properties: Vec<String> = if suffixes.is_empty() || is_vec3 || is_color {
+                vec![base.to_string()]
+            } else {
+                // Pairs like particle_size.min and particle_size.max are separate floats
+                suffixes
+                    .iter()
+                    .map(|suffix| format!("{}.{}", base, suffix))
+                    .collect()
+            };
+
+            for property in properties {
+                if let Some(value) = Self::emitter_value(uniform, &property) {
+                    candidates.push((ParamTarget::Emitter { property }, value));
+                }
+            }
+        }
+
+        for (index, anim) in emitter.particle_animations.iter().enumerate() {
+            for (field, value) in Self::json_fields(&anim.export().data) {
+                candidates.push((ParamTarget::ParticleAnimation { index, field }, value));
+            }
+        }
+
+        for (index, anim) in emitter.emitter_animations.iter().enumerate() {
+            for (field, value) in Self::json_fields(&anim.export().data) {
+                candidates.push((ParamTarget::EmitterAnimation { index, field }, value));
+            }
+        }
+
+        candidates
+    }
+
+    fn emitter_value(uniform: &EmitterUniform, property: &str) -> Option<ParamValue> {
+        let read = |suffixes: &[&str]| -> Option<Vec<f32>> {
+            suffixes
+                .iter()
+                .map(|suffix| uniform.get_property(&format!("{}.{}", property, suffix)))
+                .collect()
+        };
+
+        if let Some(value) = uniform.get_property(property) {
+            Some(ParamValue::Float(value))
+        } else if let Some(v) = VEC3_COMPONENTS.iter().find_map(|suffixes| read(suffixes)) {
+            Some(ParamValue::Vec3(Vec3::from_slice(&v)))
+        } else {
+            read(&COLOR_COMPONENTS).map(|v| ParamValue::Color(Vec4::from_slice(&v)))
+        }
+    }
+
+    fn json_fields(data: &Value) -> Vec<(String, ParamValue)> {
+        fn walk(prefix: &str, value: &Value, fields: &mut Vec<(String, ParamValue)>) {
+            if let Some(param) = ParamValue::read_json(value) {
+                fields.push((prefix.to_string(), param));
+                return;
+            }
+
+            let children: Vec<(String, &Value)> = match value {
+                Value::Object(map) => map.iter().map(|(k, v)| (k.to_string(), v)).collect(),
+                Value::Array(items) => items
+                    .iter()
+                    .enumerate()
+                    .map(|(i, v)| (i.to_string(), v))
+                    .collect(),
+                _ => return,
+            };
+
+            for (key, child) in children {
+                let path = if prefix.is_empty() {
+                    key
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+
+                walk(&path, child, fields);
+            }
+        }
+
+        let mut fields = Vec::new();
+        walk("", data, &mut fields);
+        fields
+    }
+
+    fn json_field<'a>(data: &'a mut Value, field: &str) -> Option<&'a mut Value> {
+        field.split('.').try_fold(data, |value, key| match value {
+            Value::Object(map) => map.get_mut(key),
+            Value::Array(items) => items.get_mut(key.parse::<usize>().ok()?),
+            _ => None,
+        })
+    }
+}
+
+/// Write to one target, prepared for every target before anything is written so a
+/// parameter with an invalid target doesn't change the emitter halfway. Animations apply the
+/// data in place, the replacement is only imported for animations that can't.
+enum ParamWrite {
+    Emitter(Vec<(String, f32)>),
+    ParticleAnimation {
+        index: usize,
+        export: DynamicExport,
+        replacement: Option<Box<dyn ParticleAnimation>>,
+    },
+    EmitterAnimation {
+        index: usize,
+        export: DynamicExport,
+        replacement: Option<Box<dyn EmitterAnimation>>,
+    },
+}
+
+impl ParamWrite {
+    /// Targets of the same animation have to build on the data of each other
+    fn take_export(
+        writes: &mut Vec<(ParamWrite, ParamTarget)>,
+        target: &ParamTarget,
+    ) -> Option<DynamicExport> {
+        let pos = writes.iter().position(|(write, _)| match (write, target) {
+            (
+                Self::ParticleAnimation { index, .. },
+                ParamTarget::ParticleAnimation { index: i, .. },
+            )
+            | (
+                Self::EmitterAnimation { index, .. },
+                ParamTarget::EmitterAnimation { index: i, .. },
+            ) => index == i,
+            _ => false,
+        })?;
+
+        match writes.remove(pos).0 {
+            Self::ParticleAnimation { export, .. } | Self::EmitterAnimation { export, .. } => {
+                Some(export)
+            }
+            Self::Emitter(_) => None,
+        }
+    }
+}
+
+impl EmitterState {
+    /// Writes the value to every exposed parameter with the name, GPU buffers are updated
+    /// with the next update. Nothing is written if one of the targets is invalid.
+    pub fn set_param(
+        &mut self,
+        name: &str,
+        value: ParamValue,
+        gfx: &GfxState,
+        registry_par_anims: &[Box<dyn RegisterParticleAnimation>],
+        registry_em_anims: &[Box<dyn RegisterEmitterAnimation>],
+    ) -> Result<(), ParamError> {
+        let mut writes =
+            self.prepare_writes(name, &value, registry_par_anims, registry_em_anims)?;
+
+        // Particle animations need the device to be imported, so they're staged here
+        for (write, target) in writes.iter_mut() {
+            let ParamWrite::ParticleAnimation {
+                index,
+                export,
+                replacement,
+            } = write
+            else {
+                continue;
+            };
+
+            let replaced = &self.particle_animations[*index];
+
+            if replaced.accepts_data(&export.data) {
+                continue;
+            }
+
+            let anim = registry_par_anims
+                .iter()
+                .find(|reg| reg.tag() == export.tag)
+                .and_then(|reg| reg.import(gfx, self, export.data.clone()).ok());
+
+            let Some(mut anim) = anim else {
+                return Err(ParamError::InvalidTarget {
+                    name: name.to_string(),
+                    target: target.clone(),
+                });
+            };
+
+            anim.set_enabled(replaced.enabled());
+            *replacement = Some(anim);
+        }
+
+        for (write, _) in writes {
+            self.write_param(write);
+        }
+
+        for param in self
+            .uniform
+            .exposed_params
+            .iter_mut()
+            .filter(|param| param.name == name)
+        {
+            param.value = value;
+        }
+
+        Ok(())
+    }
+
+    /// Validates every target of the parameter without writing, so parameters of multiple
+    /// emitters can be checked before any of them is set
+    pub fn check_param(
+        &self,
+        name: &str,
+        value: ParamValue,
+        registry_par_anims: &[Box<dyn RegisterParticleAnimation>],
+        registry_em_anims: &[Box<dyn RegisterEmitterAnimation>],
+    ) -> Result<(), ParamError> {
+        self.prepare_writes(name, &value, registry_par_anims, registry_em_anims)
+            .map(|_| ())
+    }
+
+    fn prepare_writes(
+        &self,
+        name: &str,
+        value: &ParamValue,
+        registry_par_anims: &[Box<dyn RegisterParticleAnimation>],
+        registry_em_anims: &[Box<dyn RegisterEmitterAnimation>],
+    ) -> Result<Vec<(ParamWrite, ParamTarget)>, ParamError> {
+        let targets: Vec<ParamTarget> = self
+            .uniform
+            .exposed_params
+            .iter()
+            .filter(|param| param.name == name)
+            .map(|param| param.target.clone())
+            .collect();
+
+        if targets.is_empty() {
+            return Err(ParamError::UnknownParam(name.to_string()));
+        }
+
+        let invalid_target = |target: ParamTarget| ParamError::InvalidTarget {
+            name: name.to_string(),
+            target,
+        };
+
+        let mut writes = Vec::new();
+
+        for target in targets.iter() {
+            let pending = ParamWrite::take_export(&mut writes, target);
+            let write = self.prepare_param(
+                target,
+                value,
+                pending,
+                registry_par_anims,
+                registry_em_anims,
+            );
+
+            match write {
+                Some(write) => writes.push((write, target.clone())),
+                None => return Err(invalid_target(target.clone())),
+            }
+        }
+
+        Ok(writes)
+    }
+
+    /// None if the target doesn't exist or has another type than the value. Animation
+    /// targets write to the pending export if an earlier target prepared one.
+    fn prepare_param(
+        &self,
+        target: &ParamTarget,
+        value: &ParamValue,
+        pending: Option<DynamicExport>,
+        registry_par_anims: &[Box<dyn RegisterParticleAnimation>],
+        registry_em_anims: &[Box<dyn RegisterEmitterAnimation>],
+    ) -> Option<ParamWrite> {
+        let write_json = |export: DynamicExport, field: &str| {
+            let mut export = pending.unwrap_or(export);
+            let json = ParamTarget::json_field(&mut export.data, field)?;
+            value.write_json(json).then_some(export)
+        };
+
+        match target {
+            ParamTarget::Emitter { property } => {
+                let uniform = &self.uniform;
+                let names = |suffixes: &[&str]| -> Vec<String> {
+                    suffixes
+                        .iter()
+                        .map(|suffix| format!("{}.{}", property, suffix))
+                        .collect()
+                };
+                let exist = |names: &[String]| {
+                    names
+                        .iter()
+                        .all(|name| uniform.get_property(name).is_some())
+                };
+
+                let names = match value {
+                    ParamValue::Float(_) => vec![property.to_string()],
+                    ParamValue::Vec3(_) => VEC3_COMPONENTS
+                        .iter()
+                        .map(|suffixes| names(suffixes))
+                        .find(|names| exist(names))?,
+                    ParamValue::Color(_) => names(&COLOR_COMPONENTS),
+                };
+
+                exist(&names).then(|| {
+                    ParamWrite::Emitter(names.into_iter().zip(value.components()).collect())
+                })
+            }
+            ParamTarget::ParticleAnimation { index, field } => {
+                let export = write_json(self.particle_animations.get(*index)?.export(), field)?;
+
+                registry_par_anims
+                    .iter()
+                    .any(|reg| reg.tag() == export.tag)
+                    .then_some(ParamWrite::ParticleAnimation {
+                        index: *index,
+                        export,
+                        replacement: None,
+                    })
+            }
+            ParamTarget::EmitterAnimation { index, field } => {
+                let replaced = self.emitter_animations.get(*index)?;
+                let export = write_json(replaced.export(), field)?;
+
+                let replacement = if replaced.accepts_data(&export.data) {
+                    None
+                } else {
+                    let reg = registry_em_anims
+                        .iter()
+                        .find(|reg| reg.tag() == export.tag)?;
+
+                    let mut anim = reg.import(export.data.clone()).ok()?;
+                    anim.set_enabled(replaced.enabled());
+                    Some(anim)
+                };
+
+                Some(ParamWrite::EmitterAnimation {
+                    index: *index,
+                    export,
+                    replacement,
+                })
+            }
+        }
+    }
+
+    /// Everything that can fail was prepared before
+    fn write_param(&mut self, write: ParamWrite) {
+        match write {
+            ParamWrite::Emitter(properties) => {
+                for (name, value) in properties {
+                    self.uniform.set_property(&name, value);
+                }
+            }
+            ParamWrite::ParticleAnimation {
+                index,
+                export,
+                replacement,
+            } => match replacement {
+                Some(anim) => self.particle_animations[index] = anim,
+                None => self.particle_animations[index].apply_data(&export.data),
+            },
+            ParamWrite::EmitterAnimation {
+                index,
+                export,
+                replacement,
+            } => match replacement {
+                Some(anim) => self.emitter_animations[index] = anim,
+                None => self.emitter_animations[index].apply_data(&export.data),
+            },
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use std::ops::Deref;
 use std::sync::Arc;
 
 use super::state::SparState;
@@ -5,12 +6,17 @@ use super::EmitterState;
 use super::SparEvents;
 use crate::fx::PostProcessState;
 use crate::init::AppVisitor;
+use crate::profiler::GpuProfiler;
+use crate::profiler::GpuProfilerSettings;
+use crate::profiler::GpuTimerScopeResult;
+use crate::profiler::ProfilerCommandRecorder;
+use crate::shaders::ShaderHotReload;
 use async_std::sync::RwLock;
 use async_std::task;
-use egui_wgpu::renderer::ScreenDescriptor;
 use egui_wgpu::wgpu;
 use egui_wgpu::wgpu::CommandEncoder;
 use egui_wgpu::Renderer;
+use egui_wgpu::ScreenDescriptor;
 use egui_winit::egui::epaint::ImageDelta;
 use egui_winit::egui::ClippedPrimitive;
 use egui_winit::egui::Context;
@@ -23,24 +29,55 @@ use egui_winit::egui::TextureId;
 use egui_winit::winit;
 use egui_winit::winit::event::WindowEvent;
 use egui_winit::EventResponse;
-use wgpu_profiler::GpuProfiler;
-use wgpu_profiler::GpuProfilerSettings;
-use wgpu_profiler::GpuTimerScopeResult;
-use wgpu_profiler::ProfilerCommandRecorder;
 use winit::dpi::PhysicalSize;
 use winit::window;
 
 pub struct GfxState {
-    pub device: wgpu::Device,
-    pub queue: wgpu::Queue,
+    pub device: SharedDevice,
+    pub queue: Arc<wgpu::Queue>,
+    /// Format and size of the frame target, only configures a surface when there is a window
     pub surface_config: wgpu::SurfaceConfiguration,
     pub ctx: Context,
-    pub window: window::Window,
     pub renderer: Renderer,
     pub screen_descriptor: ScreenDescriptor,
     pub profiler: GpuProfiler,
+    /// None when embedded in a host engine that owns the window and event loop
+    pub window: Option<WindowCtx>,
+}
+
+pub struct WindowCtx {
+    pub window: Arc<window::Window>,
+    pub surface: wgpu::Surface<'static>,
     pub winit: egui_winit::State,
-    pub surface: wgpu::Surface,
+}
+
+/// Device owned by sparticles or a handle to the device of a host engine,
+/// like `Arc<wgpu::Device>` or a wrapper around the device type of the engine
+#[derive(Clone)]
+pub struct SharedDevice(Arc<dyn Deref<Target = wgpu::Device> + Send + Sync>);
+
+impl SharedDevice {
+    pub fn new(device: impl Deref<Target = wgpu::Device> + Send + Sync + 'static) -> Self {
+        Self(Arc::new(device))
+    }
+}
+
+impl Deref for SharedDevice {
+    type Target = wgpu::Device;
+
+    fn deref(&self) -> &wgpu::Device {
+        &self.0
+    }
+}
+
+/// Device and frame target of a host engine
+pub struct EmbedOptions {
+    /// Requires [`GfxState::REQUIRED_FEATURES`] and the limits of [`GfxState::required_limits`]
+    pub device: SharedDevice,
+    pub queue: Arc<wgpu::Queue>,
+    pub format: wgpu::TextureFormat,
+    pub width: u32,
+    pub height: u32,
 }
 
 unsafe impl Send for GfxState {}
@@ -48,7 +85,6 @@ unsafe impl Sync for GfxState {}
 
 pub struct DrawGuiResult {
     pub primitives: Vec<ClippedPrimitive>,
-    pub events: SparEvents,
 }
 
 pub struct Profiler;
@@ -70,6 +106,42 @@ impl Profiler {
 }
 
 impl GfxState {
+    pub const REQUIRED_FEATURES: wgpu::Features = wgpu::Features::TEXTURE_BINDING_ARRAY
+        .union(wgpu::Features::STORAGE_RESOURCE_BINDING_ARRAY)
+        .union(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES);
+
+    /// Higher limits for Post FX
+    pub fn required_limits() -> wgpu::Limits {
+        wgpu::Limits {
+            max_sampled_textures_per_shader_stage: 32,
+            max_storage_textures_per_shader_stage: 32,
+            ..Default::default()
+        }
+    }
+
+    /// Tells what the device of a host engine lacks, None if sparticles can use it
+    pub fn unsupported_reason(device: &wgpu::Device) -> Option<String> {
+        if !device.features().contains(Self::REQUIRED_FEATURES) {
+            return Some(format!(
+                "Device is missing features required by sparticles: {:?}",
+                Self::REQUIRED_FEATURES - device.features()
+            ));
+        }
+
+        let limits = device.limits();
+        let required = Self::required_limits();
+
+        let enough = required.max_sampled_textures_per_shader_stage
+            <= limits.max_sampled_textures_per_shader_stage
+            && required.max_storage_textures_per_shader_stage
+                <= limits.max_storage_textures_per_shader_stage;
+
+        match enough {
+            true => None,
+            false => Some("Device limits are lower than required by sparticles".to_string()),
+        }
+    }
+
     fn begin_scope(&mut self, label: &str, pass: &mut impl ProfilerCommandRecorder) {
         self.profiler.begin_scope(label, pass, &self.device);
     }
@@ -121,11 +193,10 @@ impl GfxState {
     pub async fn new(window: window::Window) -> Self {
         let instance = wgpu::Instance::default();
 
-        let surface = unsafe {
-            instance
-                .create_surface(&window)
-                .expect("Can't load surface")
-        };
+        let window = Arc::new(window);
+        let surface = instance
+            .create_surface(window.clone())
+            .expect("Can't load surface");
 
         let adapter = instance
             .request_adapter(&wgpu::RequestAdapterOptions {
@@ -136,21 +207,12 @@ impl GfxState {
             .await
             .unwrap();
 
-        // Higher limits for Post FX
-        let limits = wgpu::Limits {
-            max_sampled_textures_per_shader_stage: 32,
-            max_storage_textures_per_shader_stage: 32,
-            ..Default::default()
-        };
-
         let (device, queue) = adapter
             .request_device(
                 &wgpu::DeviceDescriptor {
-                    features: wgpu::Features::TEXTURE_BINDING_ARRAY
-                        | wgpu::Features::STORAGE_RESOURCE_BINDING_ARRAY
-                        | wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES
-                        | GpuProfiler::ALL_WGPU_TIMER_FEATURES,
-                    limits,
+                    required_features: Self::REQUIRED_FEATURES
+                        | (adapter.features() & GpuProfiler::ALL_WGPU_TIMER_FEATURES),
+                    required_limits: Self::required_limits(),
                     label: None,
                 },
                 None,
@@ -176,23 +238,93 @@ impl GfxState {
             present_mode: wgpu::PresentMode::Fifo,
             alpha_mode: surface_caps.alpha_modes[0],
             view_formats: vec![],
+            desired_maximum_frame_latency: 2,
         };
 
         surface.configure(&device, &surface_config);
 
+        let ctx = Context::default();
         let raw_input = RawInput::default();
         let vp = raw_input.viewport();
 
-        let ctx = Context::default();
-
         let winit = egui_winit::State::new(
+            ctx.clone(),
             raw_input.viewport_id,
             &window,
             vp.native_pixels_per_point,
             raw_input.max_texture_side,
         );
 
-        let renderer = Renderer::new(&device, surface_format, None, 1);
+        let pixels_per_point = window.scale_factor() as f32;
+        let window = WindowCtx {
+            window,
+            surface,
+            winit,
+        };
+
+        Self::from_parts(
+            ctx,
+            SharedDevice::new(Box::new(device)),
+            Arc::new(queue),
+            surface_config,
+            pixels_per_point,
+            GpuProfilerSettings::default(),
+            Some(window),
+        )
+    }
+
+    /// Uses the device of a host engine, nothing is presented and there is no egui input
+    pub fn embedded(options: EmbedOptions) -> Self {
+        let EmbedOptions {
+            device,
+            queue,
+            format,
+            width,
+            height,
+        } = options;
+
+        if let Some(reason) = Self::unsupported_reason(&device) {
+            panic!("{}", reason);
+        }
+
+        let surface_config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format,
+            width: width.max(1),
+            height: height.max(1),
+            present_mode: wgpu::PresentMode::Fifo,
+            alpha_mode: wgpu::CompositeAlphaMode::Auto,
+            view_formats: vec![],
+            desired_maximum_frame_latency: 2,
+        };
+
+        // Timer scopes need the frame to be ended after the host submitted its encoder
+        let profiler_settings = GpuProfilerSettings {
+            enable_timer_scopes: false,
+            ..Default::default()
+        };
+
+        Self::from_parts(
+            Context::default(),
+            device,
+            queue,
+            surface_config,
+            1.,
+            profiler_settings,
+            None,
+        )
+    }
+
+    fn from_parts(
+        ctx: Context,
+        device: SharedDevice,
+        queue: Arc<wgpu::Queue>,
+        surface_config: wgpu::SurfaceConfiguration,
+        pixels_per_point: f32,
+        profiler_settings: GpuProfilerSettings,
+        window: Option<WindowCtx>,
+    ) -> Self {
+        let renderer = Renderer::new(&device, surface_config.format, None, 1);
 
         let mut fonts = FontDefinitions::default();
 
@@ -210,38 +342,48 @@ impl GfxState {
 
         let screen_descriptor = ScreenDescriptor {
             size_in_pixels: [surface_config.width, surface_config.height],
-            pixels_per_point: window.scale_factor() as f32,
+            pixels_per_point,
         };
 
-        let profiler =
-            GpuProfiler::new(GpuProfilerSettings::default()).expect("Failed to create profiler");
+        let profiler = GpuProfiler::new(profiler_settings);
 
         Self {
-            surface,
             window,
             device,
             surface_config,
             renderer,
             queue,
-            winit,
             ctx,
             screen_descriptor,
             profiler,
         }
     }
 
-    pub async fn window_id(gfx: &Arc<RwLock<GfxState>>) -> window::WindowId {
-        gfx.read().await.window.id()
+    pub async fn window_id(gfx: &Arc<RwLock<GfxState>>) -> Option<window::WindowId> {
+        gfx.read().await.window.as_ref().map(|ctx| ctx.window.id())
     }
 
-    pub fn handle_event(gfx: &Arc<RwLock<GfxState>>, event: &WindowEvent<'_>) -> EventResponse {
+    pub fn handle_event(gfx: &Arc<RwLock<GfxState>>, event: &WindowEvent) -> EventResponse {
         let gfx = &mut task::block_on(gfx.write());
-        let ctx = gfx.ctx.clone();
-        gfx.winit.on_window_event(&ctx, event)
+
+        match &mut gfx.window {
+            Some(window) => window.winit.on_window_event(&window.window, event),
+            None => EventResponse::default(),
+        }
+    }
+
+    pub async fn window_size(gfx: &Arc<RwLock<GfxState>>) -> Option<PhysicalSize<u32>> {
+        gfx.read()
+            .await
+            .window
+            .as_ref()
+            .map(|ctx| ctx.window.inner_size())
     }
 
     pub fn request_redraw(&self) {
-        self.window.request_redraw();
+        if let Some(ctx) = &self.window {
+            ctx.window.request_redraw();
+        }
     }
 
     pub fn process_frame(&mut self) -> Option<Vec<GpuTimerScopeResult>> {
@@ -253,22 +395,35 @@ impl GfxState {
         if size.width > 0 && size.height > 0 {
             self.surface_config.width = size.width;
             self.surface_config.height = size.height;
-            self.surface.configure(&self.device, &self.surface_config);
 
-            self.screen_descriptor = ScreenDescriptor {
-                size_in_pixels: [size.width, size.height],
-                pixels_per_point: self.window.scale_factor() as f32,
-            };
+            if let Some(ctx) = &self.window {
+                ctx.surface.configure(&self.device, &self.surface_config);
+                self.screen_descriptor.pixels_per_point = ctx.window.scale_factor() as f32;
+            }
+
+            self.screen_descriptor.size_in_pixels = [size.width, size.height];
         }
     }
 
     fn egui_input(&mut self) -> RawInput {
-        self.winit.take_egui_input(&self.window)
+        match &mut self.window {
+            Some(ctx) => ctx.winit.take_egui_input(&ctx.window),
+            None => RawInput::default(),
+        }
     }
 
     fn egui_handle_output(&mut self, platform_output: PlatformOutput) {
-        self.winit
-            .handle_platform_output(&self.window, &self.ctx, platform_output);
+        if let Some(ctx) = &mut self.window {
+            ctx.winit
+                .handle_platform_output(&ctx.window, platform_output);
+        }
+    }
+
+    fn pixels_per_point(&self) -> f32 {
+        match &self.window {
+            Some(ctx) => egui_winit::pixels_per_point(&self.ctx, &ctx.window),
+            None => self.screen_descriptor.pixels_per_point,
+        }
     }
 
     fn egui_update_texture(&mut self, tex_id: TextureId, img_delta: ImageDelta) {
@@ -294,6 +449,7 @@ impl GfxState {
         state: &mut SparState,
         encoder: &mut wgpu::CommandEncoder,
         app_visitor: &mut impl AppVisitor,
+        events: &mut SparEvents,
     ) -> DrawGuiResult {
         {
             let gfx = &mut state.gfx.write().await;
@@ -302,7 +458,9 @@ impl GfxState {
             gfx.ctx.begin_frame(input);
         }
 
-        let events = app_visitor.draw_ui(state, encoder);
+        app_visitor.draw_ui(state, events, encoder);
+
+        state.shader_reload.draw_overlay(&state.egui_ctx());
 
         {
             let gfx = &mut state.gfx.write().await;
@@ -310,7 +468,7 @@ impl GfxState {
 
             let primitives = gfx
                 .ctx
-                .tessellate(full_output.shapes, gfx.winit.pixels_per_point());
+                .tessellate(full_output.shapes, gfx.pixels_per_point());
 
             gfx.egui_handle_output(full_output.platform_output);
 
@@ -324,25 +482,35 @@ impl GfxState {
 
             gfx.egui_update_buffers(encoder, &primitives);
 
-            DrawGuiResult { events, primitives }
+            DrawGuiResult { primitives }
         }
     }
 
-    pub async fn render(state: &mut SparState, app_visitor: &mut impl AppVisitor) -> SparEvents {
+    /// The UI reads the events of the update and adds the events for the next update
+    pub async fn render(
+        state: &mut SparState,
+        app_visitor: &mut impl AppVisitor,
+        events: &mut SparEvents,
+    ) {
         let mut encoder: CommandEncoder;
         let output_view: wgpu::TextureView;
         let output_frame: wgpu::SurfaceTexture;
 
         {
             let gfx = state.gfx.read().await;
-            output_frame = match gfx.surface.get_current_texture() {
+
+            let Some(window) = &gfx.window else {
+                return;
+            };
+
+            output_frame = match window.surface.get_current_texture() {
                 Ok(frame) => frame,
                 Err(wgpu::SurfaceError::Outdated) => {
-                    return SparEvents::default();
+                    return;
                 }
                 Err(e) => {
                     eprintln!("Dropped frame with error: {}", e);
-                    return SparEvents::default();
+                    return;
                 }
             };
 
@@ -357,20 +525,20 @@ impl GfxState {
                 .create_view(&wgpu::TextureViewDescriptor::default());
         }
 
+        ShaderHotReload::update(state, &mut encoder).await;
+
         if state.play {
             EmitterState::compute_particles(state, &mut encoder).await;
         }
 
-        EmitterState::render_particles(state, &mut encoder).await;
+        EmitterState::render_particles(state, &mut encoder, wgpu::LoadOp::Clear(1.0)).await;
         PostProcessState::compute(state, &mut encoder).await;
-        let res = GfxState::draw_ui(state, &mut encoder, app_visitor).await;
+        let res = GfxState::draw_ui(state, &mut encoder, app_visitor, events).await;
         PostProcessState::render(state, output_view, &mut encoder, &res.primitives).await;
 
         state.clock.measure_cpu_time();
 
         let gfx = &mut state.gfx.write().await;
         gfx.finish_frame(encoder, output_frame);
-
-        res.events
     }
 }
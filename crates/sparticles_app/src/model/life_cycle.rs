@@ -1,6 +1,6 @@
 use serde::{Deserialize, Serialize};
 
-use super::Clock;
+use super::{Clock, Easing};
 
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct LifeCycle {
@@ -8,20 +8,139 @@ pub struct LifeCycle {
     pub until_sec: f32,
     /// Time until the animmation repeats
     pub lifetime_sec: f32,
+    #[serde(default)]
+    pub easing: Easing,
+    /// Every other repetition plays backwards
+    #[serde(default)]
+    pub ping_pong: bool,
+    /// Number of times the animation plays, 0 repeats forever
+    #[serde(default)]
+    pub repeat_count: u32,
+}
+
+impl Default for LifeCycle {
+    fn default() -> Self {
+        Self {
+            from_sec: 0.,
+            until_sec: 5.,
+            lifetime_sec: 5.,
+            easing: Easing::Linear,
+            ping_pong: false,
+            repeat_count: 0,
+        }
+    }
 }
 
 impl LifeCycle {
-    pub fn get_current_sec(&self, clock: &Clock) -> f32 {
-        clock.elapsed_sec() % self.lifetime_sec
+    /// None once all repetitions have played
+    pub fn get_current_sec(&self, clock: &Clock) -> Option<f32> {
+        self.current_sec_at(clock.elapsed_sec())
+    }
+
+    pub fn current_sec_at(&self, elapsed_sec: f32) -> Option<f32> {
+        let repetition = (elapsed_sec / self.lifetime_sec) as u32;
+
+        if 0 < self.repeat_count && self.repeat_count <= repetition {
+            return None;
+        }
+
+        let current_sec = elapsed_sec % self.lifetime_sec;
+
+        if self.ping_pong && repetition % 2 == 1 {
+            Some(self.from_sec + self.until_sec - current_sec)
+        } else {
+            Some(current_sec)
+        }
+    }
+
+    /// Where the last repetition ended, animations that hold their end state use this
+    pub fn end_sec(&self) -> f32 {
+        let is_reversed = self.ping_pong && self.repeat_count.is_multiple_of(2);
+
+        if is_reversed {
+            self.from_sec
+        } else {
+            self.until_sec
+        }
     }
 
     pub fn shoud_animate(&self, current_sec: f32) -> bool {
         self.from_sec <= current_sec && current_sec <= self.until_sec
     }
 
+    /// Eased fraction of the animation
     pub fn get_fraction(&self, current_sec: f32) -> f32 {
         let delta_current = current_sec - self.from_sec;
         let delta_max = self.until_sec - self.from_sec;
-        delta_current / delta_max
+
+        if delta_max <= 0. {
+            return 1.;
+        }
+
+        self.easing.apply(delta_current / delta_max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn life_cycle(repeat_count: u32, ping_pong: bool) -> LifeCycle {
+        LifeCycle {
+            from_sec: 1.,
+            until_sec: 3.,
+            lifetime_sec: 4.,
+            easing: Easing::Linear,
+            ping_pong,
+            repeat_count,
+        }
+    }
+
+    fn animates_at(life_cycle: &LifeCycle, elapsed_sec: f32) -> bool {
+        life_cycle
+            .current_sec_at(elapsed_sec)
+            .is_some_and(|sec| life_cycle.shoud_animate(sec))
+    }
+
+    #[test]
+    fn repeat_count_zero_repeats_forever() {
+        let life_cycle = life_cycle(0, false);
+
+        assert_eq!(life_cycle.current_sec_at(2.), Some(2.));
+        assert_eq!(life_cycle.current_sec_at(402.), Some(2.));
+        assert!(animates_at(&life_cycle, 402.));
+    }
+
+    #[test]
+    fn repeat_count_one_stops_after_first_lifetime() {
+        let life_cycle = life_cycle(1, false);
+
+        assert!(animates_at(&life_cycle, 2.));
+        assert!(!animates_at(&life_cycle, 3.5));
+        assert_eq!(life_cycle.current_sec_at(4.), None);
+        assert_eq!(life_cycle.current_sec_at(6.), None);
+        assert!(!animates_at(&life_cycle, 6.));
+    }
+
+    #[test]
+    fn repeat_count_n_stops_after_n_lifetimes() {
+        let life_cycle = life_cycle(3, false);
+
+        assert!(animates_at(&life_cycle, 2.));
+        assert!(animates_at(&life_cycle, 6.));
+        assert!(animates_at(&life_cycle, 10.));
+        assert_eq!(life_cycle.current_sec_at(12.), None);
+        assert!(!animates_at(&life_cycle, 14.));
+        assert_eq!(life_cycle.end_sec(), 3.);
+    }
+
+    #[test]
+    fn ping_pong_reverses_every_other_repetition() {
+        let life_cycle = life_cycle(2, true);
+
+        assert_eq!(life_cycle.current_sec_at(1.5), Some(1.5));
+        assert_eq!(life_cycle.current_sec_at(5.5), Some(2.5));
+        assert_eq!(life_cycle.current_sec_at(8.), None);
+        assert_eq!(life_cycle.end_sec(), 1.);
     }
 }
@@ -0,0 +1,560 @@
+use super::emitter::particle_stride;
+use super::scene_lighting::SceneLightingUniform;
+use super::{Camera, Clock, EmitterState, EnvironmentCtx, GfxState, SceneLighting};
+use crate::shaders::{layout::gpu_struct, ShaderOptions};
+use crate::traits::BufferContent;
+use crate::util::ID;
+use egui_wgpu::wgpu;
+use encase::ShaderType;
+use glam::{Mat4, Vec2};
+use serde::{Deserialize, Serialize};
+use std::num::NonZeroU64;
+
+/// Screen tiles (x, y) and depth slices (z) of the cluster grid
+const CLUSTER_GRID: [u32; 3] = [16, 9, 24];
+/// Lights that don't fit in a cluster are skipped
+const MAX_LIGHTS_PER_CLUSTER: u32 = 128;
+const WORKGROUP_SIZE: u32 = 64;
+
+/// Light emitters that light a normal emitter
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub enum LightSelection {
+    #[default]
+    All,
+    /// Ids of the light emitters
+    Only(Vec<ID>),
+}
+
+impl LightSelection {
+    pub fn contains(&self, id: &str) -> bool {
+        match self {
+            Self::All => true,
+            Self::Only(ids) => ids.iter().any(|light_id| light_id == id),
+        }
+    }
+}
+
+/// The particles of one light emitter in the shared light buffer
+#[derive(Debug, Clone, PartialEq)]
+struct LightSet {
+    id: ID,
+    particle_count: u64,
+    range: f32,
+    lifetime_sec: f32,
+}
+
+gpu_struct! {
+    pub(crate) struct LightSetUniform as "LightSet" {
+        offset: u32,
+        count: u32,
+        range: f32,
+        lifetime: f32,
+    }
+}
+
+gpu_struct! {
+    pub(crate) struct LightClustersUniform as "LightClusters" {
+        view: Mat4,
+        proj: Mat4,
+        screen_size: Vec2,
+        near: f32,
+        far: f32,
+        grid_x: u32,
+        grid_y: u32,
+        grid_z: u32,
+        max_lights: u32,
+    }
+}
+
+/// Concatenates the particles of all light emitters into one shared light buffer.
+/// Every light emitter is a light set, normal emitters select sets with a bit mask.
+/// Each frame the light particles are binned into clusters (screen tiles x depth slices),
+/// so a fragment only iterates the lights that can reach it.
+pub struct LightsCtx {
+    pub bg_layout: wgpu::BindGroupLayout,
+    bg: wgpu::BindGroup,
+    cull_bg_layout: wgpu::BindGroupLayout,
+    cull_bg: wgpu::BindGroup,
+    reset_pipeline: wgpu::ComputePipeline,
+    cull_pipeline: wgpu::ComputePipeline,
+    pub light_buffer: wgpu::Buffer,
+    pub sets_buffer: wgpu::Buffer,
+    pub clusters_buffer: wgpu::Buffer,
+    pub cluster_counts_buffer: wgpu::Buffer,
+    pub cluster_lights_buffer: wgpu::Buffer,
+    pub scene_lighting_buffer: wgpu::Buffer,
+    /// In buffer order
+    sets: Vec<LightSet>,
+    environment_generation: u64,
+}
+
+impl LightsCtx {
+    /// Light sets are selected with a u32 mask
+    pub const MAX_LIGHT_SETS: usize = 32;
+
+    pub fn bg(&self) -> &wgpu::BindGroup {
+        &self.bg
+    }
+
+    /// Ids of the light emitters that are part of the shared light buffer
+    pub fn set_ids(&self) -> impl Iterator<Item = &str> {
+        self.sets.iter().map(|set| set.id.as_str())
+    }
+
+    fn cluster_count() -> u32 {
+        CLUSTER_GRID.iter().product()
+    }
+
+    fn light_count(&self) -> u64 {
+        self.sets.iter().map(|set| set.particle_count).sum()
+    }
+
+    /// Rebuilds the light buffer when light emitters changed and updates the light masks
+    pub fn update(
+        &mut self,
+        emitters: &mut [EmitterState],
+        camera: &Camera,
+        scene_lighting: &SceneLighting,
+        environment: &EnvironmentCtx,
+        gfx: &GfxState,
+    ) {
+        let sets: Vec<LightSet> = emitters
+            .iter()
+            .filter(|em| em.is_light)
+            .take(Self::MAX_LIGHT_SETS)
+            .map(|em| LightSet {
+                id: em.id().to_string(),
+                particle_count: em.particle_count(),
+                range: em.uniform.light_range,
+                lifetime_sec: em.uniform.particle_lifetime_sec,
+            })
+            .collect();
+
+        let is_resized = sets.len() != self.sets.len()
+            || sets
+                .iter()
+                .zip(self.sets.iter())
+                .any(|(a, b)| a.id != b.id || a.particle_count != b.particle_count);
+
+        self.sets = sets;
+
+        let scene_content = Self::scene_lighting_content(scene_lighting);
+        let is_scene_resized = scene_content.len() as u64 != self.scene_lighting_buffer.size();
+
+        if is_resized {
+            self.light_buffer = Self::create_light_buffer(&self.sets, gfx);
+            self.sets_buffer = Self::create_sets_buffer(&self.sets, gfx);
+        }
+
+        if is_scene_resized {
+            self.scene_lighting_buffer =
+                Self::create_scene_lighting_buffer(scene_content.len() as u64, gfx);
+        }
+
+        let is_environment_changed = environment.generation() != self.environment_generation;
+
+        if is_resized || is_scene_resized || is_environment_changed {
+            self.environment_generation = environment.generation();
+            self.create_bind_groups(environment, gfx);
+        }
+
+        gfx.queue
+            .write_buffer(&self.scene_lighting_buffer, 0, &scene_content);
+
+        gfx.queue
+            .write_buffer(&self.sets_buffer, 0, &Self::sets_buffer_content(&self.sets));
+
+        let size = &gfx.surface_config;
+        let clusters_uniform = LightClustersUniform {
+            view: camera.view_mat(),
+            proj: camera.proj(),
+            screen_size: Vec2::new(size.width as f32, size.height as f32),
+            near: camera.near(),
+            far: camera.far(),
+            grid_x: CLUSTER_GRID[0],
+            grid_y: CLUSTER_GRID[1],
+            grid_z: CLUSTER_GRID[2],
+            max_lights: MAX_LIGHTS_PER_CLUSTER,
+        };
+
+        gfx.queue
+            .write_buffer(&self.clusters_buffer, 0, &clusters_uniform.buffer_content());
+
+        for emitter in emitters.iter_mut().filter(|em| !em.is_light) {
+            let selection = &emitter.uniform.lights;
+
+            emitter.uniform.light_mask = self
+                .sets
+                .iter()
+                .enumerate()
+                .filter(|(_, set)| selection.contains(&set.id))
+                .fold(0, |mask, (i, _)| mask | (1 << i));
+        }
+    }
+
+    /// Copies the particles that were just computed into the shared light buffer and
+    /// assigns them to the clusters they reach
+    pub fn cull_lights(
+        &self,
+        emitters: &[EmitterState],
+        clock: &Clock,
+        encoder: &mut wgpu::CommandEncoder,
+    ) {
+        let nr = clock.get_alt_bindgroup_nr();
+        let mut offset = 0;
+
+        for set in self.sets.iter() {
+            let size = set.particle_count * particle_stride();
+
+            if let Some(light) = emitters.iter().find(|em| em.id() == set.id) {
+                encoder.copy_buffer_to_buffer(
+                    light.particle_buffer(nr),
+                    0,
+                    &self.light_buffer,
+                    offset,
+                    size,
+                );
+            }
+
+            offset += size;
+        }
+
+        let mut c_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Light culling"),
+            timestamp_writes: None,
+        });
+
+        c_pass.set_bind_group(0, &self.cull_bg, &[]);
+
+        c_pass.set_pipeline(&self.reset_pipeline);
+        c_pass.dispatch_workgroups(Self::cluster_count().div_ceil(WORKGROUP_SIZE), 1, 1);
+
+        let light_count = self.light_count() as u32;
+
+        if 0 < light_count {
+            c_pass.set_pipeline(&self.cull_pipeline);
+            c_pass.dispatch_workgroups(light_count.div_ceil(WORKGROUP_SIZE), 1, 1);
+        }
+    }
+
+    pub fn new(gfx: &GfxState, environment: &EnvironmentCtx) -> Self {
+        let device = &gfx.device;
+
+        let clusters_size = LightClustersUniform::min_size().get();
+        let cluster_count = Self::cluster_count() as u64;
+
+        let clusters_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Light clusters buffer"),
+            size: clusters_size,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let cluster_counts_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Light cluster counts buffer"),
+            size: cluster_count * 4,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+
+        let cluster_lights_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Light cluster lights buffer"),
+            size: cluster_count * MAX_LIGHTS_PER_CLUSTER as u64 * 4,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+
+        let layout_entries = |visibility: wgpu::ShaderStages, read_only: bool| {
+            let storage =
+                |binding: u32, read_only: bool, min_size: u64| wgpu::BindGroupLayoutEntry {
+                    binding,
+                    visibility,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only },
+                        has_dynamic_offset: false,
+                        min_binding_size: NonZeroU64::new(min_size),
+                    },
+                    count: None,
+                };
+
+            vec![
+                // Light particles
+                storage(0, true, particle_stride()),
+                // Light sets
+                storage(1, true, LightSetUniform::min_size().get()),
+                // Clusters settings
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: NonZeroU64::new(clusters_size),
+                    },
+                    count: None,
+                },
+                // Light count per cluster
+                storage(3, read_only, cluster_count * 4),
+                // Light entries per cluster
+                storage(
+                    4,
+                    read_only,
+                    cluster_count * MAX_LIGHTS_PER_CLUSTER as u64 * 4,
+                ),
+                // Scene lighting
+                storage(5, true, SceneLightingUniform::min_size().get()),
+            ]
+        };
+
+        let texture = |binding: u32, view_dimension| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension,
+                multisampled: false,
+            },
+            count: None,
+        };
+
+        let mut entries = layout_entries(wgpu::ShaderStages::FRAGMENT, true);
+
+        entries.extend([
+            // Irradiance
+            texture(6, wgpu::TextureViewDimension::Cube),
+            // Prefiltered specular
+            texture(7, wgpu::TextureViewDimension::Cube),
+            // BRDF lookup table
+            texture(8, wgpu::TextureViewDimension::D2),
+            wgpu::BindGroupLayoutEntry {
+                binding: 9,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+            // Environment settings
+            wgpu::BindGroupLayoutEntry {
+                binding: 10,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ]);
+
+        let bg_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Lights layout"),
+            entries: &entries,
+        });
+
+        let cull_bg_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Light culling layout"),
+            entries: &layout_entries(wgpu::ShaderStages::COMPUTE, false),
+        });
+
+        let (reset_pipeline, cull_pipeline) = Self::create_pipelines(&cull_bg_layout, gfx);
+
+        let light_buffer = Self::create_light_buffer(&[], gfx);
+        let sets_buffer = Self::create_sets_buffer(&[], gfx);
+        let scene_lighting_buffer = Self::create_scene_lighting_buffer(
+            Self::scene_lighting_content(&SceneLighting::default()).len() as u64,
+            gfx,
+        );
+
+        let buffers = [
+            &light_buffer,
+            &sets_buffer,
+            &clusters_buffer,
+            &cluster_counts_buffer,
+            &cluster_lights_buffer,
+            &scene_lighting_buffer,
+        ];
+
+        let bg = Self::create_bind_group(&bg_layout, buffers, Some(environment), gfx);
+        let cull_bg = Self::create_bind_group(&cull_bg_layout, buffers, None, gfx);
+
+        Self {
+            bg_layout,
+            bg,
+            cull_bg_layout,
+            cull_bg,
+            reset_pipeline,
+            cull_pipeline,
+            light_buffer,
+            sets_buffer,
+            clusters_buffer,
+            cluster_counts_buffer,
+            cluster_lights_buffer,
+            scene_lighting_buffer,
+            sets: vec![],
+            environment_generation: environment.generation(),
+        }
+    }
+
+    /// Used after light_cluster.wgsl changed
+    pub fn recreate_pipelines(&mut self, gfx: &GfxState) {
+        (self.reset_pipeline, self.cull_pipeline) =
+            Self::create_pipelines(&self.cull_bg_layout, gfx);
+    }
+
+    /// Reset and cull pipeline
+    fn create_pipelines(
+        cull_bg_layout: &wgpu::BindGroupLayout,
+        gfx: &GfxState,
+    ) -> (wgpu::ComputePipeline, wgpu::ComputePipeline) {
+        let device = &gfx.device;
+
+        let shader = gfx.create_shader_builtin(ShaderOptions {
+            files: &["light_cluster.wgsl"],
+            if_directives: &[],
+            label: "Light culling",
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Light culling layout"),
+            bind_group_layouts: &[cull_bg_layout],
+            push_constant_ranges: &[],
+        });
+
+        let create_pipeline = |entry_point| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Light culling pipeline"),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point,
+            })
+        };
+
+        (create_pipeline("reset"), create_pipeline("main"))
+    }
+
+    fn create_light_buffer(sets: &[LightSet], gfx: &GfxState) -> wgpu::Buffer {
+        let particle_count: u64 = sets.iter().map(|set| set.particle_count).sum();
+
+        gfx.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Light particles buffer"),
+            size: particle_count.max(1) * particle_stride(),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn create_sets_buffer(sets: &[LightSet], gfx: &GfxState) -> wgpu::Buffer {
+        gfx.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Light sets buffer"),
+            size: Self::sets_buffer_content(sets).len() as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn create_scene_lighting_buffer(size: u64, gfx: &GfxState) -> wgpu::Buffer {
+        gfx.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Scene lighting buffer"),
+            size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn scene_lighting_content(scene_lighting: &SceneLighting) -> Vec<u8> {
+        let mut buffer = encase::StorageBuffer::new(Vec::new());
+        buffer.write(&scene_lighting.uniform()).unwrap();
+        buffer.into_inner()
+    }
+
+    fn sets_buffer_content(sets: &[LightSet]) -> Vec<u8> {
+        let mut offset = 0;
+        let mut uniforms = Vec::new();
+
+        for set in sets.iter() {
+            uniforms.push(LightSetUniform {
+                offset: offset as u32,
+                count: set.particle_count as u32,
+                range: set.range,
+                lifetime: set.lifetime_sec,
+            });
+
+            offset += set.particle_count;
+        }
+
+        // Bindings can't be empty, an empty set is never binned
+        if uniforms.is_empty() {
+            uniforms.push(LightSetUniform {
+                offset: 0,
+                count: 0,
+                range: 0.,
+                lifetime: 0.,
+            });
+        }
+
+        let mut buffer = encase::StorageBuffer::new(Vec::new());
+        buffer.write(&uniforms).unwrap();
+        buffer.into_inner()
+    }
+
+    fn create_bind_groups(&mut self, environment: &EnvironmentCtx, gfx: &GfxState) {
+        let buffers = [
+            &self.light_buffer,
+            &self.sets_buffer,
+            &self.clusters_buffer,
+            &self.cluster_counts_buffer,
+            &self.cluster_lights_buffer,
+            &self.scene_lighting_buffer,
+        ];
+
+        self.bg = Self::create_bind_group(&self.bg_layout, buffers, Some(environment), gfx);
+        self.cull_bg = Self::create_bind_group(&self.cull_bg_layout, buffers, None, gfx);
+    }
+
+    fn create_bind_group(
+        layout: &wgpu::BindGroupLayout,
+        buffers: [&wgpu::Buffer; 6],
+        environment: Option<&EnvironmentCtx>,
+        gfx: &GfxState,
+    ) -> wgpu::BindGroup {
+        let mut entries: Vec<wgpu::BindGroupEntry> = buffers
+            .iter()
+            .enumerate()
+            .map(|(binding, buffer)| wgpu::BindGroupEntry {
+                binding: binding as u32,
+                resource: buffer.as_entire_binding(),
+            })
+            .collect();
+
+        // Only the fragment layout samples the environment
+        if let Some(environment) = environment {
+            entries.extend([
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: wgpu::BindingResource::TextureView(&environment.irradiance_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 7,
+                    resource: wgpu::BindingResource::TextureView(&environment.specular_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 8,
+                    resource: wgpu::BindingResource::TextureView(&environment.brdf_lut_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 9,
+                    resource: wgpu::BindingResource::Sampler(&environment.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 10,
+                    resource: environment.buffer.as_entire_binding(),
+                },
+            ]);
+        }
+
+        gfx.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Lights bind group"),
+            layout,
+            entries: &entries,
+        })
+    }
+}
@@ -2,6 +2,7 @@ use super::GfxState;
 use crate::{loader::CIRCLE_MAT_ID, texture::TexType, traits::CreateFxView, util::ID};
 use egui_wgpu::wgpu;
 use std::collections::HashMap;
+use std::sync::Arc;
 
 pub struct Material {
     pub ctx: MaterialCtx,
@@ -9,16 +10,17 @@ pub struct Material {
     pub bg_layout: wgpu::BindGroupLayout,
 }
 
+/// Textures are shared between the materials of a model that use the same image
 pub struct MaterialCtx {
-    pub albedo_tex: wgpu::Texture,
+    pub albedo_tex: Arc<wgpu::Texture>,
     pub albedo_s: wgpu::Sampler,
-    pub metallic_roughness_tex: wgpu::Texture,
+    pub metallic_roughness_tex: Arc<wgpu::Texture>,
     pub metallic_roughness_s: wgpu::Sampler,
-    pub normal_tex: wgpu::Texture,
+    pub normal_tex: Arc<wgpu::Texture>,
     pub normal_s: wgpu::Sampler,
-    pub emissive_tex: wgpu::Texture,
+    pub emissive_tex: Arc<wgpu::Texture>,
     pub emissive_s: wgpu::Sampler,
-    pub ao_tex: wgpu::Texture,
+    pub ao_tex: Arc<wgpu::Texture>,
     pub ao_s: wgpu::Sampler,
     pub cull_mode: Option<wgpu::Face>,
 }
@@ -28,16 +30,16 @@ impl Material {
         let mut materials = HashMap::new();
 
         // White
-        let albedo_tex = gfx.create_builtin_tex(TexType::White);
+        let albedo_tex = Arc::new(gfx.create_builtin_tex(TexType::White));
         let albedo_s = gfx.create_sampler();
-        let metallic_roughness_tex = gfx.create_builtin_tex(TexType::Black);
+        let metallic_roughness_tex = Arc::new(gfx.create_builtin_tex(TexType::Black));
         let metallic_roughness_s = gfx.create_sampler();
 
-        let normal_tex = gfx.create_builtin_tex(TexType::Normal);
+        let normal_tex = Arc::new(gfx.create_builtin_tex(TexType::Normal));
         let normal_s = gfx.create_sampler();
-        let emissive_tex = gfx.create_builtin_tex(TexType::Black);
+        let emissive_tex = Arc::new(gfx.create_builtin_tex(TexType::Black));
         let emissive_s = gfx.create_sampler();
-        let ao_tex = gfx.create_builtin_tex(TexType::White);
+        let ao_tex = Arc::new(gfx.create_builtin_tex(TexType::White));
         let ao_s = gfx.create_sampler();
 
         materials.insert(
@@ -1,22 +1,42 @@
 pub mod camera;
 pub mod clock;
 pub mod color;
+pub mod easing;
+pub mod effect_library;
 pub mod emitter;
 pub mod emitter_state;
+pub mod environment;
 pub mod events;
+pub mod exposed_params;
 pub mod gfx_state;
 pub mod life_cycle;
+pub mod lights;
 pub mod material;
 pub mod mesh;
+pub mod scene_lighting;
 pub mod state;
+pub mod static_scene;
+pub mod sub_emitter;
+pub mod transform;
 
-pub use camera::{Camera, TonemapType};
+pub use camera::{Camera, ExternalView, TonemapType};
 pub use clock::Clock;
-pub use emitter::{Boundry, EmitterSettings, EmitterUniform, MaterialRef, MeshRef};
+pub use easing::{Easing, EasingCurve, EasingMode};
+pub use effect_library::{EffectError, EffectHandle, EffectLibrary, StopMode};
+pub use emitter::{
+    Boundry, EmitterGpu, EmitterSettings, EmitterUniform, MaterialRef, MeshRef, ParticleGpu,
+};
 pub use emitter_state::{CreateEmitterOptions, EmitterState, EmitterType};
+pub use environment::{EnvironmentCtx, EnvironmentMap};
 pub use events::SparEvents;
-pub use gfx_state::GfxState;
+pub use exposed_params::{ExposedParam, ParamError, ParamTarget, ParamValue};
+pub use gfx_state::{EmbedOptions, GfxState, SharedDevice, WindowCtx};
 pub use life_cycle::LifeCycle;
+pub use lights::{LightSelection, LightsCtx};
 pub use material::Material;
 pub use mesh::{Mesh, ModelVertex};
+pub use scene_lighting::{HemisphereLight, SceneLight, SceneLightKind, SceneLighting};
 pub use state::SparState;
+pub use static_scene::StaticScene;
+pub use sub_emitter::{SubEmitter, SubEmitterCtx, SubEmitterTrigger};
+pub use transform::{EffectTransform, EmitterParent, SimulationSpace};
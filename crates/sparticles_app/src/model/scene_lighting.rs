@@ -0,0 +1,228 @@
+use super::EnvironmentMap;
+use crate::asset_manager::{AssetKind, AssetManager};
+use crate::shaders::layout::gpu_struct;
+use glam::{Mat4, Vec3, Vec4};
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum SceneLightKind {
+    /// Infinitely far away, only the direction is used
+    Directional,
+    Point,
+    /// Cone angles in radians
+    Spot {
+        inner_cone: f32,
+        outer_cone: f32,
+    },
+}
+
+impl SceneLightKind {
+    pub const ALL: [SceneLightKind; 3] = [
+        Self::Directional,
+        Self::Point,
+        Self::Spot {
+            inner_cone: 0.,
+            outer_cone: std::f32::consts::FRAC_PI_4,
+        },
+    ];
+
+    pub fn same_kind(&self, other: &Self) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
+
+    fn shader_value(&self) -> f32 {
+        match self {
+            Self::Directional => 0.,
+            Self::Point => 1.,
+            Self::Spot { .. } => 2.,
+        }
+    }
+}
+
+impl Display for SceneLightKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Directional => f.write_str("Directional"),
+            Self::Point => f.write_str("Point"),
+            Self::Spot { .. } => f.write_str("Spot"),
+        }
+    }
+}
+
+/// Static light source of the scene, lights every emitter
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SceneLight {
+    pub kind: SceneLightKind,
+    /// Unused by directional lights
+    pub position: Vec3,
+    /// Direction the light travels in, unused by point lights
+    pub direction: Vec3,
+    pub color: Vec3,
+    pub intensity: f32,
+    /// Distance at which the light stops lighting, unlimited if none
+    pub range: Option<f32>,
+}
+
+impl SceneLight {
+    pub fn new(kind: SceneLightKind) -> Self {
+        Self {
+            kind,
+            position: Vec3::new(0., 5., 0.),
+            direction: Vec3::new(0., -1., 0.),
+            color: Vec3::ONE,
+            intensity: 1.,
+            range: None,
+        }
+    }
+}
+
+/// Ambient light that blends from the ground color to the sky color along the normal
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HemisphereLight {
+    pub sky_color: Vec3,
+    pub ground_color: Vec3,
+    pub intensity: f32,
+}
+
+impl Default for HemisphereLight {
+    fn default() -> Self {
+        Self {
+            sky_color: Vec3::ONE,
+            ground_color: Vec3::splat(0.2),
+            intensity: 0.,
+        }
+    }
+}
+
+/// Light sources besides light particles, evaluated for every lit emitter
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SceneLighting {
+    pub ambient: HemisphereLight,
+    pub lights: Vec<SceneLight>,
+    #[serde(default)]
+    pub environment: EnvironmentMap,
+}
+
+gpu_struct! {
+    pub(crate) struct SceneLightUniform as "SceneLight" {
+        /// w: kind
+        position: Vec4,
+        /// w: range, 0 is unlimited
+        direction: Vec4,
+        /// w: intensity
+        color: Vec4,
+        /// x: cosine inner cone, y: cosine outer cone
+        cone: Vec4,
+    }
+}
+
+gpu_struct! {
+    pub(crate) struct SceneLightingUniform as "SceneLighting" {
+        sky_color: Vec4,
+        ground_color: Vec4,
+        light_count: u32,
+        #[size(runtime)]
+        lights: Vec<SceneLightUniform>,
+    }
+}
+
+impl SceneLighting {
+    /// Punctual lights (KHR_lights_punctual) of every scene in the model file
+    pub fn load_gltf(filename: &str) -> anyhow::Result<Vec<SceneLight>> {
+        let data = AssetManager::read(AssetKind::Model, filename)?;
+        let gltf = gltf::Gltf::from_slice(&data)?;
+
+        let mut lights = Vec::new();
+
+        for scene in gltf.scenes() {
+            for node in scene.nodes() {
+                Self::collect_gltf_lights(&node, Mat4::IDENTITY, &mut lights);
+            }
+        }
+
+        Ok(lights)
+    }
+
+    fn collect_gltf_lights(node: &gltf::Node, parent_world: Mat4, lights: &mut Vec<SceneLight>) {
+        let world = parent_world * Mat4::from_cols_array_2d(&node.transform().matrix());
+
+        if let Some(light) = node.light() {
+            let kind = match light.kind() {
+                gltf::khr_lights_punctual::Kind::Directional => SceneLightKind::Directional,
+                gltf::khr_lights_punctual::Kind::Point => SceneLightKind::Point,
+                gltf::khr_lights_punctual::Kind::Spot {
+                    inner_cone_angle,
+                    outer_cone_angle,
+                } => SceneLightKind::Spot {
+                    inner_cone: inner_cone_angle,
+                    outer_cone: outer_cone_angle,
+                },
+            };
+
+            // Lights point down their local -Z axis
+            lights.push(SceneLight {
+                kind,
+                position: world.transform_point3(Vec3::ZERO),
+                direction: world
+                    .transform_vector3(Vec3::NEG_Z)
+                    .try_normalize()
+                    .unwrap_or(Vec3::NEG_Y),
+                color: light.color().into(),
+                intensity: light.intensity(),
+                range: light.range(),
+            });
+        }
+
+        for child in node.children() {
+            Self::collect_gltf_lights(&child, world, lights);
+        }
+    }
+
+    pub(crate) fn uniform(&self) -> SceneLightingUniform {
+        let mut lights: Vec<SceneLightUniform> = self
+            .lights
+            .iter()
+            .map(|light| {
+                let (cos_inner, cos_outer) = match light.kind {
+                    SceneLightKind::Spot {
+                        inner_cone,
+                        outer_cone,
+                    } => (inner_cone.cos(), outer_cone.cos()),
+                    _ => (1., -1.),
+                };
+
+                SceneLightUniform {
+                    position: light.position.extend(light.kind.shader_value()),
+                    direction: light
+                        .direction
+                        .normalize_or_zero()
+                        .extend(light.range.unwrap_or(0.)),
+                    color: light.color.extend(light.intensity),
+                    cone: Vec4::new(cos_inner, cos_outer, 0., 0.),
+                }
+            })
+            .collect();
+
+        let light_count = lights.len() as u32;
+
+        // Runtime sized arrays need at least one element
+        if lights.is_empty() {
+            lights.push(SceneLightUniform {
+                position: Vec4::ZERO,
+                direction: Vec4::ZERO,
+                color: Vec4::ZERO,
+                cone: Vec4::ZERO,
+            });
+        }
+
+        let ambient = &self.ambient;
+
+        SceneLightingUniform {
+            sky_color: ambient.sky_color.extend(ambient.intensity),
+            ground_color: ambient.ground_color.extend(ambient.intensity),
+            light_count,
+            lights,
+        }
+    }
+}
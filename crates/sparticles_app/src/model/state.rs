@@ -1,15 +1,23 @@
 use super::{
-    Camera, Clock, EmitterState, GfxState, Material, MaterialRef, Mesh, MeshRef, SparEvents,
+    Camera, Clock, EffectLibrary, EffectTransform, EmbedOptions, EmitterState, EnvironmentCtx,
+    GfxState, LightsCtx, Material, MaterialRef, Mesh, MeshRef, ParamError, ParamValue,
+    SceneLighting, SparEvents, StaticScene,
 };
+use crate::asset_manager::{AssetLoader, AssetManager};
 use crate::fx::PostProcessState;
 use crate::init::{AppVisitor, Init};
-use crate::loader::Model;
+use crate::loader::{Model, BUILTIN_ID, CIRCLE_MAT_ID, CIRCLE_MESH_ID};
+use crate::shaders::ShaderHotReload;
 use crate::traits::*;
-use crate::util::ID;
+use crate::util::{
+    ExportCamera, ImportDiagnostic, SceneDocument, SceneError, Unrecognized, ID,
+    SCENE_FORMAT_VERSION,
+};
 use async_std::sync::RwLock;
 use async_std::task;
-use egui_winit::winit::{dpi::PhysicalSize, event::KeyboardInput, window::Window};
+use egui_winit::winit::{dpi::PhysicalSize, event::KeyEvent, window::Window};
 use std::collections::HashMap;
+use std::path::PathBuf;
 use std::sync::Arc;
 
 /// Sparticles state
@@ -17,13 +25,30 @@ pub struct SparState {
     pub camera: Camera,
     pub clock: Clock,
     pub emitters: Vec<EmitterState>,
+    pub effect_root: EffectTransform,
+    /// Effect templates and the instances spawned from them
+    pub effects: EffectLibrary,
+    pub lights: LightsCtx,
+    pub scene_lighting: SceneLighting,
+    pub environment: EnvironmentCtx,
+    pub static_scene: StaticScene,
     pub post_process: PostProcessState,
     pub gfx: Arc<RwLock<GfxState>>,
     pub collection: Arc<RwLock<HashMap<ID, Model>>>,
+    pub assets: AssetLoader,
+    pub shader_reload: ShaderHotReload,
     pub play: bool,
     pub registry_par_anims: Vec<Box<dyn RegisterParticleAnimation>>,
     pub registry_em_anims: Vec<Box<dyn RegisterEmitterAnimation>>,
     pub registered_post_fx: Vec<Box<dyn RegisterPostFx>>,
+    /// Where the scene document is exported to, see AppVisitor::exports_dir
+    pub exports_dir: PathBuf,
+    /// Problems found while importing the scene on startup
+    pub import_diagnostics: Vec<ImportDiagnostic>,
+    /// Fields of the imported scene document this build doesn't know, exported again
+    pub unknown_scene_fields: serde_json::Map<String, serde_json::Value>,
+    /// Emitters of the imported scene document that couldn't be loaded, exported again as is
+    pub unrecognized_emitters: Vec<Unrecognized<serde_json::Value>>,
 }
 
 pub trait FastFetch {
@@ -31,35 +56,115 @@ pub trait FastFetch {
     fn get_mat(&self, mat_ref: &MaterialRef) -> &Material;
 }
 
+/// Collections that are not loaded (yet) are replaced by the builtin circle
 impl FastFetch for HashMap<ID, Model> {
     fn get_mesh(&self, mesh_ref: &MeshRef) -> &Mesh {
-        self.get(&mesh_ref.collection_id)
-            .unwrap_or_else(|| panic!("Collection doesn't exist: {:?}", &mesh_ref.collection_id))
+        let Some(model) = self.get(&mesh_ref.collection_id) else {
+            return &self[BUILTIN_ID].meshes[CIRCLE_MESH_ID];
+        };
+
+        model
             .meshes
             .get(&mesh_ref.mesh_id)
             .unwrap_or_else(|| panic!("Mesh doesn't exist: {:?}", &mesh_ref.mesh_id))
     }
 
     fn get_mat(&self, mat_ref: &MaterialRef) -> &Material {
-        self.get(&mat_ref.collection_id)
-            .unwrap_or_else(|| panic!("Collection doesn't exist: {:?}", &mat_ref.collection_id))
+        let Some(model) = self.get(&mat_ref.collection_id) else {
+            return &self[BUILTIN_ID].materials[CIRCLE_MAT_ID];
+        };
+
+        model
             .materials
             .get(&mat_ref.material_id)
-            .unwrap_or_else(|| panic!("Mesh doesn't exist: {:?}", &mat_ref.material_id))
+            .unwrap_or_else(|| panic!("Material doesn't exist: {:?}", &mat_ref.material_id))
     }
 }
 
 impl SparState {
-    pub async fn update(&mut self, events: &SparEvents) {
+    /// Consumes the events, afterwards they only report what happened during the update
+    pub async fn update(&mut self, events: &mut SparEvents) {
         self.clock.update(self.play);
 
         if events.toggle_play {
             self.play = !self.play;
         }
 
+        AssetLoader::update(self, events).await;
         Camera::update(self, events).await;
         PostProcessState::update(self, events).await;
+        EnvironmentCtx::update(self).await;
+        StaticScene::update(self).await;
+        EffectLibrary::update(self).await;
         EmitterState::update(self, events).await;
+
+        if events.export_scene {
+            match self.export_scene() {
+                Ok(path) => println!("Exported scene to {}", path.display()),
+                Err(err) => println!("{}", err),
+            }
+        }
+
+        *events = SparEvents {
+            asset_events: self.assets.drain_events(),
+            ..Default::default()
+        };
+    }
+
+    /// The emitters, post fx, camera and lighting of the scene
+    pub fn scene_document(&self) -> SceneDocument {
+        SceneDocument {
+            format_version: SCENE_FORMAT_VERSION,
+            emitters: EmitterState::export(&self.emitters),
+            post_fx: PostProcessState::export(&self.post_process),
+            camera: Some(ExportCamera::new(&self.camera)),
+            scene_lighting: self.scene_lighting.clone(),
+            effect_root: self.effect_root,
+            unknown_fields: self.unknown_scene_fields.clone(),
+            unrecognized_emitters: self.unrecognized_emitters.clone(),
+            diagnostics: vec![],
+        }
+    }
+
+    pub fn export_scene(&self) -> Result<PathBuf, SceneError> {
+        self.scene_document().save(&self.exports_dir)
+    }
+
+    /// Sets an exposed parameter of the scene emitters, use the effect library for instances.
+    /// Nothing is written if one of the emitters has an invalid target.
+    pub async fn set_param(&mut self, name: &str, value: ParamValue) -> Result<(), ParamError> {
+        let gfx = &self.gfx.read().await;
+        let is_exposed = |em: &&mut EmitterState| {
+            em.instance_root.is_none() && em.uniform.exposed_params.iter().any(|p| p.name == name)
+        };
+
+        let mut emitters: Vec<&mut EmitterState> =
+            self.emitters.iter_mut().filter(is_exposed).collect();
+
+        if emitters.is_empty() {
+            return Err(ParamError::UnknownParam(name.to_string()));
+        }
+
+        for emitter in emitters.iter() {
+            emitter.check_param(
+                name,
+                value,
+                &self.registry_par_anims,
+                &self.registry_em_anims,
+            )?;
+        }
+
+        for emitter in emitters.iter_mut() {
+            emitter.set_param(
+                name,
+                value,
+                gfx,
+                &self.registry_par_anims,
+                &self.registry_em_anims,
+            )?;
+        }
+
+        Ok(())
     }
 
     pub async fn resize(&mut self, size: PhysicalSize<u32>) {
@@ -69,7 +174,7 @@ impl SparState {
         self.camera.resize(&gfx);
     }
 
-    pub fn process_events(&mut self, input: &KeyboardInput) {
+    pub fn process_events(&mut self, input: &KeyEvent) {
         self.camera.process_input(input);
     }
 
@@ -79,11 +184,33 @@ impl SparState {
     }
 
     pub async fn new(init: &mut impl AppVisitor, window: Window) -> Self {
+        AssetManager::configure(init.asset_config());
+
         let gfx = GfxState::new(window).await;
+        Self::from_gfx(init, gfx).await
+    }
+
+    /// Renders with the device of a host engine instead of creating a window
+    pub async fn embedded(init: &mut impl AppVisitor, options: EmbedOptions) -> Self {
+        AssetManager::configure(init.asset_config());
+
+        let gfx = GfxState::embedded(options);
+        Self::from_gfx(init, gfx).await
+    }
+
+    async fn from_gfx(init: &mut impl AppVisitor, gfx: GfxState) -> Self {
         let clock = Clock::default();
 
-        let camera = Camera::new(&gfx);
+        let mut camera = Camera::new(&gfx);
+        let environment = EnvironmentCtx::new(&gfx, &camera);
+        let lights = LightsCtx::new(&gfx, &environment);
         let builtin = Model::load_builtin(&gfx);
+        let static_scene = StaticScene::new(
+            &gfx,
+            &camera,
+            &lights,
+            &builtin.materials[CIRCLE_MAT_ID].bg_layout,
+        );
 
         let mut collection = HashMap::new();
         let mut post_process = PostProcessState::new(&gfx, init);
@@ -93,19 +220,39 @@ impl SparState {
         let gfx = Arc::new(RwLock::new(gfx));
         let collection = Arc::new(RwLock::new(collection));
 
-        let init_settings = Init::new(init, &gfx, &camera, &collection, &mut post_process).await;
+        let init_settings =
+            Init::new(init, &gfx, &camera, &lights, &collection, &mut post_process).await;
+
+        let mut effects = EffectLibrary::default();
+        init.register_effects(&mut effects);
+
+        if let Some(export) = &init_settings.camera {
+            export.apply(&mut camera);
+        }
 
         let mut state = Self {
             clock,
             camera,
             emitters: init_settings.emitters,
+            effect_root: init_settings.effect_root,
+            effects,
+            lights,
+            scene_lighting: init_settings.scene_lighting,
+            environment,
+            static_scene,
             post_process,
             gfx,
             registry_par_anims: init_settings.registry_par_anims,
             registry_em_anims: init_settings.registry_em_anims,
             registered_post_fx: init_settings.registry_post_fx,
             collection,
+            assets: AssetLoader::default(),
+            shader_reload: ShaderHotReload::new(),
             play: true,
+            exports_dir: init.exports_dir(),
+            import_diagnostics: init_settings.diagnostics,
+            unknown_scene_fields: init_settings.unknown_scene_fields,
+            unrecognized_emitters: init_settings.unrecognized_emitters,
         };
 
         init.add_widget_builders(&mut state);
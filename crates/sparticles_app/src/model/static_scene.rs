@@ -0,0 +1,258 @@
+use super::state::FastFetch;
+use super::{Camera, GfxState, LightsCtx, MaterialRef, MeshRef, ModelVertex, SparState};
+use crate::fx::PostProcessState;
+use crate::loader::{Model, BUILTIN_ID, CIRCLE_MAT_ID};
+use crate::shaders::{layout::gpu_struct, ShaderOptions};
+use crate::traits::BufferContent;
+use crate::util::ID;
+use egui_wgpu::wgpu::{self, util::DeviceExt};
+use encase::ShaderType;
+use glam::Mat4;
+use std::collections::HashMap;
+use std::num::NonZeroU64;
+
+gpu_struct! {
+    pub(crate) struct SceneNodeUniform as "SceneNode" {
+        world: Mat4,
+        normal: Mat4,
+    }
+}
+
+struct StaticNode {
+    mesh: MeshRef,
+    material: MaterialRef,
+    bg: wgpu::BindGroup,
+}
+
+/// Draws the node hierarchy of an imported glTF model as static geometry,
+/// sharing the depth buffer and the lights with the particles
+pub struct StaticScene {
+    /// Collection id of the model, nothing is drawn if none
+    pub model_id: Option<ID>,
+    built_id: Option<ID>,
+    nodes: Vec<StaticNode>,
+    node_bg_layout: wgpu::BindGroupLayout,
+    pipeline: wgpu::RenderPipeline,
+}
+
+impl StaticScene {
+    /// Rebuilds the nodes once the selected model is part of the collection
+    pub async fn update(state: &mut SparState) {
+        let SparState {
+            static_scene,
+            collection,
+            gfx,
+            ..
+        } = state;
+
+        if static_scene.model_id == static_scene.built_id {
+            return;
+        }
+
+        let collection = collection.read().await;
+        let gfx = &gfx.read().await;
+
+        match &static_scene.model_id {
+            Some(model_id) => {
+                let Some(model) = collection.get(model_id) else {
+                    // Still being imported
+                    return;
+                };
+
+                static_scene.nodes = static_scene.create_nodes(model, gfx);
+            }
+            None => static_scene.nodes.clear(),
+        }
+
+        static_scene.built_id = static_scene.model_id.clone();
+    }
+
+    pub fn render<'a>(
+        &'a self,
+        camera: &'a Camera,
+        lights: &'a LightsCtx,
+        collection: &'a HashMap<ID, Model>,
+        r_pass: &mut wgpu::RenderPass<'a>,
+    ) {
+        if self.nodes.is_empty() {
+            return;
+        }
+
+        r_pass.set_pipeline(&self.pipeline);
+        r_pass.set_bind_group(0, camera.bg(), &[]);
+        r_pass.set_bind_group(3, lights.bg(), &[]);
+
+        for node in self.nodes.iter() {
+            let mesh = collection.get_mesh(&node.mesh);
+            let mat = collection.get_mat(&node.material);
+
+            r_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+            r_pass.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+            r_pass.set_bind_group(1, &mat.bg, &[]);
+            r_pass.set_bind_group(2, &node.bg, &[]);
+            r_pass.draw_indexed(mesh.indices_range(), 0, 0..1);
+        }
+    }
+
+    fn create_nodes(&self, model: &Model, gfx: &GfxState) -> Vec<StaticNode> {
+        model
+            .nodes
+            .iter()
+            .map(|node| {
+                let uniform = SceneNodeUniform {
+                    world: node.world,
+                    normal: node.world.inverse().transpose(),
+                };
+
+                let buffer = gfx
+                    .device
+                    .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                        label: Some("Scene node buffer"),
+                        contents: &uniform.buffer_content(),
+                        usage: wgpu::BufferUsages::UNIFORM,
+                    });
+
+                let bg = gfx.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("Scene node bind group"),
+                    layout: &self.node_bg_layout,
+                    entries: &[wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: buffer.as_entire_binding(),
+                    }],
+                });
+
+                let material = match &node.material_id {
+                    Some(material_id) => MaterialRef {
+                        collection_id: model.id.to_string(),
+                        material_id: material_id.to_string(),
+                    },
+                    None => MaterialRef {
+                        collection_id: BUILTIN_ID.to_string(),
+                        material_id: CIRCLE_MAT_ID.to_string(),
+                    },
+                };
+
+                StaticNode {
+                    mesh: MeshRef {
+                        collection_id: model.id.to_string(),
+                        mesh_id: node.mesh_id.to_string(),
+                    },
+                    material,
+                    bg,
+                }
+            })
+            .collect()
+    }
+
+    /// Material layouts are equal for every material
+    pub fn new(
+        gfx: &GfxState,
+        camera: &Camera,
+        lights: &LightsCtx,
+        material_layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        let device = &gfx.device;
+
+        let node_bg_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Scene node layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: NonZeroU64::new(SceneNodeUniform::min_size().get()),
+                },
+                count: None,
+            }],
+        });
+
+        let pipeline = Self::create_pipeline(gfx, camera, lights, material_layout, &node_bg_layout);
+
+        Self {
+            model_id: None,
+            built_id: None,
+            nodes: vec![],
+            node_bg_layout,
+            pipeline,
+        }
+    }
+
+    /// Used after static_scene.wgsl changed
+    pub fn recreate_pipeline(
+        &mut self,
+        gfx: &GfxState,
+        camera: &Camera,
+        lights: &LightsCtx,
+        material_layout: &wgpu::BindGroupLayout,
+    ) {
+        self.pipeline =
+            Self::create_pipeline(gfx, camera, lights, material_layout, &self.node_bg_layout);
+    }
+
+    fn create_pipeline(
+        gfx: &GfxState,
+        camera: &Camera,
+        lights: &LightsCtx,
+        material_layout: &wgpu::BindGroupLayout,
+        node_bg_layout: &wgpu::BindGroupLayout,
+    ) -> wgpu::RenderPipeline {
+        let device = &gfx.device;
+
+        let shader = gfx.create_shader_builtin(ShaderOptions {
+            files: &["static_scene.wgsl"],
+            if_directives: &[],
+            label: "Static scene",
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Static scene pipeline layout"),
+            bind_group_layouts: &[
+                &camera.bg_layout,
+                material_layout,
+                node_bg_layout,
+                &lights.bg_layout,
+            ],
+            push_constant_ranges: &[],
+        });
+
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Static scene pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[ModelVertex::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[
+                    Some(wgpu::ColorTargetState {
+                        format: PostProcessState::TEXTURE_FORMAT,
+                        blend: Some(wgpu::BlendState::REPLACE),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }),
+                    Some(wgpu::ColorTargetState {
+                        format: PostProcessState::TEXTURE_FORMAT,
+                        blend: Some(wgpu::BlendState::REPLACE),
+                        write_mask: wgpu::ColorWrites::COLOR,
+                    }),
+                ],
+            }),
+            primitive: wgpu::PrimitiveState {
+                cull_mode: Some(wgpu::Face::Back),
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: GfxState::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        })
+    }
+}
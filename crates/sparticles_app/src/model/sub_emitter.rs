@@ -0,0 +1,604 @@
+use super::{Clock, EmitterState, GfxState};
+use crate::shaders::{layout::gpu_struct, ShaderOptions};
+use crate::traits::BufferContent;
+use crate::util::ID;
+use egui_wgpu::wgpu;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use std::num::NonZeroU64;
+
+/// Header (count, head, max_events, padding) in front of the events array
+const EVENTS_HEADER_SIZE: u64 = 4 * 4;
+/// Position, velocity and color as vec4's
+const EVENT_SIZE: u64 = 12 * 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum SubEmitterTrigger {
+    /// When a parent particle is spawned
+    Spawn,
+    /// When a parent particle exceeds its lifetime
+    Death,
+    /// When a parent particle moves downwards through the horizontal plane at plane_y
+    Collision { plane_y: f32 },
+    /// Every interval during the lifetime of a parent particle
+    Interval { every_sec: f32 },
+}
+
+impl SubEmitterTrigger {
+    pub const ALL: [SubEmitterTrigger; 4] = [
+        Self::Spawn,
+        Self::Death,
+        Self::Collision { plane_y: 0. },
+        Self::Interval { every_sec: 1. },
+    ];
+
+    fn shader_values(&self) -> (u32, f32) {
+        match self {
+            Self::Spawn => (0, 0.),
+            Self::Death => (1, 0.),
+            Self::Collision { plane_y } => (2, *plane_y),
+            Self::Interval { every_sec } => (3, *every_sec),
+        }
+    }
+
+    pub fn same_kind(&self, other: &Self) -> bool {
+        self.shader_values().0 == other.shader_values().0
+    }
+}
+
+impl Display for SubEmitterTrigger {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Spawn => f.write_str("On spawn"),
+            Self::Death => f.write_str("On death"),
+            Self::Collision { .. } => f.write_str("On collision"),
+            Self::Interval { .. } => f.write_str("Every N seconds"),
+        }
+    }
+}
+
+/// Spawns particles of the child emitter at the position of triggered parent particles
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SubEmitter {
+    pub child_id: ID,
+    pub trigger: SubEmitterTrigger,
+    /// Child particles spawned per trigger event
+    pub spawn_count: u32,
+    /// Fraction of the parent particle velocity added to the child particles
+    pub inherit_velocity: f32,
+    /// Use the parent particle color instead of the child emitter color
+    pub inherit_color: bool,
+}
+
+impl SubEmitter {
+    pub fn new(child_id: ID) -> Self {
+        Self {
+            child_id,
+            trigger: SubEmitterTrigger::Death,
+            spawn_count: 10,
+            inherit_velocity: 0.,
+            inherit_color: false,
+        }
+    }
+}
+
+gpu_struct! {
+    pub(crate) struct SubEmitterUniform as "SubEmitter" {
+        trigger: u32,
+        trigger_value: f32,
+        spawn_count: u32,
+        inherit_velocity: f32,
+        inherit_color: u32,
+    }
+}
+
+impl From<&SubEmitter> for SubEmitterUniform {
+    fn from(value: &SubEmitter) -> Self {
+        let (trigger, trigger_value) = value.trigger.shader_values();
+
+        Self {
+            trigger,
+            trigger_value,
+            spawn_count: value.spawn_count.max(1),
+            inherit_velocity: value.inherit_velocity,
+            inherit_color: value.inherit_color as u32,
+        }
+    }
+}
+
+/// GPU side of a sub emitter, owned by the parent emitter
+pub struct SubEmitterCtx {
+    pub config: SubEmitter,
+    parent_particle_count: u64,
+    child_particle_count: u64,
+    trigger_pipeline: wgpu::ComputePipeline,
+    spawn_pipeline: wgpu::ComputePipeline,
+    reset_pipeline: wgpu::ComputePipeline,
+    bg: wgpu::BindGroup,
+    pub events_buffer: wgpu::Buffer,
+    pub uniform_buffer: wgpu::Buffer,
+}
+
+impl SubEmitterCtx {
+    /// (Re)creates the sub emitter links of all emitters that changed since the last update
+    pub fn link_all(emitters: &mut [EmitterState], gfx: &GfxState) {
+        for i in 0..emitters.len() {
+            let Some(config) = emitters[i].uniform.sub_emitter.clone() else {
+                emitters[i].sub_emitter = None;
+                continue;
+            };
+
+            let child_idx = emitters
+                .iter()
+                .position(|em| em.id() == config.child_id && em.id() != emitters[i].id());
+
+            let Some(child_idx) = child_idx else {
+                emitters[i].sub_emitter = None;
+                continue;
+            };
+
+            let parent = &emitters[i];
+            let child = &emitters[child_idx];
+
+            let is_outdated = parent.sub_emitter.as_ref().is_none_or(|ctx| {
+                ctx.config != config
+                    || ctx.parent_particle_count != parent.particle_count()
+                    || ctx.child_particle_count != child.particle_count()
+            });
+
+            if is_outdated {
+                let ctx = Self::new(config, parent, child, gfx);
+                emitters[i].sub_emitter = Some(ctx);
+            }
+        }
+    }
+
+    /// Ids of emitters that only spawn through a parent
+    pub fn child_ids(emitters: &[EmitterState]) -> Vec<ID> {
+        emitters
+            .iter()
+            .filter_map(|em| em.sub_emitter.as_ref())
+            .map(|ctx| ctx.config.child_id.to_string())
+            .collect()
+    }
+
+    /// Runs after all emitters are computed so children consume the events in the same frame.
+    /// Parents higher up the chain go first, so grand children can react to spawned children.
+    pub fn compute<'a>(
+        emitters: &'a [EmitterState],
+        clock: &Clock,
+        c_pass: &mut wgpu::ComputePass<'a>,
+    ) {
+        let nr = clock.get_bindgroup_nr();
+        let mut links: Vec<(usize, &EmitterState)> = emitters
+            .iter()
+            .filter(|em| em.sub_emitter.is_some())
+            .map(|em| (Self::depth(emitters, em), em))
+            .collect();
+
+        links.sort_by_key(|(depth, _)| *depth);
+
+        for (_, parent) in links {
+            let ctx = parent.sub_emitter.as_ref().unwrap();
+            let child = emitters.iter().find(|em| em.id() == ctx.config.child_id);
+
+            let Some(child) = child else {
+                continue;
+            };
+
+            c_pass.set_pipeline(&ctx.trigger_pipeline);
+            c_pass.set_bind_group(0, &parent.bgs[nr], &[]);
+            c_pass.set_bind_group(1, &ctx.bg, &[]);
+            c_pass.dispatch_workgroups(parent.dispatch_x_count, 1, 1);
+
+            c_pass.set_pipeline(&ctx.spawn_pipeline);
+            c_pass.set_bind_group(0, &child.bgs[nr], &[]);
+            c_pass.dispatch_workgroups(child.dispatch_x_count, 1, 1);
+
+            c_pass.set_pipeline(&ctx.reset_pipeline);
+            c_pass.dispatch_workgroups(1, 1, 1);
+        }
+    }
+
+    fn depth(emitters: &[EmitterState], emitter: &EmitterState) -> usize {
+        let mut depth = 0;
+        let mut id = emitter.id();
+
+        // Bounded by the emitter count so cyclic links can't loop forever
+        while depth < emitters.len() {
+            let parent = emitters.iter().find(|em| {
+                em.sub_emitter
+                    .as_ref()
+                    .is_some_and(|ctx| ctx.config.child_id == id)
+            });
+
+            match parent {
+                Some(parent) => {
+                    id = parent.id();
+                    depth += 1;
+                }
+                None => break,
+            }
+        }
+
+        depth
+    }
+
+    fn new(
+        config: SubEmitter,
+        parent: &EmitterState,
+        child: &EmitterState,
+        gfx_state: &GfxState,
+    ) -> Self {
+        let device = &gfx_state.device;
+        let max_events = parent.particle_count().max(1);
+        let events_size = EVENTS_HEADER_SIZE + max_events * EVENT_SIZE;
+
+        let events_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Sub emitter events buffer"),
+            size: events_size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let header = [0u32, 0, max_events as u32, 0];
+        gfx_state
+            .queue
+            .write_buffer(&events_buffer, 0, bytemuck::cast_slice(&header));
+
+        let uniform_content = SubEmitterUniform::from(&config).buffer_content();
+
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Sub emitter uniform buffer"),
+            size: uniform_content.len() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        gfx_state
+            .queue
+            .write_buffer(&uniform_buffer, 0, &uniform_content);
+
+        let bg_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Sub emitter layout"),
+            entries: &[
+                // Events
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: NonZeroU64::new(events_size),
+                    },
+                    count: None,
+                },
+                // Sub emitter settings
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: NonZeroU64::new(uniform_content.len() as u64),
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let bg = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Sub emitter bind group"),
+            layout: &bg_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: events_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let trigger_shader = gfx_state.create_shader_builtin(ShaderOptions {
+            files: &["sub_emitter_trigger.wgsl"],
+            if_directives: &[],
+            label: "Sub emitter trigger",
+        });
+
+        let spawn_shader = gfx_state.create_shader_builtin(ShaderOptions {
+            files: &["sub_emitter_spawn.wgsl"],
+            if_directives: &[],
+            label: "Sub emitter spawn",
+        });
+
+        let create_pipeline = |emitter: &EmitterState, shader: &wgpu::ShaderModule, entry_point| {
+            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Sub emitter layout"),
+                bind_group_layouts: &[&emitter.bg_layout, &bg_layout],
+                push_constant_ranges: &[],
+            });
+
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Sub emitter pipeline"),
+                layout: Some(&layout),
+                module: shader,
+                entry_point,
+            })
+        };
+
+        let trigger_pipeline = create_pipeline(parent, &trigger_shader, "main");
+        let spawn_pipeline = create_pipeline(child, &spawn_shader, "main");
+        let reset_pipeline = create_pipeline(child, &spawn_shader, "reset");
+
+        Self {
+            config,
+            parent_particle_count: parent.particle_count(),
+            child_particle_count: child.particle_count(),
+            trigger_pipeline,
+            spawn_pipeline,
+            reset_pipeline,
+            bg,
+            events_buffer,
+            uniform_buffer,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{EmitterGpu, ParticleGpu};
+    use crate::shaders::BuiltinShader;
+    use async_std::task;
+    use egui_wgpu::wgpu::util::DeviceExt;
+    use glam::{Mat4, Vec4};
+
+    /// Any adapter will do, software adapters included
+    fn device() -> Option<(wgpu::Device, wgpu::Queue)> {
+        let instance = wgpu::Instance::default();
+        let adapter =
+            task::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions::default()))?;
+
+        let descriptor = wgpu::DeviceDescriptor {
+            label: None,
+            required_features: wgpu::Features::empty(),
+            required_limits: adapter.limits(),
+        };
+
+        task::block_on(adapter.request_device(&descriptor, None)).ok()
+    }
+
+    fn storage_entry(binding: u32, read_only: bool) -> wgpu::BindGroupLayoutEntry {
+        wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        }
+    }
+
+    fn uniform_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+        wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        }
+    }
+
+    fn particle(lifetime: f32) -> ParticleGpu {
+        ParticleGpu {
+            model: Mat4::IDENTITY,
+            color: Vec4::ONE,
+            vel_mass: Vec4::ZERO,
+            scale: 1.,
+            lifetime,
+        }
+    }
+
+    /// Runs the emitter and the trigger shader for a number of frames, returns the event count
+    fn count_events(trigger: SubEmitterTrigger, lifetimes: &[f32], frames: usize) -> Option<u32> {
+        let (device, queue) = device()?;
+
+        let particles: Vec<ParticleGpu> = lifetimes.iter().map(|l| particle(*l)).collect();
+        let mut particle_content = encase::StorageBuffer::new(Vec::new());
+        particle_content.write(&particles).unwrap();
+
+        let particle_buffers: Vec<wgpu::Buffer> = (0..2)
+            .map(|_| {
+                device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: None,
+                    contents: particle_content.as_ref(),
+                    usage: wgpu::BufferUsages::STORAGE,
+                })
+            })
+            .collect();
+
+        let emitter = EmitterGpu {
+            delta_sec: 0.1,
+            particle_lifetime: 1.,
+            ..Default::default()
+        };
+
+        let emitter_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: &emitter.buffer_content(),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        let max_events = lifetimes.len() as u32;
+        let mut events_content = bytemuck::cast_slice(&[0u32, 0, max_events, 0]).to_vec();
+        events_content.resize(
+            (EVENTS_HEADER_SIZE + max_events as u64 * EVENT_SIZE) as usize,
+            0,
+        );
+
+        let events_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: &events_content,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        });
+
+        let mut config = SubEmitter::new("child".to_string());
+        config.trigger = trigger;
+
+        let sub_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: &SubEmitterUniform::from(&config).buffer_content(),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        let emitter_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                storage_entry(0, true),
+                storage_entry(1, false),
+                uniform_entry(2),
+            ],
+        });
+
+        let sub_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[storage_entry(0, false), uniform_entry(1)],
+        });
+
+        let emitter_bgs: Vec<wgpu::BindGroup> = (0..2)
+            .map(|i| {
+                device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: None,
+                    layout: &emitter_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: particle_buffers[i].as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: particle_buffers[(i + 1) % 2].as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 2,
+                            resource: emitter_buffer.as_entire_binding(),
+                        },
+                    ],
+                })
+            })
+            .collect();
+
+        let sub_bg = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &sub_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: events_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: sub_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let create_pipeline = |file: &str, layouts: &[&wgpu::BindGroupLayout]| {
+            let shader = BuiltinShader {
+                files: vec![file.to_string()],
+                if_directives: vec![],
+                label: file.to_string(),
+            };
+
+            let source = shader.source().unwrap();
+            let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: None,
+                source: wgpu::ShaderSource::Wgsl(source.code.into()),
+            });
+
+            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: None,
+                bind_group_layouts: layouts,
+                push_constant_ranges: &[],
+            });
+
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: None,
+                layout: Some(&layout),
+                module: &module,
+                entry_point: "main",
+            })
+        };
+
+        let emitter_pipeline = create_pipeline("emitter.wgsl", &[&emitter_layout]);
+        let trigger_pipeline =
+            create_pipeline("sub_emitter_trigger.wgsl", &[&emitter_layout, &sub_layout]);
+
+        let read_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: 4,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+
+        for frame in 0..frames {
+            let mut c_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor::default());
+
+            c_pass.set_pipeline(&emitter_pipeline);
+            c_pass.set_bind_group(0, &emitter_bgs[frame % 2], &[]);
+            c_pass.dispatch_workgroups(1, 1, 1);
+
+            // Events are counted over all frames, the reset pass isn't used
+            c_pass.set_pipeline(&trigger_pipeline);
+            c_pass.set_bind_group(1, &sub_bg, &[]);
+            c_pass.dispatch_workgroups(1, 1, 1);
+        }
+
+        encoder.copy_buffer_to_buffer(&events_buffer, 0, &read_buffer, 0, 4);
+        queue.submit(Some(encoder.finish()));
+
+        let slice = read_buffer.slice(..);
+        slice.map_async(wgpu::MapMode::Read, |result| result.unwrap());
+        device.poll(wgpu::Maintain::Wait);
+
+        let count = bytemuck::cast_slice::<u8, u32>(&slice.get_mapped_range())[0];
+        Some(count)
+    }
+
+    #[test]
+    fn particles_trigger_death_once() {
+        // Die after 1, 3 and 6 frames, the last one outlives the frames
+        let lifetimes = [0.95, 0.75, 0.45, -0.5];
+
+        let Some(count) = count_events(SubEmitterTrigger::Death, &lifetimes, 10) else {
+            eprintln!("No graphics adapter, skipped");
+            return;
+        };
+
+        assert_eq!(count, 3);
+    }
+
+    #[test]
+    fn particles_trigger_every_interval() {
+        let lifetimes = [0.05, 0.45];
+
+        let interval = SubEmitterTrigger::Interval { every_sec: 0.25 };
+        let Some(count) = count_events(interval, &lifetimes, 4) else {
+            eprintln!("No graphics adapter, skipped");
+            return;
+        };
+
+        // 0.05 passes 0.25, 0.45 passes 0.5 and 0.75
+        assert_eq!(count, 3);
+    }
+}
@@ -0,0 +1,131 @@
+use super::EmitterState;
+use crate::util::ID;
+use glam::{Mat3, Mat4, Vec3};
+use serde::{Deserialize, Serialize};
+
+/// What the local transform (box position and rotation) of an emitter is relative to
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum EmitterParent {
+    Emitter(ID),
+    EffectRoot,
+}
+
+/// Space the particles of an emitter are simulated in
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum SimulationSpace {
+    /// Spawned particles stay where they are when the emitter moves, which leaves trails
+    #[default]
+    World,
+    /// Spawned particles move along with the emitter transform
+    Local,
+}
+
+/// Root transform of an effect, moves every emitter parented to it
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct EffectTransform {
+    pub position: Vec3,
+    /// yaw, pitch, roll
+    pub rotation: Vec3,
+}
+
+/// Same rotation as yaw_matrix * pitch_matrix * roll_matrix in declarations.wgsl,
+/// which multiplies with row vectors
+pub fn rotation_matrix(rotation: Vec3) -> Mat3 {
+    Mat3::from_rotation_x(-rotation.z)
+        * Mat3::from_rotation_z(-rotation.y)
+        * Mat3::from_rotation_y(-rotation.x)
+}
+
+pub fn local_matrix(position: Vec3, rotation: Vec3) -> Mat4 {
+    Mat4::from_translation(position) * Mat4::from_mat3(rotation_matrix(rotation))
+}
+
+impl EffectTransform {
+    pub fn matrix(&self) -> Mat4 {
+        local_matrix(self.position, self.rotation)
+    }
+
+    /// Composes the parent world matrix of every emitter, parents are resolved before their children
+    pub fn update_world_matrices(&self, emitters: &mut [EmitterState]) {
+        let mut worlds: Vec<Option<Mat4>> = vec![None; emitters.len()];
+
+        let parent_worlds: Vec<Mat4> = (0..emitters.len())
+            .map(|i| self.parent_world(emitters, i, &mut worlds, 0))
+            .collect();
+
+        for (emitter, parent_world) in emitters.iter_mut().zip(parent_worlds) {
+            emitter.uniform.parent_world = parent_world;
+        }
+    }
+
+    fn parent_world(
+        &self,
+        emitters: &[EmitterState],
+        idx: usize,
+        worlds: &mut [Option<Mat4>],
+        depth: usize,
+    ) -> Mat4 {
+        // Cyclic parents are cut off once the chain is longer than the emitter list
+        if emitters.len() < depth {
+            return Mat4::IDENTITY;
+        }
+
+        match &emitters[idx].uniform.parent {
+            None => Mat4::IDENTITY,
+            Some(EmitterParent::EffectRoot) => match &emitters[idx].instance_root {
+                Some(instance_root) => instance_root.matrix(),
+                None => self.matrix(),
+            },
+            Some(EmitterParent::Emitter(id)) => {
+                let parent_idx = emitters.iter().position(|em| em.id() == id);
+
+                match parent_idx {
+                    Some(parent_idx) if parent_idx != idx => {
+                        self.world(emitters, parent_idx, worlds, depth + 1)
+                    }
+                    _ => Mat4::IDENTITY,
+                }
+            }
+        }
+    }
+
+    fn world(
+        &self,
+        emitters: &[EmitterState],
+        idx: usize,
+        worlds: &mut [Option<Mat4>],
+        depth: usize,
+    ) -> Mat4 {
+        if let Some(world) = worlds[idx] {
+            return world;
+        }
+
+        let parent_world = self.parent_world(emitters, idx, worlds, depth);
+        let world = parent_world * emitters[idx].uniform.local_matrix();
+        worlds[idx] = Some(world);
+
+        world
+    }
+}
+
+impl EmitterParent {
+    /// Whether the emitter with id is (indirectly) parented to ancestor_id
+    pub fn is_descendant(emitters: &[EmitterState], id: &str, ancestor_id: &str) -> bool {
+        let mut id = id.to_string();
+
+        for _ in 0..emitters.len() {
+            let parent = emitters
+                .iter()
+                .find(|em| em.id() == id)
+                .and_then(|em| em.uniform.parent.as_ref());
+
+            match parent {
+                Some(EmitterParent::Emitter(parent_id)) if parent_id == ancestor_id => return true,
+                Some(EmitterParent::Emitter(parent_id)) => id = parent_id.to_string(),
+                _ => return false,
+            }
+        }
+
+        false
+    }
+}
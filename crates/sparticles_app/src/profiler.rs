@@ -0,0 +1,317 @@
+//! GPU timer scopes, read back a few frames later so the queue never stalls
+use crate::wgpu;
+use std::collections::VecDeque;
+use std::fmt::{Display, Formatter};
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Two timestamps per scope
+const QUERIES_PER_FRAME: u32 = 1024;
+
+pub struct GpuProfilerSettings {
+    /// Disabled scopes still have to be balanced but don't write timestamps
+    pub enable_timer_scopes: bool,
+    /// Older frames are dropped when their results are not read in time
+    pub max_num_pending_frames: usize,
+}
+
+impl Default for GpuProfilerSettings {
+    fn default() -> Self {
+        Self {
+            enable_timer_scopes: true,
+            max_num_pending_frames: 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GpuTimerScopeResult {
+    pub label: String,
+    /// Seconds
+    pub time: Range<f64>,
+    pub nested_scopes: Vec<GpuTimerScopeResult>,
+}
+
+#[derive(Debug)]
+pub enum ProfilerError {
+    NoOpenScope,
+    /// Frame ended while scopes were still open
+    OpenScopes(usize),
+}
+
+impl Display for ProfilerError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoOpenScope => write!(f, "Ended a scope without opening one"),
+            Self::OpenScopes(count) => write!(f, "Frame ended with {} open scopes", count),
+        }
+    }
+}
+
+impl std::error::Error for ProfilerError {}
+
+pub trait ProfilerCommandRecorder {
+    fn write_timestamp(&mut self, query_set: &wgpu::QuerySet, query_index: u32);
+}
+
+impl ProfilerCommandRecorder for wgpu::CommandEncoder {
+    fn write_timestamp(&mut self, query_set: &wgpu::QuerySet, query_index: u32) {
+        wgpu::CommandEncoder::write_timestamp(self, query_set, query_index);
+    }
+}
+
+impl ProfilerCommandRecorder for wgpu::ComputePass<'_> {
+    fn write_timestamp(&mut self, query_set: &wgpu::QuerySet, query_index: u32) {
+        wgpu::ComputePass::write_timestamp(self, query_set, query_index);
+    }
+}
+
+impl ProfilerCommandRecorder for wgpu::RenderPass<'_> {
+    fn write_timestamp(&mut self, query_set: &wgpu::QuerySet, query_index: u32) {
+        wgpu::RenderPass::write_timestamp(self, query_set, query_index);
+    }
+}
+
+struct Scope {
+    label: String,
+    /// Start query, the end query follows it. None when timestamps are disabled or the frame is full
+    query: Option<u32>,
+    nested: Vec<Scope>,
+}
+
+struct Frame {
+    query_set: wgpu::QuerySet,
+    resolve_buffer: wgpu::Buffer,
+    read_buffer: wgpu::Buffer,
+    next_query: u32,
+    resolved: u32,
+    scopes: Vec<Scope>,
+    mapped: Arc<AtomicBool>,
+}
+
+impl Frame {
+    fn new(device: &wgpu::Device) -> Self {
+        let size = QUERIES_PER_FRAME as wgpu::BufferAddress * wgpu::QUERY_SIZE as u64;
+
+        Self {
+            query_set: device.create_query_set(&wgpu::QuerySetDescriptor {
+                label: Some("Profiler queries"),
+                ty: wgpu::QueryType::Timestamp,
+                count: QUERIES_PER_FRAME,
+            }),
+            resolve_buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Profiler resolve buffer"),
+                size,
+                usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            }),
+            read_buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Profiler read buffer"),
+                size,
+                usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                mapped_at_creation: false,
+            }),
+            next_query: 0,
+            resolved: 0,
+            scopes: Vec::new(),
+            mapped: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    fn read_size(&self) -> wgpu::BufferAddress {
+        self.resolved as wgpu::BufferAddress * wgpu::QUERY_SIZE as u64
+    }
+
+    fn reset(&mut self) {
+        self.next_query = 0;
+        self.resolved = 0;
+        self.scopes.clear();
+        self.mapped.store(false, Ordering::Release);
+    }
+}
+
+pub struct GpuProfiler {
+    settings: GpuProfilerSettings,
+    current: Option<Frame>,
+    open_scopes: Vec<Scope>,
+    closed_scopes: Vec<Scope>,
+    pending: VecDeque<Frame>,
+    unused: Vec<Frame>,
+}
+
+impl GpuProfiler {
+    pub const ALL_WGPU_TIMER_FEATURES: wgpu::Features =
+        wgpu::Features::TIMESTAMP_QUERY.union(wgpu::Features::TIMESTAMP_QUERY_INSIDE_PASSES);
+
+    pub fn new(settings: GpuProfilerSettings) -> Self {
+        Self {
+            settings,
+            current: None,
+            open_scopes: Vec::new(),
+            closed_scopes: Vec::new(),
+            pending: VecDeque::new(),
+            unused: Vec::new(),
+        }
+    }
+
+    pub fn begin_scope(
+        &mut self,
+        label: &str,
+        recorder: &mut impl ProfilerCommandRecorder,
+        device: &wgpu::Device,
+    ) {
+        let mut query = None;
+
+        if self.settings.enable_timer_scopes
+            && device.features().contains(Self::ALL_WGPU_TIMER_FEATURES)
+        {
+            let unused = &mut self.unused;
+            let frame = self
+                .current
+                .get_or_insert_with(|| unused.pop().unwrap_or_else(|| Frame::new(device)));
+
+            if frame.next_query + 2 <= QUERIES_PER_FRAME {
+                recorder.write_timestamp(&frame.query_set, frame.next_query);
+                query = Some(frame.next_query);
+                frame.next_query += 2;
+            }
+        }
+
+        self.open_scopes.push(Scope {
+            label: label.to_string(),
+            query,
+            nested: Vec::new(),
+        });
+    }
+
+    pub fn end_scope(
+        &mut self,
+        recorder: &mut impl ProfilerCommandRecorder,
+    ) -> Result<(), ProfilerError> {
+        let scope = self.open_scopes.pop().ok_or(ProfilerError::NoOpenScope)?;
+
+        if let (Some(query), Some(frame)) = (scope.query, &self.current) {
+            recorder.write_timestamp(&frame.query_set, query + 1);
+        }
+
+        match self.open_scopes.last_mut() {
+            Some(parent) => parent.nested.push(scope),
+            None => self.closed_scopes.push(scope),
+        }
+
+        Ok(())
+    }
+
+    /// Copies the timestamps of this frame to a readable buffer, call before submitting the encoder
+    pub fn resolve_queries(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        let Some(frame) = &mut self.current else {
+            return;
+        };
+
+        if frame.next_query == 0 {
+            return;
+        }
+
+        frame.resolved = frame.next_query;
+
+        encoder.resolve_query_set(
+            &frame.query_set,
+            0..frame.resolved,
+            &frame.resolve_buffer,
+            0,
+        );
+        encoder.copy_buffer_to_buffer(
+            &frame.resolve_buffer,
+            0,
+            &frame.read_buffer,
+            0,
+            frame.read_size(),
+        );
+    }
+
+    /// Call after submitting the encoder that resolved the queries
+    pub fn end_frame(&mut self) -> Result<(), ProfilerError> {
+        if !self.open_scopes.is_empty() {
+            return Err(ProfilerError::OpenScopes(self.open_scopes.len()));
+        }
+
+        let scopes = std::mem::take(&mut self.closed_scopes);
+
+        let Some(mut frame) = self.current.take() else {
+            return Ok(());
+        };
+
+        if frame.resolved == 0 {
+            frame.reset();
+            self.unused.push(frame);
+            return Ok(());
+        }
+
+        frame.scopes = scopes;
+
+        let mapped = frame.mapped.clone();
+        frame
+            .read_buffer
+            .slice(..frame.read_size())
+            .map_async(wgpu::MapMode::Read, move |res| {
+                mapped.store(res.is_ok(), Ordering::Release);
+            });
+
+        self.pending.push_back(frame);
+
+        while self.pending.len() > self.settings.max_num_pending_frames {
+            self.pending.pop_front();
+        }
+
+        Ok(())
+    }
+
+    /// Results of the oldest pending frame, None when the GPU hasn't finished it yet
+    pub fn process_finished_frame(
+        &mut self,
+        timestamp_period: f32,
+    ) -> Option<Vec<GpuTimerScopeResult>> {
+        let frame = self.pending.front()?;
+
+        if !frame.mapped.load(Ordering::Acquire) {
+            return None;
+        }
+
+        let mut frame = self.pending.pop_front()?;
+
+        let results = {
+            let view = frame
+                .read_buffer
+                .slice(..frame.read_size())
+                .get_mapped_range();
+            let timestamps: &[u64] = bytemuck::cast_slice(&view);
+            let to_sec = timestamp_period as f64 / 1_000_000_000.;
+
+            Self::results(&frame.scopes, timestamps, to_sec)
+        };
+
+        frame.read_buffer.unmap();
+        frame.reset();
+        self.unused.push(frame);
+
+        Some(results)
+    }
+
+    fn results(scopes: &[Scope], timestamps: &[u64], to_sec: f64) -> Vec<GpuTimerScopeResult> {
+        scopes
+            .iter()
+            .filter_map(|scope| {
+                let query = scope.query? as usize;
+                let start = *timestamps.get(query)? as f64 * to_sec;
+                let end = *timestamps.get(query + 1)? as f64 * to_sec;
+
+                Some(GpuTimerScopeResult {
+                    label: scope.label.clone(),
+                    time: start..end.max(start),
+                    nested_scopes: Self::results(&scope.nested, timestamps, to_sec),
+                })
+            })
+            .collect()
+    }
+}
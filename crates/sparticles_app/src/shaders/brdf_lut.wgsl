@@ -0,0 +1,56 @@
+#include "declarations.wgsl"
+#include "pbr/ibl.wgsl"
+
+@group(0) @binding(0) var output: texture_storage_2d<rgba16float, write>;
+
+const SAMPLE_COUNT: u32 = 512u;
+
+fn geometry_schlick_ggx_ibl(NdotV: f32, roughness: f32) -> f32 {
+    let k = (roughness * roughness) / 2.0;
+    return NdotV / (NdotV * (1.0 - k) + k);
+}
+
+// Split sum scale (x) and bias (y) to F0, by NdotV (u) and roughness (v)
+@compute
+@workgroup_size(8, 8, 1)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = textureDimensions(output);
+
+    if size.x <= id.x || size.y <= id.y {
+        return;
+    }
+
+    let uv = (vec2<f32>(id.xy) + 0.5) / vec2<f32>(size);
+    let NdotV = uv.x;
+    let roughness = uv.y;
+
+    let V = vec3(sqrt(1.0 - NdotV * NdotV), 0.0, NdotV);
+    let N = vec3(0.0, 0.0, 1.0);
+
+    var scale = 0.0;
+    var bias = 0.0;
+
+    for (var i = 0u; i < SAMPLE_COUNT; i++) {
+        let xi = hammersley(i, SAMPLE_COUNT);
+        let H = importance_sample_ggx(xi, N, roughness);
+        let L = normalize(2.0 * dot(V, H) * H - V);
+
+        let NdotL = max(L.z, 0.0);
+        let NdotH = max(H.z, 0.0);
+        let VdotH = max(dot(V, H), 0.0);
+
+        if 0.0 < NdotL {
+            let G = geometry_schlick_ggx_ibl(NdotV, roughness)
+                * geometry_schlick_ggx_ibl(NdotL, roughness);
+            let G_vis = (G * VdotH) / (NdotH * NdotV);
+            let Fc = pow(1.0 - VdotH, 5.0);
+
+            scale += (1.0 - Fc) * G_vis;
+            bias += Fc * G_vis;
+        }
+    }
+
+    let brdf = vec2(scale, bias) / f32(SAMPLE_COUNT);
+
+    textureStore(output, id.xy, vec4(brdf, 0.0, 1.0));
+}
@@ -1,3 +1,5 @@
+#include "declarations.wgsl"
+
 // Includes declarations
 
 struct ColorAnimation {
@@ -22,7 +22,7 @@ struct Emitter {
     box_roll: f32,
     diffusion_width: f32,
     diffusion_depth: f32,
-    padding: f32,
+    light_mask: u32,
     particle_model: mat4x4<f32>,
     particle_color_r: f32,
     particle_color_g: f32,
@@ -36,8 +36,84 @@ struct Emitter {
     material_mass: f32,
     particle_lifetime: f32,
     padding_2: f32,
+    parent_world: mat4x4<f32>,
+    space_delta: mat4x4<f32>,
+    velocity: vec3<f32>,
+    inherit_velocity: f32,
 };
 
+struct LightSet {
+    offset: u32,
+    count: u32,
+    range: f32,
+    lifetime: f32,
+}
+
+struct LightClusters {
+    view: mat4x4<f32>,
+    proj: mat4x4<f32>,
+    screen_size: vec2<f32>,
+    near: f32,
+    far: f32,
+    grid_x: u32,
+    grid_y: u32,
+    grid_z: u32,
+    max_lights: u32,
+}
+
+const SCENE_LIGHT_DIRECTIONAL = 0.;
+const SCENE_LIGHT_POINT = 1.;
+const SCENE_LIGHT_SPOT = 2.;
+
+struct SceneLight {
+    // w: kind
+    position: vec4<f32>,
+    // w: range, 0 is unlimited
+    direction: vec4<f32>,
+    // w: intensity
+    color: vec4<f32>,
+    // x: cosine inner cone, y: cosine outer cone
+    cone: vec4<f32>,
+}
+
+struct SceneLighting {
+    // w: intensity
+    sky_color: vec4<f32>,
+    ground_color: vec4<f32>,
+    light_count: u32,
+    lights: array<SceneLight>,
+}
+
+struct Environment {
+    // Camera rotation only, to sample the background
+    inv_view_proj: mat4x4<f32>,
+    intensity: f32,
+    // Highest mip level of the specular map
+    specular_max_mip: f32,
+}
+
+struct SpawnEvent {
+    position: vec4<f32>,
+    velocity: vec4<f32>,
+    color: vec4<f32>,
+}
+
+struct SpawnEvents {
+    count: atomic<u32>,
+    head: u32,
+    max_events: u32,
+    padding: u32,
+    events: array<SpawnEvent>,
+}
+
+struct SubEmitter {
+    trigger: u32,
+    trigger_value: f32,
+    spawn_count: u32,
+    inherit_velocity: f32,
+    inherit_color: u32,
+}
+
 struct CameraUniform {
     view_proj: mat4x4<f32>,
     view: mat4x4<f32>,
@@ -66,6 +142,36 @@ struct FxIO {
 
 const PI: f32 = 3.141592653589;
 
+const TRIGGER_SPAWN: u32 = 0u;
+const TRIGGER_DEATH: u32 = 1u;
+const TRIGGER_COLLISION: u32 = 2u;
+const TRIGGER_INTERVAL: u32 = 3u;
+
+// Cluster light entries store the light set in the upper bits
+const CLUSTER_SET_SHIFT: u32 = 27u;
+const CLUSTER_LIGHT_MASK: u32 = 0x7ffffffu;
+
+// Exponential slices, so near clusters stay small
+fn cluster_depth_slice(clusters: LightClusters, depth: f32) -> u32 {
+    let slice = log(depth / clusters.near) / log(clusters.far / clusters.near) * f32(clusters.grid_z);
+    return u32(clamp(slice, 0., f32(clusters.grid_z - 1u)));
+}
+
+// Screen uv has its origin top left
+fn cluster_tile(clusters: LightClusters, uv: vec2<f32>) -> vec2<u32> {
+    let grid = vec2(f32(clusters.grid_x), f32(clusters.grid_y));
+    return vec2<u32>(clamp(uv * grid, vec2(0.), grid - 1.));
+}
+
+fn cluster_index(clusters: LightClusters, cluster: vec3<u32>) -> u32 {
+    return cluster.x + cluster.y * clusters.grid_x + cluster.z * clusters.grid_x * clusters.grid_y;
+}
+
+fn light_attenuation(distance: f32, range: f32) -> f32 {
+    let window = saturate(1. - pow(distance / range, 4.));
+    return window * window / (distance * distance);
+}
+
 fn is_decayed(em: Emitter, par: Particle) -> bool {
     return em.particle_lifetime < par.lifetime;
 }
@@ -1,3 +1,5 @@
+#include "declarations.wgsl"
+
 @group(0) @binding(0) var<storage, read> particles_src : array<Particle>;
 @group(0) @binding(1) var<storage, read_write> particles_dst : array<Particle>;
 @group(0) @binding(2) var<uniform> em: Emitter; 
@@ -6,7 +8,9 @@ fn create_velocity(input_random: f32, speed_random: f32) -> vec3<f32> {
     let diff_width = gen_dyn_range(input_random * 0.12, em.diffusion_width, em.elapsed_sec) / 2.;
     let diff_depth = gen_dyn_range(input_random * 0.45, em.diffusion_depth, em.elapsed_sec) / 2.;
 
-    return vec3<f32>(0., speed_random, 0.) * yaw_matrix(em.box_yaw) * pitch_matrix(em.box_pitch + diff_width) * roll_matrix(em.box_roll + diff_depth);
+    let local_vel = vec3<f32>(0., speed_random, 0.) * yaw_matrix(em.box_yaw) * pitch_matrix(em.box_pitch + diff_width) * roll_matrix(em.box_roll + diff_depth);
+
+    return (em.parent_world * vec4<f32>(local_vel, 0.)).xyz;
 }
 
 fn create_particle_position(input_random: f32) -> vec3<f32> {
@@ -26,7 +30,9 @@ fn create_particle_position(input_random: f32) -> vec3<f32> {
 
     let local_rot = local_pos * yaw_matrix(em.box_yaw) * pitch_matrix(em.box_pitch) * roll_matrix(em.box_roll);
 
-    return vec3<f32>(em.box_x, em.box_y, em.box_z) + local_rot;
+    let box_pos = vec3<f32>(em.box_x, em.box_y, em.box_z) + local_rot;
+
+    return (em.parent_world * vec4<f32>(box_pos, 1.)).xyz;
 }
 
 fn spawn_particle(index: u32) {
@@ -48,7 +54,7 @@ fn spawn_particle(index: u32) {
     let speed_random = gen_abs_range(input_random + 40., speed_delta, em.elapsed_sec);
     let particle_speed = em.particle_speed_min + speed_random;
     let position = create_particle_position(input_random);
-    let velocity = create_velocity(input_random, particle_speed);
+    let velocity = create_velocity(input_random, particle_speed) + em.velocity * em.inherit_velocity;
 
     particle.scale = size;
     particle.color = particle_color;
@@ -84,6 +90,10 @@ fn main(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
 
     particle.lifetime += em.delta_sec;
 
+    // Moves local space particles along with the emitter
+    particle.model = em.space_delta * particle.model;
+    particle.vel_mass = vec4<f32>((em.space_delta * vec4<f32>(particle.vel_mass.xyz, 0.)).xyz, particle.vel_mass.w);
+
     let new_vel = particle.vel_mass.xyz * em.particle_friction_coefficient;
     particle.vel_mass = vec4<f32>(new_vel, particle.vel_mass.w);
 
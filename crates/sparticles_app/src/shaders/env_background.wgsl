@@ -0,0 +1,44 @@
+#include "declarations.wgsl"
+#include "pbr/tonemapping.wgsl"
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) ndc: vec2<f32>,
+}
+
+struct FragmentOutput {
+    @location(0) color: vec4<f32>,
+    @location(1) split: vec4<f32>,
+}
+
+@group(0) @binding(0) var<uniform> camera: CameraUniform;
+@group(1) @binding(0) var env_cube: texture_cube<f32>;
+@group(1) @binding(1) var env_s: sampler;
+@group(1) @binding(2) var<uniform> environment: Environment;
+
+// Fullscreen triangle on the far plane
+@vertex
+fn vs_main(@builtin(vertex_index) vert_idx: u32) -> VertexOutput {
+    let uv = vec2(f32((vert_idx << 1u) & 2u), f32(vert_idx & 2u));
+    let ndc = uv * 2.0 - 1.0;
+
+    var out: VertexOutput;
+    out.clip_position = vec4(ndc, 1.0, 1.0);
+    out.ndc = ndc;
+
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> FragmentOutput {
+    let world = environment.inv_view_proj * vec4(in.ndc, 1.0, 1.0);
+    let dir = normalize(world.xyz / world.w);
+
+    let env = textureSampleLevel(env_cube, env_s, dir, 0.0).rgb * environment.intensity;
+    let color = tonemap(env, camera.tonemap);
+
+    var out: FragmentOutput;
+    out.color = vec4(linear_to_srgb(color), 1.0);
+
+    return out;
+}
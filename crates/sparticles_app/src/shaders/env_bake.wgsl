@@ -0,0 +1,110 @@
+#include "declarations.wgsl"
+#include "pbr/ibl.wgsl"
+
+struct EnvBake {
+    roughness: f32,
+    sample_count: u32,
+}
+
+@group(0) @binding(0) var equirect: texture_2d<f32>;
+@group(0) @binding(1) var env_cube: texture_cube<f32>;
+@group(0) @binding(2) var env_s: sampler;
+@group(0) @binding(3) var output: texture_storage_2d_array<rgba16float, write>;
+@group(0) @binding(4) var<uniform> bake: EnvBake;
+
+fn texel_dir(id: vec3<u32>) -> vec3<f32> {
+    let size = vec2<f32>(textureDimensions(output));
+    let uv = (vec2<f32>(id.xy) + 0.5) / size;
+    return cube_dir(id.z, uv);
+}
+
+fn is_outside(id: vec3<u32>) -> bool {
+    let size = textureDimensions(output);
+    return size.x <= id.x || size.y <= id.y;
+}
+
+@compute
+@workgroup_size(8, 8, 1)
+fn equirect_to_cube(@builtin(global_invocation_id) id: vec3<u32>) {
+    if is_outside(id) {
+        return;
+    }
+
+    let dir = texel_dir(id);
+    let size = vec2<f32>(textureDimensions(equirect));
+
+    let uv = vec2(atan2(dir.z, dir.x) / (2.0 * PI) + 0.5, acos(clamp(dir.y, -1.0, 1.0)) / PI);
+    let texel = min(vec2<u32>(uv * size), vec2<u32>(size) - 1u);
+
+    let color = textureLoad(equirect, texel, 0);
+
+    textureStore(output, id.xy, id.z, vec4(color.rgb, 1.0));
+}
+
+// Cosine weighted convolution of the hemisphere around the normal
+@compute
+@workgroup_size(8, 8, 1)
+fn irradiance(@builtin(global_invocation_id) id: vec3<u32>) {
+    if is_outside(id) {
+        return;
+    }
+
+    let N = texel_dir(id);
+
+    var up = vec3(0.0, 1.0, 0.0);
+
+    if 0.999 < abs(N.y) {
+        up = vec3(0.0, 0.0, 1.0);
+    }
+
+    let right = normalize(cross(up, N));
+    up = cross(N, right);
+
+    let sample_delta = 0.05;
+    var irradiance = vec3(0.0);
+    var sample_count = 0.0;
+
+    for (var phi = 0.0; phi < 2.0 * PI; phi += sample_delta) {
+        for (var theta = 0.0; theta < 0.5 * PI; theta += sample_delta) {
+            let tangent_sample = vec3(sin(theta) * cos(phi), sin(theta) * sin(phi), cos(theta));
+            let dir = tangent_sample.x * right + tangent_sample.y * up + tangent_sample.z * N;
+
+            irradiance += textureSampleLevel(env_cube, env_s, dir, 0.0).rgb * cos(theta) * sin(theta);
+            sample_count += 1.0;
+        }
+    }
+
+    irradiance = PI * irradiance / sample_count;
+
+    textureStore(output, id.xy, id.z, vec4(irradiance, 1.0));
+}
+
+// Specular reflections for one roughness (mip level), assumes the view direction equals the normal
+@compute
+@workgroup_size(8, 8, 1)
+fn prefilter(@builtin(global_invocation_id) id: vec3<u32>) {
+    if is_outside(id) {
+        return;
+    }
+
+    let N = texel_dir(id);
+    let V = N;
+
+    var color = vec3(0.0);
+    var total_weight = 0.0;
+
+    for (var i = 0u; i < bake.sample_count; i++) {
+        let xi = hammersley(i, bake.sample_count);
+        let H = importance_sample_ggx(xi, N, bake.roughness);
+        let L = normalize(2.0 * dot(V, H) * H - V);
+
+        let NdotL = dot(N, L);
+
+        if 0.0 < NdotL {
+            color += textureSampleLevel(env_cube, env_s, L, 0.0).rgb * NdotL;
+            total_weight += NdotL;
+        }
+    }
+
+    textureStore(output, id.xy, id.z, vec4(color / max(total_weight, 0.0001), 1.0));
+}
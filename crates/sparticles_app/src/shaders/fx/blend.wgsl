@@ -1,3 +1,5 @@
+#include "declarations.wgsl"
+
 struct Blend {
     io_mix: f32,
 }
@@ -1,3 +1,6 @@
+#include "declarations.wgsl"
+#include "pbr/tonemapping.wgsl"
+
 struct ColorCorrection {
     gamma: f32,
     contrast: f32,
@@ -0,0 +1,36 @@
+struct VertexOutput {
+    @builtin(position) pos: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+var<private> positions: array<vec2<f32>, 3> = array<vec2<f32>, 3>(
+    vec2<f32>(-1.0, -3.0),
+    vec2<f32>(-1.0, 1.0),
+    vec2<f32>(3.0, 1.0)
+);
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    var out: VertexOutput;
+    out.pos = vec4<f32>(positions[vertex_index], 0., 1.);
+    out.uv = out.pos.xy * 0.5 + 0.5;
+    out.uv.y = 1.0 - out.uv.y;
+    return out;
+}
+
+struct DepthImport {
+    scale: f32,
+    offset: f32,
+}
+
+// Depth of the host engine, can have another size than the particle frame
+@group(0) @binding(0) var host_depth: texture_depth_2d;
+@group(0) @binding(1) var<uniform> remap: DepthImport;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @builtin(frag_depth) f32 {
+    let size = vec2<f32>(textureDimensions(host_depth));
+    let pos = min(vec2<i32>(in.uv * size), vec2<i32>(size) - 1);
+    let depth = textureLoad(host_depth, pos, 0);
+    return clamp(depth * remap.scale + remap.offset, 0.0, 1.0);
+}
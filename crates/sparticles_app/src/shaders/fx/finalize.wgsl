@@ -1,3 +1,6 @@
+#include "declarations.wgsl"
+#include "pbr/tonemapping.wgsl"
+
 struct VertexOutput {
     @builtin(position) pos: vec4<f32>,
     @location(0) uv: vec2<f32>,
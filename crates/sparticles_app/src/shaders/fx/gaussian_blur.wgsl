@@ -1,3 +1,5 @@
+#include "declarations.wgsl"
+
 @group(0) @binding(0) var fx_tex: binding_array<texture_storage_2d<rgba16float, read_write>, 16>;
 @group(1) @binding(0) var<uniform> fx_io: FxIO; 
 @group(2) @binding(0) var<uniform> globals: GaussianBlur; 
@@ -1,3 +1,5 @@
+#include "declarations.wgsl"
+
 // Includes declarations 
 struct GravitationalForce {
     gravitational_force: f32,
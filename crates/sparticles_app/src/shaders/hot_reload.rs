@@ -0,0 +1,273 @@
+use super::BuiltinShader;
+use crate::asset_manager::{AssetKind, AssetManager};
+use crate::loader::{BUILTIN_ID, CIRCLE_MAT_ID};
+use crate::model::{EmitterState, SparState};
+use egui_wgpu::wgpu;
+use egui_winit::egui::{self, Align2, Color32, RichText};
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Pipelines that are rebuilt after a file of one of their shaders changed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum ReloadTarget {
+    /// Emitter, particle render and particle animation pipelines
+    Emitters,
+    SubEmitters,
+    PostFx,
+    /// Finalize pipeline and the pipelines of embedded renderers
+    Finalize,
+    Lights,
+    EnvironmentBackground,
+    EnvironmentBake,
+    BrdfLut,
+    StaticScene,
+}
+
+/// Builtin shader labels and what to rebuild when one of their files changed
+const RELOAD_TARGETS: [(&str, ReloadTarget); 21] = [
+    ("Emitter compute", ReloadTarget::Emitters),
+    ("Particle render", ReloadTarget::Emitters),
+    ("Light particle render", ReloadTarget::Emitters),
+    ("Color animation", ReloadTarget::Emitters),
+    ("Gravity animation", ReloadTarget::Emitters),
+    ("Force animation", ReloadTarget::Emitters),
+    ("Stray animation", ReloadTarget::Emitters),
+    ("Sub emitter trigger", ReloadTarget::SubEmitters),
+    ("Sub emitter spawn", ReloadTarget::SubEmitters),
+    ("Gaussian blur", ReloadTarget::PostFx),
+    ("Downscale", ReloadTarget::PostFx),
+    ("Blend", ReloadTarget::PostFx),
+    ("Color processing", ReloadTarget::PostFx),
+    ("Finalize Post FX", ReloadTarget::Finalize),
+    ("Composite Post FX", ReloadTarget::Finalize),
+    ("Import host depth", ReloadTarget::Finalize),
+    ("Light culling", ReloadTarget::Lights),
+    (
+        "Environment background",
+        ReloadTarget::EnvironmentBackground,
+    ),
+    ("Environment bake", ReloadTarget::EnvironmentBake),
+    ("BRDF lookup table", ReloadTarget::BrdfLut),
+    ("Static scene", ReloadTarget::StaticScene),
+];
+
+/// Watches the shader folder and rebuilds the pipelines of the shaders that use a changed file.
+/// Pipelines are only rebuilt once every shader using the changed files validates.
+pub struct ShaderHotReload {
+    _watcher: Option<notify::RecommendedWatcher>,
+    changed: Arc<Mutex<HashSet<String>>>,
+    /// Changed files since the last successful rebuild
+    pending: HashSet<String>,
+    /// Validation errors of the changed shaders, the old pipelines are used meanwhile.
+    /// Also lists changed shaders that can't be reloaded.
+    pub errors: Vec<String>,
+}
+
+impl ShaderHotReload {
+    pub async fn update(state: &mut SparState, encoder: &mut wgpu::CommandEncoder) {
+        let shader_reload = &mut state.shader_reload;
+
+        {
+            let mut changed = shader_reload.changed.lock().unwrap();
+
+            if changed.is_empty() {
+                return;
+            }
+
+            shader_reload.pending.extend(changed.drain());
+        }
+
+        let pending = &shader_reload.pending;
+        let mut affected = Vec::new();
+
+        shader_reload.errors = BuiltinShader::created()
+            .into_iter()
+            .filter_map(|shader| match shader.source() {
+                Ok(source) if pending.iter().any(|file| source.files.contains(file)) => {
+                    let result = shader.validate(&source).err();
+                    affected.push(shader);
+                    result
+                }
+                Ok(_) => None,
+                Err(err) => Some(err.to_string()),
+            })
+            .collect();
+
+        if !shader_reload.errors.is_empty() {
+            return;
+        }
+
+        println!("Reloading shaders: {:?}", pending);
+        shader_reload.pending.clear();
+
+        let mut targets = HashSet::new();
+
+        for shader in affected {
+            let target = RELOAD_TARGETS
+                .iter()
+                .find(|(label, _)| *label == shader.label);
+
+            match target {
+                Some((_, target)) => {
+                    targets.insert(*target);
+                }
+                None => shader_reload.errors.push(format!(
+                    "{}: reloading isn't supported, restart to apply the change",
+                    shader.label
+                )),
+            }
+        }
+
+        for target in targets {
+            Self::rebuild(state, target, encoder).await;
+        }
+    }
+
+    async fn rebuild(
+        state: &mut SparState,
+        target: ReloadTarget,
+        encoder: &mut wgpu::CommandEncoder,
+    ) {
+        if target == ReloadTarget::Emitters {
+            EmitterState::recreate_emitters(state, encoder, |_| true).await;
+            return;
+        }
+
+        let SparState {
+            emitters,
+            post_process,
+            registered_post_fx,
+            lights,
+            environment,
+            static_scene,
+            camera,
+            collection,
+            gfx,
+            ..
+        } = state;
+
+        if target == ReloadTarget::PostFx {
+            post_process.recreate_fx(gfx, registered_post_fx).await;
+            return;
+        }
+
+        let gfx = &gfx.read().await;
+
+        match target {
+            ReloadTarget::Emitters | ReloadTarget::PostFx => {}
+            ReloadTarget::SubEmitters => {
+                // Linked again with new pipelines in the next update
+                for emitter in emitters.iter_mut() {
+                    emitter.sub_emitter = None;
+                }
+            }
+            ReloadTarget::Finalize => post_process.recreate_pipelines(gfx),
+            ReloadTarget::Lights => lights.recreate_pipelines(gfx),
+            ReloadTarget::EnvironmentBackground => {
+                environment.recreate_background_pipeline(gfx, camera)
+            }
+            ReloadTarget::EnvironmentBake => environment.rebake(),
+            ReloadTarget::BrdfLut => environment.rebake_brdf_lut(gfx),
+            ReloadTarget::StaticScene => {
+                let collection = collection.read().await;
+                let material_layout = &collection[BUILTIN_ID].materials[CIRCLE_MAT_ID].bg_layout;
+
+                static_scene.recreate_pipeline(gfx, camera, lights, material_layout);
+            }
+        }
+    }
+
+    pub fn draw_overlay(&self, ctx: &egui::Context) {
+        if self.errors.is_empty() {
+            return;
+        }
+
+        egui::Window::new("Shader error")
+            .anchor(Align2::CENTER_BOTTOM, [0., -10.])
+            .resizable(false)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                for err in self.errors.iter() {
+                    ui.label(RichText::new(err).monospace().color(Color32::LIGHT_RED));
+                }
+            });
+    }
+
+    /// Nothing is watched if the shaders are embedded or the shader folder doesn't exist
+    pub fn new() -> Self {
+        let changed = Arc::new(Mutex::new(HashSet::new()));
+
+        let watcher = match AssetManager::dir(AssetKind::Shader) {
+            Some(dir) if !cfg!(feature = "embed-assets") => Self::watch(dir, changed.clone()),
+            _ => None,
+        };
+
+        Self {
+            _watcher: watcher,
+            changed,
+            pending: HashSet::new(),
+            errors: vec![],
+        }
+    }
+
+    fn watch(
+        dir: PathBuf,
+        changed: Arc<Mutex<HashSet<String>>>,
+    ) -> Option<notify::RecommendedWatcher> {
+        let dir = dir.canonicalize().unwrap_or(dir);
+        let shader_dir = dir.clone();
+
+        let handler = move |res: notify::Result<notify::Event>| {
+            let Ok(event) = res else {
+                return;
+            };
+
+            if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                return;
+            }
+
+            for path in event.paths.iter() {
+                if let Some(filename) = Self::shader_name(&shader_dir, path) {
+                    changed.lock().unwrap().insert(filename);
+                }
+            }
+        };
+
+        let watcher = notify::recommended_watcher(handler).and_then(|mut watcher| {
+            watcher.watch(&dir, RecursiveMode::Recursive)?;
+            Ok(watcher)
+        });
+
+        match watcher {
+            Ok(watcher) => Some(watcher),
+            Err(err) => {
+                println!("Can't watch shaders: {}", err);
+                None
+            }
+        }
+    }
+
+    /// Name as used in the shader options, like "pbr/pbr.wgsl"
+    fn shader_name(dir: &Path, path: &Path) -> Option<String> {
+        if path.extension().is_none_or(|ext| ext != "wgsl") {
+            return None;
+        }
+
+        let relative = path.strip_prefix(dir).ok()?;
+
+        let parts: Vec<&str> = relative
+            .components()
+            .filter_map(|part| part.as_os_str().to_str())
+            .collect();
+
+        Some(parts.join("/"))
+    }
+}
+
+impl Default for ShaderHotReload {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,264 @@
+use crate::animations::particle::{ForceGpu, GravityGpu};
+use crate::fx::embed::DepthImportUniform;
+use crate::model::environment::{EnvBakeUniform, EnvironmentUniform};
+use crate::model::lights::{LightClustersUniform, LightSetUniform};
+use crate::model::scene_lighting::{SceneLightUniform, SceneLightingUniform};
+use crate::model::static_scene::SceneNodeUniform;
+use crate::model::sub_emitter::SubEmitterUniform;
+use crate::model::{EmitterGpu, ParticleGpu};
+
+/// Byte layout of a uniform struct field as written by encase
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldLayout {
+    pub name: &'static str,
+    pub offset: u64,
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GpuLayout {
+    pub rust_struct: &'static str,
+    pub wgsl_struct: &'static str,
+    pub size: u64,
+    pub fields: Vec<FieldLayout>,
+}
+
+/// Implemented by [`gpu_struct!`], the layout is compared with the WGSL struct of the same name
+pub trait GpuStruct {
+    fn layout() -> GpuLayout;
+}
+
+/// Derives `encase::ShaderType` and records the field names, so the layout can be
+/// compared with the WGSL struct after naga parsed the shader.
+macro_rules! gpu_struct {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident as $wgsl:literal {
+            $($(#[$field_meta:meta])* $field_vis:vis $field:ident: $ty:ty),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(encase::ShaderType)]
+        $vis struct $name {
+            $($(#[$field_meta])* $field_vis $field: $ty),*
+        }
+
+        impl $crate::shaders::layout::GpuStruct for $name {
+            fn layout() -> $crate::shaders::layout::GpuLayout {
+                use encase::ShaderType;
+
+                let names = [$(stringify!($field)),*];
+                let sizes = [$(<$ty as ShaderType>::min_size().get()),*];
+                let offsets = <$name as ShaderType>::METADATA.extra.offsets;
+
+                $crate::shaders::layout::GpuLayout {
+                    rust_struct: stringify!($name),
+                    wgsl_struct: $wgsl,
+                    size: <$name as ShaderType>::min_size().get(),
+                    fields: names
+                        .into_iter()
+                        .zip(offsets)
+                        .zip(sizes)
+                        .map(|((name, offset), size)| $crate::shaders::layout::FieldLayout {
+                            name,
+                            offset,
+                            size,
+                        })
+                        .collect(),
+                }
+            }
+        }
+    };
+}
+
+pub(crate) use gpu_struct;
+
+/// Every uniform struct that is written from Rust
+pub fn gpu_layouts() -> Vec<GpuLayout> {
+    vec![
+        EmitterGpu::layout(),
+        ParticleGpu::layout(),
+        GravityGpu::layout(),
+        ForceGpu::layout(),
+        SubEmitterUniform::layout(),
+        LightSetUniform::layout(),
+        LightClustersUniform::layout(),
+        SceneLightUniform::layout(),
+        SceneLightingUniform::layout(),
+        EnvironmentUniform::layout(),
+        EnvBakeUniform::layout(),
+        SceneNodeUniform::layout(),
+        DepthImportUniform::layout(),
+    ]
+}
+
+/// Compares the Rust layouts with the structs declared in the module.
+/// Structs that aren't declared in the module are skipped.
+pub fn check_layouts(module: &naga::Module) -> Result<(), String> {
+    let errors: Vec<String> = gpu_layouts()
+        .iter()
+        .filter_map(|layout| check_layout(module, layout).err())
+        .collect();
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors.join("\n"))
+    }
+}
+
+fn check_layout(module: &naga::Module, layout: &GpuLayout) -> Result<(), String> {
+    let wgsl_struct = module.types.iter().find_map(|(_, ty)| match &ty.inner {
+        naga::TypeInner::Struct { members, span }
+            if ty.name.as_deref() == Some(layout.wgsl_struct) =>
+        {
+            Some((members, *span as u64))
+        }
+        _ => None,
+    });
+
+    let Some((members, span)) = wgsl_struct else {
+        return Ok(());
+    };
+
+    let mismatch = |msg: String| {
+        format!(
+            "Layout of {} doesn't match WGSL struct {}: {}",
+            layout.rust_struct, layout.wgsl_struct, msg
+        )
+    };
+
+    if members.len() != layout.fields.len() {
+        return Err(mismatch(format!(
+            "{} fields in Rust, {} in WGSL",
+            layout.fields.len(),
+            members.len()
+        )));
+    }
+
+    for (field, member) in layout.fields.iter().zip(members.iter()) {
+        let name = member.name.as_deref().unwrap_or_default();
+        let offset = member.offset as u64;
+        let size = module.types[member.ty].inner.size(module.to_ctx()) as u64;
+
+        if field.name != name {
+            return Err(mismatch(format!(
+                "expected field {} at offset {}, found {}",
+                name, offset, field.name
+            )));
+        }
+
+        if field.offset != offset || field.size != size {
+            return Err(mismatch(format!(
+                "field {} has offset {} and size {} in Rust, offset {} and size {} in WGSL",
+                name, field.offset, field.size, offset, size
+            )));
+        }
+    }
+
+    if layout.size != span {
+        return Err(mismatch(format!(
+            "size is {} in Rust, {} in WGSL",
+            layout.size, span
+        )));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asset_manager::{AssetKind, AssetManager};
+    use crate::shaders::preprocessor::ShaderSource;
+    use crate::shaders::{validate_source, BuiltinShader};
+    use std::path::Path;
+
+    /// Shader files with an entry point, the others are only included
+    fn builtin_files(dir: &Path, prefix: &str, files: &mut Vec<String>) {
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            let name = format!("{}{}", prefix, path.file_name().unwrap().to_str().unwrap());
+
+            if path.is_dir() {
+                builtin_files(&path, &format!("{}/", name), files);
+                continue;
+            }
+
+            let is_entry = |text: String| {
+                ["@compute", "@vertex", "@fragment"]
+                    .iter()
+                    .any(|stage| text.contains(stage))
+            };
+
+            if name.ends_with(".wgsl") && std::fs::read_to_string(&path).is_ok_and(is_entry) {
+                files.push(name);
+            }
+        }
+    }
+
+    fn builtin_sources() -> Vec<(String, ShaderSource)> {
+        let mut files = Vec::new();
+        builtin_files(
+            &AssetManager::dir(AssetKind::Shader).unwrap(),
+            "",
+            &mut files,
+        );
+        files.sort();
+
+        assert!(!files.is_empty());
+
+        files
+            .into_iter()
+            .map(|file| {
+                let shader = BuiltinShader {
+                    files: vec![file.clone()],
+                    if_directives: vec![],
+                    label: file.clone(),
+                };
+
+                let source = shader.source().unwrap();
+                (file, source)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn builtin_shaders_match_the_rust_layouts() {
+        for (file, source) in builtin_sources() {
+            if let Err(err) = validate_source(&source) {
+                panic!("{}:\n{}", file, err);
+            }
+        }
+    }
+
+    #[test]
+    fn every_layout_has_a_wgsl_struct() {
+        let modules: Vec<naga::Module> = builtin_sources()
+            .iter()
+            .map(|(_, source)| naga::front::wgsl::parse_str(&source.code).unwrap())
+            .collect();
+
+        for layout in gpu_layouts() {
+            let is_declared = modules.iter().any(|module| {
+                module
+                    .types
+                    .iter()
+                    .any(|(_, ty)| ty.name.as_deref() == Some(layout.wgsl_struct))
+            });
+
+            assert!(is_declared, "{} isn't declared", layout.wgsl_struct);
+        }
+    }
+
+    #[test]
+    fn mismatches_are_reported() {
+        let code = "struct LightSet { offset: u32, count: u32, lifetime: f32, range: f32 }
+            @group(0) @binding(0) var<uniform> light_set: LightSet;";
+        let module = naga::front::wgsl::parse_str(code).unwrap();
+
+        let err = check_layouts(&module).unwrap_err();
+        assert!(err.contains("LightSetUniform"), "{}", err);
+        assert!(err.contains("expected field lifetime"), "{}", err);
+    }
+}
@@ -0,0 +1,107 @@
+#include "declarations.wgsl"
+
+@group(0) @binding(0) var<storage, read> light_particles: array<Particle>;
+@group(0) @binding(1) var<storage, read> light_sets: array<LightSet>;
+@group(0) @binding(2) var<uniform> clusters: LightClusters;
+@group(0) @binding(3) var<storage, read_write> cluster_counts: array<atomic<u32>>;
+@group(0) @binding(4) var<storage, read_write> cluster_lights: array<u32>;
+
+const NO_LIGHT_SET: u32 = 0xffffffffu;
+
+@compute
+@workgroup_size(64)
+fn reset(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
+    let cluster_idx = global_invocation_id.x;
+
+    if cluster_idx < arrayLength(&cluster_counts) {
+        atomicStore(&cluster_counts[cluster_idx], 0u);
+    }
+}
+
+fn ndc_to_uv(ndc: vec2<f32>) -> vec2<f32> {
+    return vec2(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+}
+
+@compute
+@workgroup_size(64)
+fn main(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
+    let light_idx = global_invocation_id.x;
+
+    var set_idx = NO_LIGHT_SET;
+
+    for (var i = 0u; i < arrayLength(&light_sets); i++) {
+        let light_set = light_sets[i];
+
+        if light_set.offset <= light_idx && light_idx < light_set.offset + light_set.count {
+            set_idx = i;
+            break;
+        }
+    }
+
+    if set_idx == NO_LIGHT_SET {
+        return;
+    }
+
+    let light_set = light_sets[set_idx];
+    let light = light_particles[light_idx];
+
+    if light_set.lifetime < light.lifetime {
+        return;
+    }
+
+    let range = light_set.range;
+    let view_pos = (clusters.view * vec4(light.model.w.xyz, 1.)).xyz;
+    let depth = -view_pos.z;
+
+    if depth + range < clusters.near || clusters.far < depth - range {
+        return;
+    }
+
+    let slice_from = cluster_depth_slice(clusters, max(depth - range, clusters.near));
+    let slice_until = cluster_depth_slice(clusters, min(depth + range, clusters.far));
+
+    var tile_from = vec2(0u);
+    var tile_until = vec2(clusters.grid_x - 1u, clusters.grid_y - 1u);
+
+    // Spheres crossing the near plane can't be projected, they cover the whole screen
+    if clusters.near < depth - range {
+        var ndc_min = vec2(1.);
+        var ndc_max = vec2(-1.);
+
+        for (var corner_idx = 0u; corner_idx < 8u; corner_idx++) {
+            let corner = view_pos + vec3(
+                select(-range, range, (corner_idx & 1u) != 0u),
+                select(-range, range, (corner_idx & 2u) != 0u),
+                select(-range, range, (corner_idx & 4u) != 0u),
+            );
+
+            let clip = clusters.proj * vec4(corner, 1.);
+            let ndc = clip.xy / clip.w;
+
+            ndc_min = min(ndc_min, ndc);
+            ndc_max = max(ndc_max, ndc);
+        }
+
+        if any(ndc_max < vec2(-1.)) || any(vec2(1.) < ndc_min) {
+            return;
+        }
+
+        tile_from = cluster_tile(clusters, ndc_to_uv(vec2(ndc_min.x, ndc_max.y)));
+        tile_until = cluster_tile(clusters, ndc_to_uv(vec2(ndc_max.x, ndc_min.y)));
+    }
+
+    let entry = (set_idx << CLUSTER_SET_SHIFT) | light_idx;
+
+    for (var z = slice_from; z <= slice_until; z++) {
+        for (var y = tile_from.y; y <= tile_until.y; y++) {
+            for (var x = tile_from.x; x <= tile_until.x; x++) {
+                let cluster_idx = cluster_index(clusters, vec3(x, y, z));
+                let slot = atomicAdd(&cluster_counts[cluster_idx], 1u);
+
+                if slot < clusters.max_lights {
+                    cluster_lights[cluster_idx * clusters.max_lights + slot] = entry;
+                }
+            }
+        }
+    }
+}
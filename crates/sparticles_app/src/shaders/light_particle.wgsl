@@ -1,3 +1,7 @@
+#include "declarations.wgsl"
+#include "pbr/tonemapping.wgsl"
+#include "pbr/pbr.wgsl"
+
 struct VertexInput {
     @builtin(vertex_index) vert_idx: u32,
     @builtin(instance_index) instance_idx: u32,
@@ -12,6 +16,9 @@ struct VertexOutput {
     @location(2) uv: vec2<f32>,
 }
 
+@group(2) @binding(0) var<storage, read> particles: array<Particle>;
+@group(2) @binding(2) var<uniform> em: Emitter;
+
 @vertex
 fn vs_main(in: VertexInput) -> VertexOutput {
     let p = particles[in.instance_idx];
@@ -1,3 +1,7 @@
+pub mod hot_reload;
+pub mod layout;
+pub mod preprocessor;
 pub mod shader;
 
+pub use hot_reload::ShaderHotReload;
 pub use shader::*;
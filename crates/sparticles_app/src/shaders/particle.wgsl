@@ -1,3 +1,8 @@
+#include "declarations.wgsl"
+#include "pbr/tonemapping.wgsl"
+#include "pbr/pbr.wgsl"
+#include "pbr/lighting.wgsl"
+
 struct VertexInput {
     @builtin(vertex_index) vert_idx: u32,
     @builtin(instance_index) instance_idx: u32,
@@ -18,7 +23,8 @@ struct VertexOutput {
     @location(5) bitangent: vec3<f32>,
 }
 
-@group(3) @binding(0) var<storage, read> light_particles: array<Particle>;
+@group(2) @binding(0) var<storage, read> particles: array<Particle>;
+@group(2) @binding(2) var<uniform> em: Emitter;
 
 @vertex
 fn vs_main(in: VertexInput) -> VertexOutput {
@@ -42,60 +48,6 @@ fn vs_main(in: VertexInput) -> VertexOutput {
     return out;
 }
 
-fn apply_pbr(in: VertexOutput, N: vec3<f32>, WN: vec3<f32>, ALB: vec3<f32>) -> FragmentOutput {
-    let albedo = pow(ALB, vec3(2.2));
-    let metallic_roughness = textureSample(metal_rough_tex, metal_rough_s, in.uv).rg;
-    let metallic = metallic_roughness.r;
-    let roughness = metallic_roughness.g;
-    let ao = textureSample(ao_tex, ao_s, in.uv).r;
-    let emissive = pow(textureSample(emissive_tex, emissive_s, in.uv).rgb, vec3(2.2));
-
-    let F0 = mix(vec3(0.04), albedo, metallic);
-    let V = normalize(camera.position.xyz - in.world_pos);
-    var Lo = vec3(0.0);
-    var Diff = vec3(0.0);
-
-    for (var i = 0u; i < arrayLength(&light_particles); i++) {
-        let light = light_particles[i];
-        let light_pos = light.model.w.xyz;
-        let light_col = light.color.rgb;
-
-        // calculate per-light radiance
-        let L = normalize(light_pos - in.world_pos);
-        let H = normalize(V + L);
-
-        let distance = length(light_pos - in.world_pos);
-        let radiance = light_col / (distance * distance);
-
-        // Cook-Torrance BRDF
-        let NDF = distribution_ggx(N, H, roughness);
-        let G = geometry_smith(N, V, L, roughness);
-        let F = fresnel_schlick(max(dot(H, V), 0.0), F0);
-
-        let numerator = NDF * G * F;
-
-        let NdotL = max(dot(N, L), 0.0);
-        let denominator = 4.0 * max(dot(N, V), 0.0) * NdotL;
-        let specular = numerator / (denominator + 0.0001);
-        let kD = (vec3(1.0) - F) * (1.0 - metallic);
-
-        Diff += max(dot(WN, L), 0.0) * radiance;
-        Lo += (kD * albedo / PI + specular) * radiance * NdotL;
-    }
-
-    var out: FragmentOutput;
-
-    let color = tonemap(Diff * vec3(0.4) * albedo * ao + Lo + emissive, camera.tonemap);
-
-    out.color = vec4(linear_to_srgb(color), 1.0);
-
-    if any(camera.bloom_treshold < out.color.rgb) {
-        out.split = out.color;
-    }
-
-    return out;
-}
-
 @fragment
 fn fs_model(in: VertexOutput) -> FragmentOutput {
     let tangent_normal = textureSample(normal_tex, normal_s, in.uv).rgb * 2.0 - 1.0;
@@ -104,7 +56,7 @@ fn fs_model(in: VertexOutput) -> FragmentOutput {
     let N = normalize(TBN * tangent_normal);
     let albedo = textureSample(albedo_tex, albedo_s, in.uv).rgb;
 
-    return apply_pbr(in, N, in.normal, albedo);
+    return apply_pbr(in.world_pos, in.clip_position.xy, in.uv, N, in.normal, albedo, em.light_mask);
 }
 
 @fragment
@@ -120,5 +72,5 @@ fn fs_circle(in: VertexOutput) -> FragmentOutput {
     let y = v_pos.y * -1.;
     let WN = (vec4(x, y, sqrt(1. - x * x - y * y), 0.) * camera.view).xyz;
 
-    return apply_pbr(in, WN, WN, in.color.rgb);
+    return apply_pbr(in.world_pos, in.clip_position.xy, in.uv, WN, WN, in.color.rgb, em.light_mask);
 }
@@ -0,0 +1,49 @@
+fn radical_inverse_vdc(input: u32) -> f32 {
+    var bits = input;
+    bits = (bits << 16u) | (bits >> 16u);
+    bits = ((bits & 0x55555555u) << 1u) | ((bits & 0xAAAAAAAAu) >> 1u);
+    bits = ((bits & 0x33333333u) << 2u) | ((bits & 0xCCCCCCCCu) >> 2u);
+    bits = ((bits & 0x0F0F0F0Fu) << 4u) | ((bits & 0xF0F0F0F0u) >> 4u);
+    bits = ((bits & 0x00FF00FFu) << 8u) | ((bits & 0xFF00FF00u) >> 8u);
+    return f32(bits) * 2.3283064365386963e-10;
+}
+
+fn hammersley(i: u32, count: u32) -> vec2<f32> {
+    return vec2(f32(i) / f32(count), radical_inverse_vdc(i));
+}
+
+// Half vector around N, distributed by the GGX lobe of the roughness
+fn importance_sample_ggx(xi: vec2<f32>, N: vec3<f32>, roughness: f32) -> vec3<f32> {
+    let a = roughness * roughness;
+
+    let phi = 2.0 * PI * xi.x;
+    let cos_theta = sqrt((1.0 - xi.y) / (1.0 + (a * a - 1.0) * xi.y));
+    let sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+
+    let H = vec3(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta);
+
+    var up = vec3(1.0, 0.0, 0.0);
+
+    if abs(N.z) < 0.999 {
+        up = vec3(0.0, 0.0, 1.0);
+    }
+
+    let tangent = normalize(cross(up, N));
+    let bitangent = cross(N, tangent);
+
+    return normalize(tangent * H.x + bitangent * H.y + N * H.z);
+}
+
+// Direction of a cube face texel, uv from the top left of the face
+fn cube_dir(face: u32, uv: vec2<f32>) -> vec3<f32> {
+    let c = uv * 2.0 - 1.0;
+
+    switch face {
+        case 0u: { return normalize(vec3(1.0, -c.y, -c.x)); }
+        case 1u: { return normalize(vec3(-1.0, -c.y, c.x)); }
+        case 2u: { return normalize(vec3(c.x, 1.0, c.y)); }
+        case 3u: { return normalize(vec3(c.x, -1.0, -c.y)); }
+        case 4u: { return normalize(vec3(c.x, -c.y, 1.0)); }
+        default: { return normalize(vec3(-c.x, -c.y, -1.0)); }
+    }
+}
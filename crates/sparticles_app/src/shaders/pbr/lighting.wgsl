@@ -0,0 +1,153 @@
+#include "declarations.wgsl"
+#include "pbr/pbr.wgsl"
+
+@group(3) @binding(0) var<storage, read> light_particles: array<Particle>;
+@group(3) @binding(1) var<storage, read> light_sets: array<LightSet>;
+@group(3) @binding(2) var<uniform> clusters: LightClusters;
+@group(3) @binding(3) var<storage, read> cluster_counts: array<u32>;
+@group(3) @binding(4) var<storage, read> cluster_lights: array<u32>;
+@group(3) @binding(5) var<storage, read> scene_lighting: SceneLighting;
+@group(3) @binding(6) var irradiance_tex: texture_cube<f32>;
+@group(3) @binding(7) var specular_tex: texture_cube<f32>;
+@group(3) @binding(8) var brdf_lut_tex: texture_2d<f32>;
+@group(3) @binding(9) var env_s: sampler;
+@group(3) @binding(10) var<uniform> environment: Environment;
+
+// Cook-Torrance BRDF, multiply with the radiance of the light
+fn brdf(N: vec3<f32>, V: vec3<f32>, L: vec3<f32>, F0: vec3<f32>, albedo: vec3<f32>, metallic: f32, roughness: f32) -> vec3<f32> {
+    let H = normalize(V + L);
+
+    let NDF = distribution_ggx(N, H, roughness);
+    let G = geometry_smith(N, V, L, roughness);
+    let F = fresnel_schlick(max(dot(H, V), 0.0), F0);
+
+    let numerator = NDF * G * F;
+
+    let NdotL = max(dot(N, L), 0.0);
+    let denominator = 4.0 * max(dot(N, V), 0.0) * NdotL;
+    let specular = numerator / (denominator + 0.0001);
+    let kD = (vec3(1.0) - F) * (1.0 - metallic);
+
+    return (kD * albedo / PI + specular) * NdotL;
+}
+
+fn fresnel_schlick_roughness(cos_theta: f32, F0: vec3<f32>, roughness: f32) -> vec3<f32> {
+    return F0 + (max(vec3(1.0 - roughness), F0) - F0) * pow(clamp(1.0 - cos_theta, 0.0, 1.0), 5.0);
+}
+
+// Split sum image based lighting from the environment map
+fn environment_light(N: vec3<f32>, V: vec3<f32>, F0: vec3<f32>, albedo: vec3<f32>, metallic: f32, roughness: f32) -> vec3<f32> {
+    let NdotV = max(dot(N, V), 0.0);
+    let R = reflect(-V, N);
+
+    let F = fresnel_schlick_roughness(NdotV, F0, roughness);
+    let kD = (vec3(1.0) - F) * (1.0 - metallic);
+
+    let irradiance = textureSample(irradiance_tex, env_s, N).rgb;
+    let prefiltered = textureSampleLevel(specular_tex, env_s, R, roughness * environment.specular_max_mip).rgb;
+    let brdf = textureSample(brdf_lut_tex, env_s, vec2(NdotV, roughness)).rg;
+
+    let diffuse = kD * irradiance * albedo;
+    let specular = prefiltered * (F * brdf.x + brdf.y);
+
+    return (diffuse + specular) * environment.intensity;
+}
+
+fn scene_light_radiance(light: SceneLight, world_pos: vec3<f32>) -> vec3<f32> {
+    let radiance = light.color.rgb * light.color.w;
+
+    if light.position.w == SCENE_LIGHT_DIRECTIONAL {
+        return radiance;
+    }
+
+    let distance = max(length(light.position.xyz - world_pos), 0.01);
+    let range = light.direction.w;
+
+    var attenuation = 1. / (distance * distance);
+
+    if 0. < range {
+        attenuation = light_attenuation(distance, range);
+    }
+
+    if light.position.w == SCENE_LIGHT_SPOT {
+        let L = normalize(light.position.xyz - world_pos);
+        let cos_angle = dot(-L, light.direction.xyz);
+        attenuation *= smoothstep(light.cone.y, light.cone.x, cos_angle);
+    }
+
+    return radiance * attenuation;
+}
+
+// Lights the surface with the light particles in the light mask, the scene lights and the environment
+fn apply_pbr(world_pos: vec3<f32>, frag_coord: vec2<f32>, uv: vec2<f32>, N: vec3<f32>, WN: vec3<f32>, ALB: vec3<f32>, light_mask: u32) -> FragmentOutput {
+    let albedo = pow(ALB, vec3(2.2));
+    let metallic_roughness = textureSample(metal_rough_tex, metal_rough_s, uv).rg;
+    let metallic = metallic_roughness.r;
+    let roughness = metallic_roughness.g;
+    let ao = textureSample(ao_tex, ao_s, uv).r;
+    let emissive = pow(textureSample(emissive_tex, emissive_s, uv).rgb, vec3(2.2));
+
+    let F0 = mix(vec3(0.04), albedo, metallic);
+    let V = normalize(camera.position.xyz - world_pos);
+    var Lo = vec3(0.0);
+    var Diff = vec3(0.0);
+
+    let depth = -(clusters.view * vec4(world_pos, 1.0)).z;
+    let tile = cluster_tile(clusters, frag_coord / clusters.screen_size);
+    let cluster_idx = cluster_index(clusters, vec3(tile, cluster_depth_slice(clusters, depth)));
+    let light_count = min(cluster_counts[cluster_idx], clusters.max_lights);
+
+    for (var i = 0u; i < light_count; i++) {
+        let entry = cluster_lights[cluster_idx * clusters.max_lights + i];
+        let set_idx = entry >> CLUSTER_SET_SHIFT;
+
+        if (light_mask & (1u << set_idx)) == 0u {
+            continue;
+        }
+
+        let light = light_particles[entry & CLUSTER_LIGHT_MASK];
+        let light_pos = light.model.w.xyz;
+        let light_col = light.color.rgb;
+
+        // calculate per-light radiance
+        let L = normalize(light_pos - world_pos);
+
+        let distance = length(light_pos - world_pos);
+        let radiance = light_col * light_attenuation(distance, light_sets[set_idx].range);
+
+        Diff += max(dot(WN, L), 0.0) * radiance;
+        Lo += brdf(N, V, L, F0, albedo, metallic, roughness) * radiance;
+    }
+
+    for (var i = 0u; i < scene_lighting.light_count; i++) {
+        let light = scene_lighting.lights[i];
+
+        var L = normalize(light.position.xyz - world_pos);
+
+        if light.position.w == SCENE_LIGHT_DIRECTIONAL {
+            L = -light.direction.xyz;
+        }
+
+        let radiance = scene_light_radiance(light, world_pos);
+
+        Lo += brdf(N, V, L, F0, albedo, metallic, roughness) * radiance;
+    }
+
+    let sky = scene_lighting.sky_color;
+    let hemisphere = mix(scene_lighting.ground_color.rgb, sky.rgb, N.y * 0.5 + 0.5) * sky.w;
+
+    var out: FragmentOutput;
+
+    let ibl = environment_light(N, V, F0, albedo, metallic, roughness);
+    let ambient = (Diff * vec3(0.4) + hemisphere) * albedo * ao + ibl * ao;
+    let color = tonemap(ambient + Lo + emissive, camera.tonemap);
+
+    out.color = vec4(linear_to_srgb(color), 1.0);
+
+    if any(camera.bloom_treshold < out.color.rgb) {
+        out.split = out.color;
+    }
+
+    return out;
+}
+
@@ -1,6 +1,6 @@
+#include "declarations.wgsl"
+
 @group(0) @binding(0) var<uniform> camera: CameraUniform;
-@group(2) @binding(0) var<storage, read> particles: array<Particle>;
-@group(2) @binding(2) var<uniform> em: Emitter; 
 
 
 struct FragmentOutput {
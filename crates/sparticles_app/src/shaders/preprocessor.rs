@@ -0,0 +1,679 @@
+use crate::asset_manager::{AssetError, AssetKind, AssetManager};
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+
+/// Position of a preprocessed line in the original shader file, 1-based
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceLine {
+    pub file: String,
+    pub line: usize,
+}
+
+impl Display for SourceLine {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.file, self.line)
+    }
+}
+
+/// Preprocessed WGSL with the origin of every line
+#[derive(Debug, Clone, Default)]
+pub struct ShaderSource {
+    pub code: String,
+    pub lines: Vec<SourceLine>,
+    /// Every file that was included, also the ones without any output
+    pub files: Vec<String>,
+}
+
+impl ShaderSource {
+    /// Origin of a 1-based line of the preprocessed code
+    pub fn origin(&self, line_number: u32) -> Option<&SourceLine> {
+        self.lines.get((line_number as usize).checked_sub(1)?)
+    }
+
+    /// Prefixes the message with the original file, line and column
+    pub fn describe(&self, location: Option<naga::SourceLocation>, message: &str) -> String {
+        let origin = location.and_then(|loc| {
+            self.origin(loc.line_number)
+                .map(|origin| format!("{}:{}", origin, loc.line_position))
+        });
+
+        match origin {
+            Some(origin) => format!("{}: {}", origin, message),
+            None => message.to_string(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum PreprocessError {
+    Asset(AssetError),
+    Syntax { origin: SourceLine, msg: String },
+}
+
+impl Display for PreprocessError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Asset(err) => err.fmt(f),
+            Self::Syntax { origin, msg } => write!(f, "{}: {}", origin, msg),
+        }
+    }
+}
+
+impl std::error::Error for PreprocessError {}
+
+impl From<AssetError> for PreprocessError {
+    fn from(err: AssetError) -> Self {
+        Self::Asset(err)
+    }
+}
+
+struct Conditional {
+    /// Lines of the current branch are emitted
+    active: bool,
+    /// One of the branches was active, the remaining are skipped
+    taken: bool,
+    parent_active: bool,
+    has_else: bool,
+}
+
+/// Supports:
+/// - `#include "file"`, relative to the shader folder, every file is included once
+/// - `#define NAME value` and `#undef NAME`, values replace whole words
+/// - `#ifdef NAME`, `#ifndef NAME`, `#if EXPR`, `#elif EXPR`, `#else`, `#endif`, nestable.
+///   EXPR supports `defined(NAME)`, `!`, `&&`, `||`, parentheses, names and integers
+pub struct Preprocessor {
+    defines: HashMap<String, String>,
+    included: HashSet<String>,
+    source: ShaderSource,
+}
+
+impl Preprocessor {
+    /// Flags are defined without a value
+    pub fn new(flags: &[&str]) -> Self {
+        Self {
+            defines: flags
+                .iter()
+                .map(|flag| (flag.to_string(), String::new()))
+                .collect(),
+            included: HashSet::new(),
+            source: ShaderSource::default(),
+        }
+    }
+
+    pub fn define(&mut self, name: &str, value: &str) {
+        self.defines.insert(name.to_string(), value.to_string());
+    }
+
+    /// Appends the file, nothing happens if it was included before
+    pub fn include(&mut self, file: &str) -> Result<(), PreprocessError> {
+        if !self.included.insert(file.to_string()) {
+            return Ok(());
+        }
+
+        self.source.files.push(file.to_string());
+
+        let text = AssetManager::read_to_string(AssetKind::Shader, file)?;
+        self.process(file, &text)
+    }
+
+    /// Appends generated code, errors point to lines of the text under the given name
+    pub fn include_source(&mut self, name: &str, text: &str) -> Result<(), PreprocessError> {
+        self.process(name, text)
+    }
+
+    pub fn finish(self) -> ShaderSource {
+        self.source
+    }
+
+    fn process(&mut self, file: &str, text: &str) -> Result<(), PreprocessError> {
+        let mut stack: Vec<Conditional> = Vec::new();
+        let mut last_line = 0;
+
+        for (idx, line) in text.lines().enumerate() {
+            let origin = SourceLine {
+                file: file.to_string(),
+                line: idx + 1,
+            };
+
+            last_line = idx + 1;
+
+            let active = stack.last().is_none_or(|cond| cond.active);
+            let syntax = |msg: &str| PreprocessError::Syntax {
+                origin: origin.clone(),
+                msg: msg.to_string(),
+            };
+
+            let Some(directive) = line.trim().strip_prefix('#') else {
+                if active {
+                    self.source.code += &self.substitute(line);
+                    self.source.code.push('\n');
+                    self.source.lines.push(origin);
+                }
+
+                continue;
+            };
+
+            let (keyword, arg) = match directive.trim().split_once(char::is_whitespace) {
+                Some((keyword, arg)) => (keyword, arg.trim()),
+                None => (directive.trim(), ""),
+            };
+
+            match keyword {
+                "include" => {
+                    if active {
+                        let include = arg
+                            .strip_prefix('"')
+                            .and_then(|arg| arg.strip_suffix('"'))
+                            .ok_or_else(|| syntax("Expected #include \"file\""))?;
+
+                        self.include(include)?;
+                    }
+                }
+                "define" => {
+                    if active {
+                        let (name, value) =
+                            arg.split_once(char::is_whitespace).unwrap_or((arg, ""));
+
+                        if !is_identifier(name) {
+                            return Err(syntax("Expected #define NAME value"));
+                        }
+
+                        self.define(name, value.trim());
+                    }
+                }
+                "undef" => {
+                    if active {
+                        self.defines.remove(arg);
+                    }
+                }
+                "ifdef" | "ifndef" | "if" => {
+                    let is_true = match keyword {
+                        "ifdef" => self.defines.contains_key(arg),
+                        "ifndef" => !self.defines.contains_key(arg),
+                        _ => self.evaluate(arg).map_err(|msg| syntax(&msg))?,
+                    };
+
+                    stack.push(Conditional {
+                        active: active && is_true,
+                        taken: is_true,
+                        parent_active: active,
+                        has_else: false,
+                    });
+                }
+                "elif" => {
+                    let is_true = self.evaluate(arg).map_err(|msg| syntax(&msg))?;
+                    let cond = stack
+                        .last_mut()
+                        .ok_or_else(|| syntax("#elif without #if"))?;
+
+                    if cond.has_else {
+                        return Err(syntax("#elif after #else"));
+                    }
+
+                    cond.active = cond.parent_active && !cond.taken && is_true;
+                    cond.taken |= is_true;
+                }
+                "else" => {
+                    let cond = stack
+                        .last_mut()
+                        .ok_or_else(|| syntax("#else without #if"))?;
+
+                    if cond.has_else {
+                        return Err(syntax("Multiple #else"));
+                    }
+
+                    cond.active = cond.parent_active && !cond.taken;
+                    cond.taken = true;
+                    cond.has_else = true;
+                }
+                "endif" => {
+                    stack.pop().ok_or_else(|| syntax("#endif without #if"))?;
+                }
+                _ => return Err(syntax(&format!("Unknown directive #{}", keyword))),
+            }
+        }
+
+        if !stack.is_empty() {
+            return Err(PreprocessError::Syntax {
+                origin: SourceLine {
+                    file: file.to_string(),
+                    line: last_line,
+                },
+                msg: "Missing #endif".to_string(),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Replaces whole words that are defined with a value
+    fn substitute(&self, line: &str) -> String {
+        if self.defines.values().all(|value| value.is_empty()) {
+            return line.to_string();
+        }
+
+        let mut result = String::with_capacity(line.len());
+        let mut word = String::new();
+
+        let flush = |word: &mut String, result: &mut String| {
+            match self.defines.get(word.as_str()) {
+                Some(value) if !value.is_empty() => result.push_str(value),
+                _ => result.push_str(word),
+            }
+
+            word.clear();
+        };
+
+        for ch in line.chars() {
+            if ch.is_alphanumeric() || ch == '_' {
+                word.push(ch);
+            } else {
+                flush(&mut word, &mut result);
+                result.push(ch);
+            }
+        }
+
+        flush(&mut word, &mut result);
+
+        result
+    }
+
+    fn evaluate(&self, expr: &str) -> Result<bool, String> {
+        let tokens = tokenize(expr)?;
+        let mut parser = ExprParser {
+            tokens: &tokens,
+            pos: 0,
+            defines: &self.defines,
+        };
+
+        let value = parser.or()?;
+
+        if parser.pos != tokens.len() {
+            return Err(format!("Unexpected tokens in #if {}", expr));
+        }
+
+        Ok(value != 0)
+    }
+}
+
+pub(crate) fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+
+    chars
+        .next()
+        .is_some_and(|ch| ch.is_alphabetic() || ch == '_')
+        && chars.all(|ch| ch.is_alphanumeric() || ch == '_')
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    Number(i64),
+    Not,
+    And,
+    Or,
+    Open,
+    Close,
+}
+
+fn tokenize(expr: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = expr.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            ' ' | '\t' => {}
+            '!' => tokens.push(Token::Not),
+            '(' => tokens.push(Token::Open),
+            ')' => tokens.push(Token::Close),
+            '&' if chars.next_if_eq(&'&').is_some() => tokens.push(Token::And),
+            '|' if chars.next_if_eq(&'|').is_some() => tokens.push(Token::Or),
+            _ if ch.is_alphanumeric() || ch == '_' => {
+                let mut word = ch.to_string();
+
+                while let Some(next) = chars.next_if(|ch| ch.is_alphanumeric() || *ch == '_') {
+                    word.push(next);
+                }
+
+                match word.parse::<i64>() {
+                    Ok(number) => tokens.push(Token::Number(number)),
+                    Err(_) => tokens.push(Token::Ident(word)),
+                }
+            }
+            _ => return Err(format!("Unexpected '{}' in #if {}", ch, expr)),
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct ExprParser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+    defines: &'a HashMap<String, String>,
+}
+
+impl<'a> ExprParser<'a> {
+    fn next(&mut self) -> Option<&'a Token> {
+        let token = self.tokens.get(self.pos);
+        self.pos += 1;
+        token
+    }
+
+    fn eat(&mut self, token: Token) -> bool {
+        let is_next = self.tokens.get(self.pos) == Some(&token);
+
+        if is_next {
+            self.pos += 1;
+        }
+
+        is_next
+    }
+
+    fn or(&mut self) -> Result<i64, String> {
+        let mut value = self.and()?;
+
+        while self.eat(Token::Or) {
+            let rhs = self.and()?;
+            value = (value != 0 || rhs != 0) as i64;
+        }
+
+        Ok(value)
+    }
+
+    fn and(&mut self) -> Result<i64, String> {
+        let mut value = self.unary()?;
+
+        while self.eat(Token::And) {
+            let rhs = self.unary()?;
+            value = (value != 0 && rhs != 0) as i64;
+        }
+
+        Ok(value)
+    }
+
+    fn unary(&mut self) -> Result<i64, String> {
+        if self.eat(Token::Not) {
+            return Ok((self.unary()? == 0) as i64);
+        }
+
+        match self.next() {
+            Some(Token::Number(number)) => Ok(*number),
+            Some(Token::Open) => {
+                let value = self.or()?;
+
+                if !self.eat(Token::Close) {
+                    return Err("Expected ')'".to_string());
+                }
+
+                Ok(value)
+            }
+            Some(Token::Ident(name)) if name == "defined" => {
+                let has_parens = self.eat(Token::Open);
+
+                let Some(Token::Ident(name)) = self.next() else {
+                    return Err("Expected a name after defined".to_string());
+                };
+
+                if has_parens && !self.eat(Token::Close) {
+                    return Err("Expected ')'".to_string());
+                }
+
+                Ok(self.defines.contains_key(name) as i64)
+            }
+            // Flags without a value are true, undefined names are false
+            Some(Token::Ident(name)) => match self.defines.get(name).map(|v| v.as_str()) {
+                None | Some("false") => Ok(0),
+                Some("") | Some("true") => Ok(1),
+                Some(value) => value
+                    .parse::<i64>()
+                    .map_err(|_| format!("{} is not a number", name)),
+            },
+            _ => Err("Expected an expression".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn preprocess(flags: &[&str], text: &str) -> Result<ShaderSource, PreprocessError> {
+        let mut preprocessor = Preprocessor::new(flags);
+        preprocessor.include_source("test.wgsl", text)?;
+        Ok(preprocessor.finish())
+    }
+
+    fn code_lines(source: &ShaderSource) -> Vec<&str> {
+        source.code.lines().collect()
+    }
+
+    fn syntax_origin(err: PreprocessError) -> (SourceLine, String) {
+        match err {
+            PreprocessError::Syntax { origin, msg } => (origin, msg),
+            PreprocessError::Asset(err) => panic!("Expected a syntax error, got {}", err),
+        }
+    }
+
+    const NESTED: &str = "\
+#if defined(A)
+a
+#ifdef B
+ab
+#elif C
+ac
+#else
+a_else
+#endif
+#elif B
+b
+#else
+none
+#endif";
+
+    #[test]
+    fn nested_conditionals_pick_one_branch() {
+        let cases: [(&[&str], &[&str]); 5] = [
+            (&["A", "B"], &["a", "ab"]),
+            (&["A", "C"], &["a", "ac"]),
+            (&["A"], &["a", "a_else"]),
+            (&["B", "C"], &["b"]),
+            (&[], &["none"]),
+        ];
+
+        for (flags, expected) in cases {
+            let source = preprocess(flags, NESTED).unwrap();
+            assert_eq!(code_lines(&source), expected, "flags {:?}", flags);
+        }
+    }
+
+    #[test]
+    fn elif_after_taken_branch_is_skipped() {
+        let text = "#if 1\none\n#elif 1\ntwo\n#else\nthree\n#endif";
+        let source = preprocess(&[], text).unwrap();
+
+        assert_eq!(code_lines(&source), ["one"]);
+    }
+
+    #[test]
+    fn unbalanced_conditionals_are_errors() {
+        let cases = [
+            ("#if 1\na", "Missing #endif"),
+            ("#endif", "#endif without #if"),
+            ("#else", "#else without #if"),
+            ("#elif 1", "#elif without #if"),
+            ("#if 1\n#else\n#elif 1\n#endif", "#elif after #else"),
+            ("#if 1\n#else\n#else\n#endif", "Multiple #else"),
+        ];
+
+        for (text, expected) in cases {
+            let (_, msg) = syntax_origin(preprocess(&[], text).unwrap_err());
+            assert_eq!(msg, expected, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn include_guards_emit_code_once() {
+        let guarded = "#ifndef GUARD\n#define GUARD\nguarded\n#endif";
+        let mut preprocessor = Preprocessor::new(&[]);
+
+        preprocessor
+            .include_source("guarded.wgsl", guarded)
+            .unwrap();
+        preprocessor
+            .include_source("guarded.wgsl", guarded)
+            .unwrap();
+
+        assert_eq!(code_lines(&preprocessor.finish()), ["guarded"]);
+    }
+
+    #[test]
+    fn include_cycles_include_every_file_once() {
+        AssetManager::embed(
+            AssetKind::Shader,
+            "preprocessor_test/cycle_a.wgsl",
+            b"#include \"preprocessor_test/cycle_b.wgsl\"\na",
+        );
+        AssetManager::embed(
+            AssetKind::Shader,
+            "preprocessor_test/cycle_b.wgsl",
+            b"#include \"preprocessor_test/cycle_a.wgsl\"\nb",
+        );
+
+        let mut preprocessor = Preprocessor::new(&[]);
+        preprocessor
+            .include("preprocessor_test/cycle_a.wgsl")
+            .unwrap();
+        preprocessor
+            .include("preprocessor_test/cycle_b.wgsl")
+            .unwrap();
+
+        let source = preprocessor.finish();
+
+        assert_eq!(code_lines(&source), ["b", "a"]);
+        assert_eq!(
+            source.files,
+            [
+                "preprocessor_test/cycle_a.wgsl",
+                "preprocessor_test/cycle_b.wgsl"
+            ]
+        );
+    }
+
+    #[test]
+    fn substitute_replaces_whole_words() {
+        let mut preprocessor = Preprocessor::new(&["FLAG"]);
+        preprocessor.define("SIZE", "64u");
+
+        assert_eq!(
+            preprocessor.substitute("let x = SIZE + SIZE_2 * FLAG(SIZE);"),
+            "let x = 64u + SIZE_2 * FLAG(64u);"
+        );
+        assert_eq!(preprocessor.substitute(""), "");
+    }
+
+    #[test]
+    fn defines_apply_to_following_lines_only() {
+        let text = "a = N;\n#define N 3\nb = N;\n#undef N\nc = N;";
+        let source = preprocess(&[], text).unwrap();
+
+        assert_eq!(code_lines(&source), ["a = N;", "b = 3;", "c = N;"]);
+    }
+
+    #[test]
+    fn evaluate_expressions() {
+        let mut preprocessor = Preprocessor::new(&["FLAG"]);
+        preprocessor.define("ZERO", "0");
+        preprocessor.define("TWO", "2");
+        preprocessor.define("OFF", "false");
+
+        let cases = [
+            ("1", true),
+            ("0", false),
+            ("FLAG", true),
+            ("UNDEFINED", false),
+            ("ZERO", false),
+            ("TWO", true),
+            ("OFF", false),
+            ("defined(ZERO)", true),
+            ("defined UNDEFINED", false),
+            ("!FLAG || TWO && ZERO", false),
+            ("(!FLAG || TWO) && !ZERO", true),
+            ("!!TWO", true),
+        ];
+
+        for (expr, expected) in cases {
+            assert_eq!(preprocessor.evaluate(expr), Ok(expected), "{}", expr);
+        }
+
+        preprocessor.define("NAME", "abc");
+
+        for expr in ["", "(1", "1 2", "1 & 2", "defined()", "NAME", "1 + 2"] {
+            assert!(preprocessor.evaluate(expr).is_err(), "{}", expr);
+        }
+    }
+
+    #[test]
+    fn lines_map_back_to_included_files() {
+        AssetManager::embed(
+            AssetKind::Shader,
+            "preprocessor_test/lib.wgsl",
+            b"// lib\n#ifdef SKIPPED\nskipped\n#endif\nlib_fn",
+        );
+
+        let text = "main_start\n#include \"preprocessor_test/lib.wgsl\"\nmain_end";
+        let source = preprocess(&[], text).unwrap();
+
+        assert_eq!(
+            code_lines(&source),
+            ["main_start", "// lib", "lib_fn", "main_end"]
+        );
+
+        let origins: Vec<String> = (1..=4)
+            .map(|line| source.origin(line).unwrap().to_string())
+            .collect();
+
+        assert_eq!(
+            origins,
+            [
+                "test.wgsl:1",
+                "preprocessor_test/lib.wgsl:1",
+                "preprocessor_test/lib.wgsl:5",
+                "test.wgsl:3"
+            ]
+        );
+        assert_eq!(source.origin(0), None);
+        assert_eq!(source.origin(5), None);
+
+        let location = naga::SourceLocation {
+            line_number: 3,
+            line_position: 7,
+            offset: 0,
+            length: 1,
+        };
+
+        assert_eq!(
+            source.describe(Some(location), "unknown function"),
+            "preprocessor_test/lib.wgsl:5:7: unknown function"
+        );
+        assert_eq!(
+            source.describe(None, "unknown function"),
+            "unknown function"
+        );
+    }
+
+    #[test]
+    fn syntax_errors_point_to_the_included_file() {
+        AssetManager::embed(
+            AssetKind::Shader,
+            "preprocessor_test/broken.wgsl",
+            b"ok\n\n#if (1\n#endif",
+        );
+
+        let text = "#include \"preprocessor_test/broken.wgsl\"";
+        let (origin, msg) = syntax_origin(preprocess(&[], text).unwrap_err());
+
+        assert_eq!(origin.to_string(), "preprocessor_test/broken.wgsl:3");
+        assert_eq!(msg, "Expected ')'");
+    }
+}
@@ -1,9 +1,15 @@
+use super::layout;
+use super::preprocessor::{PreprocessError, Preprocessor, ShaderSource};
 use crate::model::GfxState;
 use egui_wgpu::wgpu::{self};
+use std::error::Error;
+use std::sync::{Mutex, OnceLock};
 use std::{borrow::Cow, fs, path::PathBuf};
 
 pub const SDR_PBR: &str = "pbr/pbr.wgsl";
 pub const SDR_TONEMAPPING: &str = "pbr/tonemapping.wgsl";
+pub const SDR_IBL: &str = "pbr/ibl.wgsl";
+pub const SDR_LIGHTING: &str = "pbr/lighting.wgsl";
 pub const DECLARATIONS: &str = "declarations.wgsl";
 pub const DIR_HAS_LIGHTS: &str = "HAS_LIGHTS";
 
@@ -12,59 +18,142 @@ pub struct ShaderLocation<'a> {
     pub filenames: &'a [&'a str],
 }
 
-fn finalize_shader(shader_str: String, if_directives: &[&str]) -> String {
-    let mut append_line = true;
-    let mut result = String::new();
-
-    for line_raw in shader_str.lines() {
-        let line = line_raw.trim();
-
-        if let Some(stripped) = line.strip_prefix("#if") {
-            let key = stripped.trim();
-            append_line = if_directives.contains(&key);
-        } else if line.starts_with("#else") {
-            append_line = !append_line;
-        } else if line.starts_with("#endif") {
-            append_line = true;
-        } else if append_line {
-            result.push_str(line);
-            result.push('\n');
+pub struct ShaderOptions<'a> {
+    /// Included in order, files include their own dependencies
+    pub files: &'a [&'a str],
+    /// Defined without a value before preprocessing
+    pub if_directives: &'a [&'a str],
+    pub label: &'a str,
+}
+
+/// Owned shader options, kept to validate the shader again after one of its files changed
+#[derive(Debug, Clone, PartialEq)]
+pub struct BuiltinShader {
+    pub files: Vec<String>,
+    pub if_directives: Vec<String>,
+    pub label: String,
+}
+
+impl BuiltinShader {
+    fn registry() -> &'static Mutex<Vec<BuiltinShader>> {
+        static CREATED: OnceLock<Mutex<Vec<BuiltinShader>>> = OnceLock::new();
+        CREATED.get_or_init(|| Mutex::new(vec![]))
+    }
+
+    /// Every distinct builtin shader created since startup
+    pub fn created() -> Vec<BuiltinShader> {
+        Self::registry().lock().unwrap().clone()
+    }
+
+    pub fn source(&self) -> Result<ShaderSource, PreprocessError> {
+        let flags: Vec<&str> = self.if_directives.iter().map(|d| d.as_str()).collect();
+        let mut preprocessor = Preprocessor::new(&flags);
+
+        for file in self.files.iter() {
+            preprocessor.include(file)?;
         }
+
+        Ok(preprocessor.finish())
+    }
+
+    pub fn validate(&self, source: &ShaderSource) -> Result<(), String> {
+        validate_source(source)
     }
 
-    result
+    fn register(&self) {
+        let mut created = Self::registry().lock().unwrap();
+
+        if !created.contains(self) {
+            created.push(self.clone());
+        }
+    }
 }
 
-pub struct ShaderOptions<'a> {
-    pub files: &'a [&'a str],
-    pub if_directives: &'a [&'a str],
-    pub label: &'a str,
+/// Parses and validates the source with naga, errors point to the original files.
+/// Structs that are written from Rust are compared with their encase layout.
+pub fn validate_source(source: &ShaderSource) -> Result<(), String> {
+    let code = &source.code;
+
+    let module = naga::front::wgsl::parse_str(code).map_err(|err| {
+        let mut msg = source.describe(err.location(code), err.message());
+
+        for (span, label) in err.labels().filter(|(_, label)| !label.is_empty()) {
+            msg += &format!("\n  {}", source.describe(Some(span.location(code)), label));
+        }
+
+        msg
+    })?;
+
+    let mut validator = naga::valid::Validator::new(
+        naga::valid::ValidationFlags::all(),
+        naga::valid::Capabilities::all(),
+    );
+
+    validator.validate(&module).map_err(|err| {
+        let mut message = err.as_inner().to_string();
+        let mut cause = err.as_inner().source();
+
+        while let Some(err) = cause {
+            message += &format!(": {}", err);
+            cause = err.source();
+        }
+
+        let mut msg = source.describe(err.location(code), &message);
+
+        for (span, label) in err.spans().filter(|(_, label)| !label.is_empty()) {
+            msg += &format!("\n  {}", source.describe(Some(span.location(code)), label));
+        }
+
+        msg
+    })?;
+
+    layout::check_layouts(&module)
+}
+
+impl From<&ShaderOptions<'_>> for BuiltinShader {
+    fn from(options: &ShaderOptions<'_>) -> Self {
+        Self {
+            files: options.files.iter().map(|f| f.to_string()).collect(),
+            if_directives: options
+                .if_directives
+                .iter()
+                .map(|d| d.to_string())
+                .collect(),
+            label: options.label.to_string(),
+        }
+    }
 }
 
 impl GfxState {
-    /// Uses builtin path /src/shaders/
+    /// Preprocesses the files as shader assets, embedded or from src/shaders/
     pub fn create_shader_builtin(&self, options: ShaderOptions) -> wgpu::ShaderModule {
-        let device = &self.device;
-        let mut shader_str = String::new();
-        let all_files = [&["declarations.wgsl"], options.files].concat();
+        let shader = BuiltinShader::from(&options);
 
-        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-        path.push("src/shaders/dummy.txt");
+        let source = shader.source().unwrap_or_else(|err| panic!("{}", err));
 
-        for filename in all_files {
-            let file_path = path.with_file_name(filename);
-            let error_msg = format!("Path not found: {}", filename);
-            let file = fs::read_to_string(file_path).expect(&error_msg);
+        let module = self
+            .create_shader_module(&source, options.label)
+            .unwrap_or_else(|err| panic!("Invalid shader {}:\n{}", options.label, err));
 
-            let res = &finalize_shader(file, options.if_directives);
-            //println!("{res}");
-            shader_str += res;
-        }
+        shader.register();
 
-        device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some(options.label),
-            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(&shader_str)),
-        })
+        module
+    }
+
+    /// Validates preprocessed code before creating the module, used for generated shaders
+    pub fn create_shader_module(
+        &self,
+        source: &ShaderSource,
+        label: &str,
+    ) -> Result<wgpu::ShaderModule, String> {
+        validate_source(source)?;
+
+        Ok(self
+            .device
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some(label),
+                source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(&source.code)),
+            }))
     }
 
     /// Automatically includes declarations.wgsl
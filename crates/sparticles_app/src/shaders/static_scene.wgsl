@@ -0,0 +1,57 @@
+#include "declarations.wgsl"
+#include "pbr/tonemapping.wgsl"
+#include "pbr/pbr.wgsl"
+#include "pbr/lighting.wgsl"
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) uv: vec2<f32>,
+    @location(2) normal: vec3<f32>,
+    @location(3) tangent: vec3<f32>,
+    @location(4) bitangent: vec3<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_pos: vec3<f32>,
+    @location(1) uv: vec2<f32>,
+    @location(2) normal: vec3<f32>,
+    @location(3) tangent: vec3<f32>,
+    @location(4) bitangent: vec3<f32>,
+}
+
+struct SceneNode {
+    world: mat4x4<f32>,
+    // Inverse transpose of the world matrix
+    normal: mat4x4<f32>,
+}
+
+@group(2) @binding(0) var<uniform> node: SceneNode;
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput {
+    let normal_mat = mat3x3(node.normal[0].xyz, node.normal[1].xyz, node.normal[2].xyz);
+    let world_mat = mat3x3(node.world[0].xyz, node.world[1].xyz, node.world[2].xyz);
+
+    var out: VertexOutput;
+    out.world_pos = (node.world * vec4(in.position, 1.0)).xyz;
+    out.uv = in.uv;
+    out.normal = normalize(normal_mat * in.normal);
+    out.tangent = normalize(world_mat * in.tangent);
+    out.bitangent = normalize(world_mat * in.bitangent);
+    out.clip_position = camera.view_proj * vec4(out.world_pos, 1.0);
+
+    return out;
+}
+
+// Static geometry is lit by every light emitter
+@fragment
+fn fs_main(in: VertexOutput) -> FragmentOutput {
+    let tangent_normal = textureSample(normal_tex, normal_s, in.uv).rgb * 2.0 - 1.0;
+    let TBN = mat3x3(in.tangent, in.bitangent, in.normal);
+
+    let N = normalize(TBN * tangent_normal);
+    let albedo = textureSample(albedo_tex, albedo_s, in.uv).rgb;
+
+    return apply_pbr(in.world_pos, in.clip_position.xy, in.uv, N, in.normal, albedo, 0xffffffffu);
+}
@@ -0,0 +1,96 @@
+#include "declarations.wgsl"
+
+@group(0) @binding(1) var<storage, read_write> particles: array<Particle>;
+@group(0) @binding(2) var<uniform> em: Emitter; 
+@group(1) @binding(0) var<storage, read_write> events: SpawnEvents; 
+@group(1) @binding(1) var<uniform> sub: SubEmitter; 
+
+// Particles that can be spawned this frame, bounded by the child particle buffer
+fn spawn_total() -> u32 {
+    let event_count = min(atomicLoad(&events.count), events.max_events);
+    return min(event_count * sub.spawn_count, arrayLength(&particles));
+}
+
+fn create_velocity(input_random: f32, speed_random: f32) -> vec3<f32> {
+    let diff_width = gen_dyn_range(input_random * 0.12, em.diffusion_width, em.elapsed_sec) / 2.;
+    let diff_depth = gen_dyn_range(input_random * 0.45, em.diffusion_depth, em.elapsed_sec) / 2.;
+
+    let local_vel = vec3<f32>(0., speed_random, 0.) * yaw_matrix(em.box_yaw) * pitch_matrix(em.box_pitch + diff_width) * roll_matrix(em.box_roll + diff_depth);
+
+    return (em.parent_world * vec4<f32>(local_vel, 0.)).xyz;
+}
+
+fn create_offset(input_random: f32) -> vec3<f32> {
+    let random_width = random(input_random * 1.6, em.elapsed_sec) - 0.5;
+    let random_height = random(input_random * 0.42, em.elapsed_sec) - 0.5;
+    let random_depth = random(input_random / 0.11, em.elapsed_sec) - 0.5;
+
+    let local_pos = vec3<f32>(
+        random_width * em.box_width,
+        random_height * em.box_height,
+        random_depth * em.box_depth,
+    );
+
+    let local_rot = local_pos * yaw_matrix(em.box_yaw) * pitch_matrix(em.box_pitch) * roll_matrix(em.box_roll);
+
+    return (em.parent_world * vec4<f32>(local_rot, 0.)).xyz;
+}
+
+@compute
+@workgroup_size(128)
+fn main(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
+    let particle_len = arrayLength(&particles);
+    let index = global_invocation_id.x;
+
+    if particle_len <= index {
+        return;
+    }
+
+    // Child particles are used as a ring buffer starting at the head
+    let ring_idx = (index + particle_len - events.head % particle_len) % particle_len;
+
+    if spawn_total() <= ring_idx {
+        return;
+    }
+
+    // In world space, the trigger includes the motion of local space parents
+    let event = events.events[ring_idx / sub.spawn_count];
+    let input_random = f32(index);
+
+    let size_delta = em.particle_size_max - em.particle_size_min;
+    let size = em.particle_size_min + gen_abs_range(input_random + 100., size_delta, em.elapsed_sec);
+
+    let speed_delta = em.particle_speed_max - em.particle_speed_min;
+    let speed = em.particle_speed_min + gen_abs_range(input_random + 40., speed_delta, em.elapsed_sec);
+
+    let velocity = create_velocity(input_random, speed) + event.velocity.xyz * sub.inherit_velocity;
+    let position = event.position.xyz + create_offset(input_random);
+
+    var particle: Particle;
+
+    if sub.inherit_color == 1u {
+        particle.color = event.color;
+    } else {
+        particle.color = vec4<f32>(
+            em.particle_color_r,
+            em.particle_color_g,
+            em.particle_color_b,
+            em.particle_color_a,
+        );
+    }
+
+    particle.scale = size;
+    particle.vel_mass = vec4<f32>(velocity, em.material_mass * size);
+    particle.lifetime = 0.;
+    particle.model = em.particle_model;
+    particle.model.w = vec4(position, 1.0);
+
+    particles[index] = particle;
+}
+
+@compute
+@workgroup_size(1)
+fn reset() {
+    events.head = (events.head + spawn_total()) % arrayLength(&particles);
+    atomicStore(&events.count, 0u);
+}
@@ -0,0 +1,78 @@
+#include "declarations.wgsl"
+
+@group(0) @binding(0) var<storage, read> particles_src: array<Particle>;
+@group(0) @binding(1) var<storage, read_write> particles: array<Particle>;
+@group(0) @binding(2) var<uniform> em: Emitter; 
+@group(1) @binding(0) var<storage, read_write> events: SpawnEvents; 
+@group(1) @binding(1) var<uniform> sub: SubEmitter; 
+
+// Compares the particle before and after the emitter computed it this frame
+fn is_triggered(prev: Particle, particle: Particle) -> bool {
+    // Decayed particles keep their lifetime, only the frame it's exceeded counts
+    if sub.trigger == TRIGGER_DEATH {
+        return !is_decayed(em, prev) && is_decayed(em, particle);
+    }
+
+    if is_decayed(em, particle) {
+        return false;
+    }
+
+    let lifetime = particle.lifetime;
+
+    if lifetime == 0. {
+        return sub.trigger == TRIGGER_SPAWN;
+    }
+
+    if sub.trigger == TRIGGER_COLLISION {
+        // Includes the emitter motion of local space particles
+        let pos_y = particle.model.w.y;
+        let prev_pos_y = prev.model.w.y;
+        return pos_y < sub.trigger_value && sub.trigger_value <= prev_pos_y;
+    } else if sub.trigger == TRIGGER_INTERVAL && 0. < sub.trigger_value {
+        let prev_lifetime = prev.lifetime;
+        return floor(prev_lifetime / sub.trigger_value) != floor(lifetime / sub.trigger_value);
+    }
+
+    return false;
+}
+
+// Velocity in world space, local space particles also moved along with the emitter this frame
+fn world_velocity(prev: Particle, particle: Particle) -> vec3<f32> {
+    if particle.lifetime == 0. || em.delta_sec <= 0. {
+        return particle.vel_mass.xyz;
+    }
+
+    let prev_pos = prev.model.w;
+    let carried_pos = em.space_delta * prev_pos;
+
+    return particle.vel_mass.xyz + (carried_pos.xyz - prev_pos.xyz) / em.delta_sec;
+}
+
+@compute
+@workgroup_size(128)
+fn main(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
+    let particle_len = arrayLength(&particles);
+    let index = global_invocation_id.x;
+
+    if particle_len <= index {
+        return;
+    }
+
+    let prev = particles_src[index];
+    let particle = particles[index];
+
+    if !is_triggered(prev, particle) {
+        return;
+    }
+
+    let event_idx = atomicAdd(&events.count, 1u);
+
+    if event_idx < events.max_events {
+        var event: SpawnEvent;
+        event.position = vec4<f32>(particle.model.w.xyz, 1.);
+        event.velocity = vec4<f32>(world_velocity(prev, particle), 0.);
+        event.color = particle.color;
+
+        events.events[event_idx] = event;
+    }
+}
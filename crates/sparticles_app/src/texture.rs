@@ -277,8 +277,8 @@ impl TextureHandler {
         gfx_arc: &Arc<RwLock<GfxState>>,
         path: &str,
         std_rgb: bool,
-    ) -> wgpu::Texture {
-        let bytes = fs::read(path).expect("Can't read texture image");
+    ) -> anyhow::Result<wgpu::Texture> {
+        let bytes = fs::read(path)?;
         Self::tex_from_bytes(gfx_arc, &bytes, std_rgb).await
     }
 
@@ -286,8 +286,8 @@ impl TextureHandler {
         gfx_arc: &Arc<RwLock<GfxState>>,
         bytes: &[u8],
         std_rgb: bool,
-    ) -> wgpu::Texture {
-        let diffuse_image = image::load_from_memory(bytes).unwrap();
+    ) -> anyhow::Result<wgpu::Texture> {
+        let diffuse_image = image::load_from_memory(bytes)?;
         let diffuse_rgba = diffuse_image.to_rgba8();
         let dimensions = diffuse_image.dimensions();
 
@@ -328,6 +328,6 @@ impl TextureHandler {
             texture_size,
         );
 
-        diffuse_texture
+        Ok(diffuse_texture)
     }
 }
@@ -6,7 +6,7 @@ use crate::{
 };
 use async_std::sync::RwLock;
 use egui_wgpu::wgpu::{self, CommandEncoder};
-use egui_winit::winit::event::KeyboardInput;
+use egui_winit::winit::event::KeyEvent;
 use std::{any::Any, num::NonZeroU64, slice::IterMut, sync::Arc};
 
 pub trait FromRGB {
@@ -22,15 +22,18 @@ pub trait ToVecF32 {
 }
 
 // --------------------------- Animations ------------------------------
-pub trait RegisterEmitterAnimation {
+pub trait RegisterEmitterAnimation: Send + Sync {
     fn tag(&self) -> &'static str;
 
     fn create_default(&self) -> Box<dyn EmitterAnimation>;
 
-    fn import(&self, value: serde_json::Value) -> Box<dyn EmitterAnimation>;
+    fn import(
+        &self,
+        value: serde_json::Value,
+    ) -> Result<Box<dyn EmitterAnimation>, serde_json::Error>;
 }
 
-pub trait RegisterParticleAnimation {
+pub trait RegisterParticleAnimation: Send + Sync {
     fn tag(&self) -> &'static str;
 
     fn create_default(
@@ -44,7 +47,7 @@ pub trait RegisterParticleAnimation {
         gfx_state: &GfxState,
         emitter: &EmitterState,
         value: serde_json::Value,
-    ) -> Box<dyn ParticleAnimation>;
+    ) -> Result<Box<dyn ParticleAnimation>, serde_json::Error>;
 }
 
 impl PartialEq for dyn RegisterParticleAnimation {
@@ -66,13 +69,22 @@ pub trait ParticleAnimation: HandleAction {
 
     fn recreate(&self, gfx_state: &GfxState, emitter: &EmitterState) -> Box<dyn ParticleAnimation>;
     fn update(&mut self, clock: &Clock, gfx: &GfxState);
+
+    /// True if [`Self::apply_data`] can take the exported data, otherwise the animation is
+    /// imported again
+    fn accepts_data(&self, data: &serde_json::Value) -> bool {
+        false
+    }
+
+    /// Replaces the settings with accepted data while keeping the pipeline and runtime state
+    fn apply_data(&mut self, data: &serde_json::Value) {}
 }
 
 pub trait WidgetBuilder {
     fn process_input(
         &mut self,
         events: &mut SparEvents,
-        input: &KeyboardInput,
+        input: &KeyEvent,
         shift_pressed: bool,
     ) -> bool;
 
@@ -87,6 +99,15 @@ pub trait WidgetBuilder {
 pub trait EmitterAnimation: HandleAction {
     fn animate(&mut self, emitter: &mut EmitterUniform, clock: &Clock);
     fn as_any(&mut self) -> &mut dyn Any;
+
+    /// True if [`Self::apply_data`] can take the exported data, otherwise the animation is
+    /// imported again
+    fn accepts_data(&self, _data: &serde_json::Value) -> bool {
+        false
+    }
+
+    /// Replaces the settings with accepted data while keeping the runtime state
+    fn apply_data(&mut self, _data: &serde_json::Value) {}
 }
 
 // Post FX
@@ -104,16 +125,22 @@ pub trait PostFx: HandleAction {
     fn as_any(&mut self) -> &mut dyn Any;
 }
 
-pub trait RegisterPostFx {
+pub trait RegisterPostFx: Send + Sync {
     fn tag(&self) -> &'static str;
     fn create_default(&self, options: &FxOptions) -> Box<dyn PostFx>;
-    fn import(&self, options: &FxOptions, value: serde_json::Value) -> Box<dyn PostFx>;
+    fn import(
+        &self,
+        options: &FxOptions,
+        value: serde_json::Value,
+    ) -> Result<Box<dyn PostFx>, serde_json::Error>;
 }
 
-pub trait HandleAction {
+/// Animations and post fx are Send so a host engine can render from another thread
+pub trait HandleAction: Send + Sync {
     fn selected_action(&mut self) -> &mut ListAction;
     fn export(&self) -> DynamicExport;
     fn enabled(&self) -> bool;
+    fn set_enabled(&mut self, enabled: bool);
 }
 
 pub trait CreateFxView {
@@ -0,0 +1,97 @@
+use super::ID;
+use crate::model::EmitterUniform;
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiagnosticKind {
+    /// Animation or post fx that isn't registered, the entry is kept as is and exported again
+    UnknownTag(String),
+    /// Missing field or a field of the wrong type
+    InvalidData(String),
+    /// The value is replaced by the default
+    OutOfRange { value: f64, expected: &'static str },
+    /// Emitter with the id of an earlier emitter, it's kept as is and exported again
+    DuplicateId(ID),
+}
+
+/// Problem found while importing a scene document
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportDiagnostic {
+    /// JSON path of the entry, like "emitters[0].particle_animations[1].data"
+    pub path: String,
+    pub kind: DiagnosticKind,
+}
+
+impl Display for ImportDiagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.kind {
+            DiagnosticKind::UnknownTag(tag) => {
+                write!(f, "{}: unknown type '{}', kept as is", self.path, tag)
+            }
+            DiagnosticKind::InvalidData(msg) => write!(f, "{}: {}", self.path, msg),
+            DiagnosticKind::OutOfRange { value, expected } => write!(
+                f,
+                "{}: {} is out of range, expected {}, using the default",
+                self.path, value, expected
+            ),
+            DiagnosticKind::DuplicateId(id) => {
+                write!(
+                    f,
+                    "{}: emitter id '{}' is used twice, kept as is",
+                    self.path, id
+                )
+            }
+        }
+    }
+}
+
+impl ImportDiagnostic {
+    pub fn new(path: impl Into<String>, kind: DiagnosticKind) -> Self {
+        Self {
+            path: path.into(),
+            kind,
+        }
+    }
+
+    /// Replaces values that can't create particle buffers with the defaults of a new emitter
+    pub(crate) fn check_emitter(
+        uniform: &mut EmitterUniform,
+        path: &str,
+        diagnostics: &mut Vec<ImportDiagnostic>,
+    ) {
+        let default = EmitterUniform::new(uniform.id.to_string());
+        let is_positive = |value: f32| value > 0.;
+        let mut out_of_range = |field: &str, value: f64, expected: &'static str| {
+            diagnostics.push(Self::new(
+                format!("{}.{}", path, field),
+                DiagnosticKind::OutOfRange { value, expected },
+            ));
+        };
+
+        if uniform.spawn_count == 0 {
+            out_of_range("spawn_count", 0., "at least 1");
+            uniform.spawn_count = default.spawn_count;
+        }
+
+        if !is_positive(uniform.spawn_delay_sec) {
+            out_of_range("spawn_delay_sec", uniform.spawn_delay_sec as f64, "above 0");
+            uniform.spawn_delay_sec = default.spawn_delay_sec;
+        }
+
+        if !is_positive(uniform.particle_lifetime_sec) {
+            let value = uniform.particle_lifetime_sec as f64;
+            out_of_range("particle_lifetime_sec", value, "above 0");
+            uniform.particle_lifetime_sec = default.particle_lifetime_sec;
+        }
+
+        if uniform.spawn_batches_count == Some(0) {
+            out_of_range("spawn_batches_count", 0., "at least 1");
+            uniform.spawn_batches_count = default.spawn_batches_count;
+        }
+
+        if !is_positive(uniform.light_range) {
+            out_of_range("light_range", uniform.light_range as f64, "above 0");
+            uniform.light_range = default.light_range;
+        }
+    }
+}
@@ -1,6 +1,10 @@
 pub mod common;
+pub mod diagnostics;
 pub mod math;
 pub mod persistence;
+pub mod scene_document;
 
 pub use common::{ListAction, Tag, UniformContext, ID};
-pub use persistence::{DynamicExport, ExportEmitter, ExportType, Persistence};
+pub use diagnostics::{DiagnosticKind, ImportDiagnostic};
+pub use persistence::{with_unrecognized, DynamicExport, ExportEmitter, Persistence, Unrecognized};
+pub use scene_document::{ExportCamera, SceneDocument, SceneError, SCENE_FORMAT_VERSION};
@@ -0,0 +1,560 @@
+use super::diagnostics::{DiagnosticKind, ImportDiagnostic};
+use super::persistence::{with_unrecognized, DynamicExport, ExportEmitter, Unrecognized};
+use crate::model::{Camera, EffectTransform, EmitterUniform, SceneLighting, TonemapType};
+use glam::Vec3;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt::{Display, Formatter};
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+/// Version written by this build, documents of older versions are migrated when loaded
+pub const SCENE_FORMAT_VERSION: u32 = 1;
+pub const SCENE_FILE: &str = "scene.json";
+
+/// Upgrades a document from the version of its index to the next version
+const MIGRATIONS: [fn(&mut Value); SCENE_FORMAT_VERSION as usize] = [migrate_v0];
+
+/// Files the emitters, post fx and lighting were exported to before scene documents
+const LEGACY_POST_FX_FILE: &str = "post_fx.json";
+const LEGACY_SCENE_LIGHTING_FILE: &str = "scene_lighting.json";
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io {
+        path: PathBuf,
+        err: std::io::Error,
+    },
+    Json(serde_json::Error),
+    /// Written by a newer build than this one
+    NewerVersion(u32),
+}
+
+impl Display for SceneError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io { path, err } => write!(f, "Can't access {}: {}", path.display(), err),
+            Self::Json(err) => write!(f, "Invalid scene document: {}", err),
+            Self::NewerVersion(version) => write!(
+                f,
+                "Scene format version {} is newer than the supported version {}",
+                version, SCENE_FORMAT_VERSION
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+impl From<serde_json::Error> for SceneError {
+    fn from(err: serde_json::Error) -> Self {
+        Self::Json(err)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct ExportCamera {
+    pub position: Vec3,
+    pub pitch: f32,
+    pub yaw: f32,
+    pub tonemap_type: TonemapType,
+}
+
+impl ExportCamera {
+    pub fn new(camera: &Camera) -> Self {
+        Self {
+            position: camera.position,
+            pitch: camera.pitch,
+            yaw: camera.yaw,
+            tonemap_type: camera.tonemap_type,
+        }
+    }
+
+    pub fn apply(&self, camera: &mut Camera) {
+        camera.position = self.position;
+        camera.pitch = self.pitch;
+        camera.yaw = self.yaw;
+        camera.tonemap_type = self.tonemap_type;
+    }
+}
+
+/// Everything the editor exports about a scene, without runtime state
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SceneDocument {
+    pub format_version: u32,
+    pub emitters: Vec<ExportEmitter>,
+    #[serde(default)]
+    pub post_fx: Vec<DynamicExport>,
+    #[serde(default)]
+    pub camera: Option<ExportCamera>,
+    #[serde(default)]
+    pub scene_lighting: SceneLighting,
+    #[serde(default)]
+    pub effect_root: EffectTransform,
+    /// Fields of newer versions, exported as they were
+    #[serde(flatten)]
+    pub unknown_fields: serde_json::Map<String, Value>,
+    /// Emitters that can't be read or have the id of an earlier emitter, as they were in the
+    /// document. Inserted in the emitters again by to_value and save.
+    #[serde(skip)]
+    pub unrecognized_emitters: Vec<Unrecognized<Value>>,
+    /// Problems of the emitters found while loading, those emitters are skipped
+    #[serde(skip)]
+    pub diagnostics: Vec<ImportDiagnostic>,
+}
+
+impl SceneDocument {
+    /// Reads a scene document of any version. An emitters.json of before scene documents is
+    /// loaded with the post_fx.json and scene_lighting.json next to it.
+    pub fn load(path: &Path) -> Result<Self, SceneError> {
+        let read = |path: &Path| {
+            fs::read_to_string(path).map_err(|err| SceneError::Io {
+                path: path.to_path_buf(),
+                err,
+            })
+        };
+
+        let value: Value = serde_json::from_str(&read(path)?)?;
+
+        if !value.is_array() {
+            return Self::from_value(value);
+        }
+
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let sibling = |name: &str| -> Result<Option<Value>, SceneError> {
+            let path = dir.join(name);
+
+            match path.exists() {
+                true => Ok(Some(serde_json::from_str(&read(&path)?)?)),
+                false => Ok(None),
+            }
+        };
+
+        let post_fx = sibling(LEGACY_POST_FX_FILE)?;
+        let scene_lighting = sibling(LEGACY_SCENE_LIGHTING_FILE)?;
+
+        Self::from_value(legacy_document(value, post_fx, scene_lighting))
+    }
+
+    /// Parses an emitters.json of before scene documents with the post_fx.json and
+    /// scene_lighting.json next to it, for hosts that read the files themselves
+    pub fn from_legacy_json(
+        emitters: &str,
+        post_fx: Option<&str>,
+        scene_lighting: Option<&str>,
+    ) -> Result<Self, SceneError> {
+        let post_fx = post_fx.map(serde_json::from_str).transpose()?;
+        let scene_lighting = scene_lighting.map(serde_json::from_str).transpose()?;
+
+        Self::from_value(legacy_document(
+            serde_json::from_str(emitters)?,
+            post_fx,
+            scene_lighting,
+        ))
+    }
+
+    /// Parses a scene document of any version, or the emitter array of before scene documents
+    pub fn from_json(json: &str) -> Result<Self, SceneError> {
+        let value: Value = serde_json::from_str(json)?;
+
+        match value.is_array() {
+            true => Self::from_value(legacy_document(value, None, None)),
+            false => Self::from_value(value),
+        }
+    }
+
+    fn from_value(mut value: Value) -> Result<Self, SceneError> {
+        if !value.is_object() {
+            let msg = "expected a scene document object";
+            return Err(SceneError::Json(serde::de::Error::custom(msg)));
+        }
+
+        let version = value
+            .get("format_version")
+            .and_then(Value::as_u64)
+            .unwrap_or(0) as u32;
+
+        if SCENE_FORMAT_VERSION < version {
+            return Err(SceneError::NewerVersion(version));
+        }
+
+        for migrate in MIGRATIONS[version as usize..].iter() {
+            migrate(&mut value);
+        }
+
+        value["format_version"] = Value::from(SCENE_FORMAT_VERSION);
+
+        // Emitters are read one by one so a broken emitter doesn't fail the document
+        let emitters = std::mem::replace(&mut value["emitters"], Value::Array(vec![]));
+        let mut document: Self = serde_json::from_value(value)?;
+        document.read_emitters(emitters);
+
+        Ok(document)
+    }
+
+    fn read_emitters(&mut self, emitters: Value) {
+        let Value::Array(items) = emitters else {
+            let kind = DiagnosticKind::InvalidData("expected an array".to_string());
+            self.diagnostics
+                .push(ImportDiagnostic::new("emitters", kind));
+            return;
+        };
+
+        for (idx, item) in items.into_iter().enumerate() {
+            let path = format!("emitters[{}]", idx);
+
+            let mut export = match serde_json::from_value::<ExportEmitter>(item.clone()) {
+                Ok(export) => export,
+                Err(err) => {
+                    let kind = DiagnosticKind::InvalidData(err.to_string());
+                    self.diagnostics.push(ImportDiagnostic::new(path, kind));
+                    self.unrecognized_emitters.push((idx, item));
+                    continue;
+                }
+            };
+
+            let id = &export.emitter.id;
+
+            if self.emitters.iter().any(|other| &other.emitter.id == id) {
+                let kind = DiagnosticKind::DuplicateId(id.to_string());
+                let path = format!("{}.emitter.id", path);
+                self.diagnostics.push(ImportDiagnostic::new(path, kind));
+                self.unrecognized_emitters.push((idx, item));
+                continue;
+            }
+
+            let path = format!("{}.emitter", path);
+            ImportDiagnostic::check_emitter(&mut export.emitter, &path, &mut self.diagnostics);
+            export.document_index = Some(idx);
+            self.emitters.push(export);
+        }
+    }
+
+    /// The document with the unrecognized emitters at their position
+    pub fn to_value(&self) -> Result<Value, SceneError> {
+        let mut value = serde_json::to_value(self)?;
+        let emitters = std::mem::take(&mut value["emitters"]);
+
+        if let Value::Array(emitters) = emitters {
+            value["emitters"] =
+                Value::Array(with_unrecognized(emitters, &self.unrecognized_emitters));
+        }
+
+        Ok(value)
+    }
+
+    /// Writes the document as scene.json into the directory
+    pub fn save(&self, dir: &Path) -> Result<PathBuf, SceneError> {
+        let path = dir.join(SCENE_FILE);
+        let io_err = |err| SceneError::Io {
+            path: path.to_path_buf(),
+            err,
+        };
+
+        fs::create_dir_all(dir).map_err(io_err)?;
+        let file = File::create(&path).map_err(io_err)?;
+
+        let mut writer = BufWriter::new(file);
+        serde_json::to_writer(&mut writer, &self.to_value()?)?;
+
+        Ok(path)
+    }
+}
+
+/// Version 0 are the separate export files of before scene documents
+fn legacy_document(
+    emitters: Value,
+    post_fx: Option<Value>,
+    scene_lighting: Option<Value>,
+) -> Value {
+    let mut document = serde_json::json!({
+        "format_version": 0,
+        "emitters": emitters,
+        "post_fx": post_fx.unwrap_or(Value::Array(vec![])),
+    });
+
+    if let Some(scene_lighting) = scene_lighting {
+        document["scene_lighting"] = scene_lighting;
+    }
+
+    document
+}
+
+/// Removes the runtime state that was exported with the emitters and gravity animations.
+/// The spawn batches count was fixed when the emitter was created, it's kept if the lifetime
+/// or delay changed afterwards so the amount of particles stays the same.
+fn migrate_v0(document: &mut Value) {
+    const EMITTER_RUNTIME: [&str; 5] = [
+        "spawn_from",
+        "spawn_until",
+        "elapsed_sec",
+        "delta_sec",
+        "iteration",
+    ];
+
+    const GRAVITY_RUNTIME: [&str; 2] = ["should_animate", "current_pos"];
+
+    let Some(emitters) = document.get_mut("emitters").and_then(Value::as_array_mut) else {
+        return;
+    };
+
+    for export in emitters.iter_mut() {
+        if let Some(emitter) = export.get_mut("emitter").and_then(Value::as_object_mut) {
+            for key in EMITTER_RUNTIME {
+                emitter.remove(key);
+            }
+
+            let seconds = |key: &str| emitter.get(key).and_then(Value::as_f64);
+            let derived = seconds("particle_lifetime_sec")
+                .zip(seconds("spawn_delay_sec"))
+                .map(|(lifetime, delay)| {
+                    EmitterUniform::derived_batches_count(lifetime as f32, delay as f32)
+                });
+
+            let batches = emitter.get("spawn_batches_count").and_then(Value::as_u64);
+
+            if batches.is_none() || batches == derived.map(u64::from) {
+                emitter.remove("spawn_batches_count");
+            }
+        }
+
+        let Some(animations) = export
+            .get_mut("particle_animations")
+            .and_then(Value::as_array_mut)
+        else {
+            continue;
+        };
+
+        for animation in animations
+            .iter_mut()
+            .filter(|anim| anim["type"] == "gravity")
+        {
+            if let Some(data) = animation.get_mut("data").and_then(Value::as_object_mut) {
+                for key in GRAVITY_RUNTIME {
+                    data.remove(key);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Exported by the editor before scene documents existed
+    const V0_EMITTERS: &str = include_str!("fixtures/v0_emitters.json");
+    const V0_POST_FX: &str = include_str!("fixtures/v0_post_fx.json");
+
+    fn v0_document() -> Value {
+        let emitters = serde_json::from_str(V0_EMITTERS).unwrap();
+        let post_fx = serde_json::from_str(V0_POST_FX).unwrap();
+
+        legacy_document(emitters, Some(post_fx), None)
+    }
+
+    /// Directory in the temp folder that is empty
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "sparticles_scene_document_{}_{}",
+            name,
+            std::process::id()
+        ));
+
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn legacy_document_wraps_the_export_files() {
+        let document = v0_document();
+
+        assert_eq!(document["format_version"], 0);
+        assert_eq!(document["emitters"].as_array().unwrap().len(), 2);
+        assert_eq!(document["post_fx"][0]["type"], "bloom");
+        assert!(document.get("scene_lighting").is_none());
+
+        let lighting = serde_json::to_value(SceneLighting::default()).unwrap();
+        let document = legacy_document(Value::Array(vec![]), None, Some(lighting.clone()));
+
+        assert_eq!(document["post_fx"], Value::Array(vec![]));
+        assert_eq!(document["scene_lighting"], lighting);
+    }
+
+    #[test]
+    fn migrate_v0_removes_runtime_state() {
+        let mut document = v0_document();
+        migrate_v0(&mut document);
+
+        for export in document["emitters"].as_array().unwrap() {
+            let emitter = export["emitter"].as_object().unwrap();
+
+            for key in [
+                "spawn_from",
+                "spawn_until",
+                "elapsed_sec",
+                "delta_sec",
+                "iteration",
+            ] {
+                assert!(!emitter.contains_key(key), "{}", key);
+            }
+
+            for anim in export["particle_animations"].as_array().unwrap() {
+                assert!(anim["data"].get("current_pos").is_none());
+                assert!(anim["data"].get("should_animate").is_none());
+            }
+        }
+
+        let gravity = &document["emitters"][0]["particle_animations"][1];
+        assert_eq!(gravity["type"], "gravity");
+        assert_eq!(gravity["data"]["mass"], 100000.0);
+    }
+
+    #[test]
+    fn migrate_v0_keeps_batches_that_differ_from_the_lifetime() {
+        let mut document = v0_document();
+
+        // 6 sec lifetime with a delay of 1 sec derives the same count
+        document["emitters"][0]["emitter"]["spawn_batches_count"] = Value::from(6);
+        migrate_v0(&mut document);
+
+        let emitter = |idx: usize| document["emitters"][idx]["emitter"].as_object().unwrap();
+
+        assert!(!emitter(0).contains_key("spawn_batches_count"));
+        assert_eq!(emitter(1)["spawn_batches_count"], 12);
+    }
+
+    #[test]
+    fn v0_files_load_as_the_current_version() {
+        let document = SceneDocument::from_value(v0_document()).unwrap();
+
+        assert_eq!(document.format_version, SCENE_FORMAT_VERSION);
+        assert_eq!(document.diagnostics, vec![]);
+        assert!(document.unknown_fields.is_empty());
+
+        let ids: Vec<&str> = document
+            .emitters
+            .iter()
+            .map(|export| export.emitter.id.as_str())
+            .collect();
+        assert_eq!(ids, ["Light", "Particles"]);
+
+        for export in document.emitters.iter() {
+            assert!(export.emitter.unknown_fields.is_empty());
+            // Both emitters were created with the default lifetime and delay
+            assert_eq!(export.emitter.spawn_batches_count, Some(12));
+        }
+
+        assert_eq!(document.post_fx.len(), 1);
+        assert_eq!(document.post_fx[0].tag, "bloom");
+        assert_eq!(document.scene_lighting, SceneLighting::default());
+    }
+
+    #[test]
+    fn from_json_reads_legacy_emitter_arrays() {
+        let document = SceneDocument::from_json(V0_EMITTERS).unwrap();
+
+        assert_eq!(document.emitters.len(), 2);
+        assert!(document.post_fx.is_empty());
+    }
+
+    #[test]
+    fn from_legacy_json_reads_the_sibling_files() {
+        let document =
+            SceneDocument::from_legacy_json(V0_EMITTERS, Some(V0_POST_FX), None).unwrap();
+
+        assert_eq!(document.emitters.len(), 2);
+        assert_eq!(document.post_fx.len(), 1);
+        assert_eq!(document.post_fx[0].tag, "bloom");
+    }
+
+    #[test]
+    fn resaved_documents_load_unchanged() {
+        let migrated = SceneDocument::from_value(v0_document()).unwrap();
+        let saved = migrated.to_value().unwrap();
+
+        assert_eq!(saved["format_version"], SCENE_FORMAT_VERSION);
+
+        let reloaded = SceneDocument::from_value(saved.clone()).unwrap();
+
+        assert_eq!(reloaded.diagnostics, vec![]);
+        assert_eq!(reloaded.to_value().unwrap(), saved);
+    }
+
+    #[test]
+    fn unrecognized_emitters_keep_their_position() {
+        let mut saved = SceneDocument::from_value(v0_document())
+            .unwrap()
+            .to_value()
+            .unwrap();
+
+        let emitters = saved["emitters"].as_array_mut().unwrap();
+        let duplicate = emitters[0].clone();
+        emitters.insert(1, serde_json::json!({ "emitter": 5 }));
+        emitters.push(duplicate);
+
+        let document = SceneDocument::from_value(saved.clone()).unwrap();
+
+        let indices: Vec<Option<usize>> = document
+            .emitters
+            .iter()
+            .map(|export| export.document_index)
+            .collect();
+        assert_eq!(indices, [Some(0), Some(2)]);
+
+        let paths: Vec<&str> = document
+            .diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.path.as_str())
+            .collect();
+        assert_eq!(paths, ["emitters[1]", "emitters[3].emitter.id"]);
+
+        assert_eq!(document.to_value().unwrap(), saved);
+    }
+
+    #[test]
+    fn load_reads_legacy_sibling_files() {
+        let dir = temp_dir("legacy");
+        let mut lighting = SceneLighting::default();
+        lighting.ambient.intensity = 0.25;
+
+        fs::write(dir.join("emitters.json"), V0_EMITTERS).unwrap();
+        fs::write(dir.join(LEGACY_POST_FX_FILE), V0_POST_FX).unwrap();
+        fs::write(
+            dir.join(LEGACY_SCENE_LIGHTING_FILE),
+            serde_json::to_string(&lighting).unwrap(),
+        )
+        .unwrap();
+
+        let document = SceneDocument::load(&dir.join("emitters.json")).unwrap();
+
+        assert_eq!(document.emitters.len(), 2);
+        assert_eq!(document.post_fx.len(), 1);
+        assert_eq!(document.scene_lighting, lighting);
+
+        let path = document.save(&dir).unwrap();
+        let resaved = SceneDocument::load(&path).unwrap();
+
+        assert_eq!(
+            serde_json::to_value(&resaved).unwrap(),
+            serde_json::to_value(&document).unwrap()
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn newer_versions_are_rejected() {
+        let json = format!(
+            "{{\"format_version\": {}, \"emitters\": []}}",
+            SCENE_FORMAT_VERSION + 1
+        );
+
+        assert!(matches!(
+            SceneDocument::from_json(&json),
+            Err(SceneError::NewerVersion(version)) if version == SCENE_FORMAT_VERSION + 1
+        ));
+    }
+}
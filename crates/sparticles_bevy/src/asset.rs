@@ -0,0 +1,182 @@
+use bevy::asset::io::{AssetReaderError, Reader};
+use bevy::asset::{AssetLoader, AsyncReadExt, LoadContext, ReadAssetBytesError};
+use bevy::prelude::*;
+use bevy::utils::BoxedFuture;
+use sparticles_app::util::{
+    DynamicExport, ExportEmitter, ImportDiagnostic, SceneDocument, SceneError,
+};
+use std::fmt::{Display, Formatter};
+
+/// Emitters and post fx of a scene.json, or of an emitters.json with the post_fx.json and
+/// scene_lighting.json that were exported next to it
+#[derive(Asset, TypePath, Debug, Clone)]
+pub struct ParticleEffectAsset {
+    pub emitters: Vec<ExportEmitter>,
+    /// Applied when the asset is the post fx of a [`crate::ParticleCamera`]
+    pub post_fx: Vec<DynamicExport>,
+    /// Problems of the document, emitters with problems are left out
+    pub diagnostics: Vec<ImportDiagnostic>,
+}
+
+impl From<SceneDocument> for ParticleEffectAsset {
+    fn from(document: SceneDocument) -> Self {
+        Self {
+            emitters: document.emitters,
+            post_fx: document.post_fx,
+            diagnostics: document.diagnostics,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ParticleEffectLoaderError {
+    Io(std::io::Error),
+    Read(ReadAssetBytesError),
+    Scene(SceneError),
+}
+
+impl Display for ParticleEffectLoaderError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(err) => err.fmt(f),
+            Self::Read(err) => err.fmt(f),
+            Self::Scene(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for ParticleEffectLoaderError {}
+
+#[derive(Default)]
+pub struct ParticleEffectLoader;
+
+impl ParticleEffectLoader {
+    const LEGACY_POST_FX_FILE: &'static str = "post_fx.json";
+    const LEGACY_SCENE_LIGHTING_FILE: &'static str = "scene_lighting.json";
+
+    /// Reads a file next to the loaded asset, None if it doesn't exist
+    async fn read_sibling(
+        load_context: &mut LoadContext<'_>,
+        name: &str,
+    ) -> Result<Option<String>, ParticleEffectLoaderError> {
+        let path = load_context.path().with_file_name(name);
+
+        match load_context.read_asset_bytes(path).await {
+            Ok(bytes) => Ok(Some(String::from_utf8_lossy(&bytes).into_owned())),
+            Err(ReadAssetBytesError::AssetReaderError(AssetReaderError::NotFound(_))) => Ok(None),
+            Err(err) => Err(ParticleEffectLoaderError::Read(err)),
+        }
+    }
+}
+
+impl AssetLoader for ParticleEffectLoader {
+    type Asset = ParticleEffectAsset;
+    type Settings = ();
+    type Error = ParticleEffectLoaderError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<ParticleEffectAsset, ParticleEffectLoaderError>> {
+        Box::pin(async move {
+            let mut json = String::new();
+            reader
+                .read_to_string(&mut json)
+                .await
+                .map_err(ParticleEffectLoaderError::Io)?;
+
+            // Emitter arrays were exported before scene documents existed
+            let document = if json.trim_start().starts_with('[') {
+                let post_fx = Self::read_sibling(load_context, Self::LEGACY_POST_FX_FILE).await?;
+                let scene_lighting =
+                    Self::read_sibling(load_context, Self::LEGACY_SCENE_LIGHTING_FILE).await?;
+
+                SceneDocument::from_legacy_json(
+                    &json,
+                    post_fx.as_deref(),
+                    scene_lighting.as_deref(),
+                )
+            } else {
+                SceneDocument::from_json(&json)
+            }
+            .map_err(ParticleEffectLoaderError::Scene)?;
+
+            for diagnostic in document.diagnostics.iter() {
+                warn!("{}: {}", load_context.path().display(), diagnostic);
+            }
+
+            Ok(document.into())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["json"]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+
+    const V0_EMITTERS: &str =
+        include_str!("../../sparticles_app/src/util/fixtures/v0_emitters.json");
+    const V0_POST_FX: &str = include_str!("../../sparticles_app/src/util/fixtures/v0_post_fx.json");
+
+    fn asset_dir() -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("sparticles_bevy_assets_{}", std::process::id()));
+
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn legacy_exports_load_with_their_post_fx() {
+        let dir = asset_dir();
+        fs::write(dir.join("emitters.json"), V0_EMITTERS).unwrap();
+        fs::write(dir.join("post_fx.json"), V0_POST_FX).unwrap();
+
+        let mut app = App::new();
+        app.add_plugins((
+            MinimalPlugins,
+            AssetPlugin {
+                file_path: dir.to_string_lossy().into_owned(),
+                ..Default::default()
+            },
+        ))
+        .init_asset::<ParticleEffectAsset>()
+        .init_asset_loader::<ParticleEffectLoader>();
+
+        let handle: Handle<ParticleEffectAsset> =
+            app.world.resource::<AssetServer>().load("emitters.json");
+
+        for _ in 0..1000 {
+            app.update();
+
+            if app
+                .world
+                .resource::<Assets<ParticleEffectAsset>>()
+                .contains(&handle)
+            {
+                break;
+            }
+
+            std::thread::sleep(std::time::Duration::from_millis(2));
+        }
+
+        let assets = app.world.resource::<Assets<ParticleEffectAsset>>();
+        let effect = assets.get(&handle).expect("asset didn't load");
+
+        assert_eq!(effect.emitters.len(), 2);
+        assert_eq!(effect.post_fx.len(), 1);
+        assert_eq!(effect.post_fx[0].tag, "bloom");
+        assert_eq!(effect.diagnostics, vec![]);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
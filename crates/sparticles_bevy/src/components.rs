@@ -0,0 +1,74 @@
+use crate::asset::ParticleEffectAsset;
+use bevy::math::EulerRot;
+use bevy::prelude::*;
+use sparticles_app::glam;
+use sparticles_app::model::{EffectTransform, StopMode};
+
+/// Spawns the emitters of the asset at the global transform of the entity and moves them with
+/// it. The effect stops when the component or the entity is removed. Scale isn't applied.
+#[derive(Component, Clone, Debug)]
+pub struct ParticleEffect {
+    pub asset: Handle<ParticleEffectAsset>,
+    /// How the effect ends when the component is removed
+    pub stop_mode: StopMode,
+}
+
+impl ParticleEffect {
+    pub fn new(asset: Handle<ParticleEffectAsset>) -> Self {
+        Self {
+            asset,
+            stop_mode: StopMode::Finish,
+        }
+    }
+}
+
+/// Particles are rendered into the 3d camera with this component, only the first active one is
+/// used. Particles are hidden behind the scene when the depth texture of the camera has
+/// texture binding usage and MSAA is off.
+#[derive(Component, Clone, Debug, Default)]
+pub struct ParticleCamera {
+    /// The post fx of this asset are applied to the particles
+    pub post_fx: Option<Handle<ParticleEffectAsset>>,
+}
+
+/// Position and rotation of the transform in the yaw, pitch, roll order of sparticles
+pub fn effect_transform(transform: &GlobalTransform) -> EffectTransform {
+    let (_, rotation, translation) = transform.to_scale_rotation_translation();
+    let (roll, pitch, yaw) = rotation.to_euler(EulerRot::XZY);
+
+    EffectTransform {
+        position: to_vec3(translation),
+        rotation: glam::Vec3::new(-yaw, -pitch, -roll),
+    }
+}
+
+/// Bevy and sparticles depend on other glam releases
+pub(crate) fn to_vec3(v: Vec3) -> glam::Vec3 {
+    glam::Vec3::from_array(v.to_array())
+}
+
+pub(crate) fn to_mat4(m: Mat4) -> glam::Mat4 {
+    glam::Mat4::from_cols_array(&m.to_cols_array())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sparticles_app::model::transform::rotation_matrix;
+
+    #[test]
+    fn effect_transform_keeps_the_rotation() {
+        let rotation = Quat::from_euler(EulerRot::YXZ, 0.7, -0.4, 1.2);
+        let transform = GlobalTransform::from(
+            Transform::from_xyz(1., 2., 3.)
+                .with_rotation(rotation)
+                .with_scale(Vec3::splat(2.)),
+        );
+
+        let effect = effect_transform(&transform);
+        let expected = glam::Mat3::from_cols_array(&Mat3::from_quat(rotation).to_cols_array());
+
+        assert_eq!(effect.position, glam::Vec3::new(1., 2., 3.));
+        assert!(rotation_matrix(effect.rotation).abs_diff_eq(expected, 1e-5));
+    }
+}
@@ -0,0 +1,43 @@
+//! Renders sparticles effects inside the render graph of Bevy. The emitters are simulated and
+//! rendered after the main pass of the particle camera, before tonemapping.
+use bevy::core_pipeline::core_3d::graph::{Core3d, Node3d};
+use bevy::prelude::*;
+use bevy::render::render_graph::{RenderGraphApp, ViewNodeRunner};
+use bevy::render::{ExtractSchedule, Render, RenderApp, RenderSet};
+use render::{
+    extract_cameras, extract_effects, prepare_effects, ExtractedEffects, SparticlesNode,
+    SparticlesState,
+};
+
+pub use asset::{ParticleEffectAsset, ParticleEffectLoader, ParticleEffectLoaderError};
+pub use components::{effect_transform, ParticleCamera, ParticleEffect};
+pub use render::SparticlesLabel;
+pub use sparticles_app::model::StopMode;
+
+mod asset;
+mod components;
+mod render;
+
+pub struct SparticlesPlugin;
+
+impl Plugin for SparticlesPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<ParticleEffectAsset>()
+            .init_asset_loader::<ParticleEffectLoader>();
+
+        let Ok(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+
+        render_app
+            .init_resource::<SparticlesState>()
+            .init_resource::<ExtractedEffects>()
+            .add_systems(ExtractSchedule, (extract_cameras, extract_effects))
+            .add_systems(Render, prepare_effects.in_set(RenderSet::Prepare))
+            .add_render_graph_node::<ViewNodeRunner<SparticlesNode>>(Core3d, SparticlesLabel)
+            .add_render_graph_edges(
+                Core3d,
+                (Node3d::EndMainPass, SparticlesLabel, Node3d::Tonemapping),
+            );
+    }
+}
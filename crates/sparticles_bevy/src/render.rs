@@ -0,0 +1,342 @@
+use crate::asset::ParticleEffectAsset;
+use crate::components::{effect_transform, to_mat4, ParticleCamera, ParticleEffect};
+use async_std::task;
+use bevy::ecs::query::QueryItem;
+use bevy::prelude::*;
+use bevy::render::render_graph::{NodeRunError, RenderGraphContext, RenderLabel, ViewNode};
+use bevy::render::render_resource::{TextureFormat, TextureUsages};
+use bevy::render::renderer::{RenderContext, RenderDevice, RenderQueue};
+use bevy::render::view::{ViewDepthTexture, ViewTarget};
+use bevy::render::Extract;
+use bevy::utils::{HashMap, HashSet};
+use sparticles_app::embed::{DepthRange, FrameTarget, SparRenderer};
+use sparticles_app::glam;
+use sparticles_app::model::{
+    EffectHandle, EffectTransform, EmbedOptions, ExternalView, GfxState, SharedDevice, StopMode,
+};
+use sparticles_app::wgpu;
+use std::ops::Deref;
+use std::sync::Mutex;
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, RenderLabel)]
+pub struct SparticlesLabel;
+
+/// Render world copy of a [`ParticleCamera`]
+#[derive(Component, Clone)]
+pub(crate) struct ExtractedParticleCamera {
+    view: ExternalView,
+    post_fx: Option<AssetId<ParticleEffectAsset>>,
+}
+
+struct ExtractedEffect {
+    entity: Entity,
+    asset: AssetId<ParticleEffectAsset>,
+    stop_mode: StopMode,
+    transform: EffectTransform,
+}
+
+#[derive(Resource, Default)]
+pub(crate) struct ExtractedEffects {
+    effects: Vec<ExtractedEffect>,
+    /// Assets that were loaded or changed since the last frame
+    changed: Vec<(AssetId<ParticleEffectAsset>, ParticleEffectAsset)>,
+    removed: Vec<AssetId<ParticleEffectAsset>>,
+}
+
+struct Spawned {
+    asset: AssetId<ParticleEffectAsset>,
+    handle: EffectHandle,
+    stop_mode: StopMode,
+}
+
+/// Owns the renderer, it's created for the format of the first particle camera
+#[derive(Resource, Default)]
+pub(crate) struct SparticlesState {
+    renderer: Option<Mutex<SparRenderer>>,
+    view_entity: Option<Entity>,
+    format: Option<TextureFormat>,
+    size: UVec2,
+    templates: HashMap<AssetId<ParticleEffectAsset>, ParticleEffectAsset>,
+    spawned: HashMap<Entity, Spawned>,
+    post_fx: Option<AssetId<ParticleEffectAsset>>,
+    unsupported: bool,
+}
+
+/// Hands the device of Bevy to sparticles
+struct BevyDevice(RenderDevice);
+
+impl Deref for BevyDevice {
+    type Target = wgpu::Device;
+
+    fn deref(&self) -> &wgpu::Device {
+        self.0.wgpu_device()
+    }
+}
+
+fn template_name(asset: AssetId<ParticleEffectAsset>) -> String {
+    format!("{:?}", asset)
+}
+
+type CameraQuery = (
+    Entity,
+    &'static Camera,
+    &'static ParticleCamera,
+    &'static Projection,
+    &'static GlobalTransform,
+);
+
+pub(crate) fn extract_cameras(mut commands: Commands, cameras: Extract<Query<CameraQuery>>) {
+    for (entity, camera, particle_camera, projection, transform) in cameras.iter() {
+        // Sparticles renders with a finite perspective, the depth of Bevy is remapped to it
+        let Projection::Perspective(projection) = projection else {
+            continue;
+        };
+
+        if !camera.is_active {
+            continue;
+        }
+
+        let view = ExternalView {
+            view: to_mat4(transform.compute_matrix().inverse()),
+            proj: glam::Mat4::perspective_rh(
+                projection.fov,
+                projection.aspect_ratio,
+                projection.near,
+                projection.far,
+            ),
+            near: projection.near,
+            far: projection.far,
+        };
+
+        commands
+            .get_or_spawn(entity)
+            .insert(ExtractedParticleCamera {
+                view,
+                post_fx: particle_camera.post_fx.as_ref().map(Handle::id),
+            });
+    }
+}
+
+pub(crate) fn extract_effects(
+    mut extracted: ResMut<ExtractedEffects>,
+    effects: Extract<Query<(Entity, &ParticleEffect, &GlobalTransform)>>,
+    mut events: Extract<EventReader<AssetEvent<ParticleEffectAsset>>>,
+    assets: Extract<Res<Assets<ParticleEffectAsset>>>,
+) {
+    extracted.effects = effects
+        .iter()
+        .map(|(entity, effect, transform)| ExtractedEffect {
+            entity,
+            asset: effect.asset.id(),
+            stop_mode: effect.stop_mode,
+            transform: effect_transform(transform),
+        })
+        .collect();
+
+    for event in events.read() {
+        match *event {
+            AssetEvent::Added { id } | AssetEvent::Modified { id } => {
+                if let Some(asset) = assets.get(id) {
+                    extracted.changed.retain(|(changed, _)| *changed != id);
+                    extracted.changed.push((id, asset.clone()));
+                }
+            }
+            AssetEvent::Removed { id } => extracted.removed.push(id),
+            _ => {}
+        }
+    }
+}
+
+/// Creates and resizes the renderer and syncs the effects with the entities
+pub(crate) fn prepare_effects(
+    mut state: ResMut<SparticlesState>,
+    mut extracted: ResMut<ExtractedEffects>,
+    device: Res<RenderDevice>,
+    queue: Res<RenderQueue>,
+    views: Query<(Entity, &ViewTarget, &ExtractedParticleCamera)>,
+) {
+    let state = &mut *state;
+    let mut changed = HashSet::new();
+
+    for (asset, effect) in extracted.changed.drain(..) {
+        state.templates.insert(asset, effect);
+        changed.insert(asset);
+    }
+
+    for asset in extracted.removed.drain(..) {
+        state.templates.remove(&asset);
+    }
+
+    let Some((view_entity, target, camera)) = views.iter().next() else {
+        state.view_entity = None;
+        return;
+    };
+
+    state.view_entity = Some(view_entity);
+
+    let format = target.main_texture_format();
+    let size = target.main_texture().size();
+    let size = UVec2::new(size.width, size.height);
+
+    if state.format != Some(format) {
+        if let Some(reason) = GfxState::unsupported_reason(device.wgpu_device()) {
+            if !state.unsupported {
+                error!("Particles are not rendered: {}", reason);
+                state.unsupported = true;
+            }
+
+            return;
+        }
+
+        let options = EmbedOptions {
+            device: SharedDevice::new(BevyDevice(device.clone())),
+            queue: queue.0.clone(),
+            format,
+            width: size.x,
+            height: size.y,
+        };
+
+        let mut renderer = task::block_on(SparRenderer::new(options));
+
+        for (asset, effect) in state.templates.iter() {
+            renderer
+                .effects()
+                .insert(&template_name(*asset), effect.emitters.clone());
+        }
+
+        state.renderer = Some(Mutex::new(renderer));
+        state.format = Some(format);
+        state.size = size;
+        state.spawned.clear();
+        state.post_fx = None;
+    }
+
+    let Some(renderer) = &mut state.renderer else {
+        return;
+    };
+
+    let renderer = renderer.get_mut().unwrap();
+
+    if state.size != size {
+        task::block_on(renderer.resize(size.x, size.y));
+        state.size = size;
+    }
+
+    if state.post_fx != camera.post_fx || camera.post_fx.is_some_and(|id| changed.contains(&id)) {
+        let post_fx = camera
+            .post_fx
+            .and_then(|id| state.templates.get(&id))
+            .map(|effect| effect.post_fx.clone());
+
+        // Waits for the asset to load
+        if camera.post_fx.is_none() || post_fx.is_some() {
+            let diagnostics = task::block_on(renderer.set_post_fx(post_fx.unwrap_or_default()));
+
+            for diagnostic in diagnostics.iter() {
+                warn!("{}", diagnostic);
+            }
+
+            state.post_fx = camera.post_fx;
+        }
+    }
+
+    let effects = renderer.effects();
+
+    for asset in changed.iter() {
+        effects.insert(
+            &template_name(*asset),
+            state.templates[asset].emitters.clone(),
+        );
+    }
+
+    state.spawned.retain(|entity, spawned| {
+        let current = extracted
+            .effects
+            .iter()
+            .find(|effect| effect.entity == *entity);
+
+        let keep = current.is_some_and(|effect| {
+            effect.asset == spawned.asset && !changed.contains(&effect.asset)
+        });
+
+        if !keep {
+            effects.stop(spawned.handle, spawned.stop_mode);
+        }
+
+        keep
+    });
+
+    for effect in extracted.effects.iter() {
+        match state.spawned.get(&effect.entity) {
+            // Effects that ended keep their entry, so they aren't spawned again
+            Some(spawned) => {
+                effects.set_transform(spawned.handle, effect.transform);
+            }
+            None if state.templates.contains_key(&effect.asset) => {
+                let name = template_name(effect.asset);
+
+                if let Ok(handle) = effects.spawn(&name, effect.transform) {
+                    let spawned = Spawned {
+                        asset: effect.asset,
+                        handle,
+                        stop_mode: effect.stop_mode,
+                    };
+
+                    state.spawned.insert(effect.entity, spawned);
+                }
+            }
+            None => {}
+        }
+    }
+}
+
+/// Simulates and renders the particles into the main texture of the particle camera
+#[derive(Default)]
+pub(crate) struct SparticlesNode;
+
+impl ViewNode for SparticlesNode {
+    type ViewQuery = (
+        &'static ViewTarget,
+        &'static ExtractedParticleCamera,
+        Option<&'static ViewDepthTexture>,
+    );
+
+    fn run<'w>(
+        &self,
+        graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext<'w>,
+        (target, camera, depth): QueryItem<'w, Self::ViewQuery>,
+        world: &'w World,
+    ) -> Result<(), NodeRunError> {
+        let state = world.resource::<SparticlesState>();
+
+        if state.view_entity != Some(graph.view_entity()) {
+            return Ok(());
+        }
+
+        let Some(renderer) = &state.renderer else {
+            return Ok(());
+        };
+
+        // Multisampled depth can't be imported
+        let depth = depth.filter(|depth| {
+            depth
+                .texture
+                .usage()
+                .contains(TextureUsages::TEXTURE_BINDING)
+                && depth.texture.sample_count() == 1
+        });
+
+        let frame = FrameTarget {
+            color: target.main_texture_view(),
+            depth: depth.map(|depth| &**depth.view()),
+            depth_range: DepthRange::ReversedInfinite,
+            view: camera.view,
+        };
+
+        let mut renderer = renderer.lock().unwrap();
+        task::block_on(renderer.render(render_context.command_encoder(), frame));
+
+        Ok(())
+    }
+}
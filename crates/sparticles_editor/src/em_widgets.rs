@@ -1,10 +1,16 @@
 use sparticles_app::{
-    animations::{DiffusionAnimation, SwayAnimation},
-    gui::egui::{DragValue, Ui},
+    animations::{
+        keyframe_animation::{Interpolation, Keyframe, KeyframeTrack},
+        path_animation::{PathMode, SplineType},
+        DiffusionAnimation, KeyframeAnimation, PathAnimation, SwayAnimation,
+    },
+    glam::{Mat4, Vec3},
+    gui::egui::{self, Color32, DragValue, Pos2, Stroke, Ui},
+    model::EmitterUniform,
     traits::{EmitterAnimation, HandleAngles},
 };
 
-use crate::{EditorData, EditorWidgets};
+use crate::{Editor, EditorData, EditorWidgets};
 
 impl EditorWidgets {
     pub fn sway_anim(editor: &mut EditorData, anim: &mut Box<dyn EmitterAnimation>, ui: &mut Ui) {
@@ -15,24 +21,7 @@ impl EditorWidgets {
             let life_cycle = &mut anim.life_cycle;
             let gui = &mut anim.gui;
 
-            ui.horizontal(|ui| {
-                ui.label("Animate from sec");
-                ui.add(DragValue::new(&mut life_cycle.from_sec).speed(0.1));
-            });
-
-            ui.horizontal(|ui| {
-                ui.label("Animate until sec");
-                ui.add(
-                    DragValue::new(&mut life_cycle.until_sec)
-                        .speed(0.1)
-                        .clamp_range(life_cycle.from_sec..=life_cycle.lifetime_sec),
-                );
-            });
-
-            ui.horizontal(|ui| {
-                ui.label("Until restart animation");
-                ui.add(DragValue::new(&mut life_cycle.lifetime_sec).speed(0.1));
-            });
+            Editor::create_life_cycle(ui, life_cycle);
 
             ui.horizontal(|ui| {
                 ui.label("Pitch (from - until)");
@@ -73,41 +62,244 @@ impl EditorWidgets {
             let life_cycle = &mut anim.life_cycle;
             let gui = &mut anim.gui;
 
+            Editor::create_life_cycle(ui, life_cycle);
+
             ui.horizontal(|ui| {
-                ui.label("Animate from sec");
-                ui.add(DragValue::new(&mut life_cycle.from_sec).speed(0.1));
+                ui.label("Diffusion width (from - until)");
+                ui.add(DragValue::new(&mut gui.diff_width.x).speed(0.1));
+                ui.add(DragValue::new(&mut gui.diff_width.y).speed(0.1));
             });
 
             ui.horizontal(|ui| {
-                ui.label("Animate until sec");
+                ui.label("Diffusion depth (from - until)");
+                ui.add(DragValue::new(&mut gui.diff_depth.x).speed(0.1));
+                ui.add(DragValue::new(&mut gui.diff_depth.y).speed(0.1));
+            });
+
+            ui.checkbox(&mut anim.enabled, "Enabled");
+
+            anim.diff_width = gui.diff_width.to_radians();
+            anim.diff_depth = gui.diff_depth.to_radians();
+        }
+    }
+
+    pub fn keyframe_anim(
+        editor: &mut EditorData,
+        anim: &mut Box<dyn EmitterAnimation>,
+        ui: &mut Ui,
+    ) {
+        let downcast = anim.as_any().downcast_mut::<KeyframeAnimation>();
+
+        if let Some(anim) = downcast {
+            anim.selected_action = editor.create_li_header(ui, "Keyframe animation");
+
+            let mut remove_idx = None;
+
+            for (i, track) in anim.tracks.iter_mut().enumerate() {
+                ui.group(|ui| {
+                    if Self::keyframe_track(track, ui) {
+                        remove_idx = Some(i);
+                    }
+                });
+            }
+
+            if let Some(i) = remove_idx {
+                anim.tracks.remove(i);
+            }
+
+            ui.horizontal(|ui| {
+                if ui.button("Add track").clicked() {
+                    anim.tracks
+                        .push(KeyframeTrack::new(EmitterUniform::PROPERTIES[0]));
+                }
+
+                ui.checkbox(&mut anim.looping, "Loop");
+                ui.checkbox(&mut anim.enabled, "Enabled");
+            });
+        }
+    }
+
+    /// Returns true if the track should be removed
+    fn keyframe_track(track: &mut KeyframeTrack, ui: &mut Ui) -> bool {
+        let track_id = egui::Id::new(track as *const KeyframeTrack);
+        let mut remove_track = false;
+
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_source(track_id.with("property"))
+                .selected_text(&track.property)
+                .show_ui(ui, |ui| {
+                    for property in EmitterUniform::PROPERTIES {
+                        ui.selectable_value(&mut track.property, property.to_string(), property);
+                    }
+                });
+
+            remove_track = ui.button("Remove track").clicked();
+        });
+
+        let mut remove_idx = None;
+
+        for (i, key) in track.keys.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                ui.label("Sec");
                 ui.add(
-                    DragValue::new(&mut life_cycle.until_sec)
-                        .speed(0.1)
-                        .clamp_range(life_cycle.from_sec..=life_cycle.lifetime_sec),
+                    DragValue::new(&mut key.time_sec)
+                        .speed(0.05)
+                        .clamp_range(0f32..=f32::MAX),
                 );
+                ui.label("Value");
+                ui.add(DragValue::new(&mut key.value).speed(0.05));
+
+                egui::ComboBox::from_id_source(track_id.with(i))
+                    .selected_text(key.interpolation.to_string())
+                    .show_ui(ui, |ui| {
+                        for interpolation in Interpolation::ALL {
+                            let is_selected = key.interpolation.same_kind(&interpolation);
+
+                            if ui
+                                .selectable_label(is_selected, interpolation.to_string())
+                                .clicked()
+                                && !is_selected
+                            {
+                                key.interpolation = interpolation;
+                            }
+                        }
+                    });
+
+                if ui.button("x").clicked() {
+                    remove_idx = Some(i);
+                }
             });
 
+            if let Interpolation::Bezier { p1, p2 } = &mut key.interpolation {
+                ui.horizontal(|ui| {
+                    ui.label("Control points");
+                    ui.add(DragValue::new(&mut p1.x).speed(0.01).clamp_range(0f32..=1.));
+                    ui.add(DragValue::new(&mut p1.y).speed(0.01));
+                    ui.add(DragValue::new(&mut p2.x).speed(0.01).clamp_range(0f32..=1.));
+                    ui.add(DragValue::new(&mut p2.y).speed(0.01));
+                });
+            }
+        }
+
+        if let Some(i) = remove_idx {
+            track.keys.remove(i);
+        }
+
+        if ui.button("Add key").clicked() {
+            let last = track.keys.last();
+
+            track.keys.push(Keyframe {
+                time_sec: last.map_or(0., |key| key.time_sec + 1.),
+                value: last.map_or(0., |key| key.value),
+                interpolation: Interpolation::Linear,
+            });
+        }
+
+        track.sort_keys();
+
+        remove_track
+    }
+
+    pub fn path_anim(editor: &mut EditorData, anim: &mut Box<dyn EmitterAnimation>, ui: &mut Ui) {
+        let downcast = anim.as_any().downcast_mut::<PathAnimation>();
+
+        if let Some(anim) = downcast {
+            anim.selected_action = editor.create_li_header(ui, "Path animation");
+
             ui.horizontal(|ui| {
-                ui.label("Until restart animation");
-                ui.add(DragValue::new(&mut life_cycle.lifetime_sec).speed(0.1));
+                ui.selectable_value(&mut anim.spline, SplineType::CatmullRom, "Catmull-Rom");
+                ui.selectable_value(&mut anim.spline, SplineType::Bezier, "Bezier");
             });
 
             ui.horizontal(|ui| {
-                ui.label("Diffusion width (from - until)");
-                ui.add(DragValue::new(&mut gui.diff_width.x).speed(0.1));
-                ui.add(DragValue::new(&mut gui.diff_width.y).speed(0.1));
+                ui.selectable_value(&mut anim.mode, PathMode::Once, "Once");
+                ui.selectable_value(&mut anim.mode, PathMode::Loop, "Loop");
+                ui.selectable_value(&mut anim.mode, PathMode::PingPong, "Ping-pong");
             });
 
             ui.horizontal(|ui| {
-                ui.label("Diffusion depth (from - until)");
-                ui.add(DragValue::new(&mut gui.diff_depth.x).speed(0.1));
-                ui.add(DragValue::new(&mut gui.diff_depth.y).speed(0.1));
+                ui.label("Duration sec");
+                ui.add(
+                    DragValue::new(&mut anim.duration_sec)
+                        .speed(0.1)
+                        .clamp_range(0.1f32..=f32::MAX),
+                );
             });
 
-            ui.checkbox(&mut anim.enabled, "Enabled");
+            Editor::create_easing(ui, &mut anim.easing);
 
-            anim.diff_width = gui.diff_width.to_radians();
-            anim.diff_depth = gui.diff_depth.to_radians();
+            ui.checkbox(&mut anim.orient_to_tangent, "Orient along path");
+
+            if anim.spline == SplineType::Bezier {
+                ui.label("Bezier: point, handle, handle, point, ...");
+            }
+
+            let mut remove_idx = None;
+
+            for (i, point) in anim.points.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.label(format!("Point {}", i));
+                    ui.add(DragValue::new(&mut point.x).speed(0.1));
+                    ui.add(DragValue::new(&mut point.y).speed(0.1));
+                    ui.add(DragValue::new(&mut point.z).speed(0.1));
+
+                    if ui.button("x").clicked() {
+                        remove_idx = Some(i);
+                    }
+                });
+            }
+
+            if let Some(i) = remove_idx {
+                anim.points.remove(i);
+            }
+
+            ui.horizontal(|ui| {
+                if ui.button("Add point").clicked() {
+                    let last = anim.points.last().copied().unwrap_or(Vec3::ZERO);
+                    anim.points.push(last + Vec3::X);
+                }
+
+                ui.checkbox(&mut anim.enabled, "Enabled");
+            });
+
+            let polyline: Vec<Vec3> = anim
+                .polyline()
+                .into_iter()
+                .map(|point| anim.parent_world.transform_point3(point))
+                .collect();
+
+            Self::draw_debug_polyline(ui, &editor.view_proj, &polyline);
+        }
+    }
+
+    /// Draws a world space polyline on top of the scene
+    fn draw_debug_polyline(ui: &mut Ui, view_proj: &Mat4, points: &[Vec3]) {
+        let screen = ui.ctx().screen_rect();
+        let layer = egui::LayerId::new(egui::Order::Background, egui::Id::new("debug-polyline"));
+        let painter = ui.ctx().layer_painter(layer);
+
+        let to_screen = |point: &Vec3| {
+            let clip = *view_proj * point.extend(1.);
+
+            // Behind the camera
+            if clip.w <= 0. {
+                return None;
+            }
+
+            let ndc = clip.truncate() / clip.w;
+
+            Some(Pos2::new(
+                screen.left() + (ndc.x + 1.) / 2. * screen.width(),
+                screen.top() + (1. - ndc.y) / 2. * screen.height(),
+            ))
+        };
+
+        let stroke = Stroke::new(1.5, Color32::YELLOW);
+
+        for window in points.windows(2) {
+            if let (Some(from), Some(until)) = (to_screen(&window[0]), to_screen(&window[1])) {
+                painter.line_segment([from, until], stroke);
+            }
         }
     }
 }
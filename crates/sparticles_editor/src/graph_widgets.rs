@@ -0,0 +1,248 @@
+use crate::{Editor, EditorData, EditorWidgets};
+use sparticles_app::{
+    animations::{
+        node_graph::{FunctionOp, MathOp, Node, NodeGraph, NodeKind},
+        GraphAnimation,
+    },
+    glam::Vec2,
+    gui::egui::{
+        self,
+        color_picker::{color_edit_button_rgba, Alpha},
+        epaint::CubicBezierShape,
+        Align, Color32, ComboBox, DragValue, Frame, Id, Label, Layout, Pos2, Rect, Rgba, RichText,
+        Sense, Shape, Stroke, Ui,
+    },
+    traits::ParticleAnimation,
+};
+
+const NODE_WIDTH: f32 = 180.;
+const MIN_CANVAS_HEIGHT: f32 = 320.;
+
+impl EditorWidgets {
+    pub fn graph_anim(editor: &mut EditorData, anim: &mut Box<dyn ParticleAnimation>, ui: &mut Ui) {
+        let downcast = anim.as_any().downcast_mut::<GraphAnimation>();
+
+        if let Some(anim) = downcast {
+            anim.selected_action = editor.create_li_header(ui, "Node graph animation");
+
+            let id = Id::new(&anim.uniform as *const _);
+            let before = anim.uniform.graph.clone();
+
+            Self::graph_canvas(ui, id, &mut anim.uniform.graph);
+
+            ui.horizontal(|ui| {
+                ComboBox::from_id_source(id.with("add"))
+                    .selected_text("Add node")
+                    .show_ui(ui, |ui| {
+                        for kind in NodeKind::ALL {
+                            if ui.selectable_label(false, kind.to_string()).clicked() {
+                                let offset = anim.uniform.graph.nodes.len() as f32 * 10.;
+                                let pos = Vec2::new(10. + offset, 10. + offset);
+                                anim.uniform.graph.nodes.push(Node::new(kind, pos));
+                            }
+                        }
+                    });
+
+                ui.checkbox(&mut anim.enabled, "Enabled");
+            });
+
+            let graph = &anim.uniform.graph;
+
+            if !before.same_structure(graph) {
+                anim.recompile = true;
+            } else if before != *graph {
+                anim.update_constants = true;
+            }
+
+            for err in anim.error.iter().chain(anim.script.error.iter()) {
+                ui.label(RichText::new(err).monospace().color(Color32::LIGHT_RED));
+            }
+
+            egui::CollapsingHeader::new("Generated WGSL")
+                .id_source(id.with("wgsl"))
+                .show(ui, |ui| {
+                    let script = &anim.script.uniform;
+                    let code = format!("{}{}", script.functions, script.source);
+                    ui.label(RichText::new(code).monospace());
+                });
+        }
+    }
+
+    fn graph_canvas(ui: &mut Ui, id: Id, graph: &mut NodeGraph) {
+        let height = graph
+            .nodes
+            .iter()
+            .map(|node| node.pos.y + 200.)
+            .fold(MIN_CANVAS_HEIGHT, f32::max);
+
+        let (canvas, _) =
+            ui.allocate_exact_size(egui::vec2(ui.available_width(), height), Sense::hover());
+
+        let painter = ui.painter_at(canvas);
+        painter.rect_filled(canvas, 4., Color32::from_gray(24));
+
+        // Links are drawn below the nodes
+        let links_shape = painter.add(Shape::Noop);
+
+        let link_options: Vec<(usize, String)> = graph
+            .nodes
+            .iter()
+            .enumerate()
+            .filter(|(_, node)| !node.kind.is_output())
+            .map(|(idx, node)| (idx, format!("#{} {}", idx, node.kind)))
+            .collect();
+
+        let mut outputs: Vec<Option<Pos2>> = vec![None; graph.nodes.len()];
+        let mut inputs: Vec<(usize, Pos2)> = vec![];
+        let mut remove_idx = None;
+
+        for (idx, node) in graph.nodes.iter_mut().enumerate() {
+            let min = canvas.min + egui::vec2(node.pos.x, node.pos.y);
+            let max_rect = Rect::from_min_size(min, egui::vec2(NODE_WIDTH, 400.));
+            let mut child = ui.child_ui(max_rect, Layout::top_down(Align::Min));
+            child.set_clip_rect(canvas);
+
+            let frame = Frame::window(ui.style()).show(&mut child, |ui| {
+                ui.set_width(NODE_WIDTH - 16.);
+
+                let header = ui.horizontal(|ui| {
+                    let title = format!("#{} {}", idx, node.kind);
+                    let label = Label::new(RichText::new(title).strong()).sense(Sense::drag());
+                    let drag = ui.add(label);
+
+                    if drag.dragged() {
+                        let delta = drag.drag_delta();
+                        node.pos = (node.pos + Vec2::new(delta.x, delta.y)).max(Vec2::ZERO);
+                    }
+
+                    ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                        if ui.small_button("x").clicked() {
+                            remove_idx = Some(idx);
+                        }
+                    });
+                });
+
+                Self::graph_node_fields(ui, id.with(idx), node);
+
+                for (i, name) in node.kind.inputs().iter().enumerate() {
+                    let input = &mut node.inputs[i];
+
+                    let row = ui.horizontal(|ui| {
+                        ui.label(*name);
+
+                        let selected = match input.link {
+                            Some(link) => format!("#{}", link),
+                            None => "Value".to_string(),
+                        };
+
+                        ComboBox::from_id_source(id.with((idx, i)))
+                            .selected_text(selected)
+                            .width(60.)
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut input.link, None, "Value");
+
+                                for (option, label) in link_options.iter() {
+                                    if *option != idx {
+                                        ui.selectable_value(&mut input.link, Some(*option), label);
+                                    }
+                                }
+                            });
+
+                        if input.link.is_none() {
+                            ui.add(DragValue::new(&mut input.value).speed(0.01));
+                        }
+                    });
+
+                    if let Some(link) = input.link {
+                        inputs.push((link, row.response.rect.left_center()));
+                    }
+                }
+
+                header.response.rect
+            });
+
+            if !node.kind.is_output() {
+                let header = frame.inner;
+                outputs[idx] = Some(Pos2::new(frame.response.rect.right(), header.center().y));
+            }
+        }
+
+        let stroke = Stroke::new(2., Color32::from_rgb(120, 180, 255));
+
+        let links: Vec<Shape> = inputs
+            .into_iter()
+            .filter_map(|(link, to)| {
+                let from = outputs.get(link).copied().flatten()?;
+                let bend = egui::vec2(((to.x - from.x).abs() / 2.).max(30.), 0.);
+
+                Some(Shape::CubicBezier(CubicBezierShape::from_points_stroke(
+                    [from, from + bend, to - bend, to],
+                    false,
+                    Color32::TRANSPARENT,
+                    stroke,
+                )))
+            })
+            .collect();
+
+        painter.set(links_shape, Shape::Vec(links));
+
+        if let Some(idx) = remove_idx {
+            graph.remove(idx);
+        }
+    }
+
+    fn graph_node_fields(ui: &mut Ui, id: Id, node: &mut Node) {
+        match &mut node.kind {
+            NodeKind::Float { value } => {
+                ui.add(DragValue::new(value).speed(0.01));
+            }
+            NodeKind::Vector { value } => {
+                ui.horizontal(|ui| {
+                    ui.add(DragValue::new(&mut value.x).speed(0.1));
+                    ui.add(DragValue::new(&mut value.y).speed(0.1));
+                    ui.add(DragValue::new(&mut value.z).speed(0.1));
+                });
+            }
+            NodeKind::ColorValue { value } => {
+                let mut color = Rgba::from_rgba_premultiplied(value.x, value.y, value.z, value.w);
+                color_edit_button_rgba(ui, &mut color, Alpha::BlendOrAdditive);
+                *value = color.to_array().into();
+            }
+            NodeKind::Math { op } => {
+                ComboBox::from_id_source(id.with("op"))
+                    .selected_text(op.to_string())
+                    .show_ui(ui, |ui| {
+                        for option in MathOp::ALL {
+                            ui.selectable_value(op, option, option.to_string());
+                        }
+                    });
+            }
+            NodeKind::Function { op } => {
+                ComboBox::from_id_source(id.with("op"))
+                    .selected_text(op.to_string())
+                    .show_ui(ui, |ui| {
+                        for option in FunctionOp::ALL {
+                            ui.selectable_value(op, option, option.to_string());
+                        }
+                    });
+            }
+            NodeKind::Noise { scale } => {
+                ui.horizontal(|ui| {
+                    ui.label("Scale");
+                    ui.add(DragValue::new(scale).speed(0.01));
+                });
+            }
+            NodeKind::Curve { from, to, easing } => {
+                ui.horizontal(|ui| {
+                    ui.label("From");
+                    ui.add(DragValue::new(from).speed(0.01));
+                    ui.label("to");
+                    ui.add(DragValue::new(to).speed(0.01));
+                });
+
+                Editor::create_easing(ui, easing);
+            }
+            _ => {}
+        }
+    }
+}
@@ -5,23 +5,29 @@ use menu::{
     declarations::MenuCtx,
     emitter::{EmitterMenu, Tab},
     import::ImportMenu,
+    lighting::LightingMenu,
     none::NoneMenu,
     post_fx::PostFxMenu,
     MenuWidget,
 };
 use sparticles_app::{
     animations::{
-        ColorAnimation, DiffusionAnimation, ForceAnimation, GravityAnimation, StrayAnimation,
-        SwayAnimation,
+        ColorAnimation, DiffusionAnimation, ForceAnimation, GraphAnimation, GravityAnimation,
+        KeyframeAnimation, PathAnimation, ScriptAnimation, StrayAnimation, SwayAnimation,
     },
     fx::{blur::BlurFx, BloomFx, ColorFx},
+    glam::Mat4,
     gui::egui::{load::SizedTexture, *},
     gui::{
         egui::{self},
-        winit::event::{ElementState, KeyboardInput, VirtualKeyCode},
+        winit::{
+            event::{ElementState, KeyEvent},
+            keyboard::{KeyCode, PhysicalKey},
+        },
     },
     model::{
-        events::ViewIOEvent, EmitterSettings, EmitterUniform, GfxState, SparEvents, SparState,
+        events::ViewIOEvent, Easing, EasingCurve, EasingMode, EmitterSettings, EmitterUniform,
+        EnvironmentCtx, GfxState, LifeCycle, SparEvents, SparState,
     },
     profiler::GpuTimerScopeResult,
     texture::IconTexture,
@@ -37,6 +43,7 @@ use std::{
 
 pub mod em_widgets;
 pub mod fx_widgets;
+pub mod graph_widgets;
 pub mod menu;
 pub mod pa_widgets;
 
@@ -79,11 +86,19 @@ pub struct EditorData {
     selected_new_par_anim: usize,
     selected_new_em_anim: usize,
     selected_new_post_fx: usize,
+    new_param_name: String,
+    selected_param_target: usize,
+    /// Latest background load events, oldest first
+    asset_log: Vec<String>,
 
     //performance_event: Option<DisplayEvent>,
     //display_event: Option<DisplayEvent>,
     pub emitter_settings: Option<EmitterSettings>,
     pub model_files: Vec<PathBuf>,
+    /// Equirectangular environment maps
+    pub environment_files: Vec<PathBuf>,
+    /// Camera view projection of the current frame, used to draw debug overlays
+    pub view_proj: Mat4,
 }
 
 const CHEVRON_UP_ID: &str = "chevron-up";
@@ -91,12 +106,13 @@ const CHEVRON_DOWN_ID: &str = "chevron-down";
 const TRASH_ID: &str = "trash";
 const MENU_ID: &str = "menu";
 pub const WINDOW_MARGIN: f32 = 10.;
+const ASSET_LOG_LEN: usize = 5;
 
 impl WidgetBuilder for Editor {
     fn process_input(
         &mut self,
         events: &mut SparEvents,
-        input: &KeyboardInput,
+        input: &KeyEvent,
         shift_pressed: bool,
     ) -> bool {
         if input.state == ElementState::Pressed {
@@ -105,23 +121,25 @@ impl WidgetBuilder for Editor {
 
         let data = &mut self.data;
 
-        let keycode = input.virtual_keycode.unwrap_or(VirtualKeyCode::Return);
+        let PhysicalKey::Code(keycode) = input.physical_key else {
+            return false;
+        };
 
         match keycode {
-            VirtualKeyCode::T if shift_pressed => {
+            KeyCode::KeyT if shift_pressed => {
                 events.io_view = Some(ViewIOEvent::Subtract);
             }
-            VirtualKeyCode::T if !shift_pressed => {
+            KeyCode::KeyT if !shift_pressed => {
                 events.io_view = Some(ViewIOEvent::Add);
             }
-            VirtualKeyCode::Key1 => data.selected_menu_idx = 1,
-            VirtualKeyCode::Key2 => data.selected_menu_idx = 2,
-            VirtualKeyCode::Key3 => data.selected_menu_idx = 3,
-            VirtualKeyCode::Key4 => data.selected_menu_idx = 4,
-            VirtualKeyCode::Key0 => data.selected_menu_idx = 0,
-            //VirtualKeyCode::C => gui.display_event.set(DisplayEvent::ToggleCollapse),
-            //VirtualKeyCode::P => gui.performance_event.set(DisplayEvent::ToggleCollapse),
-            VirtualKeyCode::F => events.toggle_play = true,
+            KeyCode::Digit1 => data.selected_menu_idx = 1,
+            KeyCode::Digit2 => data.selected_menu_idx = 2,
+            KeyCode::Digit3 => data.selected_menu_idx = 3,
+            KeyCode::Digit4 => data.selected_menu_idx = 4,
+            KeyCode::Digit0 => data.selected_menu_idx = 0,
+            //KeyCode::KeyC => gui.display_event.set(DisplayEvent::ToggleCollapse),
+            //KeyCode::KeyP => gui.performance_event.set(DisplayEvent::ToggleCollapse),
+            KeyCode::KeyF => events.toggle_play = true,
             _ => return false,
         }
 
@@ -156,6 +174,13 @@ impl WidgetBuilder for Editor {
                     });
             });
 
+        let camera = &state.camera;
+        self.data.view_proj = camera.view_proj(&camera.view_mat());
+
+        let asset_log = &mut self.data.asset_log;
+        asset_log.extend(events.asset_events.iter().map(|event| event.to_string()));
+        asset_log.drain(..asset_log.len().saturating_sub(ASSET_LOG_LEN));
+
         let idx = self.data.selected_menu_idx;
 
         let mut menu_ctx = MenuCtx {
@@ -237,6 +262,16 @@ impl Editor {
             Box::new(EditorWidgets::stray_anim),
         );
 
+        pa_widgets.insert(
+            TypeId::of::<ScriptAnimation>(),
+            Box::new(EditorWidgets::script_anim),
+        );
+
+        pa_widgets.insert(
+            TypeId::of::<GraphAnimation>(),
+            Box::new(EditorWidgets::graph_anim),
+        );
+
         em_widgets.insert(
             TypeId::of::<SwayAnimation>(),
             Box::new(EditorWidgets::sway_anim),
@@ -247,6 +282,16 @@ impl Editor {
             Box::new(EditorWidgets::diffusion_anim),
         );
 
+        em_widgets.insert(
+            TypeId::of::<KeyframeAnimation>(),
+            Box::new(EditorWidgets::keyframe_anim),
+        );
+
+        em_widgets.insert(
+            TypeId::of::<PathAnimation>(),
+            Box::new(EditorWidgets::path_anim),
+        );
+
         fx_widgets.insert(TypeId::of::<BloomFx>(), Box::new(EditorWidgets::bloom_fx));
         fx_widgets.insert(TypeId::of::<BlurFx>(), Box::new(EditorWidgets::blur_fx));
         fx_widgets.insert(TypeId::of::<ColorFx>(), Box::new(EditorWidgets::color_fx));
@@ -289,6 +334,9 @@ impl Editor {
             selected_new_par_anim: 0,
             selected_new_em_anim: 0,
             selected_new_post_fx: 0,
+            new_param_name: "".to_string(),
+            selected_param_target: 0,
+            asset_log: vec![],
             icon_textures,
             new_emitter_tag: "".to_string(),
             profiling_results: Vec::new(),
@@ -296,6 +344,8 @@ impl Editor {
             //performance_event: None,
             emitter_settings: None,
             model_files,
+            environment_files: EnvironmentCtx::files(),
+            view_proj: Mat4::IDENTITY,
         };
 
         let menus: Vec<Box<dyn MenuWidget>> = vec![
@@ -303,6 +353,7 @@ impl Editor {
             Box::new(EmitterMenu),
             Box::new(ImportMenu),
             Box::new(PostFxMenu),
+            Box::new(LightingMenu),
             Box::new(CameraPerformanceMenu),
         ];
 
@@ -319,6 +370,81 @@ impl Editor {
         }
     }
 
+    /// Timing of animations driven by a life cycle
+    pub fn create_life_cycle(ui: &mut Ui, life_cycle: &mut LifeCycle) {
+        ui.horizontal(|ui| {
+            ui.label("Animate from sec");
+            ui.add(DragValue::new(&mut life_cycle.from_sec).speed(0.1));
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("Animate until sec");
+            ui.add(
+                DragValue::new(&mut life_cycle.until_sec)
+                    .speed(0.1)
+                    .clamp_range(life_cycle.from_sec..=life_cycle.lifetime_sec),
+            );
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("Until restart animation");
+            ui.add(DragValue::new(&mut life_cycle.lifetime_sec).speed(0.1));
+        });
+
+        Self::create_easing(ui, &mut life_cycle.easing);
+
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut life_cycle.ping_pong, "Ping-pong");
+            ui.label("Repeat count (0 is forever)");
+            ui.add(DragValue::new(&mut life_cycle.repeat_count));
+        });
+    }
+
+    pub fn create_easing(ui: &mut Ui, easing: &mut Easing) {
+        let id = Id::new(easing as *const Easing);
+
+        ui.horizontal(|ui| {
+            ComboBox::from_id_source(id.with("kind"))
+                .selected_text(easing.to_string())
+                .show_ui(ui, |ui| {
+                    for kind in Easing::KINDS {
+                        let is_selected = easing.same_kind(&kind);
+
+                        if ui.selectable_label(is_selected, kind.to_string()).clicked()
+                            && !is_selected
+                        {
+                            *easing = kind;
+                        }
+                    }
+                });
+
+            match easing {
+                Easing::Linear => {}
+                Easing::Penner { curve, mode } => {
+                    ComboBox::from_id_source(id.with("curve"))
+                        .selected_text(curve.to_string())
+                        .show_ui(ui, |ui| {
+                            for option in EasingCurve::ALL {
+                                ui.selectable_value(curve, option, option.to_string());
+                            }
+                        });
+
+                    ui.selectable_value(mode, EasingMode::In, "In");
+                    ui.selectable_value(mode, EasingMode::Out, "Out");
+                    ui.selectable_value(mode, EasingMode::InOut, "In out");
+                }
+                Easing::CubicBezier { p1, p2 } => {
+                    ui.add(DragValue::new(&mut p1.x).speed(0.01).clamp_range(0f32..=1.));
+                    ui.add(DragValue::new(&mut p1.y).speed(0.01));
+                    ui.add(DragValue::new(&mut p2.x).speed(0.01).clamp_range(0f32..=1.));
+                    ui.add(DragValue::new(&mut p2.y).speed(0.01));
+                }
+            }
+
+            ui.label("Easing");
+        });
+    }
+
     pub fn create_degree_slider(ui: &mut Ui, val: &mut f32, str: &str) {
         ui.add(Slider::new(val, 0.0..=360.).text(str));
     }
@@ -1,15 +1,19 @@
 use crate::{DynamicWidgets, Editor, EditorData};
 use async_std::task;
 use sparticles_app::{
-    fx::PostProcessState,
+    glam::Vec4,
     gui::egui::{
         self,
         color_picker::{color_edit_button_rgba, Alpha},
         scroll_area::ScrollBarVisibility,
         Color32, Rgba, RichText, Ui,
     },
-    model::{emitter_state::RecreateEmitterOptions, EmitterState, EmitterType, SparState},
-    traits::Splitting,
+    model::{
+        emitter_state::RecreateEmitterOptions, EffectTransform, EmitterParent, EmitterSettings,
+        EmitterState, EmitterType, ExposedParam, LightSelection, ParamTarget, ParamValue,
+        SimulationSpace, SparState, SubEmitter, SubEmitterTrigger,
+    },
+    traits::HandleAngles,
     wgpu,
 };
 
@@ -39,7 +43,7 @@ impl MenuWidget for EmitterMenu {
             .show(menu_ctx.ctx, |ui| {
                 let SparState {
                     emitters,
-                    post_process,
+                    effect_root,
                     ..
                 } = menu_ctx.state;
 
@@ -66,6 +70,14 @@ impl MenuWidget for EmitterMenu {
                         events.create_emitter = Some(data.new_emitter_tag.to_string());
                         data.new_emitter_tag = "".to_string();
                     }
+
+                    if ui
+                        .add_enabled(is_enabled, egui::Button::new("Add light"))
+                        .clicked()
+                    {
+                        events.create_light_emitter = Some(data.new_emitter_tag.to_string());
+                        data.new_emitter_tag = "".to_string();
+                    }
                 });
 
                 ui.add_space(6.0);
@@ -73,9 +85,8 @@ impl MenuWidget for EmitterMenu {
                 ui.separator();
 
                 ui.horizontal(|ui| {
-                    if ui.button("Export settings").clicked() {
-                        EmitterState::export(emitters);
-                        PostProcessState::export(post_process);
+                    if ui.button("Export scene").clicked() {
+                        events.export_scene = true;
                     }
 
                     ui.add_space(4.0);
@@ -90,13 +101,23 @@ impl MenuWidget for EmitterMenu {
                     ui.add_space(4.0);
 
                     let emitter = &emitters[data.selected_emitter_idx];
-                    if !emitter.is_light && ui.button("Remove emitter").clicked() {
+                    if 1 < emitters.len() && ui.button("Remove emitter").clicked() {
                         let id = emitter.id().to_string();
                         events.delete_emitter = Some(id);
                         data.selected_emitter_idx = 0;
                     }
                 });
 
+                ui.add_space(4.0);
+
+                parent_settings(ui, emitters, data.selected_emitter_idx);
+
+                ui.add_space(4.0);
+
+                egui::CollapsingHeader::new("Effect root").show(ui, |ui| {
+                    effect_root_settings(ui, effect_root);
+                });
+
                 ui.separator();
 
                 ui.horizontal(|ui| {
@@ -213,6 +234,35 @@ impl EmitterMenu {
             ..
         } = menu_ctx;
 
+        let selected_id = state.emitters[data.selected_emitter_idx].id().to_string();
+        let child_ids: Vec<String> = state
+            .emitters
+            .iter()
+            .filter(|em| !em.is_light && *em.id() != selected_id)
+            .map(|em| em.id().to_string())
+            .collect();
+
+        let is_light = state.emitters[data.selected_emitter_idx].is_light;
+        let light_ids: Vec<String> = state.lights.set_ids().map(|id| id.to_string()).collect();
+
+        let emitter = &mut state.emitters[data.selected_emitter_idx];
+        ui.add_space(5.0);
+        ui.horizontal(|ui| {
+            if emitter.clock.is_running() {
+                if ui.button("Stop").clicked() {
+                    emitter.stop();
+                }
+            } else if ui.button("Start").clicked() {
+                emitter.start();
+            }
+
+            if ui.button("Restart").clicked() {
+                emitter.restart();
+            }
+
+            ui.label(format!("Timeline: {:.2}s", emitter.clock.elapsed_sec()));
+        });
+
         let uniform = &mut state.emitters[data.selected_emitter_idx].uniform;
         data.sync_emitter_settings(&uniform);
         let emitter_settings = data.emitter_settings.as_mut().unwrap();
@@ -319,14 +369,325 @@ impl EmitterMenu {
             .text("Particle size max"),
         );
 
+        ui.add_space(5.0);
+        Editor::create_label(ui, "Simulation");
+
+        ui.horizontal(|ui| {
+            let space = &mut emitter_settings.simulation_space;
+
+            ui.selectable_value(space, SimulationSpace::World, "World space");
+            ui.selectable_value(space, SimulationSpace::Local, "Local space");
+        });
+
+        ui.add(
+            egui::Slider::new(&mut emitter_settings.inherit_velocity, 0.0..=1.0)
+                .text("Inherit emitter velocity"),
+        );
+
+        if is_light {
+            ui.add(
+                egui::Slider::new(&mut emitter_settings.light_range, 0.1..=100.0)
+                    .text("Light range"),
+            );
+        }
+
+        if !is_light {
+            ui.add_space(5.0);
+            sub_emitter_settings(ui, emitter_settings, &child_ids);
+
+            ui.add_space(5.0);
+            light_settings(ui, &mut uniform.lights, &light_ids);
+        }
+
         uniform.update_settings(&emitter_settings);
 
         if emitter_settings.recreate {
             recreate_emitter(data, state, encoder).await;
         }
+
+        ui.add_space(5.0);
+
+        if exposed_params_settings(ui, data, state) {
+            // Parameters can write emitter settings, those are copied again
+            data.emitter_settings = None;
+        }
+    }
+}
+
+/// Lists the exposed parameters to preview values like game code sets them, returns true if
+/// the emitter changed
+fn exposed_params_settings(ui: &mut Ui, data: &mut EditorData, state: &mut SparState) -> bool {
+    Editor::create_label(ui, "Exposed parameters");
+
+    let SparState {
+        emitters,
+        gfx,
+        registry_par_anims,
+        registry_em_anims,
+        ..
+    } = state;
+
+    let gfx = &task::block_on(gfx.read());
+    let emitter = &mut emitters[data.selected_emitter_idx];
+
+    let mut changed_values = Vec::new();
+    let mut remove_idx = None;
+
+    for (idx, param) in emitter.uniform.exposed_params.iter().enumerate() {
+        let mut value = param.value;
+
+        ui.horizontal(|ui| {
+            let is_changed = match &mut value {
+                ParamValue::Float(v) => ui.add(egui::DragValue::new(v).speed(0.01)).changed(),
+                ParamValue::Vec3(v) => {
+                    let x = ui.add(egui::DragValue::new(&mut v.x).speed(0.01));
+                    let y = ui.add(egui::DragValue::new(&mut v.y).speed(0.01));
+                    let z = ui.add(egui::DragValue::new(&mut v.z).speed(0.01));
+
+                    x.changed() || y.changed() || z.changed()
+                }
+                ParamValue::Color(v) => {
+                    let mut color = Rgba::from_rgba_unmultiplied(v.x, v.y, v.z, v.w);
+                    let is_changed =
+                        color_edit_button_rgba(ui, &mut color, Alpha::OnlyBlend).changed();
+
+                    *v = Vec4::from(color.to_array());
+                    is_changed
+                }
+            };
+
+            ui.label(&param.name)
+                .on_hover_text(param.target.to_string());
+
+            if ui.button("Remove").clicked() {
+                remove_idx = Some(idx);
+            }
+
+            if is_changed {
+                changed_values.push((param.name.to_string(), value));
+            }
+        });
+    }
+
+    let is_changed = !changed_values.is_empty();
+
+    for (name, value) in changed_values {
+        let result = emitter.set_param(&name, value, gfx, registry_par_anims, registry_em_anims);
+
+        if let Err(err) = result {
+            ui.label(RichText::new(err.to_string()).color(Color32::RED));
+        }
+    }
+
+    if let Some(idx) = remove_idx {
+        emitter.uniform.exposed_params.remove(idx);
+    }
+
+    let candidates = ParamTarget::candidates(emitter);
+    let selected = &mut data.selected_param_target;
+    *selected = (*selected).min(candidates.len().saturating_sub(1));
+
+    ui.horizontal(|ui| {
+        ui.add(egui::TextEdit::singleline(&mut data.new_param_name).desired_width(100.));
+
+        egui::ComboBox::from_id_source("exposed-param-target")
+            .selected_text(
+                candidates
+                    .get(*selected)
+                    .map_or("".to_string(), |(target, _)| target.to_string()),
+            )
+            .show_ui(ui, |ui| {
+                for (idx, (target, value)) in candidates.iter().enumerate() {
+                    ui.selectable_value(selected, idx, format!("{} ({})", target, value));
+                }
+            });
+
+        let name = data.new_param_name.trim();
+
+        if ui.button("Expose").clicked() && !name.is_empty() {
+            if let Some((target, value)) = candidates.get(*selected) {
+                emitter.uniform.exposed_params.push(ExposedParam {
+                    name: name.to_string(),
+                    target: target.clone(),
+                    value: *value,
+                });
+
+                data.new_param_name.clear();
+            }
+        }
+    });
+
+    is_changed
+}
+
+fn parent_settings(ui: &mut Ui, emitters: &mut [EmitterState], selected_idx: usize) {
+    let selected_id = emitters[selected_idx].id().to_string();
+
+    // Emitters that would create a cycle can't become the parent
+    let parent_ids: Vec<String> = emitters
+        .iter()
+        .map(|em| em.id().to_string())
+        .filter(|id| {
+            *id != selected_id && !EmitterParent::is_descendant(emitters, id, &selected_id)
+        })
+        .collect();
+
+    let parent = &mut emitters[selected_idx].uniform.parent;
+
+    let parent_text = match parent {
+        None => "None".to_string(),
+        Some(EmitterParent::EffectRoot) => "Effect root".to_string(),
+        Some(EmitterParent::Emitter(id)) => id.to_string(),
+    };
+
+    ui.horizontal(|ui| {
+        egui::ComboBox::from_id_source("emitter-parent")
+            .selected_text(parent_text)
+            .show_ui(ui, |ui| {
+                ui.selectable_value(parent, None, "None");
+                ui.selectable_value(parent, Some(EmitterParent::EffectRoot), "Effect root");
+
+                for id in parent_ids.iter() {
+                    ui.selectable_value(parent, Some(EmitterParent::Emitter(id.to_string())), id);
+                }
+            });
+
+        ui.label("Parent");
+    });
+}
+
+fn effect_root_settings(ui: &mut Ui, effect_root: &mut EffectTransform) {
+    Editor::create_label(ui, "Root position");
+
+    ui.horizontal(|ui| {
+        ui.add(egui::DragValue::new(&mut effect_root.position.x).speed(0.1));
+        ui.add(egui::DragValue::new(&mut effect_root.position.y).speed(0.1));
+        ui.add(egui::DragValue::new(&mut effect_root.position.z).speed(0.1));
+    });
+
+    let mut rotation_deg = effect_root.rotation.to_degrees();
+
+    Editor::create_degree_slider(ui, &mut rotation_deg.x, "Root yaw");
+    Editor::create_degree_slider(ui, &mut rotation_deg.y, "Root pitch");
+    Editor::create_degree_slider(ui, &mut rotation_deg.z, "Root roll");
+
+    effect_root.rotation = rotation_deg.to_radians();
+}
+
+fn light_settings(ui: &mut Ui, selection: &mut LightSelection, light_ids: &[String]) {
+    Editor::create_label(ui, "Lights");
+
+    ui.horizontal(|ui| {
+        if ui
+            .selectable_label(*selection == LightSelection::All, "All lights")
+            .clicked()
+        {
+            *selection = LightSelection::All;
+        }
+
+        if ui
+            .selectable_label(*selection != LightSelection::All, "Selected lights")
+            .clicked()
+            && *selection == LightSelection::All
+        {
+            *selection = LightSelection::Only(light_ids.to_vec());
+        }
+    });
+
+    let LightSelection::Only(selected_ids) = selection else {
+        return;
+    };
+
+    for id in light_ids {
+        let mut is_selected = selected_ids.contains(id);
+
+        if ui.checkbox(&mut is_selected, id).changed() {
+            if is_selected {
+                selected_ids.push(id.to_string());
+            } else {
+                selected_ids.retain(|selected_id| selected_id != id);
+            }
+        }
     }
 }
 
+fn sub_emitter_settings(ui: &mut Ui, emitter_settings: &mut EmitterSettings, child_ids: &[String]) {
+    Editor::create_label(ui, "Sub emitter");
+
+    ui.add_space(5.0);
+
+    let mut is_enabled = emitter_settings.sub_emitter.is_some();
+
+    ui.add_enabled_ui(!child_ids.is_empty(), |ui| {
+        ui.checkbox(&mut is_enabled, "Spawn child particles");
+    });
+
+    if !is_enabled || child_ids.is_empty() {
+        emitter_settings.sub_emitter = None;
+        return;
+    }
+
+    let sub = emitter_settings
+        .sub_emitter
+        .get_or_insert_with(|| SubEmitter::new(child_ids[0].to_string()));
+
+    ui.horizontal(|ui| {
+        egui::ComboBox::from_id_source("sub-emitter-child")
+            .selected_text(&sub.child_id)
+            .show_ui(ui, |ui| {
+                for id in child_ids {
+                    ui.selectable_value(&mut sub.child_id, id.to_string(), id);
+                }
+            });
+
+        ui.label("Child emitter");
+    });
+
+    ui.horizontal(|ui| {
+        egui::ComboBox::from_id_source("sub-emitter-trigger")
+            .selected_text(sub.trigger.to_string())
+            .show_ui(ui, |ui| {
+                for trigger in SubEmitterTrigger::ALL {
+                    let is_selected = sub.trigger.same_kind(&trigger);
+
+                    if ui
+                        .selectable_label(is_selected, trigger.to_string())
+                        .clicked()
+                        && !is_selected
+                    {
+                        sub.trigger = trigger;
+                    }
+                }
+            });
+
+        ui.label("Trigger");
+    });
+
+    match &mut sub.trigger {
+        SubEmitterTrigger::Collision { plane_y } => {
+            ui.horizontal(|ui| {
+                ui.add(egui::DragValue::new(plane_y).speed(0.1));
+                ui.label("Collision plane height");
+            });
+        }
+        SubEmitterTrigger::Interval { every_sec } => {
+            ui.horizontal(|ui| {
+                ui.add(
+                    egui::DragValue::new(every_sec)
+                        .speed(0.05)
+                        .clamp_range(0.05..=40.),
+                );
+                ui.label("Interval (sec)");
+            });
+        }
+        SubEmitterTrigger::Spawn | SubEmitterTrigger::Death => {}
+    }
+
+    ui.add(egui::Slider::new(&mut sub.spawn_count, 1..=100).text("Particles per trigger"));
+    ui.add(egui::Slider::new(&mut sub.inherit_velocity, 0.0..=1.0).text("Inherit velocity"));
+    ui.checkbox(&mut sub.inherit_color, "Inherit color");
+}
+
 async fn model_settings(menu_ctx: &mut MenuCtx<'_>, ui: &mut Ui) {
     let MenuCtx {
         emitter_data: data,
@@ -379,17 +740,20 @@ async fn model_settings(menu_ctx: &mut MenuCtx<'_>, ui: &mut Ui) {
         }
     });
 
-    let mesh_model = collection.get(&mesh.collection_id).unwrap();
-
     custom_header(ui, "Mesh");
 
-    horizontal_scroll(ui, "mesh", |ui| {
-        for (key, _) in mesh_model.meshes.iter() {
-            if ui.selectable_label(mesh.mesh_id == *key, key).clicked() {
-                mesh.mesh_id = key.clone();
+    match collection.get(&mesh.collection_id) {
+        Some(mesh_model) => horizontal_scroll(ui, "mesh", |ui| {
+            for (key, _) in mesh_model.meshes.iter() {
+                if ui.selectable_label(mesh.mesh_id == *key, key).clicked() {
+                    mesh.mesh_id = key.clone();
+                }
             }
+        }),
+        None => {
+            ui.label(format!("Loading {}", mesh.collection_id));
         }
-    });
+    }
 
     custom_header(ui, "Material collection");
 
@@ -407,17 +771,20 @@ async fn model_settings(menu_ctx: &mut MenuCtx<'_>, ui: &mut Ui) {
         }
     });
 
-    let mat_model = collection.get(&mat.collection_id).unwrap();
-
     custom_header(ui, "Material");
 
-    horizontal_scroll(ui, "mat", |ui| {
-        for (key, _) in mat_model.materials.iter() {
-            if ui.selectable_label(mat.material_id == *key, key).clicked() {
-                mat.material_id = key.clone();
+    match collection.get(&mat.collection_id) {
+        Some(mat_model) => horizontal_scroll(ui, "mat", |ui| {
+            for (key, _) in mat_model.materials.iter() {
+                if ui.selectable_label(mat.material_id == *key, key).clicked() {
+                    mat.material_id = key.clone();
+                }
             }
+        }),
+        None => {
+            ui.label(format!("Loading {}", mat.collection_id));
         }
-    });
+    }
 
     ui.add_space(10.);
 
@@ -478,56 +845,31 @@ async fn recreate_emitter(
     let SparState {
         camera,
         emitters,
+        lights,
         gfx,
         collection,
         ..
     } = state;
 
-    let (em, mut others) = emitters.split_item_mut(data.selected_emitter_idx);
-
-    if em.is_light {
-        *em = EmitterState::recreate_emitter(
-            RecreateEmitterOptions {
-                old_self: em,
-                gfx,
-                camera,
-                collection,
-                emitter_type: EmitterType::Lights,
-            },
-            encoder,
-        )
-        .await;
-
-        for other in others {
-            *other = EmitterState::recreate_emitter(
-                RecreateEmitterOptions {
-                    old_self: other,
-                    gfx,
-                    camera,
-                    collection,
-                    emitter_type: EmitterType::Normal {
-                        lights_layout: &em.bg_layout,
-                    },
-                },
-                encoder,
-            )
-            .await;
-        }
+    let em = &mut emitters[data.selected_emitter_idx];
+
+    let emitter_type = if em.is_light {
+        EmitterType::Lights
     } else {
-        let lights = others.next().unwrap();
-
-        *em = EmitterState::recreate_emitter(
-            RecreateEmitterOptions {
-                old_self: em,
-                gfx,
-                camera,
-                collection,
-                emitter_type: EmitterType::Normal {
-                    lights_layout: &lights.bg_layout,
-                },
-            },
-            encoder,
-        )
-        .await;
-    }
+        EmitterType::Normal {
+            lights_layout: &lights.bg_layout,
+        }
+    };
+
+    *em = EmitterState::recreate_emitter(
+        RecreateEmitterOptions {
+            old_self: em,
+            gfx,
+            camera,
+            collection,
+            emitter_type,
+        },
+        encoder,
+    )
+    .await;
 }
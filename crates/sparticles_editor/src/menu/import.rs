@@ -1,11 +1,9 @@
 use super::{declarations::MenuCtx, MenuWidget};
-use async_std::{sync::RwLock, task};
+use async_std::task;
 use sparticles_app::{
-    gui::egui::{self},
-    loader::Model,
-    model::GfxState,
+    asset_manager::LoadStatus,
+    gui::egui::{self, Color32, RichText},
 };
-use std::{collections::HashMap, sync::Arc};
 
 pub struct ImportMenu;
 
@@ -17,7 +15,8 @@ impl MenuWidget for ImportMenu {
     fn draw_ui(&self, menu_ctx: &mut MenuCtx) {
         let data = &mut menu_ctx.emitter_data;
         let collection = &menu_ctx.state.collection;
-        let gfx = &menu_ctx.state.gfx;
+        let assets = &menu_ctx.state.assets;
+        let static_scene = &mut menu_ctx.state.static_scene;
         let mut import_file = None;
 
         egui::Window::new("Import settings")
@@ -28,7 +27,20 @@ impl MenuWidget for ImportMenu {
             .show(menu_ctx.ctx, |ui| {
                 data.create_title(ui, "Import menu");
 
-                let coll = task::block_on(collection.write());
+                let (finished, total) = assets.progress();
+
+                if finished < total {
+                    ui.horizontal(|ui| {
+                        ui.spinner();
+                        ui.label(format!("Loading {}/{}", finished, total));
+                    });
+                }
+
+                for line in data.asset_log.iter() {
+                    ui.label(line);
+                }
+
+                let coll = task::block_on(collection.read());
 
                 for file in data.model_files.iter() {
                     let filename = file.file_name().unwrap().to_str().unwrap();
@@ -38,33 +50,36 @@ impl MenuWidget for ImportMenu {
 
                     ui.label(filename);
 
+                    match assets.status(filename) {
+                        Some(LoadStatus::Loading) => {
+                            ui.spinner();
+                        }
+                        Some(LoadStatus::Failed(err)) => {
+                            ui.label(RichText::new(err).color(Color32::LIGHT_RED));
+                        }
+                        _ => {}
+                    }
+
                     if ui.checkbox(checked, "Is imported").clicked() {
                         import_file = Some(filename.to_string());
                     }
 
+                    let mut is_scene = static_scene.model_id.as_deref() == Some(filename);
+
+                    if ui.checkbox(&mut is_scene, "Show as scene").clicked() {
+                        if is_scene {
+                            static_scene.model_id = Some(filename.to_string());
+                        } else {
+                            static_scene.model_id = None;
+                        }
+                    }
+
                     ui.separator();
                 }
             });
 
-        if let Some(filename) = import_file {
-            let gfx_clone = gfx.clone();
-            let coll_clone = collection.clone();
-
-            task::spawn(async move { load_import(gfx_clone, coll_clone, filename).await });
+        if import_file.is_some() {
+            menu_ctx.events.load_model = import_file;
         }
     }
 }
-
-async fn load_import(
-    gfx: Arc<RwLock<GfxState>>,
-    collection: Arc<RwLock<HashMap<String, Model>>>,
-    filename: String,
-) {
-    let model = Model::load_gltf(&gfx, &filename)
-        .await
-        .expect("Can't load model");
-
-    let collection = &mut collection.write().await;
-
-    collection.insert(filename.to_string(), model);
-}
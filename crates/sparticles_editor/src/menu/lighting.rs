@@ -0,0 +1,218 @@
+use super::{declarations::MenuCtx, MenuWidget};
+use crate::Editor;
+use sparticles_app::{
+    glam::Vec3,
+    gui::egui::{self, color_picker::color_edit_button_rgb, Ui},
+    model::{EnvironmentMap, HemisphereLight, SceneLight, SceneLightKind, SceneLighting},
+};
+use std::path::PathBuf;
+
+pub struct LightingMenu;
+
+impl MenuWidget for LightingMenu {
+    fn title(&self) -> &'static str {
+        "Lighting"
+    }
+
+    fn draw_ui(&self, menu_ctx: &mut MenuCtx) {
+        let data = &mut menu_ctx.emitter_data;
+        let scene_lighting = &mut menu_ctx.state.scene_lighting;
+
+        egui::Window::new("Lighting settings")
+            .vscroll(true)
+            .default_height(800.)
+            .title_bar(false)
+            .default_pos([10., 10.])
+            .show(menu_ctx.ctx, |ui| {
+                data.create_title(ui, "Lighting menu");
+
+                ui.horizontal(|ui| {
+                    if ui.button("Export scene").clicked() {
+                        menu_ctx.events.export_scene = true;
+                    }
+
+                    for kind in SceneLightKind::ALL {
+                        if ui.button(format!("Add {}", kind)).clicked() {
+                            scene_lighting.lights.push(SceneLight::new(kind));
+                        }
+                    }
+                });
+
+                ui.separator();
+
+                hemisphere_light(ui, &mut scene_lighting.ambient);
+
+                ui.separator();
+
+                environment_map(ui, &mut scene_lighting.environment, &data.environment_files);
+
+                ui.separator();
+
+                let mut delete_idx = None;
+
+                for (idx, light) in scene_lighting.lights.iter_mut().enumerate() {
+                    egui::CollapsingHeader::new(format!("{} light {}", light.kind, idx))
+                        .id_source(format!("scene-light-{}", idx))
+                        .show(ui, |ui| {
+                            scene_light(ui, light, idx);
+
+                            if ui.button("Remove light").clicked() {
+                                delete_idx = Some(idx);
+                            }
+                        });
+                }
+
+                if let Some(idx) = delete_idx {
+                    scene_lighting.lights.remove(idx);
+                }
+
+                ui.separator();
+
+                data.create_title(ui, "Import glTF lights");
+
+                for file in data.model_files.iter() {
+                    let filename = file.file_name().unwrap().to_str().unwrap();
+
+                    ui.horizontal(|ui| {
+                        ui.label(filename);
+
+                        if ui.button("Import lights").clicked() {
+                            import_lights(scene_lighting, filename);
+                        }
+                    });
+                }
+            });
+    }
+}
+
+fn import_lights(scene_lighting: &mut SceneLighting, filename: &str) {
+    match SceneLighting::load_gltf(filename) {
+        Ok(lights) => scene_lighting.lights.extend(lights),
+        Err(err) => println!("Can't import lights from {}: {}", filename, err),
+    }
+}
+
+fn hemisphere_light(ui: &mut Ui, ambient: &mut HemisphereLight) {
+    Editor::create_label(ui, "Ambient");
+
+    ui.horizontal(|ui| {
+        ui.label("Sky color: ");
+        color_vec3(ui, &mut ambient.sky_color);
+    });
+
+    ui.horizontal(|ui| {
+        ui.label("Ground color: ");
+        color_vec3(ui, &mut ambient.ground_color);
+    });
+
+    ui.add(egui::Slider::new(&mut ambient.intensity, 0.0..=5.).text("Intensity"));
+}
+
+fn environment_map(ui: &mut Ui, environment: &mut EnvironmentMap, files: &[PathBuf]) {
+    Editor::create_label(ui, "Environment");
+
+    egui::ComboBox::from_id_source("environment-file")
+        .selected_text(environment.file.as_deref().unwrap_or("None"))
+        .show_ui(ui, |ui| {
+            ui.selectable_value(&mut environment.file, None, "None");
+
+            for file in files.iter() {
+                let filename = file.file_name().unwrap().to_str().unwrap();
+                ui.selectable_value(&mut environment.file, Some(filename.to_string()), filename);
+            }
+        });
+
+    ui.add(egui::Slider::new(&mut environment.intensity, 0.0..=5.).text("Intensity"));
+    ui.checkbox(&mut environment.is_background, "Use as background");
+}
+
+fn scene_light(ui: &mut Ui, light: &mut SceneLight, idx: usize) {
+    egui::ComboBox::from_id_source(format!("scene-light-kind-{}", idx))
+        .selected_text(light.kind.to_string())
+        .show_ui(ui, |ui| {
+            for kind in SceneLightKind::ALL {
+                if ui
+                    .selectable_label(light.kind.same_kind(&kind), kind.to_string())
+                    .clicked()
+                    && !light.kind.same_kind(&kind)
+                {
+                    light.kind = kind;
+                }
+            }
+        });
+
+    if light.kind != SceneLightKind::Directional {
+        ui.horizontal(|ui| {
+            ui.label("Position");
+            drag_vec3(ui, &mut light.position);
+        });
+    }
+
+    if light.kind != SceneLightKind::Point {
+        ui.horizontal(|ui| {
+            ui.label("Direction");
+            drag_vec3(ui, &mut light.direction);
+        });
+    }
+
+    ui.horizontal(|ui| {
+        ui.label("Color: ");
+        color_vec3(ui, &mut light.color);
+    });
+
+    ui.horizontal(|ui| {
+        ui.label("Intensity");
+        ui.add(
+            egui::DragValue::new(&mut light.intensity)
+                .clamp_range(0f32..=f32::MAX)
+                .speed(0.1),
+        );
+    });
+
+    if light.kind != SceneLightKind::Directional {
+        let mut has_range = light.range.is_some();
+
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut has_range, "Range");
+
+            if let Some(range) = &mut light.range {
+                ui.add(
+                    egui::DragValue::new(range)
+                        .clamp_range(0.1f32..=f32::MAX)
+                        .speed(0.1),
+                );
+            }
+        });
+
+        if has_range != light.range.is_some() {
+            light.range = has_range.then_some(10.);
+        }
+    }
+
+    if let SceneLightKind::Spot {
+        inner_cone,
+        outer_cone,
+    } = &mut light.kind
+    {
+        let mut inner_deg = inner_cone.to_degrees();
+        let mut outer_deg = outer_cone.to_degrees();
+
+        ui.add(egui::Slider::new(&mut inner_deg, 0.0..=90.).text("Inner cone"));
+        ui.add(egui::Slider::new(&mut outer_deg, 0.0..=90.).text("Outer cone"));
+
+        *inner_cone = inner_deg.min(outer_deg).to_radians();
+        *outer_cone = outer_deg.to_radians();
+    }
+}
+
+fn drag_vec3(ui: &mut Ui, val: &mut Vec3) {
+    ui.add(egui::DragValue::new(&mut val.x).speed(0.1));
+    ui.add(egui::DragValue::new(&mut val.y).speed(0.1));
+    ui.add(egui::DragValue::new(&mut val.z).speed(0.1));
+}
+
+fn color_vec3(ui: &mut Ui, val: &mut Vec3) {
+    let mut color = val.to_array();
+    color_edit_button_rgb(ui, &mut color);
+    *val = color.into();
+}
@@ -2,6 +2,7 @@ pub mod camera_performance;
 pub mod declarations;
 pub mod emitter;
 pub mod import;
+pub mod lighting;
 pub mod none;
 pub mod post_fx;
 
@@ -1,9 +1,12 @@
-use crate::EditorData;
+use crate::{Editor, EditorData};
 use sparticles_app::{
-    animations::{ColorAnimation, ForceAnimation, GravityAnimation, StrayAnimation},
+    animations::{
+        ColorAnimation, ForceAnimation, GravityAnimation, ScriptAnimation, ScriptParamValue,
+        StrayAnimation,
+    },
     gui::egui::{
         color_picker::{color_edit_button_rgba, Alpha},
-        DragValue, Rgba, Slider, Ui,
+        Color32, DragValue, Rgba, RichText, Slider, TextEdit, Ui,
     },
     traits::ParticleAnimation,
 };
@@ -68,20 +71,7 @@ impl EditorWidgets {
             anim.selected_action = editor.create_li_header(ui, "Gravity animation");
             let mut gui = anim.uniform;
 
-            ui.horizontal(|ui| {
-                ui.label("Animate from sec");
-                ui.add(DragValue::new(&mut gui.life_cycle.from_sec).speed(0.1));
-            });
-
-            ui.horizontal(|ui| {
-                ui.label("Animate until sec");
-                ui.add(DragValue::new(&mut gui.life_cycle.until_sec).speed(0.1));
-            });
-
-            ui.horizontal(|ui| {
-                ui.label("Lifetime sec");
-                ui.add(DragValue::new(&mut gui.life_cycle.lifetime_sec).speed(0.1));
-            });
+            Editor::create_life_cycle(ui, &mut gui.life_cycle);
 
             ui.horizontal(|ui| {
                 ui.label("Start position > ");
@@ -171,20 +161,7 @@ impl EditorWidgets {
 
             let mut gui = anim.uniform;
 
-            ui.horizontal(|ui| {
-                ui.label("Animate from sec");
-                ui.add(DragValue::new(&mut gui.life_cycle.from_sec).speed(0.1));
-            });
-
-            ui.horizontal(|ui| {
-                ui.label("Animate until sec");
-                ui.add(DragValue::new(&mut gui.life_cycle.until_sec).speed(0.1));
-            });
-
-            ui.horizontal(|ui| {
-                ui.label("Lifetime sec");
-                ui.add(DragValue::new(&mut gui.life_cycle.lifetime_sec).speed(0.1));
-            });
+            Editor::create_life_cycle(ui, &mut gui.life_cycle);
 
             ui.horizontal(|ui| {
                 ui.label("Force velocity > ");
@@ -209,4 +186,74 @@ impl EditorWidgets {
             }
         }
     }
+
+    pub fn script_anim(
+        editor: &mut EditorData,
+        anim: &mut Box<dyn ParticleAnimation>,
+        ui: &mut Ui,
+    ) {
+        let downcast = anim.as_any().downcast_mut::<ScriptAnimation>();
+
+        if let Some(anim) = downcast {
+            let title = format!("Script animation: {}", anim.uniform.name);
+            anim.selected_action = editor.create_li_header(ui, &title);
+
+            let mut params = anim.uniform.params.clone();
+
+            for param in params.iter_mut() {
+                ui.horizontal(|ui| {
+                    ui.label(&param.name);
+
+                    match &mut param.value {
+                        ScriptParamValue::Float { value } => {
+                            ui.add(DragValue::new(value).speed(0.01));
+                        }
+                        ScriptParamValue::Vec3 { value } => {
+                            ui.label("x:");
+                            ui.add(DragValue::new(&mut value.x).speed(0.1));
+                            ui.label("y:");
+                            ui.add(DragValue::new(&mut value.y).speed(0.1));
+                            ui.label("z:");
+                            ui.add(DragValue::new(&mut value.z).speed(0.1));
+                        }
+                        ScriptParamValue::Color { value } => {
+                            let mut color =
+                                Rgba::from_rgba_premultiplied(value.x, value.y, value.z, value.w);
+                            color_edit_button_rgba(ui, &mut color, Alpha::BlendOrAdditive);
+                            *value = color.to_array().into();
+                        }
+                        ScriptParamValue::Curve { from, to, easing } => {
+                            ui.label("from:");
+                            ui.add(DragValue::new(from).speed(0.01));
+                            ui.label("to:");
+                            ui.add(DragValue::new(to).speed(0.01));
+                            Editor::create_easing(ui, easing);
+                        }
+                    }
+                });
+            }
+
+            if anim.uniform.params != params {
+                anim.update_uniform = true;
+                anim.uniform.params = params;
+            }
+
+            ui.add(
+                TextEdit::multiline(&mut anim.uniform.source)
+                    .code_editor()
+                    .desired_rows(6)
+                    .desired_width(f32::INFINITY),
+            );
+
+            if ui.button("Compile").clicked() {
+                anim.recompile = true;
+            }
+
+            if let Some(err) = &anim.error {
+                ui.label(RichText::new(err).monospace().color(Color32::LIGHT_RED));
+            }
+
+            ui.checkbox(&mut anim.enabled, "Enabled");
+        }
+    }
 }
@@ -6,7 +6,7 @@ use sparticles_app::{
         GravityUniformOptions, RegisterColorAnimation, RegisterForceAnimation,
         RegisterGravityAnimation, RegisterStrayAnimation, StrayUniform, SwayAnimation,
     },
-    gui::{winit::event::KeyboardInput, State},
+    gui::{winit::event::KeyEvent, State},
     init::{AppVisitor, DataSource},
     loader::{BUILTIN_ID, CIRCLE_MAT_ID, CIRCLE_MESH_ID},
     model::{
@@ -67,7 +67,7 @@ impl AppVisitor for GameState {
         };
 
         DataSource::Code {
-            lights: Box::new(lights),
+            lights: vec![lights],
             emitters: vec![emitter],
         }
     }
@@ -75,16 +75,13 @@ impl AppVisitor for GameState {
     fn draw_ui(
         &mut self,
         state: &mut SparState,
+        events: &mut SparEvents,
         encoder: &mut CommandEncoder,
-    ) -> sparticles_app::model::SparEvents {
-        let mut events = SparEvents::default();
-
+    ) {
         #[cfg(feature = "editor")]
         self.guis
             .get_mut(GUI_EDITOR)
-            .map(|gui| gui.draw_gui(state, &mut events, encoder));
-
-        events
+            .map(|gui| gui.draw_gui(state, events, encoder));
     }
 
     fn add_widget_builders(&mut self, state: &mut SparState) {
@@ -93,12 +90,7 @@ impl AppVisitor for GameState {
             .insert(GUI_EDITOR, Box::new(Editor::new(state, self.model_dir())));
     }
 
-    fn process_events(
-        &mut self,
-        events: &mut SparEvents,
-        input: &KeyboardInput,
-        shift_pressed: bool,
-    ) {
+    fn process_events(&mut self, events: &mut SparEvents, input: &KeyEvent, shift_pressed: bool) {
         #[cfg(feature = "editor")]
         self.guis
             .get_mut(GUI_EDITOR)
@@ -112,6 +104,7 @@ impl AppVisitor for GameState {
                     from_sec: 0.,
                     until_sec: 4.,
                     lifetime_sec: 4.,
+                    ..Default::default()
                 },
                 glam::Vec2::ZERO,
                 Vec2::new(30., 120.),
@@ -125,6 +118,7 @@ impl AppVisitor for GameState {
                     from_sec: 0.,
                     until_sec: 5.,
                     lifetime_sec: 5.,
+                    ..Default::default()
                 },
                 [0., 45.].into(),
                 [0., 15.].into(),
@@ -165,6 +159,7 @@ impl AppVisitor for GameState {
                         from_sec: 0.,
                         until_sec: 5.,
                         lifetime_sec: 10.,
+                        ..Default::default()
                     },
                     velocity: [-15., -15., 0.].into(),
                     mass_per_unit: 8.5,
@@ -179,6 +174,7 @@ impl AppVisitor for GameState {
                         from_sec: 5.,
                         until_sec: 10.,
                         lifetime_sec: 10.,
+                        ..Default::default()
                     },
                     velocity: [15., 0., 0.].into(),
                     mass_per_unit: 3.5,
@@ -193,6 +189,7 @@ impl AppVisitor for GameState {
                         from_sec: 1.,
                         until_sec: 6.,
                         lifetime_sec: 12.,
+                        ..Default::default()
                     },
                     gravitational_force: 0.0015,
                     dead_zone: 4.,